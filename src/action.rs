@@ -0,0 +1,93 @@
+use crate::components::Direction;
+use crate::inventory::{Inventory, ItemUsedEvent};
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<WaitEvent>().add_system(
+            resolve_actions
+                .system()
+                .label("act")
+                .after("input")
+                .after("ally")
+                .after("ai"),
+        );
+    }
+}
+
+// carries out what every actor chose this frame, whoever or whatever chose it, handing each
+// kind of action on to the systems that deal with it
+fn resolve_actions(
+    game_state: Res<GameState>,
+    mut ev_move_intent: EventWriter<MoveIntentEvent>,
+    mut ev_attack: EventWriter<AttackEvent>,
+    mut ev_item_used: EventWriter<ItemUsedEvent>,
+    mut ev_block: EventWriter<BlockEvent>,
+    mut ev_wait: EventWriter<WaitEvent>,
+    (mut ev_finished_map, mut ev_victory): (
+        EventWriter<FinishedMapEvent>,
+        EventWriter<VictoryEvent>,
+    ),
+    stairs_query: Query<&OnMap, With<Stairs>>,
+    sealed_query: Query<(), With<SealsStairs>>,
+    target_query: Query<&GridPos>,
+    mut actor_query: Query<(
+        Entity,
+        &mut NextAction,
+        &GridPos,
+        &mut Direction,
+        Option<&mut Inventory>,
+        Option<&Player>,
+    )>,
+) {
+    for (actor, mut next, loc, mut facing, inventory, player) in actor_query.iter_mut() {
+        let action = match next.0.take() {
+            Some(action) => action,
+            None => continue,
+        };
+        match action {
+            // the movement system checks walls and corners, then updates the location
+            Action::Move(direction) => ev_move_intent.send(MoveIntentEvent { actor, direction }),
+            Action::Attack(target) => {
+                // they may have moved off since
+                let target_loc = match target_query.get(target) {
+                    Ok(target_loc) if target_loc.chebyshev(loc) == 1 => target_loc,
+                    _ => continue,
+                };
+                *facing = Direction(target_loc.x - loc.x, target_loc.y - loc.y);
+                ev_attack.send(AttackEvent {
+                    attacker: actor,
+                    target,
+                    ranged: false,
+                    power: None,
+                    element: None,
+                });
+            }
+            Action::UseItem(slot) => {
+                if let Some(mut inventory) = inventory.filter(|inv| slot < inv.items.len()) {
+                    let item = inventory.items.remove(slot);
+                    ev_item_used.send(ItemUsedEvent { user: actor, item });
+                }
+            }
+            Action::Block => ev_block.send(BlockEvent { actor }),
+            Action::Wait => ev_wait.send(WaitEvent { actor }),
+            // only the player's trip down the stairs takes everyone to the next floor, and
+            // not while something seals them
+            Action::Descend => {
+                let on_stairs = stairs_query.iter().any(|stairs| stairs.0 == *loc);
+                if player.is_none() || !on_stairs || sealed_query.iter().next().is_some() {
+                    continue;
+                }
+                // the last flight of stairs leads out of the dungeon
+                if game_state.depth >= FINAL_DEPTH {
+                    ev_victory.send(VictoryEvent);
+                } else {
+                    ev_finished_map.send(FinishedMapEvent);
+                }
+            }
+        }
+    }
+}
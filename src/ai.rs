@@ -0,0 +1,421 @@
+use crate::boss::Boss;
+use crate::components::Direction;
+use crate::enemy::{Disguised, EnemyKind, EnemyTemplates, PackMember};
+use crate::light::LightMap;
+use crate::movement::can_move;
+use crate::pathfinding::{find_path, DijkstraMap, NEIGHBORS};
+use crate::prelude::*;
+use crate::scent::ScentMap;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use crate::summoner::Summoner;
+use crate::visibility::is_visible;
+use bevy::prelude::*;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+pub struct AiPlugin;
+
+// how far enemies can spot the player from
+const SIGHT_RANGE: i32 = 8;
+// how many turns a chasing enemy keeps hunting after losing sight of the player
+const LOSE_TRACK_TURNS: u32 = 5;
+
+// how close a noise has to be to wake up sleeping enemies
+const WAKE_RADIUS: i32 = 3;
+
+pub enum AiState {
+    // doesn't act until the player gets adjacent, makes noise nearby, or attacks it
+    Sleeping,
+    Wandering,
+    Chasing {
+        last_seen: GridPos,
+        turns_unseen: u32,
+    },
+    // morale broke, runs for the far end of the map until the player is out of sight
+    Fleeing {
+        turns_unseen: u32,
+    },
+    // heard something and is heading over to see what it was
+    Investigating {
+        target: GridPos,
+        turns: u32,
+    },
+}
+
+// how much attention an enemy is paying, attacks on anything short of aware land as backstabs
+#[derive(Clone, Copy, PartialEq)]
+pub enum Awareness {
+    Unaware,
+    // heard something, but doesn't know where the player is
+    Suspicious,
+    Aware,
+}
+
+impl AiState {
+    pub fn awareness(&self) -> Awareness {
+        match self {
+            AiState::Sleeping | AiState::Wandering => Awareness::Unaware,
+            AiState::Investigating { .. } => Awareness::Suspicious,
+            AiState::Chasing { .. } | AiState::Fleeing { .. } => Awareness::Aware,
+        }
+    }
+}
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(enemy_turn.system().label("ai").after("input"))
+            .add_system(mimic_turn.system().after("input").before("ai"))
+            .add_system(reveal_on_attack.system().after("combat"))
+            .add_system(hear_noise.system().after("combat"))
+            .add_system(break_pack_morale.system().after("combat"));
+    }
+}
+
+pub fn can_see(map_data: &DungeonMap, from: &GridPos, to: &GridPos) -> bool {
+    from.chebyshev(to) <= SIGHT_RANGE && is_visible(map_data, from, to)
+}
+
+// once the player's action has played out, every enemy decides at once:
+// wander until the player is spotted, then chase them down
+fn enemy_turn(
+    mut game_state: ResMut<GameState>,
+    templates: Res<EnemyTemplates>,
+    scent_map: Res<ScentMap>,
+    light_map: Res<LightMap>,
+    index: Res<SpatialIndex>,
+    mut ev_fire: EventWriter<FireProjectileEvent>,
+    map_query: Query<&Map>,
+    player_query: Query<(&GridPos, Option<&StatusEffects>), With<Player>>,
+    blocker_query: Query<(), With<BlocksMovement>>,
+    mut enemy_query: Query<
+        (
+            Entity,
+            &EnemyKind,
+            &GridPos,
+            &mut AiState,
+            Option<&PackMember>,
+            &Stats,
+            &mut NextAction,
+        ),
+        (
+            With<Enemy>,
+            Without<Boss>,
+            Without<Disguised>,
+            Without<Summoner>,
+        ),
+    >,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("wander");
+    if game_state.phase != TurnPhase::EnemyAction {
+        return;
+    }
+    // the moves get resolved and animated together, the turn system ends the turn after
+    game_state.phase = TurnPhase::EnemyAnimating;
+    if let Ok((player_loc, effects)) = player_query.single() {
+        // an invisible player can still be bumped into, heard and smelled, just not seen
+        let invisible = effects.is_some_and(|e| e.has(Status::Invisible));
+        if let Ok(current_map) = map_query.single() {
+            let map_data = &current_map.0;
+            // a pack that spots the player hunts them together
+            let mut alerted_packs: HashSet<Entity> = HashSet::new();
+            for (_, _, enemy_loc, state, pack, _, _) in enemy_query.iter_mut() {
+                if matches!(*state, AiState::Sleeping) {
+                    continue;
+                }
+                if let Some(pack) = pack {
+                    if !invisible && can_see(map_data, enemy_loc, player_loc) {
+                        alerted_packs.insert(pack.leader);
+                    }
+                }
+            }
+            // tiles next to the player that pack members have already called dibs on
+            let mut flank_claims: Vec<GridPos> = Vec::new();
+            // distances to the player, shared by everyone chasing or running from them.
+            // only worked out if somebody needs it this turn
+            let mut to_player: Option<DijkstraMap> = None;
+
+            for (enemy_entity, kind, enemy_loc, mut state, pack, stats, mut action) in
+                enemy_query.iter_mut()
+            {
+                // one whose pack has gone can't be told what to do
+                let template = match templates.get(kind) {
+                    Some(template) => template,
+                    None => continue,
+                };
+                // sleepers only notice someone standing right next to them,
+                // and spend the turn waking up
+                if matches!(*state, AiState::Sleeping) {
+                    if enemy_loc.chebyshev(player_loc) <= 1 {
+                        *state = AiState::Chasing {
+                            last_seen: *player_loc,
+                            turns_unseen: 0,
+                        };
+                    }
+                    continue;
+                }
+                // shadow creatures freeze wherever a torch or brazier shines on them
+                if template.shadow && !light_map.is_dark(enemy_loc) {
+                    continue;
+                }
+                let sees_player = !invisible && can_see(map_data, enemy_loc, player_loc);
+                let alerted =
+                    sees_player || pack.is_some_and(|p| alerted_packs.contains(&p.leader));
+                // update aggro before deciding where to go
+                if let AiState::Fleeing { turns_unseen } = &mut *state {
+                    if sees_player {
+                        *turns_unseen = 0;
+                    } else {
+                        *turns_unseen += 1;
+                        if *turns_unseen > LOSE_TRACK_TURNS {
+                            *state = AiState::Wandering;
+                        }
+                    }
+                } else if alerted && stats.hp * 4 < stats.max_hp {
+                    // badly hurt enemies lose their nerve when they see the player
+                    *state = AiState::Fleeing { turns_unseen: 0 };
+                } else if alerted {
+                    *state = AiState::Chasing {
+                        last_seen: *player_loc,
+                        turns_unseen: 0,
+                    };
+                } else if let AiState::Chasing { turns_unseen, .. } = &mut *state {
+                    *turns_unseen += 1;
+                    if *turns_unseen > LOSE_TRACK_TURNS {
+                        *state = AiState::Wandering;
+                    }
+                } else if let AiState::Investigating { target, turns } = &mut *state {
+                    // gives up once it gets there, or if it's taking too long
+                    *turns += 1;
+                    if *turns > LOSE_TRACK_TURNS
+                        || (target.x, target.y) == (enemy_loc.x, enemy_loc.y)
+                    {
+                        *state = AiState::Wandering;
+                    }
+                }
+
+                // ranged enemies shoot instead of closing the distance
+                let distance = enemy_loc.chebyshev(player_loc);
+                let fleeing = matches!(*state, AiState::Fleeing { .. });
+
+                // hounds that can't see the player put their nose to the ground
+                if !fleeing && !sees_player && template.tracks_scent {
+                    if let Some(next) = scent_map.follow(map_data, enemy_loc) {
+                        action.0 = Some(Action::Move(Direction(
+                            next.x - enemy_loc.x,
+                            next.y - enemy_loc.y,
+                        )));
+                        *state = AiState::Chasing {
+                            last_seen: next,
+                            turns_unseen: 0,
+                        };
+                        continue;
+                    }
+                }
+                if !fleeing && sees_player && distance > 1 && distance <= template.range {
+                    ev_fire.send(FireProjectileEvent {
+                        source: enemy_entity,
+                        from: *enemy_loc,
+                        to: *player_loc,
+                        power: None,
+                        element: None,
+                        payload: None,
+                    });
+                    continue;
+                }
+
+                let direction = match &*state {
+                    AiState::Chasing { last_seen, .. } => {
+                        // the leader goes straight in, the rest try to surround the player
+                        let is_follower = pack.is_some_and(|p| p.leader != enemy_entity);
+                        let goal = if is_follower && distance > 1 {
+                            flank_tile(
+                                map_data,
+                                enemy_loc,
+                                player_loc,
+                                &index,
+                                &blocker_query,
+                                &flank_claims,
+                            )
+                            .unwrap_or(*last_seen)
+                        } else {
+                            *last_seen
+                        };
+                        flank_claims.push(goal);
+                        let next = if goal == *player_loc {
+                            to_player
+                                .get_or_insert_with(|| DijkstraMap::new(map_data, [*player_loc]))
+                                .step_towards(map_data, enemy_loc)
+                        } else {
+                            find_path(map_data, enemy_loc, &goal).first().copied()
+                        };
+                        // stepping into the player is resolved as an attack
+                        next.map(|next| Direction(next.x - enemy_loc.x, next.y - enemy_loc.y))
+                    }
+                    AiState::Fleeing { .. } => {
+                        let goal = to_player
+                            .get_or_insert_with(|| DijkstraMap::new(map_data, [*player_loc]))
+                            .farthest();
+                        goal.and_then(|goal| {
+                            find_path(map_data, enemy_loc, &goal)
+                                .first()
+                                .map(|next| Direction(next.x - enemy_loc.x, next.y - enemy_loc.y))
+                        })
+                        // stepping into the player would be an attack, cornered enemies just cower
+                        .filter(|dir| {
+                            (enemy_loc.x + dir.0, enemy_loc.y + dir.1)
+                                != (player_loc.x, player_loc.y)
+                        })
+                    }
+                    AiState::Investigating { target, .. } => find_path(map_data, enemy_loc, target)
+                        .first()
+                        .map(|next| Direction(next.x - enemy_loc.x, next.y - enemy_loc.y)),
+                    AiState::Sleeping => None,
+                    AiState::Wandering => {
+                        // idle around, sometimes just standing still
+                        let (dx, dy) = NEIGHBORS[rng.gen_range(0..NEIGHBORS.len())];
+                        if rng.gen_bool(0.5) && can_move(map_data, enemy_loc, dx, dy) {
+                            Some(Direction(dx, dy))
+                        } else {
+                            None
+                        }
+                    }
+                };
+                action.0 = direction.map(Action::Move);
+            }
+        }
+    }
+}
+
+// closest open tile next to the target that nobody is standing on or heading for
+fn flank_tile(
+    map_data: &DungeonMap,
+    from: &GridPos,
+    target: &GridPos,
+    index: &SpatialIndex,
+    blockers: &Query<(), With<BlocksMovement>>,
+    claimed: &[GridPos],
+) -> Option<GridPos> {
+    NEIGHBORS
+        .iter()
+        .map(|&(dx, dy)| target.add(dx, dy))
+        .filter(|loc| can_move(map_data, target, loc.x - target.x, loc.y - target.y))
+        .filter(|loc| {
+            !index.is_blocked(loc, blockers)
+                && !claimed.iter().any(|t| t.x == loc.x && t.y == loc.y)
+        })
+        .min_by_key(|loc| from.chebyshev(loc))
+}
+
+// enemies that can't see the player go to check out noises,
+// sleepers only wake up for something close by
+fn hear_noise(
+    mut ev_noise: EventReader<NoiseEvent>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, With<Player>>,
+    mut enemy_query: Query<(&GridPos, &mut AiState), (With<Enemy>, Without<Disguised>)>,
+) {
+    if let (Ok(current_map), Ok(player_loc)) = (map_query.single(), player_query.single()) {
+        for noise in ev_noise.iter() {
+            for (enemy_loc, mut state) in enemy_query.iter_mut() {
+                let distance = enemy_loc.chebyshev(&noise.location);
+                let hears = match *state {
+                    AiState::Sleeping => distance <= WAKE_RADIUS.min(noise.radius),
+                    AiState::Wandering | AiState::Investigating { .. } => {
+                        distance <= noise.radius && !can_see(&current_map.0, enemy_loc, player_loc)
+                    }
+                    // already busy with the player
+                    AiState::Chasing { .. } | AiState::Fleeing { .. } => false,
+                };
+                if hears {
+                    *state = AiState::Investigating {
+                        target: noise.location,
+                        turns: 0,
+                    };
+                }
+            }
+        }
+    }
+}
+
+// losing the pack leader sends the rest of the pack running
+fn break_pack_morale(
+    mut ev_death: EventReader<DeathEvent>,
+    mut enemy_query: Query<(Entity, &PackMember, &mut AiState)>,
+) {
+    for death in ev_death.iter() {
+        for (entity, pack, mut state) in enemy_query.iter_mut() {
+            if pack.leader == death.entity && entity != death.entity {
+                *state = AiState::Fleeing { turns_unseen: 0 };
+            }
+        }
+    }
+}
+
+// swaps the chest sprite for the real one, the mimic acts like any other enemy from then on
+fn reveal(commands: &mut Commands, templates: &EnemyTemplates, entity: Entity, kind: &EnemyKind) {
+    let sprite = match templates.get(kind) {
+        Some(template) => template.sprite(),
+        None => return,
+    };
+    // a fresh Tint, so it gets recoloured for the palette
+    commands
+        .entity(entity)
+        .remove::<Disguised>()
+        .insert(Tint(sprite.color))
+        .insert(sprite);
+}
+
+// a disguised enemy springs on the player as soon as they step next to it
+fn mimic_turn(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, With<Player>>,
+    mut mimic_query: Query<
+        (Entity, &EnemyKind, &GridPos, &mut AiState, &mut NextAction),
+        With<Disguised>,
+    >,
+) {
+    if game_state.phase != TurnPhase::EnemyAction {
+        return;
+    }
+    if let (Ok(current_map), Ok(player_loc)) = (map_query.single(), player_query.single()) {
+        for (entity, kind, loc, mut state, mut action) in mimic_query.iter_mut() {
+            let (dx, dy) = (player_loc.x - loc.x, player_loc.y - loc.y);
+            if loc.chebyshev(player_loc) > 1 || !can_move(&current_map.0, loc, dx, dy) {
+                continue;
+            }
+            reveal(&mut commands, &templates, entity, kind);
+            *state = AiState::Chasing {
+                last_seen: *player_loc,
+                turns_unseen: 0,
+            };
+            action.0 = Some(Action::Move(Direction(dx, dy)));
+        }
+    }
+}
+
+// poking at a disguised enemy gives the game away too
+fn reveal_on_attack(
+    mut commands: Commands,
+    templates: Res<EnemyTemplates>,
+    mut ev_attack: EventReader<AttackEvent>,
+    location_query: Query<&GridPos>,
+    mut mimic_query: Query<(&EnemyKind, &mut AiState), With<Disguised>>,
+) {
+    for attack in ev_attack.iter() {
+        if let Ok((kind, mut state)) = mimic_query.get_mut(attack.target) {
+            reveal(&mut commands, &templates, attack.target, kind);
+            if let Ok(attacker_loc) = location_query.get(attack.attacker) {
+                *state = AiState::Chasing {
+                    last_seen: *attacker_loc,
+                    turns_unseen: 0,
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::npc::{Npc, NpcLibrary};
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct AltarPlugin;
+
+// odds of a floor having a shrine room
+const SHRINE_CHANCE: f64 = 0.35;
+
+// a fixture the player bumps into for a menu of offerings.
+// the menu itself is a conversation, defined with the npcs
+pub struct Altar;
+
+impl Plugin for AltarPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            place_shrine
+                .system()
+                .label("place_shrine")
+                .after("place_wanderer"),
+        );
+    }
+}
+
+fn place_shrine(
+    mut commands: Commands,
+    library: Res<NpcLibrary>,
+    window: Res<WinSize>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "shrine");
+        let shrines: Vec<usize> = (0..library.0.len())
+            .filter(|i| library.0[*i].shrine)
+            .collect();
+        if shrines.is_empty() || map_rooms.rooms.len() < 2 || !rng.gen_bool(SHRINE_CHANCE) {
+            return;
+        }
+        // any room but the one the player starts in
+        let mut room = rng.gen_range(0..map_rooms.rooms.len() - 1);
+        if room >= map_rooms.spawn_room {
+            room += 1;
+        }
+        let loc = match spawn_tiles.claim_in(&map_rooms.rooms[room], &mut rng) {
+            Some(loc) => loc,
+            None => return,
+        };
+        let index = shrines[rng.gen_range(0..shrines.len())];
+        spawn_altar(&mut commands, &library, &window, &mut materials, index, loc);
+    }
+}
+
+pub fn spawn_altar(
+    commands: &mut Commands,
+    library: &NpcLibrary,
+    window: &WinSize,
+    materials: &mut Assets<ColorMaterial>,
+    index: usize,
+    loc: GridPos,
+) {
+    let (r, g, b) = library.0[index].color;
+    let color = Color::rgb(r, g, b);
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(color.into()),
+            sprite: Sprite::new(Vec2::new(window.tile * 0.8, window.tile * 0.5)),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Altar)
+        .insert(Tint(color))
+        .insert(Interactable)
+        .insert(Npc(index))
+        .insert(Name::new(library.0[index].name.clone()))
+        .insert(BlocksMovement)
+        .insert(Faction::Neutral)
+        .insert(OnMap(loc))
+        .insert(loc);
+}
@@ -0,0 +1,81 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct AnimationPlugin;
+
+// the stairs cell followed by its glow frames, pulsing in and out
+const STAIRS_FRAMES: [u32; 4] = [TileSprite::Stairs as u32, 32, 33, 32];
+const STAIRS_FRAME_TIME: f32 = 0.35;
+const TORCH_FRAMES: [u32; 3] = [TileSprite::Torch as u32, 35, 36];
+const TORCH_FRAME_TIME: f32 = 0.15;
+// the player's two strides, with the standing cell between them
+const PLAYER_STRIDES: [u32; 2] = [37, 38];
+
+// cycles an atlas sprite through its frames on a timer
+pub struct TileAnimation {
+    frames: Vec<u32>,
+    frame: usize,
+    timer: Timer,
+}
+impl TileAnimation {
+    pub fn new(frames: &[u32], frame_time: f32) -> Self {
+        TileAnimation {
+            frames: frames.to_vec(),
+            frame: 0,
+            timer: Timer::from_seconds(frame_time, true),
+        }
+    }
+
+    pub fn stairs() -> Self {
+        TileAnimation::new(&STAIRS_FRAMES, STAIRS_FRAME_TIME)
+    }
+
+    pub fn torch() -> Self {
+        TileAnimation::new(&TORCH_FRAMES, TORCH_FRAME_TIME)
+    }
+}
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(animate_tiles.system())
+            .add_system(walk_cycle.system().after("animate"));
+    }
+}
+
+fn animate_tiles(
+    time: Res<Time>,
+    mut animation_query: Query<(&mut TileAnimation, &mut TextureAtlasSprite)>,
+) {
+    for (mut animation, mut sprite) in animation_query.iter_mut() {
+        if animation.timer.tick(time.delta()).just_finished() {
+            animation.frame = (animation.frame + 1) % animation.frames.len();
+            sprite.index = animation.frames[animation.frame];
+        }
+    }
+}
+
+// takes a stride through the first half of each step and stands for the second,
+// alternating feet from one tile to the next
+fn walk_cycle(
+    window: Res<WinSize>,
+    mut player_query: Query<(&mut TextureAtlasSprite, &Transform, Option<&MovingTo>), With<Player>>,
+) {
+    if let Ok((mut sprite, tf, moving_to)) = player_query.single_mut() {
+        let index = match moving_to {
+            Some(MovingTo(dest)) => {
+                let remaining = (dest.x as f32 * window.tile - tf.translation.x)
+                    .abs()
+                    .max((dest.y as f32 * window.tile - tf.translation.y).abs());
+                if remaining > window.tile / 2. {
+                    PLAYER_STRIDES[(dest.x + dest.y).rem_euclid(2) as usize]
+                } else {
+                    TileSprite::Player as u32
+                }
+            }
+            None => TileSprite::Player as u32,
+        };
+        if sprite.index != index {
+            sprite.index = index;
+        }
+    }
+}
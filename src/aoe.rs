@@ -0,0 +1,95 @@
+use crate::prelude::*;
+use crate::visibility::{line, line_of_sight};
+use bevy::prelude::*;
+
+// how wide a cone spreads, as the cosine of the angle off its centre line (45 degrees)
+const CONE_SPREAD: f32 = 0.7;
+
+// the area an attack covers, sizes are in tiles
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AoeShape {
+    // everything within the radius of the target tile
+    Circle(i32),
+    // fans out from the caster towards the target
+    Cone(i32),
+    // a straight bolt from the caster through the target, stopped by the first wall
+    Line(i32),
+}
+
+impl AoeShape {
+    // every open tile the effect reaches when cast from `origin` at `target`,
+    // walls block it the same way they block sight
+    pub fn tiles(&self, map_data: &DungeonMap, origin: &GridPos, target: &GridPos) -> Vec<GridPos> {
+        let is_ground = |loc: &GridPos| map_data.is_walkable(loc.x, loc.y);
+        match *self {
+            AoeShape::Circle(radius) => around(target, radius)
+                .filter(|loc| {
+                    let (dx, dy) = (loc.x - target.x, loc.y - target.y);
+                    dx * dx + dy * dy <= radius * radius + radius
+                })
+                .filter(|loc| is_ground(loc) && line_of_sight(map_data, target, loc))
+                .collect(),
+            AoeShape::Cone(length) => {
+                let (dx, dy) = ((target.x - origin.x) as f32, (target.y - origin.y) as f32);
+                let reach = (dx * dx + dy * dy).sqrt();
+                if reach == 0. {
+                    return Vec::new();
+                }
+                around(origin, length)
+                    .filter(|loc| {
+                        let (vx, vy) = ((loc.x - origin.x) as f32, (loc.y - origin.y) as f32);
+                        let dist = (vx * vx + vy * vy).sqrt();
+                        dist > 0.
+                            && dist <= length as f32 + 0.5
+                            && (vx * dx + vy * dy) / (dist * reach) >= CONE_SPREAD
+                    })
+                    .filter(|loc| is_ground(loc) && line_of_sight(map_data, origin, loc))
+                    .collect()
+            }
+            AoeShape::Line(length) => {
+                let steps = origin.chebyshev(target);
+                if steps == 0 {
+                    return Vec::new();
+                }
+                // stretch the line out to its full length past the target
+                let end = GridPos::new(
+                    origin.x + (target.x - origin.x) * length / steps,
+                    origin.y + (target.y - origin.y) * length / steps,
+                );
+                line(origin, &end)
+                    .into_iter()
+                    .take_while(|loc| is_ground(loc))
+                    .collect()
+            }
+        }
+    }
+}
+
+// the square of tiles within `radius` of `center`
+fn around(center: &GridPos, radius: i32) -> impl Iterator<Item = GridPos> {
+    let (cx, cy) = (center.x, center.y);
+    (cy - radius..=cy + radius)
+        .flat_map(move |y| (cx - radius..=cx + radius).map(move |x| GridPos::new(x, y)))
+}
+
+// a tinted square over one tile, for showing where something is about to land
+pub fn spawn_highlight(
+    commands: &mut Commands,
+    material: Handle<ColorMaterial>,
+    window: &WinSize,
+    loc: &GridPos,
+    z: f32,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(Vec2::new(window.tile, window.tile)),
+            transform: Transform::from_xyz(
+                loc.x as f32 * window.tile,
+                loc.y as f32 * window.tile,
+                z,
+            ),
+            ..Default::default()
+        })
+        .id()
+}
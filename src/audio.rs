@@ -0,0 +1,521 @@
+use crate::ai::AiState;
+use crate::boss::Boss;
+use crate::chest::{Chest, TrapTriggeredEvent};
+use crate::circuit::MechanismMovedEvent;
+use crate::inventory::PickUpEvent;
+use crate::prelude::*;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use rodio::source::ChannelVolume;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+pub struct AudioPlugin;
+
+// assets/music/<theme>.mp3 for each floor theme, and boss.mp3. a missing track is silence
+const MUSIC_FOLDER: &str = "music";
+const BOSS_TRACK: &str = "boss";
+// music sits under everything else
+const MUSIC_VOLUME: f32 = 0.6;
+const CROSSFADE_SECONDS: f32 = 2.;
+// assets/ambience/<theme>.mp3, looped under the music for the whole floor
+const AMBIENCE_FOLDER: &str = "ambience";
+const AMBIENCE_VOLUME: f32 = 0.5;
+// each new floor's ambience comes up over this long
+const AMBIENCE_FADE_SECONDS: f32 = 3.;
+// how far the ambience drops while anything is after the player, and how fast it moves
+const COMBAT_DUCK: f32 = 0.3;
+const DUCK_SECONDS: f32 = 0.5;
+// assets/sfx/<name>.mp3, see Sfx::name
+const SFX_FOLDER: &str = "sfx";
+// sounds further off than this many tiles can't be heard, and fade out towards it
+const HEARING_RANGE: f32 = 24.;
+// tiles to the side at which a sound is all in one ear
+const PAN_RANGE: f32 = 8.;
+
+// every sound effect, each played off the gameplay event it goes with so the systems
+// sending those don't need to know about sound
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Sfx {
+    Footstep,
+    // a chest creaking open
+    Open,
+    Attack,
+    Hit,
+    PickUp,
+    Stairs,
+    Click,
+    // an enemy noticing the player
+    Roar,
+    Trap,
+    // a gate or bridge grinding along
+    Mechanism,
+}
+
+impl Sfx {
+    const ALL: [Sfx; 10] = [
+        Sfx::Footstep,
+        Sfx::Open,
+        Sfx::Attack,
+        Sfx::Hit,
+        Sfx::PickUp,
+        Sfx::Stairs,
+        Sfx::Click,
+        Sfx::Roar,
+        Sfx::Trap,
+        Sfx::Mechanism,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Sfx::Footstep => "footstep",
+            Sfx::Open => "open",
+            Sfx::Attack => "attack",
+            Sfx::Hit => "hit",
+            Sfx::PickUp => "pick_up",
+            Sfx::Stairs => "stairs",
+            Sfx::Click => "click",
+            Sfx::Roar => "roar",
+            Sfx::Trap => "trap",
+            Sfx::Mechanism => "mechanism",
+        }
+    }
+}
+
+struct SfxSounds(HashMap<Sfx, Handle<AudioSource>>);
+
+#[derive(Clone, Copy, PartialEq)]
+enum MusicTrack {
+    Floor(FloorTheme),
+    Boss,
+}
+
+struct MusicTracks {
+    floors: HashMap<FloorTheme, Handle<AudioSource>>,
+    boss: Handle<AudioSource>,
+}
+
+impl MusicTracks {
+    fn get(&self, track: MusicTrack) -> &Handle<AudioSource> {
+        match track {
+            MusicTrack::Floor(theme) => &self.floors[&theme],
+            MusicTrack::Boss => &self.boss,
+        }
+    }
+}
+
+// a track playing on its own sink, faded in or out by the crossfade
+struct Playing {
+    track: MusicTrack,
+    sink: Sink,
+    fade: f32,
+}
+
+// a floor's ambient loop. the depth tells apart two floors of the same theme, so each
+// new floor still fades in
+struct Ambience {
+    depth: u32,
+    sink: Sink,
+    fade: f32,
+}
+
+// the output stream has to stay alive for anything to be heard, and isn't Send. without
+// a sound device the game is silent
+struct Speakers {
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    // the last one is the track that should be playing, the rest are fading out
+    music: Vec<Playing>,
+    // the same for the ambience, with how far it's ducked under a fight
+    ambience: Vec<Ambience>,
+    duck: f32,
+}
+
+impl Speakers {
+    // starts a source looping silently, for the caller to fade in
+    fn start_loop(&self, source: &AudioSource) -> Option<Sink> {
+        let (_, handle) = self.output.as_ref()?;
+        match (
+            Decoder::new(Cursor::new(source.clone())),
+            Sink::try_new(handle),
+        ) {
+            (Ok(decoder), Ok(sink)) => {
+                sink.set_volume(0.);
+                sink.append(decoder.repeat_infinite());
+                Some(sink)
+            }
+            (Err(e), _) => {
+                warn!("couldn't play a loop: {}", e);
+                None
+            }
+            (_, Err(e)) => {
+                warn!("couldn't play a loop: {}", e);
+                None
+            }
+        }
+    }
+
+    fn play(&self, source: &AudioSource, volume: f32) {
+        self.play_panned(source, volume, None);
+    }
+
+    // quieter the further the tile is from the player, and off to the side it's on
+    fn play_at(&self, source: &AudioSource, volume: f32, (dx, dy): (i32, i32)) {
+        let distance = ((dx * dx + dy * dy) as f32).sqrt();
+        let falloff = 1. - distance / HEARING_RANGE;
+        if falloff <= 0. {
+            return;
+        }
+        let pan = (dx as f32 / PAN_RANGE).clamp(-1., 1.);
+        let ears = [((1. - pan) / 2.).sqrt(), ((1. + pan) / 2.).sqrt()];
+        self.play_panned(source, volume * falloff, Some(ears));
+    }
+
+    // fire and forget, the sink is left to play out on its own
+    fn play_panned(&self, source: &AudioSource, volume: f32, ears: Option<[f32; 2]>) {
+        let handle = match &self.output {
+            Some((_, handle)) => handle,
+            None => return,
+        };
+        match (
+            Decoder::new(Cursor::new(source.clone())),
+            Sink::try_new(handle),
+        ) {
+            (Ok(decoder), Ok(sink)) => {
+                sink.set_volume(volume);
+                match ears {
+                    Some(ears) => sink.append(ChannelVolume::new(decoder, ears.to_vec())),
+                    None => sink.append(decoder),
+                }
+                sink.detach();
+            }
+            (Err(e), _) => warn!("couldn't play a sound: {}", e),
+            (_, Err(e)) => warn!("couldn't play a sound: {}", e),
+        }
+    }
+}
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                warn!("no sound device, the game will be silent: {}", e);
+                None
+            }
+        };
+        app.insert_non_send_resource(Speakers {
+            output,
+            music: Vec::new(),
+            ambience: Vec::new(),
+            duck: 1.,
+        })
+        .add_startup_system(load_music.system())
+        .add_startup_system(load_ambience.system())
+        .add_startup_system(load_sfx.system())
+        // keeps going through the menus, which fade it out
+        .add_system_to_stage("app_state", play_music.system())
+        .add_system_to_stage("app_state", play_ambience.system())
+        .add_system_to_stage("app_state", play_ui_sfx.system())
+        .add_system(play_gameplay_sfx.system());
+    }
+}
+
+fn load_music(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let path = |name: &str| format!("{}/{}.mp3", MUSIC_FOLDER, name);
+    commands.insert_resource(MusicTracks {
+        floors: FloorTheme::ALL
+            .iter()
+            .map(|theme| (*theme, asset_server.load(path(theme.name()).as_str())))
+            .collect(),
+        boss: asset_server.load(path(BOSS_TRACK).as_str()),
+    });
+}
+
+struct AmbientLoops(HashMap<FloorTheme, Handle<AudioSource>>);
+
+fn load_ambience(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AmbientLoops(
+        FloorTheme::ALL
+            .iter()
+            .map(|theme| {
+                let path = format!("{}/{}.mp3", AMBIENCE_FOLDER, theme.name());
+                (*theme, asset_server.load(path.as_str()))
+            })
+            .collect(),
+    ));
+}
+
+fn load_sfx(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SfxSounds(
+        Sfx::ALL
+            .iter()
+            .map(|sfx| {
+                let path = format!("{}/{}.mp3", SFX_FOLDER, sfx.name());
+                (*sfx, asset_server.load(path.as_str()))
+            })
+            .collect(),
+    ));
+}
+
+// on a floor of a run, paused or not
+fn on_floor(app_state: &State<AppState>, game_state: &GameState) -> bool {
+    let in_run = matches!(app_state.current(), AppState::InGame | AppState::Paused)
+        || matches!(app_state.inactives(), [AppState::InGame, ..]);
+    in_run && game_state.has_map && game_state.phase != TurnPhase::NewGame
+}
+
+// the floor's theme, or the boss track once a boss is after the player. nothing outside a run
+fn wanted_track(
+    app_state: &State<AppState>,
+    game_state: &GameState,
+    boss_query: &Query<&AiState, With<Boss>>,
+) -> Option<MusicTrack> {
+    if !on_floor(app_state, game_state) {
+        return None;
+    }
+    if boss_query
+        .iter()
+        .any(|ai| matches!(ai, AiState::Chasing { .. }))
+    {
+        return Some(MusicTrack::Boss);
+    }
+    Some(MusicTrack::Floor(FloorTheme::at(game_state.depth)))
+}
+
+fn play_music(
+    mut speakers: NonSendMut<Speakers>,
+    tracks: Option<Res<MusicTracks>>,
+    sources: Res<Assets<AudioSource>>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<GameState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    boss_query: Query<&AiState, With<Boss>>,
+) {
+    let tracks = match tracks {
+        Some(tracks) => tracks,
+        None => return,
+    };
+    let speakers = &mut *speakers;
+    let wanted = wanted_track(&app_state, &game_state, &boss_query);
+    let current = speakers.music.last().map(|playing| playing.track);
+    if let Some(track) = wanted.filter(|track| Some(*track) != current) {
+        // a track still fading out is brought back rather than started over, and one that
+        // hasn't finished loading is tried again next frame
+        if let Some(i) = speakers.music.iter().position(|p| p.track == track) {
+            let playing = speakers.music.remove(i);
+            speakers.music.push(playing);
+        } else if let Some(sink) = sources
+            .get(tracks.get(track))
+            .and_then(|source| speakers.start_loop(source))
+        {
+            speakers.music.push(Playing {
+                track,
+                sink,
+                fade: 0.,
+            });
+        }
+    }
+    let step = time.delta_seconds() / CROSSFADE_SECONDS;
+    let last = speakers.music.len().saturating_sub(1);
+    for (i, playing) in speakers.music.iter_mut().enumerate() {
+        let fading_in = i == last && wanted == Some(playing.track);
+        playing.fade = if fading_in {
+            (playing.fade + step).min(1.)
+        } else {
+            (playing.fade - step).max(0.)
+        };
+        playing
+            .sink
+            .set_volume(playing.fade * MUSIC_VOLUME * settings.music_level());
+    }
+    // fully faded out tracks are dropped, which stops them
+    speakers
+        .music
+        .retain(|playing| playing.fade > 0. || wanted == Some(playing.track));
+}
+
+// the floor theme's ambient loop, faded in fresh on each floor and ducked while anything
+// is chasing the player
+fn play_ambience(
+    mut speakers: NonSendMut<Speakers>,
+    loops: Option<Res<AmbientLoops>>,
+    sources: Res<Assets<AudioSource>>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<GameState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    ai_query: Query<&AiState>,
+) {
+    let loops = match loops {
+        Some(loops) => loops,
+        None => return,
+    };
+    let speakers = &mut *speakers;
+    let wanted = Some(game_state.depth).filter(|_| on_floor(&app_state, &game_state));
+    let current = speakers.ambience.last().map(|playing| playing.depth);
+    if let Some(depth) = wanted.filter(|depth| Some(*depth) != current) {
+        let theme = FloorTheme::at(depth);
+        if let Some(sink) = sources
+            .get(&loops.0[&theme])
+            .and_then(|source| speakers.start_loop(source))
+        {
+            speakers.ambience.push(Ambience {
+                depth,
+                sink,
+                fade: 0.,
+            });
+        }
+    }
+    let in_combat = ai_query
+        .iter()
+        .any(|ai| matches!(ai, AiState::Chasing { .. }));
+    let duck_step = time.delta_seconds() / DUCK_SECONDS;
+    speakers.duck = if in_combat {
+        (speakers.duck - duck_step).max(COMBAT_DUCK)
+    } else {
+        (speakers.duck + duck_step).min(1.)
+    };
+    let step = time.delta_seconds() / AMBIENCE_FADE_SECONDS;
+    let last = speakers.ambience.len().saturating_sub(1);
+    let volume = speakers.duck * AMBIENCE_VOLUME * settings.sfx_level();
+    for (i, playing) in speakers.ambience.iter_mut().enumerate() {
+        let fading_in = i == last && wanted == Some(playing.depth);
+        playing.fade = if fading_in {
+            (playing.fade + step).min(1.)
+        } else {
+            (playing.fade - step).max(0.)
+        };
+        playing.sink.set_volume(playing.fade * volume);
+    }
+    speakers
+        .ambience
+        .retain(|playing| playing.fade > 0. || wanted == Some(playing.depth));
+}
+
+// a sound for anything the player does or has done to them, once per frame at most.
+// fights, traps and enemies elsewhere are heard from where they are, so there's some
+// warning of what's going on out of sight
+fn play_gameplay_sfx(
+    speakers: NonSend<Speakers>,
+    sounds: Res<SfxSounds>,
+    sources: Res<Assets<AudioSource>>,
+    settings: Res<Settings>,
+    mut chasing: Local<HashSet<Entity>>,
+    (mut ev_moved, mut ev_attack, mut ev_hit, mut ev_finished_map): (
+        EventReader<MoveResolvedEvent>,
+        EventReader<AttackEvent>,
+        EventReader<HitEvent>,
+        EventReader<FinishedMapEvent>,
+    ),
+    (mut ev_interact, mut ev_talk, mut ev_pick_up, mut ev_trap, mut ev_mechanism): (
+        EventReader<InteractEvent>,
+        EventReader<TalkEvent>,
+        EventReader<PickUpEvent>,
+        EventReader<TrapTriggeredEvent>,
+        EventReader<MechanismMovedEvent>,
+    ),
+    player_query: Query<&GridPos, With<Player>>,
+    chest_query: Query<(), With<Chest>>,
+    enemy_query: Query<(Entity, &AiState, &GridPos)>,
+    location_query: Query<&GridPos>,
+) {
+    let player_loc = match player_query.single() {
+        Ok(loc) => *loc,
+        Err(_) => return,
+    };
+    let involves_player =
+        |a: Entity, b: Entity| player_query.get(a).is_ok() || player_query.get(b).is_ok();
+    // None plays it as the player's own, straight on
+    let mut played: Vec<(Sfx, Option<GridPos>)> = Vec::new();
+    if ev_moved
+        .iter()
+        .any(|moved| player_query.get(moved.actor).is_ok())
+    {
+        played.push((Sfx::Footstep, None));
+    }
+    for attack in ev_attack.iter().filter(|attack| !attack.ranged) {
+        if involves_player(attack.attacker, attack.target) {
+            played.push((Sfx::Attack, None));
+        } else if let Ok(loc) = location_query.get(attack.target) {
+            played.push((Sfx::Attack, Some(*loc)));
+        }
+    }
+    for hit in ev_hit.iter().filter(|hit| hit.outcome != HitOutcome::Miss) {
+        if involves_player(hit.attacker, hit.target) {
+            played.push((Sfx::Hit, None));
+        } else {
+            played.push((Sfx::Hit, Some(hit.location)));
+        }
+    }
+    for trap in ev_trap.iter() {
+        played.push((Sfx::Trap, Some(trap.location)));
+    }
+    for moved in ev_mechanism.iter() {
+        played.push((Sfx::Mechanism, Some(moved.location)));
+    }
+    // a roar from each enemy that's only just started chasing
+    let mut now_chasing = HashSet::new();
+    for (enemy, ai, loc) in enemy_query.iter() {
+        if !matches!(ai, AiState::Chasing { .. }) {
+            continue;
+        }
+        now_chasing.insert(enemy);
+        if !chasing.contains(&enemy) {
+            played.push((Sfx::Roar, Some(*loc)));
+        }
+    }
+    *chasing = now_chasing;
+    let opened = ev_interact
+        .iter()
+        .map(|interact| interact.target)
+        .chain(ev_talk.iter().map(|talk| talk.speaker))
+        .any(|target| chest_query.get(target).is_ok());
+    if opened {
+        played.push((Sfx::Open, None));
+    }
+    if ev_pick_up.iter().next().is_some() {
+        played.push((Sfx::PickUp, None));
+    }
+    if ev_finished_map.iter().next().is_some() {
+        played.push((Sfx::Stairs, None));
+    }
+    // the same sound from the same place twice in a frame would only be louder
+    played.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+    for (sfx, at) in played {
+        let source = match sources.get(&sounds.0[&sfx]) {
+            Some(source) => source,
+            None => continue,
+        };
+        match at {
+            Some(loc) => speakers.play_at(
+                source,
+                settings.sfx_level(),
+                (loc.x - player_loc.x, loc.y - player_loc.y),
+            ),
+            None => speakers.play(source, settings.sfx_level()),
+        }
+    }
+}
+
+// a click whenever a menu or screen opens or closes
+fn play_ui_sfx(
+    speakers: NonSend<Speakers>,
+    sounds: Res<SfxSounds>,
+    sources: Res<Assets<AudioSource>>,
+    settings: Res<Settings>,
+    app_state: Res<State<AppState>>,
+    mut last_state: Local<Option<AppState>>,
+) {
+    let current = *app_state.current();
+    if last_state
+        .replace(current)
+        .is_none_or(|last| last == current)
+    {
+        return;
+    }
+    if let Some(source) = sources.get(&sounds.0[&Sfx::Click]) {
+        speakers.play(source, settings.sfx_level());
+    }
+}
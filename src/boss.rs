@@ -0,0 +1,231 @@
+use crate::ai::{can_see, AiState};
+use crate::aoe::{spawn_highlight, AoeShape};
+use crate::components::Direction;
+use crate::enemy::{spawn_enemy, EnemyKind, EnemyTemplates};
+use crate::movement::can_move;
+use crate::pathfinding::{find_path, NEIGHBORS};
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct BossPlugin;
+
+// every fifth floor is guarded by a boss
+pub const BOSS_FLOOR_INTERVAL: u32 = 5;
+// summoned minions alive at once
+const MAX_MINIONS: usize = 4;
+// how far the breath attack reaches
+const BREATH_LENGTH: i32 = 4;
+
+// phase goes up as the boss loses health: 1 fights in melee,
+// 2 adds telegraphed slams and breath, 3 also calls for help
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Boss {
+    phase: u32,
+    turns: u32,
+}
+
+struct Minion;
+
+// a tile that gets slammed at the start of the boss's next turn
+struct DangerZone(Element);
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            spawn_boss
+                .system()
+                .label("place_boss")
+                .after("cleanup")
+                .after("follow"),
+        )
+        .add_system(boss_turn.system().label("boss").after("input").before("ai"))
+        .add_system(clear_danger.system().after("combat"));
+    }
+}
+
+fn spawn_boss(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    map_query: Query<(&Map, &MapRooms), (Added<Map>, Without<Restored>)>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+) {
+    if !game_state.depth.is_multiple_of(BOSS_FLOOR_INTERVAL) {
+        return;
+    }
+    if let (Ok((current_map, map_rooms)), Ok(stairs)) = (map_query.single(), stairs_query.single())
+    {
+        let exit = &stairs.0;
+        // guard the stairs if they aren't in the player's starting room
+        let room = map_rooms
+            .rooms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != map_rooms.spawn_room)
+            .max_by_key(|(_, room)| room.contains(exit))
+            .map(|(_, room)| room);
+        if let Some(room) = room {
+            let loc = (room.bottom..room.bottom + room.height)
+                .flat_map(|y| (room.left..room.left + room.width).map(move |x| GridPos::new(x, y)))
+                .find(|loc| spawn_tiles.is_free(loc));
+            if let Some(loc) = loc {
+                let loc = spawn_tiles.claim(loc);
+                let boss = spawn_enemy(
+                    &mut commands,
+                    &templates,
+                    &modifiers,
+                    &tileset,
+                    &window,
+                    EnemyKind::Warden,
+                    loc,
+                );
+                if let Some(boss) = boss {
+                    commands
+                        .entity(boss)
+                        .insert(Boss { phase: 1, turns: 0 })
+                        .insert(SealsStairs);
+                }
+            }
+        }
+    }
+}
+
+// the boss takes its turn before the rest of the enemies
+fn boss_turn(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<Materials>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    mut ev_attack: EventWriter<AttackEvent>,
+    map_query: Query<&Map>,
+    mut boss_query: Query<(Entity, &mut Boss, &Stats, &GridPos, &mut NextAction)>,
+    player_query: Query<(Entity, &GridPos, Option<&StatusEffects>), With<Player>>,
+    danger_query: Query<(Entity, &DangerZone, &GridPos)>,
+    index: Res<SpatialIndex>,
+    minion_query: Query<(), With<Minion>>,
+    blocker_query: Query<(), With<BlocksMovement>>,
+) {
+    if game_state.phase != TurnPhase::EnemyAction {
+        return;
+    }
+    if let (Ok(current_map), Ok((player_entity, player_loc, effects))) =
+        (map_query.single(), player_query.single())
+    {
+        let invisible = effects.is_some_and(|e| e.has(Status::Invisible));
+        let map_data = &current_map.0;
+        for (boss_entity, mut boss, stats, boss_loc, mut action) in boss_query.iter_mut() {
+            boss.turns += 1;
+
+            // last turn's warning comes down now
+            for (danger_entity, danger, danger_loc) in danger_query.iter() {
+                if (danger_loc.x, danger_loc.y) == (player_loc.x, player_loc.y) {
+                    ev_attack.send(AttackEvent {
+                        attacker: boss_entity,
+                        target: player_entity,
+                        ranged: true,
+                        power: None,
+                        element: Some(danger.0),
+                    });
+                }
+                commands.entity(danger_entity).despawn();
+            }
+
+            let phase = if stats.hp * 3 > stats.max_hp * 2 {
+                1
+            } else if stats.hp * 3 > stats.max_hp {
+                2
+            } else {
+                3
+            };
+            boss.phase = boss.phase.max(phase);
+
+            // guards its room until the player shows up
+            if invisible || !can_see(map_data, boss_loc, player_loc) {
+                continue;
+            }
+
+            let minions = minion_query.iter().count();
+            if boss.phase >= 3 && boss.turns.is_multiple_of(4) && minions < MAX_MINIONS {
+                // call up to two rats onto the free tiles around the boss
+                let free_tiles = NEIGHBORS
+                    .iter()
+                    .filter(|&&(dx, dy)| can_move(map_data, boss_loc, dx, dy))
+                    .map(|&(dx, dy)| boss_loc.add(dx, dy))
+                    .filter(|loc| !index.is_blocked(loc, &blocker_query))
+                    .take(2.min(MAX_MINIONS - minions));
+                for loc in free_tiles {
+                    let minion = match spawn_enemy(
+                        &mut commands,
+                        &templates,
+                        &modifiers,
+                        &tileset,
+                        &window,
+                        EnemyKind::Rat,
+                        loc,
+                    ) {
+                        Some(minion) => minion,
+                        None => continue,
+                    };
+                    commands
+                        .entity(minion)
+                        .insert(Minion)
+                        .insert(AiState::Chasing {
+                            last_seen: *player_loc,
+                            turns_unseen: 0,
+                        });
+                }
+            } else if boss.phase >= 2 && boss.turns.is_multiple_of(3) {
+                // mark where the next attack lands, the player gets one turn to step out.
+                // it alternates between slamming around the player and breathing a cone at them
+                let (shape, element) = if boss.turns.is_multiple_of(6) {
+                    (AoeShape::Cone(BREATH_LENGTH), Element::Fire)
+                } else {
+                    (AoeShape::Circle(1), Element::Physical)
+                };
+                for loc in shape.tiles(map_data, boss_loc, player_loc) {
+                    let tile =
+                        spawn_highlight(&mut commands, materials.danger.clone(), &window, &loc, 7.);
+                    commands
+                        .entity(tile)
+                        .insert(DangerZone(element))
+                        .insert(OnMap(loc))
+                        .insert(loc);
+                }
+            } else if let Some(next) = find_path(map_data, boss_loc, player_loc).first() {
+                // stepping into the player is resolved as an attack
+                action.0 = Some(Action::Move(Direction(
+                    next.x - boss_loc.x,
+                    next.y - boss_loc.y,
+                )));
+            }
+        }
+    }
+}
+
+// the warnings only come down on the boss's turn, so once it's dead they'd never go. a
+// death read a frame late finds the boss already despawned, which counts just the same
+fn clear_danger(
+    mut commands: Commands,
+    mut ev_death: EventReader<DeathEvent>,
+    boss_query: Query<(), With<Boss>>,
+    danger_query: Query<Entity, With<DangerZone>>,
+) {
+    let boss_died = ev_death
+        .iter()
+        .any(|death| boss_query.get(death.entity).is_ok() || boss_query.iter().next().is_none());
+    if boss_died {
+        for danger in danger_query.iter() {
+            commands.entity(danger).despawn();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct BoulderPlugin;
+
+// odds of any given room having a boulder in it
+const BOULDER_CHANCE: f64 = 0.15;
+// tiles a second a pushed boulder rolls at
+const ROLL_SPEED: f32 = 6.;
+// the most hp a boulder rolls straight over, anything tougher stops it
+pub const CRUSH_HP: i32 = 25;
+
+// shoved a tile along when the player walks into it, see movement::resolve_moves
+pub struct Boulder;
+
+impl Plugin for BoulderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            place_boulders
+                .system()
+                .label("place_boulders")
+                .after("place_circuits"),
+        );
+    }
+}
+
+// never against a wall, so there's always a side to push it from
+fn place_boulders(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<Materials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    map_query: Query<(&Map, &MapRooms), (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok((current_map, map_rooms)) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "boulders");
+        for room in map_rooms.rooms.iter() {
+            if !rng.gen_bool(BOULDER_CHANCE) {
+                continue;
+            }
+            let inner = RoomArea {
+                left: room.left + 1,
+                bottom: room.bottom + 1,
+                width: room.width - 2,
+                height: room.height - 2,
+            };
+            if inner.width < 1 || inner.height < 1 {
+                continue;
+            }
+            if let Some(loc) = spawn_tiles.claim_in(&inner, &mut rng) {
+                if current_map.0.is_walkable(loc.x, loc.y) {
+                    spawn_boulder(&mut commands, &materials, &window, loc);
+                }
+            }
+        }
+    }
+}
+
+pub fn spawn_boulder(
+    commands: &mut Commands,
+    materials: &Materials,
+    window: &WinSize,
+    loc: GridPos,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.boulder.clone(),
+            sprite: Sprite::new(Vec2::new(0.8, 0.8) * window.tile),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Boulder)
+        .insert(Name::new("boulder"))
+        .insert(BlocksMovement)
+        .insert(Speed(ROLL_SPEED))
+        .insert(loc);
+}
@@ -0,0 +1,332 @@
+use crate::class::PlayerClass;
+use crate::inventory::Inventory;
+use crate::item::{spawn_item, Identification, Item, ItemKind, ItemMaterials, LootDrop};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::script::{RunScriptEvent, ScriptLibrary};
+use crate::visibility::is_visible;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct ChestPlugin;
+
+// odds of a chest in any room the player doesn't start in
+const CHEST_CHANCE: f64 = 0.3;
+const LOCKED_CHANCE: f64 = 0.35;
+// traps get more common the deeper the floor
+fn trap_chance(depth: u32) -> f64 {
+    (0.1 + 0.05 * depth as f64).min(0.5)
+}
+// odds of getting a lock open without a key, one try per chest
+const PICK_CHANCE: f64 = 0.3;
+const ROGUE_PICK_CHANCE: f64 = 0.8;
+// a rogue gets one look at each trapped chest that comes into view close by, and disarms
+// a trap they've spotted instead of setting it off
+const ROGUE_SPOT_CHANCE: f64 = 0.5;
+const SPOT_RANGE: i32 = 3;
+const NEEDLE_DAMAGE: i32 = 3;
+const ALARM_RADIUS: i32 = 15;
+
+// everything a chest can hold, each line rolled on its own
+const CHEST_LOOT: [(LootDrop, f64); 6] = [
+    (LootDrop::Gold(5, 15), 0.9),
+    (LootDrop::Potion, 0.5),
+    (LootDrop::Scroll, 0.35),
+    (LootDrop::Weapon, 0.2),
+    (LootDrop::Armor, 0.2),
+    (LootDrop::Ring, 0.15),
+];
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Trap {
+    // pricks whoever opens it
+    Needle,
+    // rings out and brings the floor running
+    Alarm,
+    // runs a script from assets/scripts, listed in assets/data/traps.ron
+    Scripted(String),
+}
+
+#[derive(Deserialize)]
+struct TrapFile {
+    traps: Vec<String>,
+}
+
+// the scripts a chest can roll as its trap, on top of the needle and the alarm
+pub fn read_trap_file(path: &str) -> Result<Vec<String>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: TrapFile = ron::de::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(file.traps)
+}
+
+// bumped into to open, spills its loot onto its tile and disappears
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Chest {
+    pub locked: bool,
+    // a failed lockpick jams the lock, only a key opens it after that
+    pub jammed: bool,
+    pub trap: Option<Trap>,
+    // whether a rogue has had their look at it yet, and seen the trap
+    #[serde(default)]
+    pub searched: bool,
+    #[serde(default)]
+    pub spotted: bool,
+}
+
+// a chest's trap or a spike trap went off, whether or not it hurt anyone
+pub struct TrapTriggeredEvent {
+    pub location: GridPos,
+}
+
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<TrapTriggeredEvent>()
+            .add_system(
+                spawn_chests
+                    .system()
+                    .label("place_chests")
+                    .after("place_torches"),
+            )
+            .add_system(spot_traps.system().label("spot_traps").after("resolve"))
+            .add_system(
+                open_chests
+                    .system()
+                    .label("open_chests")
+                    .after("spot_traps"),
+            );
+    }
+}
+
+pub fn spawn_chest(
+    commands: &mut Commands,
+    tileset: &Tileset,
+    window: &WinSize,
+    chest: Chest,
+    loc: GridPos,
+) {
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TileSprite::Chest.sprite(),
+            texture_atlas: tileset.0.clone(),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(chest)
+        .insert(Name::new("chest"))
+        .insert(Interactable)
+        .insert(BlocksMovement)
+        .insert(Faction::Neutral)
+        .insert(OnMap(loc))
+        .insert(loc);
+}
+
+// each locked chest comes with a key lying somewhere else on the floor
+fn spawn_chests(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    tileset: Res<Tileset>,
+    item_materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    scripts: Res<ScriptLibrary>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "chests");
+        for (i, room) in map_rooms.rooms.iter().enumerate() {
+            if i == map_rooms.spawn_room || !rng.gen_bool(CHEST_CHANCE) {
+                continue;
+            }
+            let loc = match spawn_tiles.claim_in(room, &mut rng) {
+                Some(loc) => loc,
+                None => continue,
+            };
+            let locked = rng.gen_bool(LOCKED_CHANCE);
+            let trap = if rng.gen_bool(trap_chance(game_state.depth)) {
+                Some(match rng.gen_range(0..2 + scripts.traps.len()) {
+                    0 => Trap::Needle,
+                    1 => Trap::Alarm,
+                    i => Trap::Scripted(scripts.traps[i - 2].clone()),
+                })
+            } else {
+                None
+            };
+            let chest = Chest {
+                locked,
+                jammed: false,
+                trap,
+                searched: false,
+                spotted: false,
+            };
+            spawn_chest(&mut commands, &tileset, &window, chest, loc);
+            if !locked {
+                continue;
+            }
+            let key_room = &map_rooms.rooms[rng.gen_range(0..map_rooms.rooms.len())];
+            if let Some(key_loc) = spawn_tiles.claim_in(key_room, &mut rng) {
+                let key = Item::new(ItemKind::Key);
+                spawn_item(&mut commands, &item_materials, &window, key, key_loc);
+            }
+        }
+    }
+}
+
+// a rogue who moves within sight of a trapped chest gets their one chance to notice it
+fn spot_traps(
+    player_class: Res<PlayerClass>,
+    mut log: ResMut<MessageLog>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, (With<Player>, Changed<GridPos>)>,
+    mut chest_query: Query<(&mut Chest, &GridPos)>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("traps");
+    if *player_class != PlayerClass::Rogue {
+        return;
+    }
+    let (player_loc, current_map) = match (player_query.single(), map_query.single()) {
+        (Ok(player_loc), Ok(current_map)) => (player_loc, current_map),
+        _ => return,
+    };
+    for (mut chest, chest_loc) in chest_query.iter_mut() {
+        if chest.trap.is_none()
+            || chest.searched
+            || player_loc.chebyshev(chest_loc) > SPOT_RANGE
+            || !is_visible(&current_map.0, player_loc, chest_loc)
+        {
+            continue;
+        }
+        chest.searched = true;
+        if rng.gen_bool(ROGUE_SPOT_CHANCE) {
+            chest.spotted = true;
+            log.add("You notice a trap on the chest.");
+        }
+    }
+}
+
+// the player opens a chest by walking into it, or with the Interact key
+fn open_chests(
+    mut commands: Commands,
+    player_class: Res<PlayerClass>,
+    (item_materials, window, identification): (
+        Res<ItemMaterials>,
+        Res<WinSize>,
+        Res<Identification>,
+    ),
+    (game_state, modifiers): (Res<GameState>, Res<DifficultyModifiers>),
+    mut log: ResMut<MessageLog>,
+    mut ev_talk: EventReader<TalkEvent>,
+    mut ev_interact: EventReader<InteractEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut ev_trap: EventWriter<TrapTriggeredEvent>,
+    mut ev_script: EventWriter<RunScriptEvent>,
+    mut chest_query: Query<(&mut Chest, &GridPos)>,
+    mut player_query: Query<(Entity, &mut Stats, &mut Inventory), With<Player>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("chests");
+    let opened: Vec<Entity> = ev_talk
+        .iter()
+        .map(|talk| talk.speaker)
+        .chain(ev_interact.iter().map(|interact| interact.target))
+        .collect();
+    for chest_entity in opened {
+        let (mut chest, chest_loc) = match chest_query.get_mut(chest_entity) {
+            Ok(chest) => chest,
+            Err(_) => continue,
+        };
+        let (player, mut stats, mut inventory) = match player_query.single_mut() {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        let rogue = *player_class == PlayerClass::Rogue;
+        let pick_chance = if rogue {
+            ROGUE_PICK_CHANCE
+        } else {
+            PICK_CHANCE
+        };
+        if chest.locked {
+            let key = inventory
+                .items
+                .iter()
+                .position(|item| item.kind == ItemKind::Key);
+            if let Some(i) = key {
+                inventory.items.remove(i);
+                log.add("You unlock the chest with a key.");
+            } else if chest.jammed {
+                log.add("The lock is jammed. You'll need a key.");
+                continue;
+            } else if rng.gen_bool(pick_chance) {
+                log.add("You pick the lock.");
+            } else {
+                chest.jammed = true;
+                log.add("The lock jams. You'll need a key.");
+                continue;
+            }
+            chest.locked = false;
+        }
+
+        match &chest.trap {
+            Some(_) if chest.spotted => {
+                log.add("You disarm the trap before lifting the lid.");
+            }
+            Some(Trap::Needle) => {
+                ev_trap.send(TrapTriggeredEvent {
+                    location: *chest_loc,
+                });
+                let damage = NEEDLE_DAMAGE + game_state.depth as i32;
+                stats.hp = (stats.hp - damage).max(0);
+                log.add(format!(
+                    "A needle springs from the lock! You take {} damage.",
+                    damage
+                ));
+                if stats.hp == 0 {
+                    ev_death.send(DeathEvent {
+                        entity: player,
+                        killer: chest_entity,
+                        location: *chest_loc,
+                    });
+                }
+            }
+            Some(Trap::Alarm) => {
+                ev_trap.send(TrapTriggeredEvent {
+                    location: *chest_loc,
+                });
+                log.add("A bell inside the chest rings out!");
+                ev_noise.send(NoiseEvent {
+                    location: *chest_loc,
+                    radius: ALARM_RADIUS,
+                });
+            }
+            // the chest's despawn waits for the end of the stage, so the script still has it
+            Some(Trap::Scripted(script)) => {
+                ev_trap.send(TrapTriggeredEvent {
+                    location: *chest_loc,
+                });
+                ev_script.send(RunScriptEvent {
+                    script: script.clone(),
+                    source: chest_entity,
+                    target: player,
+                });
+            }
+            None => {}
+        }
+
+        log.add("The chest creaks open.");
+        for (drop, chance) in CHEST_LOOT.iter() {
+            if !rng.gen_bool(*chance) {
+                continue;
+            }
+            let item = drop.roll(modifiers.loot_depth(game_state.depth), &mut *rng);
+            log.add(format!("Inside is {}.", identification.describe(&item)));
+            spawn_item(&mut commands, &item_materials, &window, item, *chest_loc);
+        }
+        commands.entity(chest_entity).despawn();
+    }
+}
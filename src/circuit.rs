@@ -0,0 +1,538 @@
+use crate::boulder::Boulder;
+use crate::chest::TrapTriggeredEvent;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::message_log::MessageLog;
+use crate::pathfinding::find_path;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct CircuitPlugin;
+
+// odds of a floor getting a puzzle
+const PUZZLE_CHANCE: f64 = 0.5;
+// a vault with more ways in than this isn't worth gating
+const MAX_VAULT_DOORS: usize = 2;
+// the longest gap cut out of a corridor for a bridge
+const MAX_GAP: i32 = 3;
+// spikes around a pressure plate
+const SPIKES: std::ops::RangeInclusive<usize> = 3..=5;
+const SPIKE_DAMAGE: i32 = 6;
+// gold for each floor down in a gated vault
+const VAULT_GOLD: std::ops::RangeInclusive<u32> = 8..=15;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Switch {
+    // flipped with the Interact key, and stays where it's left
+    Lever { pulled: bool },
+    // down for as long as someone is standing on it
+    Plate { pressed: bool },
+}
+
+impl Switch {
+    fn name(&self) -> &'static str {
+        match self {
+            Switch::Lever { .. } => "lever",
+            Switch::Plate { .. } => "pressure plate",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Mechanism {
+    // the gate on a Door tile, open while its circuit is on
+    Door,
+    // planks over a Chasm tile, run out while its circuit is on
+    Bridge,
+    // shoots up and skewers whoever's on it as its circuit comes on
+    Spikes,
+}
+
+// a gate or bridge moved, heard from where it is
+pub struct MechanismMovedEvent {
+    pub location: GridPos,
+}
+
+impl Plugin for CircuitPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<CircuitEvent>()
+            .add_event::<MechanismMovedEvent>()
+            .add_system(
+                wire_floor
+                    .system()
+                    .label("place_circuits")
+                    .after("place_hoard"),
+            )
+            .add_system(pull_levers.system().label("levers").after("inventory"))
+            .add_system(press_plates.system().label("plates").after("resolve"))
+            .add_system(
+                run_circuits
+                    .system()
+                    .label("circuits")
+                    .after("levers")
+                    .after("plates"),
+            );
+    }
+}
+
+// the ways into a room, the walkable tiles just outside its walls. corners don't count,
+// nothing gets in diagonally past a wall
+fn doorways(map_data: &DungeonMap, room: &RoomArea) -> Vec<GridPos> {
+    let (left, right) = (room.left - 1, room.left + room.width);
+    let (bottom, top) = (room.bottom - 1, room.bottom + room.height);
+    let across = (room.left..right).flat_map(|x| vec![(x, bottom), (x, top)]);
+    let up = (room.bottom..top).flat_map(|y| vec![(left, y), (right, y)]);
+    across
+        .chain(up)
+        .filter(|&(x, y)| map_data.is_walkable(x, y))
+        .map(|(x, y)| GridPos::new(x, y))
+        .collect()
+}
+
+fn reaches(map_data: &DungeonMap, from: &GridPos, to: &GridPos) -> bool {
+    from == to || !find_path(map_data, from, to).is_empty()
+}
+
+// a walkable tile outside every room, part of the one wide hallways joining them
+pub fn in_corridor(map_data: &DungeonMap, rooms: &[RoomArea], loc: &GridPos) -> bool {
+    map_data.is_walkable(loc.x, loc.y) && !rooms.iter().any(|room| room.contains(loc))
+}
+
+// a tile of straight, one wide corridor, and the way it runs
+fn corridor_run(map_data: &DungeonMap, rooms: &[RoomArea], loc: &GridPos) -> Option<(i32, i32)> {
+    if !in_corridor(map_data, rooms, loc) {
+        return None;
+    }
+    let open = |dx: i32, dy: i32| map_data.is_walkable(loc.x + dx, loc.y + dy);
+    match (open(1, 0), open(-1, 0), open(0, 1), open(0, -1)) {
+        (true, true, false, false) => Some((1, 0)),
+        (false, false, true, true) => Some((0, 1)),
+        _ => None,
+    }
+}
+
+// one puzzle a floor at most, whichever of them fits first. every one is checked against
+// the floor with its gates shut and its bridge drawn back, so the stairs and the switch
+// that opens the way are always in reach of where the player starts
+fn wire_floor(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<Materials>,
+    item_materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    mut map_query: Query<(&mut Map, &MapRooms), (Added<Map>, Without<Restored>)>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+) {
+    let (mut current_map, map_rooms) = match map_query.single_mut() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let stairs = match stairs_query.single() {
+        Ok(stairs) => stairs.0,
+        Err(_) => return,
+    };
+    let mut rng = game_rng.floor(game_state.depth, "circuits");
+    if !rng.gen_bool(PUZZLE_CHANCE) {
+        return;
+    }
+    let spawn = current_map.1;
+    let rooms = &map_rooms.rooms;
+    let circuit = Circuit(0);
+    let mut order = [0, 1, 2];
+    order.shuffle(&mut rng);
+    for puzzle in order.iter() {
+        let mut wired = current_map.0.clone();
+        match puzzle {
+            // a side room behind gates, with the lever to open them somewhere else
+            0 => {
+                let mut vaults: Vec<usize> = (0..rooms.len())
+                    .filter(|&i| i != map_rooms.spawn_room && !rooms[i].contains(&stairs))
+                    .collect();
+                vaults.shuffle(&mut rng);
+                for vault in vaults {
+                    let doors = doorways(&wired, &rooms[vault]);
+                    if doors.is_empty()
+                        || doors.len() > MAX_VAULT_DOORS
+                        || !doors.iter().all(|door| spawn_tiles.is_free(door))
+                    {
+                        continue;
+                    }
+                    for door in doors.iter() {
+                        wired.set_tile(door.x, door.y, Tile::Door);
+                    }
+                    if !reaches(&wired, &spawn, &stairs) {
+                        wired = current_map.0.clone();
+                        continue;
+                    }
+                    let lever =
+                        place_lever(&wired, rooms, vault, &spawn, &mut spawn_tiles, &mut rng);
+                    let lever = match lever {
+                        Some(lever) => lever,
+                        None => {
+                            wired = current_map.0.clone();
+                            continue;
+                        }
+                    };
+                    let switch = Switch::Lever { pulled: false };
+                    spawn_switch(&mut commands, &materials, &window, switch, circuit, lever);
+                    for door in doors {
+                        spawn_tiles.claim(door);
+                        spawn_mechanism(
+                            &mut commands,
+                            &materials,
+                            &window,
+                            Mechanism::Door,
+                            circuit,
+                            door,
+                        );
+                    }
+                    if let Some(loc) = spawn_tiles.claim_in(&rooms[vault], &mut rng) {
+                        let amount = rng.gen_range(VAULT_GOLD) * game_state.depth.max(1);
+                        let item = Item::new(ItemKind::Gold(amount));
+                        spawn_item(&mut commands, &item_materials, &window, item, loc);
+                    }
+                    current_map.0 = wired;
+                    return;
+                }
+            }
+            // a corridor that's fallen away, with a lever that runs a bridge out over it
+            1 => {
+                let mut corridors: Vec<(GridPos, (i32, i32))> = (0..wired.height() as i32)
+                    .flat_map(|y| (0..wired.width() as i32).map(move |x| GridPos::new(x, y)))
+                    .filter_map(|loc| corridor_run(&wired, rooms, &loc).map(|dir| (loc, dir)))
+                    .collect();
+                corridors.shuffle(&mut rng);
+                for (start, (dx, dy)) in corridors {
+                    let length = rng.gen_range(1..=MAX_GAP);
+                    let gap: Vec<GridPos> = (0..length)
+                        .map(|i| start.add(dx * i, dy * i))
+                        .take_while(|loc| {
+                            corridor_run(&wired, rooms, loc) == Some((dx, dy))
+                                && spawn_tiles.is_free(loc)
+                        })
+                        .collect();
+                    if gap.is_empty() {
+                        continue;
+                    }
+                    for loc in gap.iter() {
+                        wired.set_tile(loc.x, loc.y, Tile::Chasm);
+                    }
+                    // the lever has to be on the player's side
+                    let lever = place_lever(
+                        &wired,
+                        rooms,
+                        usize::MAX,
+                        &spawn,
+                        &mut spawn_tiles,
+                        &mut rng,
+                    );
+                    let lever = match lever {
+                        Some(lever) => lever,
+                        None => {
+                            wired = current_map.0.clone();
+                            continue;
+                        }
+                    };
+                    let switch = Switch::Lever { pulled: false };
+                    spawn_switch(&mut commands, &materials, &window, switch, circuit, lever);
+                    for loc in gap {
+                        spawn_tiles.claim(loc);
+                        spawn_mechanism(
+                            &mut commands,
+                            &materials,
+                            &window,
+                            Mechanism::Bridge,
+                            circuit,
+                            loc,
+                        );
+                    }
+                    current_map.0 = wired;
+                    return;
+                }
+            }
+            // a plate in a room ringed with spikes, for luring something onto
+            _ => {
+                let mut candidates: Vec<usize> = (0..rooms.len())
+                    .filter(|&i| i != map_rooms.spawn_room)
+                    .collect();
+                candidates.shuffle(&mut rng);
+                for i in candidates {
+                    let plate = match spawn_tiles.claim_in(&rooms[i], &mut rng) {
+                        Some(plate) => plate,
+                        None => continue,
+                    };
+                    let mut around: Vec<GridPos> = (-2..=2)
+                        .flat_map(|dy| (-2..=2).map(move |dx| (dx, dy)))
+                        .filter(|&(dx, dy)| (dx, dy) != (0, 0))
+                        .map(|(dx, dy)| plate.add(dx, dy))
+                        .filter(|loc| rooms[i].contains(loc) && spawn_tiles.is_free(loc))
+                        .collect();
+                    around.shuffle(&mut rng);
+                    let count = rng.gen_range(SPIKES);
+                    let switch = Switch::Plate { pressed: false };
+                    spawn_switch(&mut commands, &materials, &window, switch, circuit, plate);
+                    for loc in around.into_iter().take(count) {
+                        spawn_tiles.claim(loc);
+                        spawn_mechanism(
+                            &mut commands,
+                            &materials,
+                            &window,
+                            Mechanism::Spikes,
+                            circuit,
+                            loc,
+                        );
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// a free tile for a lever in some room other than skip, that the player can walk to
+fn place_lever(
+    map_data: &DungeonMap,
+    rooms: &[RoomArea],
+    skip: usize,
+    spawn: &GridPos,
+    spawn_tiles: &mut SpawnTiles,
+    rng: &mut impl Rng,
+) -> Option<GridPos> {
+    let mut candidates: Vec<usize> = (0..rooms.len()).filter(|&i| i != skip).collect();
+    candidates.shuffle(rng);
+    for i in candidates {
+        let room = &rooms[i];
+        let loc = (0..10)
+            .map(|_| {
+                GridPos::new(
+                    room.left + rng.gen_range(0..room.width),
+                    room.bottom + rng.gen_range(0..room.height),
+                )
+            })
+            .find(|loc| spawn_tiles.is_free(loc) && reaches(map_data, spawn, loc));
+        if let Some(loc) = loc {
+            return Some(spawn_tiles.claim(loc));
+        }
+    }
+    None
+}
+
+pub fn spawn_switch(
+    commands: &mut Commands,
+    materials: &Materials,
+    window: &WinSize,
+    switch: Switch,
+    circuit: Circuit,
+    loc: GridPos,
+) {
+    let (material, size) = match switch {
+        Switch::Lever { pulled: false } => (materials.lever.clone(), Vec2::new(0.15, 0.5)),
+        Switch::Lever { pulled: true } => (materials.lever_pulled.clone(), Vec2::new(0.15, 0.5)),
+        Switch::Plate { .. } => (materials.plate.clone(), Vec2::new(0.7, 0.7)),
+    };
+    let entity = commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(size * window.tile),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(7.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(switch)
+        .insert(circuit)
+        .insert(Name::new(switch.name()))
+        .insert(OnMap(loc))
+        .id();
+    if let Switch::Lever { .. } = switch {
+        commands.entity(entity).insert(Interactable).insert(loc);
+    }
+}
+
+// gates and bridges are drawn by the map itself, only spikes get a sprite of their own
+pub fn spawn_mechanism(
+    commands: &mut Commands,
+    materials: &Materials,
+    window: &WinSize,
+    mechanism: Mechanism,
+    circuit: Circuit,
+    loc: GridPos,
+) {
+    let entity = commands
+        .spawn()
+        .insert(mechanism)
+        .insert(circuit)
+        .insert(OnMap(loc))
+        .id();
+    if mechanism == Mechanism::Spikes {
+        commands
+            .entity(entity)
+            .insert_bundle(SpriteBundle {
+                material: materials.spikes.clone(),
+                sprite: Sprite::new(Vec2::new(0.5, 0.5) * window.tile),
+                transform: Transform {
+                    translation: loc.to_world(window.tile).extend(7.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Name::new("spike trap"));
+    }
+}
+
+fn pull_levers(
+    materials: Res<Materials>,
+    mut log: ResMut<MessageLog>,
+    mut ev_interact: EventReader<InteractEvent>,
+    mut ev_circuit: EventWriter<CircuitEvent>,
+    mut lever_query: Query<(&mut Switch, &Circuit, &mut Handle<ColorMaterial>)>,
+) {
+    for interact in ev_interact.iter() {
+        if let Ok((mut switch, circuit, mut material)) = lever_query.get_mut(interact.target) {
+            if let Switch::Lever { pulled } = *switch {
+                *switch = Switch::Lever { pulled: !pulled };
+                *material = if pulled {
+                    materials.lever.clone()
+                } else {
+                    materials.lever_pulled.clone()
+                };
+                log.add("You throw the lever. Something heavy shifts nearby.");
+                ev_circuit.send(CircuitEvent {
+                    circuit: *circuit,
+                    powered: !pulled,
+                });
+            }
+        }
+    }
+}
+
+// anyone at all holds a plate down, the player, their allies or the monsters, and so does
+// a boulder pushed onto it
+fn press_plates(
+    mut log: ResMut<MessageLog>,
+    index: Res<SpatialIndex>,
+    mut ev_circuit: EventWriter<CircuitEvent>,
+    mut plate_query: Query<(&mut Switch, &Circuit, &OnMap)>,
+    actor_query: Query<Option<&Player>, Or<(With<Stats>, With<Boulder>)>>,
+) {
+    for (mut switch, circuit, on_map) in plate_query.iter_mut() {
+        let was_pressed = match *switch {
+            Switch::Plate { pressed } => pressed,
+            Switch::Lever { .. } => continue,
+        };
+        let standing: Vec<bool> = index
+            .at(&on_map.0)
+            .iter()
+            .filter_map(|&entity| actor_query.get(entity).ok())
+            .map(|player| player.is_some())
+            .collect();
+        let pressed = !standing.is_empty();
+        if pressed == was_pressed {
+            continue;
+        }
+        *switch = Switch::Plate { pressed };
+        if pressed && standing.contains(&true) {
+            log.add("The plate sinks under your feet with a click.");
+        }
+        ev_circuit.send(CircuitEvent {
+            circuit: *circuit,
+            powered: pressed,
+        });
+    }
+}
+
+// a gate or bridge with someone or something solid in the way stays as it is
+fn run_circuits(
+    mut log: ResMut<MessageLog>,
+    game_state: Res<GameState>,
+    index: Res<SpatialIndex>,
+    mut ev_circuit: EventReader<CircuitEvent>,
+    mut ev_trap: EventWriter<TrapTriggeredEvent>,
+    mut ev_moved: EventWriter<MechanismMovedEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut map_query: Query<&mut Map>,
+    mechanism_query: Query<(Entity, &Mechanism, &Circuit, &OnMap)>,
+    mut actor_query: Query<(&mut Stats, Option<&Player>, Option<&Name>)>,
+    blockers: Query<(), With<BlocksMovement>>,
+) {
+    let mut current_map = match map_query.single_mut() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    for event in ev_circuit.iter() {
+        let mut moved = None;
+        let mut sprung = false;
+        for (entity, mechanism, circuit, on_map) in mechanism_query.iter() {
+            if *circuit != event.circuit {
+                continue;
+            }
+            let loc = on_map.0;
+            let tile = current_map.0.tile_at(loc.x, loc.y).cloned();
+            let occupied = index.is_blocked(&loc, &blockers);
+            match (mechanism, event.powered, tile) {
+                (Mechanism::Door, true, Some(Tile::Door)) => {
+                    current_map.0.set_tile(loc.x, loc.y, Tile::Ground);
+                    moved = Some("You hear a gate grind open.");
+                    ev_moved.send(MechanismMovedEvent { location: loc });
+                }
+                (Mechanism::Door, false, Some(Tile::Ground)) if !occupied => {
+                    current_map.0.set_tile(loc.x, loc.y, Tile::Door);
+                    moved = Some("You hear a gate slam shut.");
+                    ev_moved.send(MechanismMovedEvent { location: loc });
+                }
+                (Mechanism::Bridge, true, Some(Tile::Chasm)) => {
+                    current_map.0.set_tile(loc.x, loc.y, Tile::Bridge);
+                    moved = Some("Planks rumble out across the chasm.");
+                    ev_moved.send(MechanismMovedEvent { location: loc });
+                }
+                (Mechanism::Bridge, false, Some(Tile::Bridge)) if !occupied => {
+                    current_map.0.set_tile(loc.x, loc.y, Tile::Chasm);
+                    moved = Some("The bridge draws back into the dark.");
+                    ev_moved.send(MechanismMovedEvent { location: loc });
+                }
+                (Mechanism::Spikes, true, _) => {
+                    ev_trap.send(TrapTriggeredEvent { location: loc });
+                    if !sprung {
+                        log.add("Spikes shoot up from the floor!");
+                        sprung = true;
+                    }
+                    for &target in index.at(&loc) {
+                        let (mut stats, player, name) = match actor_query.get_mut(target) {
+                            Ok(actor) if actor.0.hp > 0 => actor,
+                            _ => continue,
+                        };
+                        let damage = SPIKE_DAMAGE + game_state.depth as i32;
+                        stats.hp = (stats.hp - damage).max(0);
+                        match (player, name) {
+                            (Some(_), _) => log.add(format!(
+                                "The spikes run you through! You take {} damage.",
+                                damage
+                            )),
+                            (None, Some(name)) => {
+                                log.add(format!("The spikes skewer the {}.", name.as_str()))
+                            }
+                            _ => {}
+                        }
+                        if stats.hp == 0 {
+                            ev_death.send(DeathEvent {
+                                entity: target,
+                                killer: entity,
+                                location: loc,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(message) = moved {
+            log.add(message);
+        }
+    }
+}
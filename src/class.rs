@@ -0,0 +1,463 @@
+use crate::companion::Companion;
+use crate::gold::Gold;
+use crate::item::{Identification, Item, ItemKind, Rarity};
+use crate::level::LevelUp;
+use crate::magic::{Casting, Spell};
+use crate::message_log::MessageLog;
+use crate::npc::CHOICE_KEYS;
+use crate::player::give_starting_kit;
+use crate::prelude::*;
+use crate::quest::QuestLog;
+use crate::replay::Replay;
+use crate::settings::Settings;
+use crate::unlocks::{Achievement, Unlock, Unlocks};
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use serde::{Deserialize, Serialize};
+
+pub struct ClassPlugin;
+
+// picked on the new game screen, decides the starting kit and one ability that
+// the combat system checks for
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum PlayerClass {
+    // Shield Wall: a raised guard stops far more damage
+    #[default]
+    Warrior,
+    // Assassinate: sneak attacks and flanking hit much harder
+    Rogue,
+    // Arcane Affinity: damaging spells hit harder
+    Mage,
+    // Steady Aim: arrows and thrown weapons hit harder. has to be unlocked, see unlocks.rs
+    Ranger,
+}
+
+// what a class starts a run with
+pub struct ClassKit {
+    pub max_hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub speed: f32,
+    pub mana: i32,
+    pub spells: Vec<Spell>,
+    // worn from the start
+    pub gear: Vec<Item>,
+    // carried in the pack from the start
+    pub pack: Vec<Item>,
+}
+
+impl PlayerClass {
+    pub const ALL: [PlayerClass; 4] = [
+        PlayerClass::Warrior,
+        PlayerClass::Rogue,
+        PlayerClass::Mage,
+        PlayerClass::Ranger,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerClass::Warrior => "Warrior",
+            PlayerClass::Rogue => "Rogue",
+            PlayerClass::Mage => "Mage",
+            PlayerClass::Ranger => "Ranger",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            PlayerClass::Warrior => {
+                "Tough and well armored. Shield Wall: blocking stops most damage."
+            }
+            PlayerClass::Rogue => "Quick and quiet. Assassinate: sneak attacks hit twice as hard.",
+            PlayerClass::Mage => "Frail but learned. Arcane Affinity: damaging spells hit harder.",
+            PlayerClass::Ranger => {
+                "Keen-eyed and sure. Steady Aim: arrows and thrown weapons hit harder."
+            }
+        }
+    }
+
+    pub fn kit(&self) -> ClassKit {
+        match self {
+            PlayerClass::Warrior => ClassKit {
+                max_hp: 26,
+                attack: 5,
+                defense: 2,
+                speed: 10.,
+                mana: 4,
+                spells: vec![Spell::Heal],
+                gear: vec![Item::new(ItemKind::Weapon), Item::new(ItemKind::Armor)],
+                pack: Vec::new(),
+            },
+            PlayerClass::Rogue => ClassKit {
+                max_hp: 18,
+                attack: 4,
+                defense: 1,
+                speed: 12.,
+                mana: 6,
+                spells: vec![Spell::Blink],
+                gear: vec![Item::new(ItemKind::Weapon), Item::new(ItemKind::Ring)],
+                pack: vec![Item::new(ItemKind::Dagger); 3],
+            },
+            PlayerClass::Mage => ClassKit {
+                max_hp: 14,
+                attack: 2,
+                defense: 0,
+                speed: 10.,
+                mana: 18,
+                spells: vec![Spell::Firebolt, Spell::Heal, Spell::FrostCone],
+                gear: vec![Item {
+                    rarity: Rarity::Uncommon,
+                    ..Item::new(ItemKind::Ring)
+                }],
+                pack: Vec::new(),
+            },
+            PlayerClass::Ranger => ClassKit {
+                max_hp: 20,
+                attack: 3,
+                defense: 1,
+                speed: 11.,
+                mana: 6,
+                spells: vec![Spell::Blink],
+                gear: vec![Item::new(ItemKind::Bow), Item::new(ItemKind::Armor)],
+                pack: vec![Item::new(ItemKind::Arrows(30)), Item::new(ItemKind::Dagger)],
+            },
+        }
+    }
+}
+
+struct NewGameScreen;
+
+// the longest seed that still fits in a u64
+const MAX_SEED_DIGITS: usize = 19;
+
+// a seed typed in on the class screen, the next run plays from it instead of a random one
+#[derive(Default)]
+pub struct SeedEntry {
+    typing: Option<String>,
+    seed: Option<u64>,
+}
+
+impl SeedEntry {
+    pub fn is_typing(&self) -> bool {
+        self.typing.is_some()
+    }
+}
+
+impl Plugin for ClassPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(PlayerClass::default())
+            .insert_resource(SeedEntry::default())
+            .insert_resource(Difficulty::default())
+            .insert_resource(DifficultyModifiers::default())
+            .add_system(show_new_game.system())
+            .add_system(choose_class.system().before("input"));
+    }
+}
+
+// classes and layouts the profile hasn't unlocked yet are greyed out with what unlocks them
+fn show_new_game(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    unlocks: Res<Unlocks>,
+    (layout, mode, difficulty, seed_entry): (
+        Res<FloorLayout>,
+        Res<GameMode>,
+        Res<Difficulty>,
+        Res<SeedEntry>,
+    ),
+    screen_query: Query<(), With<NewGameScreen>>,
+) {
+    if game_state.phase != TurnPhase::NewGame || screen_query.iter().next().is_some() {
+        return;
+    }
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: "Choose your class\n".to_string(),
+        style: style(36., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    for (i, class) in PlayerClass::ALL.iter().enumerate() {
+        if !unlocks.has_class(*class) {
+            let requirement = Achievement::ALL
+                .iter()
+                .find(|achievement| matches!(achievement.unlock(), Unlock::Class(c) if c == *class))
+                .map_or(String::new(), |achievement| achievement.describe());
+            sections.push(TextSection {
+                value: format!("\n{}. {}\n", i + 1, class.name()),
+                style: style(22., Color::GRAY),
+            });
+            sections.push(TextSection {
+                value: format!("Locked: {}\n", requirement),
+                style: style(16., Color::GRAY),
+            });
+            continue;
+        }
+        sections.push(TextSection {
+            value: format!("\n{}. {}\n", i + 1, class.name()),
+            style: style(22., Color::WHITE),
+        });
+        sections.push(TextSection {
+            value: format!("{}\n", class.describe()),
+            style: style(16., Color::rgb(0.7, 0.8, 0.9)),
+        });
+    }
+    if FloorLayout::ALL
+        .iter()
+        .filter(|l| unlocks.has_layout(**l))
+        .count()
+        > 1
+    {
+        sections.push(TextSection {
+            value: format!("\nFloors: {}  [M] change\n", layout.name()),
+            style: style(16., Color::WHITE),
+        });
+    }
+    sections.push(TextSection {
+        value: format!("\nDeath: {}  [G] change\n", mode.name()),
+        style: style(16., Color::WHITE),
+    });
+    sections.push(TextSection {
+        value: format!("\nDifficulty: {}  [D] change\n", difficulty.name()),
+        style: style(16., Color::WHITE),
+    });
+    let seed_line = match (&seed_entry.typing, seed_entry.seed) {
+        (Some(typed), _) => format!("Seed: {}_  [Enter] set  [Esc] cancel", typed),
+        (None, Some(seed)) => format!("Seed: {}  [Tab] change", seed),
+        (None, None) => "Seed: random  [Tab] enter a seed".to_string(),
+    };
+    sections.push(TextSection {
+        value: format!("\n{}\n", seed_line),
+        style: style(16., Color::WHITE),
+    });
+    sections.push(TextSection {
+        value: "\nUnlocks\n".to_string(),
+        style: style(18., Color::rgb(0.95, 0.85, 0.4)),
+    });
+    for achievement in Achievement::ALL.iter() {
+        let (mark, color) = if unlocks.has(*achievement) {
+            ("[x]", Color::WHITE)
+        } else {
+            ("[ ]", Color::GRAY)
+        };
+        sections.push(TextSection {
+            value: format!(
+                "{} {}: {}\n",
+                mark,
+                achievement.describe(),
+                achievement.unlock().describe()
+            ),
+            style: style(14., color),
+        });
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(NewGameScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections,
+                        alignment: TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .insert(Narrated);
+        });
+}
+
+// picking a class throws away the floor and everything from the last run, then starts
+// again from the first floor. M flips through the unlocked floor layouts, G between
+// permadeath and checkpoints, D through the difficulties, and Tab types
+// in a seed, which holds the other keys back until it's set or cancelled
+fn choose_class(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut player_class: ResMut<PlayerClass>,
+    mut game_state: ResMut<GameState>,
+    mut run_stats: ResMut<RunStats>,
+    mut log: ResMut<MessageLog>,
+    mut casting: ResMut<Casting>,
+    mut level_up: ResMut<LevelUp>,
+    mut identification: ResMut<Identification>,
+    mut gold: ResMut<Gold>,
+    mut quest_log: ResMut<QuestLog>,
+    mut ev_finished_map: EventWriter<FinishedMapEvent>,
+    (
+        settings,
+        unlocks,
+        mut layout,
+        mut mode,
+        (mut difficulty, mut modifiers),
+        mut seed_entry,
+        mut game_rng,
+        mut replay,
+        mut ev_chars,
+    ): (
+        Res<Settings>,
+        Res<Unlocks>,
+        ResMut<FloorLayout>,
+        ResMut<GameMode>,
+        (ResMut<Difficulty>, ResMut<DifficultyModifiers>),
+        ResMut<SeedEntry>,
+        ResMut<GameRng>,
+        ResMut<Replay>,
+        EventReader<ReceivedCharacter>,
+    ),
+    screen_query: Query<Entity, With<NewGameScreen>>,
+    player_query: Query<Entity, With<Player>>,
+    companion_query: Query<Entity, With<Companion>>,
+) {
+    // read every frame so digits typed in the last run don't turn up in the seed
+    let typed: Vec<char> = ev_chars.iter().map(|ev| ev.char).collect();
+    if game_state.phase != TurnPhase::NewGame {
+        return;
+    }
+    // show_new_game puts the screen back up with whatever changed
+    let mut redraw = || {
+        for screen in screen_query.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+    };
+    if let Some(digits) = seed_entry.typing.clone() {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            // it would open the pause menu too
+            keyboard_input.reset(KeyCode::Escape);
+            seed_entry.typing = None;
+        } else if keyboard_input.just_pressed(KeyCode::Return) {
+            // an empty seed goes back to a random one
+            seed_entry.seed = digits.parse().ok();
+            seed_entry.typing = None;
+        } else if keyboard_input.just_pressed(KeyCode::Back) {
+            seed_entry.typing = Some(digits[..digits.len().saturating_sub(1)].to_string());
+        } else if typed.iter().any(|c| c.is_ascii_digit()) {
+            let mut digits = digits;
+            for c in typed.into_iter().filter(|c| c.is_ascii_digit()) {
+                if digits.len() < MAX_SEED_DIGITS {
+                    digits.push(c);
+                }
+            }
+            seed_entry.typing = Some(digits);
+        } else {
+            return;
+        }
+        redraw();
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        seed_entry.typing = Some(
+            seed_entry
+                .seed
+                .map_or(String::new(), |seed| seed.to_string()),
+        );
+        redraw();
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::M) {
+        let layouts: Vec<FloorLayout> = FloorLayout::ALL
+            .iter()
+            .copied()
+            .filter(|layout| unlocks.has_layout(*layout))
+            .collect();
+        let current = layouts.iter().position(|l| *l == *layout).unwrap_or(0);
+        *layout = layouts[(current + 1) % layouts.len()];
+        redraw();
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::G) {
+        *mode = mode.toggled();
+        redraw();
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::D) {
+        *difficulty = difficulty.next();
+        redraw();
+        return;
+    }
+    // a replay starts the run it recorded, whatever the profile has unlocked since
+    let watched = replay.playing().map(|run| {
+        (
+            run.class(),
+            run.layout(),
+            run.mode(),
+            run.difficulty(),
+            run.seed(),
+        )
+    });
+    let class = match watched {
+        Some((class, run_layout, run_mode, run_difficulty, seed)) => {
+            *layout = run_layout;
+            *mode = run_mode;
+            *difficulty = run_difficulty;
+            seed_entry.seed = Some(seed);
+            class
+        }
+        None => match CHOICE_KEYS
+            .iter()
+            .take(PlayerClass::ALL.len())
+            .position(|key| keyboard_input.just_pressed(*key))
+        {
+            Some(i) if unlocks.has_class(PlayerClass::ALL[i]) => PlayerClass::ALL[i],
+            _ => return,
+        },
+    };
+    *player_class = class;
+    *modifiers = difficulty.modifiers();
+    redraw();
+    for companion in companion_query.iter() {
+        commands.entity(companion).despawn_recursive();
+    }
+    if let Ok(player) = player_query.single() {
+        let kit = replay
+            .playing()
+            .map_or_else(|| unlocks.kit(class), |run| run.unlocks().kit(class));
+        give_starting_kit(&mut commands, player, kit);
+    }
+    // finishing the map bumps the depth, so this lands the player on the first floor.
+    // the turn system hands control to the player once the new floor is up
+    // a replay goes through the tutorial if the run it recorded did
+    let tutorial = replay
+        .playing()
+        .map_or(settings.tutorial, |run| run.tutorial());
+    *game_state = GameState {
+        depth: 0,
+        has_map: game_state.has_map,
+        tutorial,
+        ..Default::default()
+    };
+    *run_stats = RunStats::default();
+    *log = MessageLog::default();
+    *casting = Casting::Idle;
+    *level_up = LevelUp::default();
+    // a typed seed is used for this run only
+    *game_rng = seed_entry
+        .seed
+        .take()
+        .map_or_else(GameRng::random, GameRng::new);
+    *identification = Identification::shuffled(game_rng.stream("identification"));
+    if !replay.is_playing() {
+        let seed = game_rng.seed();
+        replay.record(seed, class, *layout, *mode, *difficulty, tutorial, &unlocks);
+    }
+    *gold = Gold::default();
+    *quest_log = QuestLog::default();
+    log.add(format!("You set out as a {}.", class.name().to_lowercase()));
+    ev_finished_map.send(FinishedMapEvent);
+}
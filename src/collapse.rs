@@ -0,0 +1,226 @@
+use crate::boss::BOSS_FLOOR_INTERVAL;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::message_log::MessageLog;
+use crate::pathfinding::find_path;
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct CollapsePlugin;
+
+// odds of a floor deep enough for it having a hoard that brings the floor down
+const COLLAPSE_CHANCE: f64 = 0.2;
+// shallowest floor a hoard can turn up on
+const MIN_DEPTH: u32 = 2;
+// gold in the hoard for each floor down
+const HOARD_GOLD: u32 = 40;
+// turns on top of the walk from the hoard to the stairs
+const SLACK: u32 = 8;
+// share of max hp a fall through the floor takes
+const FALL_DAMAGE: f32 = 0.3;
+
+// the hoard on this floor and, once it's been taken, how far the floor has come down
+#[derive(Default)]
+pub struct Collapse {
+    hoard: Option<GridPos>,
+    // the turn the hoard was taken and the turns given to get out
+    started: Option<(u32, u32)>,
+    stairs: GridPos,
+}
+
+impl Collapse {
+    pub fn is_active(&self) -> bool {
+        self.started.is_some()
+    }
+}
+
+// the line in the hud, see hud::spawn_hud
+pub struct CollapseText;
+
+impl Plugin for CollapsePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Collapse::default())
+            .add_system(
+                place_hoard
+                    .system()
+                    .label("place_hoard")
+                    .after("place_enemies"),
+            )
+            .add_system(collapse_floor.system().after("resolve"))
+            .add_system(update_countdown.system());
+    }
+}
+
+// tucked away in the room farthest from the stairs. a loaded floor never has one, and
+// neither do the boss floors, whose stairs stay sealed until the fight is over. placed
+// after everything else, so the floors without one come out the same as before
+fn place_hoard(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    mut collapse: ResMut<Collapse>,
+    map_query: Query<(&MapRooms, Option<&Restored>), Added<Map>>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+) {
+    let (map_rooms, restored) = match map_query.single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    *collapse = Collapse::default();
+    let depth = game_state.depth;
+    if restored.is_some()
+        || !(MIN_DEPTH..FINAL_DEPTH).contains(&depth)
+        || depth.is_multiple_of(BOSS_FLOOR_INTERVAL)
+    {
+        return;
+    }
+    let mut rng = game_rng.floor(depth, "collapse");
+    if !rng.gen_bool(COLLAPSE_CHANCE) {
+        return;
+    }
+    let stairs = match stairs_query.single() {
+        Ok(stairs) => stairs.0,
+        Err(_) => return,
+    };
+    let center = |room: &RoomArea| (room.left + room.width / 2, room.bottom + room.height / 2);
+    let room = map_rooms
+        .rooms
+        .iter()
+        .enumerate()
+        .filter(|(i, room)| *i != map_rooms.spawn_room && !room.contains(&stairs))
+        .max_by_key(|(_, room)| {
+            let (x, y) = center(room);
+            (x - stairs.x).abs() + (y - stairs.y).abs()
+        })
+        .map(|(_, room)| room);
+    if let Some(loc) = room.and_then(|room| spawn_tiles.claim_in(room, &mut rng)) {
+        let item = Item::new(ItemKind::Gold(HOARD_GOLD * depth));
+        spawn_item(&mut commands, &materials, &window, item, loc);
+        collapse.hoard = Some(loc);
+        collapse.stairs = stairs;
+    }
+}
+
+// how many tiles in from the nearest edge of the map
+fn ring(map_data: &DungeonMap, x: i32, y: i32) -> i32 {
+    let (width, height) = (map_data.width() as i32, map_data.height() as i32);
+    x.min(y).min(width - 1 - x).min(height - 1 - y)
+}
+
+// taking the hoard starts the countdown. each turn the floor falls away a ring at a time
+// from the edges in, all of it but the stairs by the time the countdown runs out. tiles
+// still within reach of the stairs in the turns left hold out till then, so a player
+// who heads straight there always makes it
+fn collapse_floor(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    mut last_turn: Local<u32>,
+    mut collapse: ResMut<Collapse>,
+    mut log: ResMut<MessageLog>,
+    mut ev_finished_map: EventWriter<FinishedMapEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut map_query: Query<&mut Map>,
+    mut player_query: Query<(Entity, &GridPos, &mut Stats), With<Player>>,
+    other_query: Query<(Entity, &GridPos, Option<&Name>), (Without<Player>, Without<Stairs>)>,
+) {
+    if game_state.phase == TurnPhase::GameOver {
+        return;
+    }
+    let (mut current_map, (player, player_loc, mut stats)) =
+        match (map_query.single_mut(), player_query.single_mut()) {
+            (Ok(map), Ok(player)) => (map, player),
+            _ => return,
+        };
+    if collapse.hoard == Some(*player_loc) {
+        let walk = find_path(&current_map.0, player_loc, &collapse.stairs).len() as u32;
+        collapse.hoard = None;
+        collapse.started = Some((game_state.turn, walk + SLACK));
+        log.add("The floor shudders as you lift the hoard. Get to the stairs!");
+    }
+    let (started, turns) = match collapse.started {
+        Some(started) => started,
+        None => return,
+    };
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    let elapsed = game_state.turn.saturating_sub(started);
+    let map_data = &current_map.0;
+    let (width, height) = (map_data.width() as i32, map_data.height() as i32);
+    let rings = (width.min(height) + 1) / 2;
+    let fallen = (elapsed * rings as u32 / turns.max(1)) as i32;
+    let reach = turns.saturating_sub(elapsed) as i32;
+    let stairs = collapse.stairs;
+    let falling: Vec<(i32, i32)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            let near = (x - stairs.x).abs().max((y - stairs.y).abs());
+            map_data.is_walkable(x, y)
+                && (x, y) != (stairs.x, stairs.y)
+                && (elapsed >= turns || (ring(map_data, x, y) < fallen && near > reach))
+        })
+        .collect();
+    if falling.is_empty() {
+        return;
+    }
+    for &(x, y) in falling.iter() {
+        current_map.0.set_tile(x, y, Tile::Chasm);
+    }
+    let on_chasm = |loc: &GridPos| current_map.0.tile_at(loc.x, loc.y) == Some(&Tile::Chasm);
+    for (entity, loc, name) in other_query.iter() {
+        if !on_chasm(loc) {
+            continue;
+        }
+        if let Some(name) = name {
+            log.add(format!("The {} drops into the chasm.", name.as_str()));
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+    if !on_chasm(player_loc) {
+        return;
+    }
+    let damage = ((stats.max_hp as f32 * FALL_DAMAGE) as i32).max(1);
+    stats.hp -= damage;
+    // one fall is enough, whatever happens next happens on the floor below
+    *collapse = Collapse::default();
+    if stats.hp <= 0 {
+        log.add("The floor gives way beneath you, and you fall to your death.");
+        ev_death.send(DeathEvent {
+            entity: player,
+            killer: player,
+            location: *player_loc,
+        });
+    } else {
+        log.add(format!(
+            "The floor gives way beneath you! You land hard on the floor below, taking {} damage.",
+            damage
+        ));
+        ev_finished_map.send(FinishedMapEvent);
+    }
+}
+
+fn update_countdown(
+    game_state: Res<GameState>,
+    collapse: Res<Collapse>,
+    mut text_query: Query<&mut Text, With<CollapseText>>,
+) {
+    if !collapse.is_changed() && !game_state.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let value = match collapse.started {
+            Some((started, turns)) => {
+                let left = (started + turns).saturating_sub(game_state.turn);
+                format!("The floor is collapsing! {} turns left", left)
+            }
+            None => String::new(),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
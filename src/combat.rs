@@ -0,0 +1,315 @@
+use crate::ai::{AiState, Awareness};
+use crate::class::PlayerClass;
+use crate::components::Direction;
+use crate::enemy::{EnemyKind, EnemyTemplates};
+use crate::inventory::Equipment;
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::status::{Status, StatusEffects, BLESSED_BONUS, STRENGTH_BONUS};
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct CombatPlugin;
+
+// damage multiplier against enemies caught asleep
+const SLEEPING_DAMAGE_MULTIPLIER: i32 = 2;
+// extra damage, in percent, against an enemy that's awake but hasn't noticed the attacker
+const BACKSTAB_BONUS: i32 = 50;
+// extra damage, in percent, when the attack comes from behind the target
+const FLANK_BONUS: i32 = 25;
+// how far the sound of a blow carries
+const FIGHT_NOISE_RADIUS: i32 = 6;
+// damage multiplier on a critical hit
+const CRIT_MULTIPLIER: i32 = 2;
+// percent of the damage a raised guard stops
+const BLOCK_REDUCTION: i32 = 60;
+// the warrior's Shield Wall
+const SHIELD_WALL_REDUCTION: i32 = 85;
+// the mage's Arcane Affinity, added to the power of their damaging spells
+const ARCANE_AFFINITY_POWER: i32 = 2;
+// the ranger's Steady Aim, extra damage in percent from arrows and thrown weapons
+const STEADY_AIM_BONUS: i32 = 40;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<AttackEvent>()
+            .add_event::<HitEvent>()
+            .add_event::<DeathEvent>()
+            .add_event::<NoiseEvent>()
+            .add_event::<BlockEvent>()
+            .add_system(raise_guard.system().after("act").before("combat"))
+            .add_system(resolve_attacks.system().label("combat").after("resolve"))
+            .add_system(handle_deaths.system().label("deaths").after("combat"));
+    }
+}
+
+// attack minus defense, with a little wiggle room, never less than 1
+pub fn roll_damage(attack: i32, defense: i32, rng: &mut impl Rng) -> i32 {
+    (attack + rng.gen_range(0..=2) - defense).max(1)
+}
+
+// a base 80% to hit, up with the attacker's attack and down with the target's defense and speed
+pub fn hit_chance(attacker: &Stats, target: &Stats, target_speed: f32) -> f64 {
+    let accuracy = 80 + attacker.attack * 2;
+    let evasion = target.defense * 3 + ((target_speed - 10.) * 2.) as i32;
+    ((accuracy - evasion) as f64 / 100.).clamp(0.3, 0.95)
+}
+
+// stronger attackers find the weak spots more often
+pub fn crit_chance(attacker: &Stats) -> f64 {
+    (0.05 + attacker.attack as f64 * 0.01).min(0.25)
+}
+
+// true if `from` is in the three tiles' worth of directions the actor at `loc` is facing
+pub fn is_in_front(facing: &Direction, loc: &GridPos, from: &GridPos) -> bool {
+    let dx = (from.x - loc.x).signum();
+    let dy = (from.y - loc.y).signum();
+    dx * facing.0 + dy * facing.1 > 0
+}
+
+pub fn is_behind(facing: &Direction, loc: &GridPos, from: &GridPos) -> bool {
+    is_in_front(&Direction(-facing.0, -facing.1), loc, from)
+}
+
+// the guard holds through the enemies' turn and drops when the blocker's next turn comes around
+fn raise_guard(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    mut log: ResMut<MessageLog>,
+    mut ev_block: EventReader<BlockEvent>,
+    player_query: Query<(), With<Player>>,
+) {
+    for block in ev_block.iter() {
+        commands.entity(block.actor).insert(Blocking {
+            turn: game_state.turn,
+        });
+        if player_query.get(block.actor).is_ok() {
+            log.add("You raise your guard.");
+        }
+    }
+}
+
+fn resolve_attacks(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    (window, player_class): (Res<WinSize>, Res<PlayerClass>),
+    mut ev_attack: EventReader<AttackEvent>,
+    mut ev_hit: EventWriter<HitEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
+    mut actor_query: Query<(
+        &mut Stats,
+        &GridPos,
+        &mut Transform,
+        Option<&Speed>,
+        Option<&Element>,
+        Option<&Resistances>,
+        Option<&Direction>,
+        Option<&Blocking>,
+        Option<&Equipment>,
+        Option<&StatusEffects>,
+    )>,
+    mut ai_query: Query<&mut AiState>,
+    player_query: Query<(), With<Player>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("combat");
+    for attack in ev_attack.iter() {
+        // class abilities only apply to the player's own attacks and defense
+        let by_player = player_query.get(attack.attacker).is_ok();
+        let on_player = player_query.get(attack.target).is_ok();
+        let (mut attacker_stats, attacker_loc, element) = match actor_query.get_mut(attack.attacker)
+        {
+            Ok((stats, loc, _, _, element, _, _, _, equipment, effects)) => {
+                // dead actors don't get to swing back
+                if stats.hp <= 0 {
+                    continue;
+                }
+                // a branded weapon's element wins over the actor's own
+                let element = attack
+                    .element
+                    .or_else(|| equipment.and_then(|e| e.element()))
+                    .or_else(|| element.copied());
+                // worn gear adds on top of the actor's own stats
+                let mut stats = stats.clone();
+                stats.attack += equipment.map_or(0, |e| e.bonus().0);
+                if effects.is_some_and(|e| e.has(Status::Strength)) {
+                    stats.attack += STRENGTH_BONUS;
+                }
+                if effects.is_some_and(|e| e.has(Status::Blessed)) {
+                    stats.attack += BLESSED_BONUS;
+                }
+                (stats, *loc, element.unwrap_or_default())
+            }
+            Err(_) => continue,
+        };
+        if let Ok((
+            mut target_stats,
+            target_loc,
+            _,
+            speed,
+            _,
+            resistances,
+            facing,
+            blocking,
+            equipment,
+            target_effects,
+        )) = actor_query.get_mut(attack.target)
+        {
+            if target_stats.hp <= 0 {
+                continue;
+            }
+            let mut defender = target_stats.clone();
+            defender.defense += equipment.map_or(0, |e| e.bonus().1);
+            if target_effects.is_some_and(|e| e.has(Status::Blessed)) {
+                defender.defense += BLESSED_BONUS;
+            }
+            let target_loc = *target_loc;
+            // catching someone asleep never misses, hurts a lot more, and wakes them up.
+            // anyone who wasn't already fighting takes a backstab and turns on the attacker
+            let mut asleep = false;
+            let mut unaware = false;
+            if let Ok(mut state) = ai_query.get_mut(attack.target) {
+                asleep = matches!(*state, AiState::Sleeping);
+                unaware = state.awareness() != Awareness::Aware;
+                if unaware {
+                    *state = AiState::Chasing {
+                        last_seen: attacker_loc,
+                        turns_unseen: 0,
+                    };
+                }
+            }
+            let flanked = !unaware
+                && facing.is_some_and(|facing| is_behind(facing, &target_loc, &attacker_loc));
+            if let Some(power) = attack.power {
+                attacker_stats.attack = power;
+                if by_player && *player_class == PlayerClass::Mage {
+                    attacker_stats.attack += ARCANE_AFFINITY_POWER;
+                }
+            }
+            let target_speed =
+                speed.map_or(10., |s| s.0) + equipment.map_or(0., |e| e.speed_bonus());
+            let outcome = if !asleep
+                && attack.power.is_none()
+                && !rng.gen_bool(hit_chance(&attacker_stats, &defender, target_speed))
+            {
+                HitOutcome::Miss
+            } else if rng.gen_bool(crit_chance(&attacker_stats)) {
+                HitOutcome::Critical
+            } else {
+                HitOutcome::Hit
+            };
+            let mut damage = match outcome {
+                HitOutcome::Miss => 0,
+                HitOutcome::Hit => roll_damage(attacker_stats.attack, defender.defense, &mut *rng),
+                HitOutcome::Critical => {
+                    roll_damage(attacker_stats.attack, defender.defense, &mut *rng)
+                        * CRIT_MULTIPLIER
+                }
+            };
+            if asleep {
+                damage *= SLEEPING_DAMAGE_MULTIPLIER;
+            } else if unaware || flanked {
+                let mut bonus = if unaware { BACKSTAB_BONUS } else { FLANK_BONUS };
+                if by_player && *player_class == PlayerClass::Rogue {
+                    bonus *= 2;
+                }
+                damage = damage * (100 + bonus) / 100;
+            }
+            if by_player
+                && attack.ranged
+                && attack.power.is_none()
+                && *player_class == PlayerClass::Ranger
+            {
+                damage = damage * (100 + STEADY_AIM_BONUS) / 100;
+            }
+            // resistances scale the damage, anything short of full immunity still stings a little
+            let resisted = resistances.map_or(0, |r| r.get(element));
+            if damage > 0 {
+                damage = damage * (100 - resisted) / 100;
+                if resisted < 100 {
+                    damage = damage.max(1);
+                }
+                damage = damage.max(0);
+            }
+            // a guard only helps against attacks coming from the front
+            let blocked = damage > 0
+                && blocking.is_some_and(|b| b.turn == game_state.turn)
+                && facing.is_some_and(|facing| is_in_front(facing, &target_loc, &attacker_loc));
+            if blocked {
+                let reduction = if on_player && *player_class == PlayerClass::Warrior {
+                    SHIELD_WALL_REDUCTION
+                } else {
+                    BLOCK_REDUCTION
+                };
+                damage = (damage * (100 - reduction) / 100).max(1);
+            }
+            target_stats.hp = (target_stats.hp - damage).max(0);
+            ev_hit.send(HitEvent {
+                attacker: attack.attacker,
+                target: attack.target,
+                location: target_loc,
+                outcome,
+                damage,
+                element,
+                resisted,
+                blocked,
+                backstab: unaware,
+                flanked,
+            });
+            ev_noise.send(NoiseEvent {
+                location: target_loc,
+                radius: FIGHT_NOISE_RADIUS,
+            });
+            if target_stats.hp == 0 {
+                ev_death.send(DeathEvent {
+                    entity: attack.target,
+                    killer: attack.attacker,
+                    location: target_loc,
+                });
+            }
+
+            // lunge a third of a tile at the target, the movement system slides back
+            if attack.ranged {
+                continue;
+            }
+            if let Ok((_, _, mut tf, _, _, _, _, _, _, _)) = actor_query.get_mut(attack.attacker) {
+                tf.translation.x += (target_loc.x - attacker_loc.x) as f32 * window.tile / 3.;
+                tf.translation.y += (target_loc.y - attacker_loc.y) as f32 * window.tile / 3.;
+                commands
+                    .entity(attack.attacker)
+                    .insert(MovingTo(attacker_loc));
+                game_state.animating_actions = true;
+            }
+        }
+    }
+}
+
+fn handle_deaths(
+    mut commands: Commands,
+    templates: Res<EnemyTemplates>,
+    mut game_state: ResMut<GameState>,
+    mut run_stats: ResMut<RunStats>,
+    mut ev_death: EventReader<DeathEvent>,
+    enemy_query: Query<&EnemyKind, With<Enemy>>,
+    player_query: Query<(), With<Player>>,
+    mut xp_query: Query<&mut Experience>,
+) {
+    for death in ev_death.iter() {
+        // the player sticks around at 0 hp and the run ends, enemies are removed from the map
+        if player_query.get(death.entity).is_ok() {
+            game_state.phase = TurnPhase::GameOver;
+        }
+        if let Ok(kind) = enemy_query.get(death.entity) {
+            if let (Ok(mut xp), Some(template)) =
+                (xp_query.get_mut(death.killer), templates.get(kind))
+            {
+                xp.0 += template.xp;
+            }
+            if player_query.get(death.killer).is_ok() {
+                run_stats.kills += 1;
+            }
+            commands.entity(death.entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::prelude::*;
+use crate::settings::Settings;
+use bevy::prelude::*;
+
+pub struct CombatTextPlugin;
+
+// how long a damage number hangs around, in seconds
+const TEXT_LIFETIME: f32 = 0.8;
+// how far it drifts up over that time, in tiles
+const TEXT_RISE: f32 = 0.6;
+
+// a number floating up off someone who just got hit
+struct CombatText {
+    age: f32,
+}
+
+impl Plugin for CombatTextPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(spawn_combat_text.system().after("combat"))
+            .add_system(float_combat_text.system());
+    }
+}
+
+// white for a normal hit, bigger and orange for a crit, grey for a miss, in the player's palette
+fn spawn_combat_text(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    window: Res<WinSize>,
+    settings: Res<Settings>,
+    mut ev_hit: EventReader<HitEvent>,
+) {
+    for hit in ev_hit.iter() {
+        let (value, size, color) = match hit.outcome {
+            HitOutcome::Miss => ("miss".to_string(), 16., Color::rgb(0.6, 0.6, 0.6)),
+            HitOutcome::Hit => (hit.damage.to_string(), 20., Color::WHITE),
+            HitOutcome::Critical => (format!("{}!", hit.damage), 28., Color::rgb(1., 0.6, 0.1)),
+        };
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    value,
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: size,
+                        color: settings.palette.tint(color),
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_xyz(
+                    hit.location.x as f32 * window.tile,
+                    hit.location.y as f32 * window.tile + window.tile / 3.,
+                    20.,
+                ),
+                ..Default::default()
+            })
+            .insert(CombatText { age: 0. });
+    }
+}
+
+fn float_combat_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    window: Res<WinSize>,
+    mut text_query: Query<(Entity, &mut CombatText, &mut Transform, &mut Text)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut combat_text, mut tf, mut text) in text_query.iter_mut() {
+        combat_text.age += delta;
+        if combat_text.age >= TEXT_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        tf.translation.y += window.tile * TEXT_RISE * delta / TEXT_LIFETIME;
+        for section in text.sections.iter_mut() {
+            section
+                .style
+                .color
+                .set_a(1. - combat_text.age / TEXT_LIFETIME);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::components::Direction;
+use crate::enemy::Disguised;
+use crate::movement::can_move;
+use crate::npc::Npc;
+use crate::pathfinding::{find_path, NEIGHBORS};
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct CompanionPlugin;
+
+// how far the player can get ahead before the companion catches up
+const FOLLOW_DISTANCE: i32 = 2;
+// turns the player has to spend next to a downed companion to get them back up
+const REVIVE_TURNS: u32 = 3;
+
+// follows the player from floor to floor and fights at their side
+pub struct Companion {
+    // knocked out at 0 hp, counting the turns the player has spent tending to them
+    pub downed: Option<u32>,
+    // their place in the npc library, so a save can bring them back
+    pub npc: usize,
+}
+
+impl Plugin for CompanionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            companion_turn
+                .system()
+                .label("ally")
+                .after("input")
+                .before("act"),
+        )
+        .add_system(down_companions.system().after("combat"))
+        .add_system(
+            companion_follow_to_floor
+                .system()
+                .label("follow")
+                .after("cleanup"),
+        );
+    }
+}
+
+// turns a friendly npc into a companion, they stop being part of the floor they were found on
+pub fn recruit(commands: &mut Commands, entity: Entity, npc: usize) {
+    commands
+        .entity(entity)
+        .remove::<Npc>()
+        .remove::<OnMap>()
+        .insert(Companion { downed: None, npc })
+        .insert(Faction::Player)
+        .insert(Stats {
+            max_hp: 12,
+            hp: 12,
+            attack: 3,
+            defense: 1,
+        })
+        .insert(Speed::default())
+        .insert(NextAction::default());
+}
+
+// between the player's turn and the enemies', companions attack anything next to them
+// or walk back towards the player
+fn companion_turn(
+    mut game_state: ResMut<GameState>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, With<Player>>,
+    mut companion_query: Query<(
+        &mut Companion,
+        &GridPos,
+        &mut Stats,
+        &mut Sprite,
+        &mut NextAction,
+    )>,
+    enemy_query: Query<
+        (Entity, &GridPos, &Stats),
+        (With<Enemy>, Without<Companion>, Without<Disguised>),
+    >,
+) {
+    if game_state.phase != TurnPhase::AllyAction {
+        return;
+    }
+    game_state.phase = TurnPhase::AllyAnimating;
+    if let (Ok(current_map), Ok(player_loc)) = (map_query.single(), player_query.single()) {
+        let map_data = &current_map.0;
+        for (mut companion, loc, mut stats, mut sprite, mut action) in companion_query.iter_mut() {
+            if let Some(tended) = &mut companion.downed {
+                // the player has to stay close for a few turns in a row
+                if loc.chebyshev(player_loc) <= 1 {
+                    *tended += 1;
+                } else {
+                    *tended = 0;
+                }
+                if *tended >= REVIVE_TURNS {
+                    companion.downed = None;
+                    stats.hp = (stats.max_hp / 2).max(1);
+                    sprite.size *= 2.;
+                }
+                continue;
+            }
+
+            let target = enemy_query
+                .iter()
+                .filter(|(_, enemy_loc, enemy_stats)| {
+                    enemy_stats.hp > 0
+                        && enemy_loc.chebyshev(loc) == 1
+                        && can_move(map_data, loc, enemy_loc.x - loc.x, enemy_loc.y - loc.y)
+                })
+                .map(|(enemy, ..)| Action::Attack(enemy))
+                .next();
+            action.0 = target.or_else(|| {
+                if loc.chebyshev(player_loc) <= FOLLOW_DISTANCE {
+                    return None;
+                }
+                find_path(map_data, loc, player_loc)
+                    .first()
+                    .map(|next| Action::Move(Direction(next.x - loc.x, next.y - loc.y)))
+            });
+        }
+    }
+}
+
+// companions aren't removed at 0 hp, they just lie there until revived
+fn down_companions(
+    mut ev_death: EventReader<DeathEvent>,
+    mut companion_query: Query<(&mut Companion, &mut Sprite)>,
+) {
+    for death in ev_death.iter() {
+        if let Ok((mut companion, mut sprite)) = companion_query.get_mut(death.entity) {
+            if companion.downed.is_none() {
+                companion.downed = Some(0);
+                sprite.size /= 2.;
+            }
+        }
+    }
+}
+
+// on a new floor, companions (downed or not) show up next to the player's spawn
+fn companion_follow_to_floor(
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    map_query: Query<&Map, Added<Map>>,
+    mut companion_query: Query<(&mut GridPos, &mut Transform), With<Companion>>,
+) {
+    if let Ok(current_map) = map_query.single() {
+        let spawn = &current_map.1;
+        for (mut loc, mut tf) in companion_query.iter_mut() {
+            let tile = NEIGHBORS
+                .iter()
+                .filter(|&&(dx, dy)| can_move(&current_map.0, spawn, dx, dy))
+                .map(|&(dx, dy)| spawn.add(dx, dy))
+                .find(|tile| spawn_tiles.is_free(tile));
+            if let Some(tile) = tile {
+                *loc = spawn_tiles.claim(tile);
+                tf.translation.x = loc.x as f32 * window.tile;
+                tf.translation.y = loc.y as f32 * window.tile;
+            }
+        }
+    }
+}
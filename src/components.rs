@@ -0,0 +1,219 @@
+use crate::item::Item;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub struct Player;
+
+pub struct Enemy;
+
+// actors that can't share a tile with each other
+pub struct BlocksMovement;
+
+// while any of these exist, the stairs on the floor can't be used
+pub struct SealsStairs;
+
+// actors on different sides attack each other when they bump,
+// neutral actors never fight and the player talks to them instead
+#[derive(Clone, Copy, PartialEq)]
+pub enum Faction {
+    Player,
+    Monster,
+    Neutral,
+}
+
+impl Faction {
+    pub fn is_hostile_to(&self, other: &Faction) -> bool {
+        self != other && *self != Faction::Neutral && *other != Faction::Neutral
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stats {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+}
+
+pub struct Experience(pub u32);
+
+// starts at 1, see level::xp_for_next for the thresholds
+pub struct Level(pub u32);
+
+// the kind of damage an attack deals, as a component it's what the actor's own attacks deal
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Deserialize)]
+pub enum Element {
+    #[default]
+    Physical,
+    Fire,
+    Ice,
+    Poison,
+    Arcane,
+}
+
+impl Element {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Element::Physical => "physical",
+            Element::Fire => "fire",
+            Element::Ice => "ice",
+            Element::Poison => "poison",
+            Element::Arcane => "arcane",
+        }
+    }
+}
+
+// percent of each element's damage the actor shrugs off,
+// negative values mean it takes extra, anything missing is taken in full
+#[derive(Clone, Default)]
+pub struct Resistances(pub HashMap<Element, i32>);
+
+impl Resistances {
+    pub fn get(&self, element: Element) -> i32 {
+        self.0.get(&element).copied().unwrap_or(0)
+    }
+}
+
+pub struct Speed(pub f32); // speed is measured in tiles per second
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self(10.)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Direction(pub i32, pub i32);
+
+// default direction is facing down
+impl Default for Direction {
+    fn default() -> Self {
+        Self(0, -1)
+    }
+}
+
+// guarding against attacks from the tiles in front, lasts until the actor's next turn
+pub struct Blocking {
+    pub turn: u32,
+}
+
+// what an actor means to do with their turn. whatever decides for them (the keyboard, the
+// mouse, the ai) fills in their NextAction, and resolve_actions carries it out
+#[derive(Clone, Copy)]
+pub enum Action {
+    Move(Direction),
+    // a melee attack on someone next to them
+    Attack(Entity),
+    // the item in that slot of their pack
+    UseItem(usize),
+    Block,
+    Wait,
+    // take the stairs they're standing on
+    Descend,
+}
+
+// every actor has one, empty until they've decided
+#[derive(Default)]
+pub struct NextAction(pub Option<Action>);
+
+pub struct IsCamera;
+
+// flying object, hits the first actor or wall along its path
+pub struct Projectile {
+    pub source: Entity,
+    pub path: Vec<GridPos>,
+    pub next: usize,
+    pub hit: Option<Entity>,
+    pub power: Option<i32>,
+    pub element: Option<Element>,
+    // a thrown item, dropped wherever the projectile stops
+    pub payload: Option<Item>,
+}
+
+// tile an actor is currently animating towards, removed once the sprite arrives
+pub struct MovingTo(pub GridPos);
+
+// a tile on the map, x to the right and y up. saved as a plain (x, y) pair
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(from = "(i32, i32)", into = "(i32, i32)")]
+pub struct GridPos(IVec2);
+
+impl GridPos {
+    pub fn new(x: i32, y: i32) -> Self {
+        GridPos(IVec2::new(x, y))
+    }
+
+    pub fn add(self, dx: i32, dy: i32) -> GridPos {
+        GridPos(self.0 + IVec2::new(dx, dy))
+    }
+
+    // the next tile over in a direction
+    pub fn neighbor(self, direction: &Direction) -> GridPos {
+        self.add(direction.0, direction.1)
+    }
+
+    // steps apart with no diagonals
+    pub fn manhattan(&self, other: &GridPos) -> i32 {
+        let d = (self.0 - other.0).abs();
+        d.x + d.y
+    }
+
+    // 8 way movement means a diagonal step costs the same as a straight one
+    pub fn chebyshev(&self, other: &GridPos) -> i32 {
+        (self.0 - other.0).abs().max_element()
+    }
+
+    // the centre of the tile in world space, tiles being tile_size wide
+    pub fn to_world(self, tile_size: f32) -> Vec2 {
+        self.0.as_f32() * tile_size
+    }
+}
+
+impl Default for GridPos {
+    fn default() -> Self {
+        GridPos::new(1, 1)
+    }
+}
+
+impl std::ops::Deref for GridPos {
+    type Target = IVec2;
+
+    fn deref(&self) -> &IVec2 {
+        &self.0
+    }
+}
+
+impl From<(i32, i32)> for GridPos {
+    fn from((x, y): (i32, i32)) -> Self {
+        GridPos::new(x, y)
+    }
+}
+
+impl From<GridPos> for (i32, i32) {
+    fn from(pos: GridPos) -> Self {
+        (pos.x, pos.y)
+    }
+}
+
+// on a Map loaded from a save rather than generated, the floor spawners leave it alone
+pub struct Restored;
+
+pub struct OnMap(pub GridPos);
+
+pub struct Stairs;
+
+// something next to the player that the Interact key does something with
+pub struct Interactable;
+
+// ui text the narration reads out in full whenever it appears or changes
+pub struct Narrated;
+
+// the colour a sprite is tinted in the standard palette, see palette::recolor_sprites and
+// palette::recolor_own_materials
+pub struct Tint(pub Color);
+
+// the wiring a lever or plate shares with the gates, bridges and traps it works,
+// numbered per floor by whatever laid them out
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Circuit(pub u32);
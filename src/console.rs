@@ -0,0 +1,276 @@
+use crate::enemy::{spawn_enemy, EnemyTemplates};
+use crate::gold::Gold;
+use crate::inventory::Inventory;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::magic::RevealMapEvent;
+use crate::movement::can_move;
+use crate::pathfinding::NEIGHBORS;
+use crate::prelude::*;
+use crate::replay::Replay;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+// only added to debug builds, see lib.rs
+pub struct ConsolePlugin;
+
+// lines of earlier commands and their answers kept on screen
+const CONSOLE_LINES: usize = 8;
+const HELP: &str =
+    "teleport X Y, reveal, spawn ENEMY, give ITEM, setdepth N, seed, help. ~ or Esc closes";
+
+// the drop-down debug console, ~ opens it during a run. while it's open it has the keyboard
+// to itself
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    typed: String,
+    // entered this frame, for run_command to carry out
+    entered: Option<String>,
+    // what's been entered and what came back, newest last
+    lines: Vec<String>,
+}
+
+struct ConsolePanel;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Console::default())
+            // takes the keys before the replay or anything else gets to see them
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                console_input
+                    .system()
+                    .label("console")
+                    .after(InputSystem)
+                    .before("replay"),
+            )
+            .add_system(run_command.system().before("input"))
+            .add_system_to_stage("app_state", show_console.system());
+    }
+}
+
+// anything typed into it doesn't make it into a recording, but what the commands do still
+// happens to the run, so a replay of one with cheats in it will drift
+fn console_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<GameState>,
+    replay: Res<Replay>,
+    mut console: ResMut<Console>,
+) {
+    // read every frame so nothing typed while it was shut turns up when it opens
+    let typed: Vec<char> = ev_chars.iter().map(|ev| ev.char).collect();
+    let usable = *app_state.current() == AppState::InGame
+        && game_state.phase != TurnPhase::NewGame
+        && !replay.is_playing();
+    if !usable {
+        if console.open {
+            console.open = false;
+        }
+        return;
+    }
+    if !console.open {
+        if keyboard_input.just_pressed(KeyCode::Grave) {
+            keyboard_input.reset(KeyCode::Grave);
+            console.open = true;
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Grave) || keyboard_input.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.typed);
+        if !line.trim().is_empty() {
+            console.entered = Some(line);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Back) {
+        console.typed.pop();
+    } else {
+        for c in typed {
+            if !c.is_control() && c != '`' && c != '~' {
+                console.typed.push(c);
+            }
+        }
+    }
+    // nothing else hears the keys while it's open, or as it closes
+    let held: Vec<KeyCode> = keyboard_input.get_pressed().copied().collect();
+    for key in held {
+        keyboard_input.reset(key);
+    }
+    for key in [KeyCode::Escape, KeyCode::Grave, KeyCode::Return].iter() {
+        keyboard_input.reset(*key);
+    }
+}
+
+// every command goes through what the game already does for the same thing: the magic
+// mapping event, the spawners, the pack, the stairs
+fn run_command(
+    mut commands: Commands,
+    mut console: ResMut<Console>,
+    mut game_state: ResMut<GameState>,
+    (templates, modifiers, tileset, window, item_materials, game_rng): (
+        Res<EnemyTemplates>,
+        Res<DifficultyModifiers>,
+        Res<Tileset>,
+        Res<WinSize>,
+        Res<ItemMaterials>,
+        Res<GameRng>,
+    ),
+    mut gold: ResMut<Gold>,
+    mut ev_reveal: EventWriter<RevealMapEvent>,
+    mut ev_finished_map: EventWriter<FinishedMapEvent>,
+    map_query: Query<&Map>,
+    mut player_query: Query<(&mut GridPos, &mut Transform, &mut Inventory), With<Player>>,
+    blocker_query: Query<&GridPos, (With<BlocksMovement>, Without<Player>)>,
+) {
+    let line = match console.entered.take() {
+        Some(line) => line,
+        None => return,
+    };
+    let (map_data, (mut player_loc, mut player_tf, mut inventory)) =
+        match (map_query.single(), player_query.single_mut()) {
+            (Ok(current_map), Ok(player)) => (&current_map.0, player),
+            _ => return,
+        };
+    let is_free = |loc: &GridPos| {
+        map_data.is_walkable(loc.x, loc.y) && !blocker_query.iter().any(|b| b == loc)
+    };
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let rest = words.get(1..).unwrap_or_default().join(" ");
+    let reply = match words.as_slice() {
+        ["teleport", x, y] => match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) if is_free(&GridPos::new(x, y)) => {
+                // the same as a blink or a scroll of teleportation
+                *player_loc = GridPos::new(x, y);
+                let world = player_loc.to_world(window.tile);
+                player_tf.translation.x = world.x;
+                player_tf.translation.y = world.y;
+                format!("Teleported to {}, {}.", x, y)
+            }
+            (Ok(x), Ok(y)) => format!("There's no room to stand at {}, {}.", x, y),
+            _ => "teleport takes two numbers.".to_string(),
+        },
+        ["reveal"] => {
+            ev_reveal.send(RevealMapEvent);
+            "Revealed the floor.".to_string()
+        }
+        ["spawn", ..] => {
+            let kind = templates.kinds().iter().find(|kind| {
+                templates
+                    .get(kind)
+                    .is_some_and(|template| template.name.eq_ignore_ascii_case(&rest))
+            });
+            let spot = NEIGHBORS
+                .iter()
+                .filter(|&&(dx, dy)| can_move(map_data, &player_loc, dx, dy))
+                .map(|&(dx, dy)| player_loc.add(dx, dy))
+                .find(|loc| is_free(loc));
+            match (kind, spot) {
+                (Some(kind), Some(loc)) => {
+                    spawn_enemy(
+                        &mut commands,
+                        &templates,
+                        &modifiers,
+                        &tileset,
+                        &window,
+                        *kind,
+                        loc,
+                    );
+                    let name = templates.get(kind).map_or("", |template| &template.name);
+                    format!("Spawned a {}.", name)
+                }
+                (Some(_), None) => "There's no room next to you.".to_string(),
+                (None, _) => format!("No enemy called \"{}\".", rest),
+            }
+        }
+        ["give", ..] => match ItemKind::parse(&rest) {
+            Some(ItemKind::Gold(amount)) => {
+                gold.0 += amount;
+                format!("Gave {} gold.", amount)
+            }
+            Some(kind) => {
+                let item = Item::new(kind);
+                let name = item.describe();
+                if inventory.is_full() {
+                    spawn_item(&mut commands, &item_materials, &window, item, *player_loc);
+                    format!("Your pack is full, dropped {} at your feet.", name)
+                } else {
+                    inventory.items.push(item);
+                    format!("Gave {}.", name)
+                }
+            }
+            None => format!("No item called \"{}\".", rest),
+        },
+        ["setdepth", n] => match n.parse::<u32>() {
+            Ok(depth) if (1..=FINAL_DEPTH).contains(&depth) => {
+                // taking the stairs adds one on the way down
+                game_state.depth = depth - 1;
+                ev_finished_map.send(FinishedMapEvent);
+                format!("Down to depth {}.", depth)
+            }
+            _ => format!("setdepth takes a depth from 1 to {}.", FINAL_DEPTH),
+        },
+        ["seed"] => format!("Seed: {}", game_rng.seed()),
+        ["help"] => HELP.to_string(),
+        _ => format!("Unknown command \"{}\". Try help.", line.trim()),
+    };
+    console.lines.push(format!("> {}", line.trim()));
+    console.lines.push(reply);
+}
+
+// rebuilt whenever anything in it changes, and taken down when it closes
+fn show_console(
+    mut commands: Commands,
+    console: Res<Console>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    panel_query: Query<Entity, With<ConsolePanel>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for panel in panel_query.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    if !console.open {
+        return;
+    }
+    let start = console.lines.len().saturating_sub(CONSOLE_LINES);
+    let mut text = console.lines[start..].join("\n");
+    if console.lines.is_empty() {
+        text.push_str(HELP);
+    }
+    text.push_str(&format!("\n> {}_", console.typed));
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                padding: Rect::all(Val::Px(6.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(ConsolePanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    text,
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: 14.,
+                        color: Color::rgb(0.6, 0.95, 0.6),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
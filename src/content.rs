@@ -0,0 +1,268 @@
+use crate::chest::read_trap_file;
+use crate::data::WatchedFile;
+use crate::enemy::{read_enemy_file, EnemyTemplates};
+use crate::map::FloorStyles;
+use crate::npc::{read_npc_file, DialogueEffect, NpcLibrary};
+use crate::script::{read_script_folder, Script, ScriptLibrary, SCRIPT_FOLDER};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+// what a content pack needs to name from outside the crate
+pub use crate::enemy::{EnemyKind, EnemyTemplate};
+pub use crate::map::LayoutStyle;
+pub use crate::npc::NpcDef;
+pub use crate::resources::FloorLayout;
+
+pub struct ContentPlugin;
+
+const ENEMY_FILE: &str = "assets/data/enemies.ron";
+const NPC_FILE: &str = "assets/data/npcs.ron";
+const TRAP_FILE: &str = "assets/data/traps.ron";
+
+// a bundle of dungeon content. the game's own is one, and a crate or a cargo feature can
+// bring more through run_with without touching the modules that use it
+pub trait DungeonContentPlugin: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    fn register(&self, content: &mut ContentRegistry) -> Result<(), String>;
+}
+
+// what the packs have put in so far. they go in order, the built-in one first, and a later
+// pack's enemy or floor style replaces an earlier one's for the same kind
+#[derive(Default)]
+pub struct ContentRegistry {
+    enemies: Vec<EnemyTemplate>,
+    npcs: Vec<NpcDef>,
+    layouts: HashMap<FloorLayout, LayoutStyle>,
+    // script source by name, see script.rs
+    scripts: HashMap<String, String>,
+    traps: Vec<String>,
+    // every data file read, saving any of them rebuilds the lot
+    files: Vec<String>,
+}
+
+impl ContentRegistry {
+    // new enemies use EnemyKind::Custom, picked by the pack
+    pub fn add_enemy(&mut self, template: EnemyTemplate) {
+        self.enemies.push(template);
+    }
+
+    pub fn add_npc(&mut self, npc: NpcDef) {
+        self.npcs.push(npc);
+    }
+
+    pub fn set_layout(&mut self, layout: FloorLayout, style: LayoutStyle) {
+        self.layouts.insert(layout, style);
+    }
+
+    // every enemy in a file laid out like assets/data/enemies.ron
+    pub fn add_enemy_file(&mut self, path: &str) -> Result<(), String> {
+        self.files.push(path.to_string());
+        let enemies = read_enemy_file(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.enemies.extend(enemies);
+        Ok(())
+    }
+
+    // every npc in a file laid out like assets/data/npcs.ron
+    pub fn add_npc_file(&mut self, path: &str) -> Result<(), String> {
+        self.files.push(path.to_string());
+        let npcs = read_npc_file(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.npcs.extend(npcs);
+        Ok(())
+    }
+
+    // a later pack's script replaces an earlier one's of the same name
+    pub fn add_script(&mut self, name: &str, source: &str) {
+        self.scripts.insert(name.to_string(), source.to_string());
+    }
+
+    // a chest trap, by the name of its script
+    pub fn add_trap(&mut self, script: &str) {
+        self.traps.push(script.to_string());
+    }
+
+    // every .script file in a folder, named by its file name
+    pub fn add_script_folder(&mut self, folder: &str) -> Result<(), String> {
+        for (name, path) in read_script_folder(folder).map_err(|e| format!("{}: {}", folder, e))? {
+            self.files.push(path.clone());
+            let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+            self.add_script(&name, &source);
+        }
+        Ok(())
+    }
+
+    // every trap in a file laid out like assets/data/traps.ron
+    pub fn add_trap_file(&mut self, path: &str) -> Result<(), String> {
+        self.files.push(path.to_string());
+        let traps = read_trap_file(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.traps.extend(traps);
+        Ok(())
+    }
+}
+
+// the packs the game started with, kept to build everything again when a file changes
+pub struct ContentPacks(pub Vec<Box<dyn DungeonContentPlugin>>);
+
+struct ContentFiles(Vec<WatchedFile>);
+
+// the game's own enemies, npcs and floors
+struct BaseContent;
+
+impl DungeonContentPlugin for BaseContent {
+    fn name(&self) -> &str {
+        "base"
+    }
+
+    fn register(&self, content: &mut ContentRegistry) -> Result<(), String> {
+        content.add_enemy_file(ENEMY_FILE)?;
+        // the game plays on without npcs, just with nobody to talk to
+        if let Err(e) = content.add_npc_file(NPC_FILE) {
+            warn!("couldn't load {}", e);
+        }
+        content.add_script_folder(SCRIPT_FOLDER)?;
+        content.add_trap_file(TRAP_FILE)?;
+        content.set_layout(
+            FloorLayout::Standard,
+            LayoutStyle {
+                width: 56,
+                height: 32,
+                columns: 3..=4,
+                rows: 2..=4,
+            },
+        );
+        // more, smaller rooms over a bigger map
+        content.set_layout(
+            FloorLayout::Sprawling,
+            LayoutStyle {
+                width: 80,
+                height: 44,
+                columns: 4..=5,
+                rows: 3..=4,
+            },
+        );
+        Ok(())
+    }
+}
+
+// everything the packs fill in, checked and ready to go in as resources
+struct Catalogs {
+    enemies: EnemyTemplates,
+    npcs: NpcLibrary,
+    floors: FloorStyles,
+    scripts: ScriptLibrary,
+    files: Vec<String>,
+}
+
+// every script the data files name has to be there, and has to parse
+fn build_scripts(content: &ContentRegistry) -> Result<ScriptLibrary, String> {
+    let mut scripts = HashMap::new();
+    for (name, source) in content.scripts.iter() {
+        let script = Script::parse(source).map_err(|e| format!("script {}: {}", name, e))?;
+        scripts.insert(name.clone(), script);
+    }
+    let abilities = content
+        .enemies
+        .iter()
+        .filter_map(|template| template.ability.as_ref());
+    let offerings = content
+        .npcs
+        .iter()
+        .flat_map(|npc| npc.nodes.values())
+        .flat_map(|node| node.choices.iter())
+        .filter_map(|choice| match &choice.effect {
+            Some(DialogueEffect::Script(script)) => Some(script),
+            _ => None,
+        });
+    if let Some(missing) = abilities
+        .chain(offerings)
+        .chain(content.traps.iter())
+        .find(|name| !scripts.contains_key(*name))
+    {
+        return Err(format!("no script called {}", missing));
+    }
+    Ok(ScriptLibrary::new(scripts, content.traps.clone()))
+}
+
+fn build_catalogs(packs: &[Box<dyn DungeonContentPlugin>]) -> Result<Catalogs, String> {
+    let mut content = ContentRegistry::default();
+    for pack in packs.iter() {
+        pack.register(&mut content)
+            .map_err(|e| format!("{} content: {}", pack.name(), e))?;
+    }
+    let scripts = build_scripts(&content)?;
+    Ok(Catalogs {
+        scripts,
+        enemies: EnemyTemplates::new(content.enemies)?,
+        npcs: NpcLibrary(content.npcs),
+        floors: FloorStyles::new(content.layouts)?,
+        files: content.files,
+    })
+}
+
+impl Plugin for ContentPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // anything passed to run_with goes on top of the built-in content
+        let mut packs: Vec<Box<dyn DungeonContentPlugin>> = vec![Box::new(BaseContent)];
+        if let Some(extra) = app.world_mut().remove_resource::<ContentPacks>() {
+            packs.extend(extra.0);
+        }
+        let catalogs = build_catalogs(&packs).unwrap_or_else(|e| panic!("couldn't load {}", e));
+        let files = catalogs.files.iter().map(|path| WatchedFile::new(path));
+        app.insert_resource(ContentFiles(files.collect()))
+            .insert_resource(catalogs.enemies)
+            .insert_resource(catalogs.npcs)
+            .insert_resource(catalogs.floors)
+            .insert_resource(catalogs.scripts)
+            .insert_resource(ContentPacks(packs))
+            .add_system_to_stage("app_state", reload_content.system());
+    }
+}
+
+// enemies and npcs already out keep what they were made with, the next ones get the new.
+// scripts are looked up by name each time they run, so an edit takes hold straight away
+fn reload_content(
+    time: Res<Time>,
+    packs: Res<ContentPacks>,
+    mut files: ResMut<ContentFiles>,
+    mut templates: ResMut<EnemyTemplates>,
+    mut library: ResMut<NpcLibrary>,
+    mut floor_styles: ResMut<FloorStyles>,
+    mut scripts: ResMut<ScriptLibrary>,
+    enemy_query: Query<&EnemyKind>,
+) {
+    // every file is looked at, so one save is only noticed once
+    let changed: Vec<String> = files
+        .0
+        .iter_mut()
+        .filter_map(|file| file.changed(&time).then(|| file.path.clone()))
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    match build_catalogs(&packs.0) {
+        // npcs on the floor point into the library by index, so none can go missing
+        Ok(catalogs) if catalogs.npcs.0.len() < library.0.len() => warn!(
+            "couldn't reload {}: npcs can only be added or changed while playing",
+            changed.join(", ")
+        ),
+        // nor can the kind of an enemy that's still about
+        Ok(catalogs)
+            if enemy_query
+                .iter()
+                .any(|kind| catalogs.enemies.get(kind).is_none()) =>
+        {
+            warn!(
+                "couldn't reload {}: enemies still on the floor can't be taken out while playing",
+                changed.join(", ")
+            )
+        }
+        Ok(catalogs) => {
+            *templates = catalogs.enemies;
+            *library = catalogs.npcs;
+            *floor_styles = catalogs.floors;
+            *scripts = catalogs.scripts;
+            info!("reloaded {}", changed.join(", "));
+        }
+        // a half-typed edit shouldn't take the game down, keep what worked
+        Err(e) => warn!("couldn't reload {}", e),
+    }
+}
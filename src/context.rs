@@ -0,0 +1,121 @@
+use crate::altar::Altar;
+use crate::chest::Chest;
+use crate::components::Direction;
+use crate::furniture::{interaction_target, Furniture};
+use crate::item::{Identification, Item, ItemKind};
+use crate::npc::Npc;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use bevy::prelude::*;
+
+pub struct ContextPlugin;
+
+// the line in the hud, see hud::spawn_hud
+pub struct ContextText;
+
+impl Plugin for ContextPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            context_actions
+                .system()
+                .after("resolve")
+                .after("inventory")
+                .after("index"),
+        );
+    }
+}
+
+// what the keys would do right where the player stands: the stairs, anything lying on the
+// tile, and whatever E would interact with. empty while it isn't the player's turn
+fn context_actions(
+    game_state: Res<GameState>,
+    identification: Res<Identification>,
+    index: Res<SpatialIndex>,
+    player_query: Query<(&GridPos, &Direction), With<Player>>,
+    item_query: Query<&Item, With<OnMap>>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+    sealed_query: Query<(), With<SealsStairs>>,
+    interactable_query: Query<
+        (
+            Option<&Name>,
+            Option<&Npc>,
+            Option<&Altar>,
+            Option<&Chest>,
+            Option<&Furniture>,
+        ),
+        With<Interactable>,
+    >,
+    mut text_query: Query<&mut Text, With<ContextText>>,
+) {
+    let mut prompts = Vec::new();
+    if let Ok((player_loc, facing)) = player_query.single() {
+        if game_state.has_map && game_state.phase == TurnPhase::PlayerInput {
+            if stairs_query.iter().any(|on_map| on_map.0 == *player_loc) {
+                prompts.push(if sealed_query.iter().next().is_some() {
+                    "The stairs are sealed".to_string()
+                } else if game_state.depth >= FINAL_DEPTH {
+                    "Space to leave the dungeon".to_string()
+                } else {
+                    "Space to descend".to_string()
+                });
+            }
+
+            // gold is scooped up just by walking over it
+            let items: Vec<&Item> = index
+                .at(player_loc)
+                .iter()
+                .filter_map(|&entity| item_query.get(entity).ok())
+                .filter(|item| !matches!(item.kind, ItemKind::Gold(_)))
+                .collect();
+            match items.as_slice() {
+                [] => {}
+                [item] => prompts.push(format!("G to pick up {}", identification.describe(item))),
+                _ => prompts.push(format!("G to pick up {} items", items.len())),
+            }
+
+            let target = interaction_target(&index, player_loc, facing, |entity| {
+                interactable_query.get(entity).is_ok()
+            });
+            let interaction = target.and_then(|entity| interactable_query.get(entity).ok());
+            if let Some(action) = interaction.and_then(describe_interaction) {
+                prompts.push(format!("E to {}", action));
+            }
+        }
+    }
+
+    if let Ok(mut text) = text_query.single_mut() {
+        let value = prompts.join("\n");
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// None for things E wouldn't do anything with anymore
+fn describe_interaction(
+    (name, npc, altar, chest, furniture): (
+        Option<&Name>,
+        Option<&Npc>,
+        Option<&Altar>,
+        Option<&Chest>,
+        Option<&Furniture>,
+    ),
+) -> Option<String> {
+    // npcs and altars go by proper names
+    let name = name.map_or("it", |name| name.as_str());
+    match (npc, altar, chest, furniture) {
+        (_, Some(_), ..) => Some(format!("pray at {}", name)),
+        (Some(_), ..) => Some(format!("talk to {}", name)),
+        (_, _, Some(chest), _) if chest.spotted => Some("open the trapped chest".to_string()),
+        (_, _, Some(_), _) => Some("open the chest".to_string()),
+        (.., Some(Furniture::Fountain { dry: false, .. })) => {
+            Some("drink from the fountain".to_string())
+        }
+        (.., Some(Furniture::Bookshelf { searched: false })) => {
+            Some("search the bookshelf".to_string())
+        }
+        (.., Some(Furniture::Brazier { lit: true })) => Some("put out the brazier".to_string()),
+        (.., Some(Furniture::Brazier { lit: false })) => Some("light the brazier".to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,71 @@
+use crate::enemy::EnemyKind;
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct CorpsePlugin;
+
+// turns a body lies around before it rots away
+const CORPSE_DECAY_TURNS: u32 = 20;
+
+// what's left of a dead enemy, doesn't block the tile it lies on.
+// cleared with the rest of the floor, or once it has rotted
+pub struct Corpse {
+    pub kind: EnemyKind,
+    // the turn it died on
+    pub turn: u32,
+}
+
+impl Plugin for CorpsePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(spawn_corpses.system().after("combat"))
+            .add_system(decay_corpses.system());
+    }
+}
+
+fn spawn_corpses(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<Materials>,
+    window: Res<WinSize>,
+    mut ev_death: EventReader<DeathEvent>,
+    enemy_query: Query<(&EnemyKind, &Name), With<Enemy>>,
+) {
+    for death in ev_death.iter() {
+        if let Ok((kind, name)) = enemy_query.get(death.entity) {
+            let loc = death.location;
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: materials.corpse.clone(),
+                    sprite: Sprite::new(Vec2::new(window.tile * 0.6, window.tile * 0.25)),
+                    transform: Transform::from_xyz(
+                        loc.x as f32 * window.tile,
+                        loc.y as f32 * window.tile,
+                        6.,
+                    ),
+                    ..Default::default()
+                })
+                .insert(Corpse {
+                    kind: *kind,
+                    turn: game_state.turn,
+                })
+                .insert(Name::new(format!("{} corpse", name.as_str())))
+                .insert(OnMap(loc))
+                .insert(loc);
+        }
+    }
+}
+
+fn decay_corpses(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    corpse_query: Query<(Entity, &Corpse)>,
+) {
+    if !game_state.is_changed() {
+        return;
+    }
+    for (entity, corpse) in corpse_query.iter() {
+        if game_state.turn >= corpse.turn + CORPSE_DECAY_TURNS {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use std::time::SystemTime;
+
+// how often, in seconds, the data files are looked at for changes
+const CHECK_INTERVAL: f64 = 1.;
+
+// keeps an eye on a data file so it can be read again when it's saved, and numbers tuned
+// with the game running
+pub struct WatchedFile {
+    pub path: String,
+    modified: Option<SystemTime>,
+    last_check: f64,
+}
+
+impl WatchedFile {
+    pub fn new(path: &str) -> Self {
+        WatchedFile {
+            path: path.to_string(),
+            modified: modified(path),
+            last_check: 0.,
+        }
+    }
+
+    // true once after each save, checking the file at most every CHECK_INTERVAL
+    pub fn changed(&mut self, time: &Time) -> bool {
+        let now = time.seconds_since_startup();
+        if now - self.last_check < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = now;
+        let latest = modified(&self.path);
+        if latest.is_none() || latest == self.modified {
+            return false;
+        }
+        self.modified = latest;
+        true
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,144 @@
+use crate::item::Item;
+use crate::prelude::*;
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+};
+use bevy::prelude::*;
+
+// needs the Diagnostics resource DefaultPlugins brings, so it's only in the windowed game
+pub struct DiagnosticsOverlayPlugin;
+
+const TILE_COUNT: DiagnosticId = DiagnosticId::from_u128(0x5d1e41a893c74b0fa2e60c9d7f31b845);
+const ENEMY_COUNT: DiagnosticId = DiagnosticId::from_u128(0x2b7f0e64c8514d938a1cf3e26b09d7a4);
+const ITEM_COUNT: DiagnosticId = DiagnosticId::from_u128(0x9a40c2d71f6e48b5b3d85e7a04c1f926);
+
+// whether the F3 overlay is up
+#[derive(Default)]
+pub struct DiagnosticsOverlay(pub bool);
+
+struct DiagnosticsPanel;
+struct DiagnosticsText;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(DiagnosticsOverlay::default())
+            .add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .add_startup_system(setup_counters.system())
+            // the menus are worth measuring too
+            .add_system_to_stage("app_state", count_entities.system())
+            .add_system_to_stage("app_state", overlay_input.system())
+            .add_system_to_stage("app_state", show_overlay.system());
+    }
+}
+
+fn setup_counters(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(TILE_COUNT, "tiles", 1));
+    diagnostics.add(Diagnostic::new(ENEMY_COUNT, "enemies", 1));
+    diagnostics.add(Diagnostic::new(ITEM_COUNT, "items", 1));
+}
+
+fn count_entities(
+    mut diagnostics: ResMut<Diagnostics>,
+    tiles_query: Query<(), With<MapElement>>,
+    enemy_query: Query<(), With<Enemy>>,
+    item_query: Query<(), With<Item>>,
+) {
+    diagnostics.add_measurement(TILE_COUNT, tiles_query.iter().count() as f64);
+    diagnostics.add_measurement(ENEMY_COUNT, enemy_query.iter().count() as f64);
+    diagnostics.add_measurement(ITEM_COUNT, item_query.iter().count() as f64);
+}
+
+fn overlay_input(keyboard_input: Res<Input<KeyCode>>, mut overlay: ResMut<DiagnosticsOverlay>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+fn show_overlay(
+    mut commands: Commands,
+    overlay: Res<DiagnosticsOverlay>,
+    diagnostics: Res<Diagnostics>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    panel_query: Query<Entity, With<DiagnosticsPanel>>,
+    mut text_query: Query<&mut Text, With<DiagnosticsText>>,
+    player_query: Query<&GridPos, With<Player>>,
+) {
+    if overlay.is_changed() {
+        for panel in panel_query.iter() {
+            commands.entity(panel).despawn_recursive();
+        }
+        if overlay.0 {
+            spawn_panel(&mut commands, &font, &materials);
+        }
+        // the text fills in from next frame
+        return;
+    }
+    let mut text = match text_query.single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let value = |id| diagnostics.get(id).and_then(|d| d.value()).unwrap_or(0.);
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|d| d.average())
+        .unwrap_or(0.);
+    let frame_ms = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|d| d.average())
+        .unwrap_or(0.)
+        * 1000.;
+    let mut lines = vec![
+        format!("FPS: {:.0} ({:.1} ms)", fps, frame_ms),
+        format!(
+            "Entities: {} (tiles {}, enemies {}, items {})",
+            value(EntityCountDiagnosticsPlugin::ENTITY_COUNT),
+            value(TILE_COUNT),
+            value(ENEMY_COUNT),
+            value(ITEM_COUNT)
+        ),
+        format!("Seed: {}", game_rng.seed()),
+        format!("Depth: {}", game_state.depth),
+    ];
+    if let Ok(loc) = player_query.single() {
+        lines.push(format!("Player: {}, {}", loc.x, loc.y));
+    }
+    text.sections[0].value = lines.join("\n");
+}
+
+fn spawn_panel(commands: &mut Commands, font: &UiFont, materials: &Materials) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(6.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(DiagnosticsPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 14.,
+                            color: Color::rgb(0.95, 0.9, 0.5),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(DiagnosticsText);
+        });
+}
@@ -0,0 +1,469 @@
+use crate::ai::AiState;
+use crate::components::Direction;
+use crate::fov::FieldOfView;
+use crate::item::LootEntry;
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::summoner::Summoner;
+use crate::tutorial::TutorialFloor;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub struct EnemyPlugin;
+
+// odds of a mimic hiding on a floor deep enough for them
+const MIMIC_CHANCE: f64 = 0.35;
+// odds each turn of a wanderer turning up, rising with depth up to the most there can be
+const WANDERER_CHANCE: f64 = 0.01;
+const WANDERER_CHANCE_PER_DEPTH: f64 = 0.004;
+const MAX_WANDERER_CHANCE: f64 = 0.05;
+// wanderers a floor can send, on top of a share of its depth
+const WANDERER_BUDGET: u32 = 2;
+// how far in from the outermost open tiles a wanderer can come in
+const EDGE_BAND: i32 = 4;
+// never closer to the player than this, even out of sight
+const WANDERER_MIN_DISTANCE: i32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum EnemyKind {
+    Rat,
+    Jackal,
+    Goblin,
+    Archer,
+    Orc,
+    Warden,
+    Mimic,
+    Summoner,
+    Shade,
+    // anything a content pack adds, numbered by the pack
+    Custom(u16),
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 9] = [
+        EnemyKind::Rat,
+        EnemyKind::Jackal,
+        EnemyKind::Goblin,
+        EnemyKind::Archer,
+        EnemyKind::Orc,
+        EnemyKind::Warden,
+        EnemyKind::Mimic,
+        EnemyKind::Summoner,
+        EnemyKind::Shade,
+    ];
+}
+
+#[derive(Deserialize)]
+pub struct EnemyTemplate {
+    pub kind: EnemyKind,
+    pub name: String,
+    pub hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub speed: f32,
+    pub color: (f32, f32, f32),
+    // the cell of the tileset it's drawn with, tinted by its colour
+    pub sprite: u32,
+    // the kind of damage its attacks deal
+    #[serde(default)]
+    pub element: Element,
+    // percent of each element's damage it resists, negative for a weakness
+    #[serde(default)]
+    pub resistances: HashMap<Element, i32>,
+    // how far away it can shoot from, 1 for melee only
+    #[serde(default = "melee_range")]
+    pub range: i32,
+    // spawns as a group of 3-6 that hunts together
+    #[serde(default)]
+    pub pack: bool,
+    // only placed by the boss plugin, never as a random spawn
+    #[serde(default)]
+    pub boss: bool,
+    // sits on the floor looking like a chest until someone gets close
+    #[serde(default)]
+    pub disguised: bool,
+    // hangs back and calls in minions instead of fighting
+    #[serde(default)]
+    pub summoner: bool,
+    // follows the player's scent trail when it can't see them
+    #[serde(default)]
+    pub tracks_scent: bool,
+    // only spawns in unlit rooms, and only acts where no torch reaches it
+    #[serde(default)]
+    pub shadow: bool,
+    // a script from assets/scripts it runs on whoever it lands a hit on
+    #[serde(default)]
+    pub ability: Option<String>,
+    // experience awarded for the kill
+    pub xp: u32,
+    // shallowest floor this enemy can show up on
+    pub min_depth: u32,
+    // rolled entry by entry when the enemy dies
+    #[serde(default)]
+    pub loot: Vec<LootEntry>,
+}
+
+impl EnemyTemplate {
+    pub fn sprite(&self) -> TextureAtlasSprite {
+        let (r, g, b) = self.color;
+        TextureAtlasSprite {
+            color: Color::rgb(r, g, b),
+            ..TextureAtlasSprite::new(self.sprite)
+        }
+    }
+}
+
+fn melee_range() -> i32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct EnemyFile {
+    enemies: Vec<EnemyTemplate>,
+}
+
+// every enemy's stats, built from the content packs (see content.rs)
+pub struct EnemyTemplates {
+    templates: HashMap<EnemyKind, EnemyTemplate>,
+    // the order random spawns pick from, so a seed always rolls the same enemies
+    kinds: Vec<EnemyKind>,
+}
+
+impl EnemyTemplates {
+    // later templates for the same kind replace earlier ones. the built-in kinds all need
+    // one, the game refers to them by name
+    pub fn new(list: Vec<EnemyTemplate>) -> Result<Self, String> {
+        let mut kinds: Vec<EnemyKind> = EnemyKind::ALL.to_vec();
+        let mut templates = HashMap::new();
+        for template in list {
+            if !kinds.contains(&template.kind) {
+                kinds.push(template.kind);
+            }
+            templates.insert(template.kind, template);
+        }
+        for kind in EnemyKind::ALL.iter() {
+            if !templates.contains_key(kind) {
+                return Err(format!("no enemy entry for {:?}", kind));
+            }
+        }
+        Ok(EnemyTemplates { templates, kinds })
+    }
+
+    // None for a custom kind whose pack isn't loaded
+    pub fn get(&self, kind: &EnemyKind) -> Option<&EnemyTemplate> {
+        self.templates.get(kind)
+    }
+
+    // the built-in kinds first, then whatever the packs added
+    pub fn kinds(&self) -> &[EnemyKind] {
+        &self.kinds
+    }
+}
+
+pub fn read_enemy_file(path: &str) -> Result<Vec<EnemyTemplate>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: EnemyFile = ron::de::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(file.enemies)
+}
+
+// drawn as a chest and left out of the ai until revealed
+pub struct Disguised;
+
+// wanderers sent onto the current floor so far
+#[derive(Default)]
+struct Wanderers(u32);
+
+// every member of a pack points at the same leader, the leader points at itself
+pub struct PackMember {
+    pub leader: Entity,
+}
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            spawn_enemies
+                .system()
+                .label("place_enemies")
+                .after("place_rations"),
+        )
+        .insert_resource(Wanderers::default())
+        .add_system(spawn_wanderers.system().after("resolve"))
+        .add_system(cleanup_enemies.system().label("cleanup").after("actions"));
+    }
+}
+
+// None, and nothing spawned, for a kind the templates don't have
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    templates: &EnemyTemplates,
+    modifiers: &DifficultyModifiers,
+    tileset: &Tileset,
+    window: &WinSize,
+    kind: EnemyKind,
+    loc: GridPos,
+) -> Option<Entity> {
+    let template = templates.get(&kind)?;
+    let mut stats = Stats {
+        max_hp: template.hp,
+        hp: template.hp,
+        attack: template.attack,
+        defense: template.defense,
+    };
+    modifiers.scale_stats(&mut stats);
+    let entity = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: template.sprite(),
+            texture_atlas: tileset.0.clone(),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(9.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Enemy)
+        .insert(kind)
+        .insert(Tint(template.sprite().color))
+        .insert(Name::new(template.name.clone()))
+        .insert(stats)
+        .insert(Speed(template.speed))
+        .insert(template.element)
+        .insert(Resistances(template.resistances.clone()))
+        .insert(Direction::default())
+        .insert(BlocksMovement)
+        .insert(Faction::Monster)
+        .insert(AiState::Wandering)
+        .insert(NextAction::default())
+        .insert(loc)
+        .id();
+    if template.summoner {
+        commands.entity(entity).insert(Summoner { turns: 0 });
+    }
+    Some(entity)
+}
+
+// the kinds a floor can roll at random, leaving out the ones with their own spawners
+fn random_kinds(templates: &EnemyTemplates, depth: u32) -> Vec<EnemyKind> {
+    templates
+        .kinds()
+        .iter()
+        .copied()
+        .filter(|k| {
+            templates.get(k).is_some_and(|template| {
+                template.min_depth <= depth
+                    && !template.boss
+                    && !template.disguised
+                    && !template.shadow
+            })
+        })
+        .collect()
+}
+
+fn spawn_enemies(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "enemies");
+        // never drop enemies into the room the player starts in
+        let candidates: Vec<usize> = (0..map_rooms.rooms.len())
+            .filter(|&i| i != map_rooms.spawn_room)
+            .collect();
+        if candidates.is_empty() {
+            return;
+        }
+        let kinds = random_kinds(&templates, game_state.depth);
+
+        // half of the first floor is napping, deeper floors are more alert
+        let sleep_chance = (0.55 - 0.05 * game_state.depth as f64).max(0.1);
+
+        // a couple of enemies on the first floor, one more for every floor after that
+        let count = modifiers.spawns(1 + game_state.depth).max(1);
+        for _ in 0..count {
+            let room = &map_rooms.rooms[candidates[rng.gen_range(0..candidates.len())]];
+            let loc = match spawn_tiles.claim_in(room, &mut rng) {
+                Some(loc) => loc,
+                None => continue,
+            };
+            let kind = kinds[rng.gen_range(0..kinds.len())];
+            let leader = match spawn_enemy(
+                &mut commands,
+                &templates,
+                &modifiers,
+                &tileset,
+                &window,
+                kind,
+                loc,
+            ) {
+                Some(leader) => leader,
+                None => continue,
+            };
+            if rng.gen_bool(sleep_chance) {
+                commands.entity(leader).insert(AiState::Sleeping);
+            }
+            if !templates.get(&kind).is_some_and(|template| template.pack) {
+                continue;
+            }
+            // the rest of the pack crowds into the same room
+            commands.entity(leader).insert(PackMember { leader });
+            for _ in 1..rng.gen_range(3..=6) {
+                let loc = spawn_tiles.claim_in(room, &mut rng);
+                let member = loc.and_then(|loc| {
+                    spawn_enemy(
+                        &mut commands,
+                        &templates,
+                        &modifiers,
+                        &tileset,
+                        &window,
+                        kind,
+                        loc,
+                    )
+                });
+                if let Some(member) = member {
+                    commands.entity(member).insert(PackMember { leader });
+                    if rng.gen_bool(sleep_chance) {
+                        commands.entity(member).insert(AiState::Sleeping);
+                    }
+                }
+            }
+        }
+
+        // every now and then one of the chests on the floor has teeth
+        let mimic_depth = templates
+            .get(&EnemyKind::Mimic)
+            .map_or(u32::MAX, |mimic| mimic.min_depth);
+        if game_state.depth >= mimic_depth && rng.gen_bool(MIMIC_CHANCE) {
+            let room = &map_rooms.rooms[candidates[rng.gen_range(0..candidates.len())]];
+            let loc = spawn_tiles.claim_in(room, &mut rng);
+            let mimic = loc.and_then(|loc| {
+                spawn_enemy(
+                    &mut commands,
+                    &templates,
+                    &modifiers,
+                    &tileset,
+                    &window,
+                    EnemyKind::Mimic,
+                    loc,
+                )
+            });
+            if let Some(mimic) = mimic {
+                commands
+                    .entity(mimic)
+                    .insert(TileSprite::Chest.sprite())
+                    .insert(Disguised);
+            }
+        }
+    }
+}
+
+// now and then something new wanders in from the far edges of the floor, out of sight, so
+// lingering gets more dangerous. deeper floors send them sooner and have more to send
+fn spawn_wanderers(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    fov: Res<FieldOfView>,
+    index: Res<SpatialIndex>,
+    mut log: ResMut<MessageLog>,
+    mut rng: ResMut<GameRng>,
+    mut wanderers: ResMut<Wanderers>,
+    mut last_turn: Local<u32>,
+    map_query: Query<(&Map, Option<&TutorialFloor>)>,
+    new_map_query: Query<(), Added<Map>>,
+    player_query: Query<&GridPos, With<Player>>,
+    blockers: Query<(), With<BlocksMovement>>,
+) {
+    let rng = rng.stream("wanderers");
+    if new_map_query.iter().next().is_some() {
+        wanderers.0 = 0;
+    }
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    let depth = game_state.depth;
+    let budget = modifiers.spawns(WANDERER_BUDGET + depth / 2);
+    if game_state.phase == TurnPhase::GameOver || wanderers.0 >= budget {
+        return;
+    }
+    let (current_map, player_loc) = match (map_query.single(), player_query.single()) {
+        (Ok((map, None)), Ok(loc)) => (map, loc),
+        _ => return,
+    };
+    let chance =
+        (WANDERER_CHANCE + WANDERER_CHANCE_PER_DEPTH * depth as f64).min(MAX_WANDERER_CHANCE);
+    if !rng.gen_bool(chance) {
+        return;
+    }
+    let map_data = &current_map.0;
+    let (width, height) = (map_data.width() as i32, map_data.height() as i32);
+    let edge = |x: i32, y: i32| x.min(y).min(width - 1 - x).min(height - 1 - y);
+    let open: Vec<(GridPos, i32)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| GridPos::new(x, y)))
+        .filter(|loc| {
+            let far = (loc.x - player_loc.x)
+                .abs()
+                .max((loc.y - player_loc.y).abs())
+                >= WANDERER_MIN_DISTANCE;
+            map_data.is_walkable(loc.x, loc.y)
+                && far
+                && !fov.is_visible(loc.x, loc.y)
+                && !index.is_blocked(loc, &blockers)
+        })
+        .map(|loc| (loc, edge(loc.x, loc.y)))
+        .collect();
+    let outermost = match open.iter().map(|(_, edge)| *edge).min() {
+        Some(outermost) => outermost,
+        None => return,
+    };
+    let band: Vec<GridPos> = open
+        .into_iter()
+        .filter(|(_, edge)| *edge < outermost + EDGE_BAND)
+        .map(|(loc, _)| loc)
+        .collect();
+    let kinds = random_kinds(&templates, depth);
+    if band.is_empty() || kinds.is_empty() {
+        return;
+    }
+    let loc = band[rng.gen_range(0..band.len())];
+    let kind = kinds[rng.gen_range(0..kinds.len())];
+    if spawn_enemy(
+        &mut commands,
+        &templates,
+        &modifiers,
+        &tileset,
+        &window,
+        kind,
+        loc,
+    )
+    .is_none()
+    {
+        return;
+    }
+    wanderers.0 += 1;
+    log.add("You hear something moving somewhere in the distance.");
+}
+
+fn cleanup_enemies(
+    mut commands: Commands,
+    mut ev_finished_map: EventReader<FinishedMapEvent>,
+    enemy_query: Query<Entity, With<Enemy>>,
+) {
+    if ev_finished_map.iter().next().is_some() {
+        for enemy_entity in enemy_query.iter() {
+            commands.entity(enemy_entity).despawn_recursive();
+        }
+    }
+}
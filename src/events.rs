@@ -0,0 +1,118 @@
+use crate::components::Direction;
+use crate::item::Item;
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct FinishedMapEvent;
+
+// the player took the stairs on the final floor
+pub struct VictoryEvent;
+
+// an actor wants to step one tile in a direction, the move may still be rejected
+pub struct MoveIntentEvent {
+    pub actor: Entity,
+    pub direction: Direction,
+}
+
+// melee attack from bumping into a hostile actor, or a projectile landing
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub ranged: bool,
+    // spells hit with their own power instead of the attacker's attack stat, and never miss
+    pub power: Option<i32>,
+    // overrides the attacker's own Element
+    pub element: Option<Element>,
+}
+
+// launch a projectile that flies tile by tile along a line towards a target tile
+pub struct FireProjectileEvent {
+    pub source: Entity,
+    pub from: GridPos,
+    pub to: GridPos,
+    pub power: Option<i32>,
+    pub element: Option<Element>,
+    pub payload: Option<Item>,
+}
+
+// a projectile carrying a thrown item came down on this tile
+pub struct ProjectileLandedEvent {
+    pub source: Entity,
+    pub item: Item,
+    pub location: GridPos,
+}
+
+// something loud happened, enemies within the radius may come to check it out
+pub struct NoiseEvent {
+    pub location: GridPos,
+    pub radius: i32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HitOutcome {
+    Miss,
+    Hit,
+    Critical,
+}
+
+// how an attack played out once accuracy, evasion and crits were rolled
+pub struct HitEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub location: GridPos,
+    pub outcome: HitOutcome,
+    pub damage: i32,
+    pub element: Element,
+    // the target's resistance that was applied, in percent
+    pub resisted: i32,
+    // the target caught it on their guard
+    pub blocked: bool,
+    // the target hadn't noticed the attacker yet
+    pub backstab: bool,
+    // the attack came from behind the target
+    pub flanked: bool,
+}
+
+// an actor spends their turn raising their guard
+pub struct BlockEvent {
+    pub actor: Entity,
+}
+
+// an actor lets their turn go by
+pub struct WaitEvent {
+    pub actor: Entity,
+}
+
+// an actor's hp hit zero, sent before the entity is despawned
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Entity,
+    pub location: GridPos,
+}
+
+// the player bumped into a neutral actor that has something to say
+pub struct TalkEvent {
+    pub speaker: Entity,
+}
+
+// the player pressed the Interact key at an Interactable
+pub struct InteractEvent {
+    pub actor: Entity,
+    pub target: Entity,
+}
+
+// sent once a move has passed the map checks and the actor's GridPos was updated
+pub struct MoveResolvedEvent {
+    pub actor: Entity,
+    pub from: GridPos,
+    pub to: GridPos,
+}
+
+// a lever or plate switched its circuit on or off, everything on the circuit follows
+pub struct CircuitEvent {
+    pub circuit: Circuit,
+    pub powered: bool,
+}
+
+// a line for the narration to read out, see narration::deliver
+pub struct AnnounceEvent(pub String);
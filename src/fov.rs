@@ -0,0 +1,214 @@
+use crate::light::LightMap;
+use crate::magic::RevealMapEvent;
+use crate::prelude::*;
+use crate::settings::{Palette, Settings};
+use crate::visibility::field_of_view;
+use array2d::Array2D;
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+pub struct FovPlugin;
+
+// how far the player can see down a clear corridor
+pub const FOV_RADIUS: i32 = 8;
+// tiles lit more faintly than this are drawn darker
+const DUSK: f32 = 0.5;
+const DUSK_SHADE: f32 = 0.65;
+// explored tiles out of sight
+const REMEMBERED_SHADE: f32 = 0.4;
+
+// the tiles the player can currently see, recomputed whenever they or a light move
+#[derive(Default)]
+pub struct FieldOfView(HashSet<(i32, i32)>);
+impl FieldOfView {
+    pub fn is_visible(&self, x: i32, y: i32) -> bool {
+        self.0.contains(&(x, y))
+    }
+
+    // how brightly the player sees a tile, 0 if they can't see it at all
+    pub fn light(&self, x: i32, y: i32, light_map: &LightMap) -> f32 {
+        if self.is_visible(x, y) {
+            light_map.level(x, y)
+        } else {
+            0.
+        }
+    }
+}
+
+// every tile the player has seen on this floor, stored next to the Map on the same entity.
+// out of sight but explored tiles are drawn dimmed, without anything standing on them
+pub struct Explored(pub Array2D<bool>);
+impl Explored {
+    pub fn new(map_data: &DungeonMap) -> Self {
+        Explored(Array2D::filled_with(
+            false,
+            map_data.height(),
+            map_data.width(),
+        ))
+    }
+
+    pub fn is_explored(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && self.0.get(y as usize, x as usize) == Some(&true)
+    }
+}
+
+impl Plugin for FovPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(FieldOfView::default())
+            .add_system(
+                update_fov
+                    .system()
+                    .label("fov")
+                    .after("resolve")
+                    .after("light"),
+            )
+            .add_system(reveal_map.system().before("fov"))
+            .add_system(shade_tiles.system().after("fov"))
+            .add_system(hide_unseen.system().after("fov"));
+    }
+}
+
+// how a map tile is drawn, given how brightly it's lit where the player can see it.
+// the edge of a light is dusky, remembered tiles are dimmed and anything never seen is black
+pub fn tile_sprite(
+    map_data: &DungeonMap,
+    x: i32,
+    y: i32,
+    light: f32,
+    explored: bool,
+    palette: Palette,
+) -> TextureAtlasSprite {
+    let shade = match light {
+        _ if light <= 0. && !explored => 0.,
+        _ if light <= 0. => REMEMBERED_SHADE,
+        _ if light < DUSK => DUSK_SHADE,
+        _ => 1.,
+    };
+    let mut sprite = TileSprite::at(map_data, x, y, palette);
+    let tint = sprite.color;
+    sprite.color = Color::rgb(tint.r() * shade, tint.g() * shade, tint.b() * shade);
+    sprite
+}
+
+// a ray to every tile in range, a tile is seen if nothing solid is in the way and some
+// light falls on it. walls stop the ray but are still seen themselves
+fn update_fov(
+    mut fov: ResMut<FieldOfView>,
+    light_map: Res<LightMap>,
+    mut map_query: Query<(&Map, &mut Explored)>,
+    new_map_query: Query<(), Added<Map>>,
+    player_query: Query<(&GridPos, ChangeTrackers<GridPos>), With<Player>>,
+) {
+    let (player_loc, tracker) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if !tracker.is_changed() && !light_map.is_changed() && new_map_query.iter().next().is_none() {
+        return;
+    }
+    let (current_map, mut explored) = match map_query.single_mut() {
+        Ok(current_map) => current_map,
+        Err(_) => return,
+    };
+    let map_data = &current_map.0;
+    let mut visible = HashSet::new();
+    for loc in field_of_view(map_data, player_loc, FOV_RADIUS) {
+        if light_map.level(loc.x, loc.y) > 0. {
+            visible.insert((loc.x, loc.y));
+            explored.0.set(loc.y as usize, loc.x as usize, true).ok();
+        }
+    }
+    fov.0 = visible;
+}
+
+// magic mapping marks every floor tile as explored, along with the walls around them
+fn reveal_map(
+    mut ev_reveal: EventReader<RevealMapEvent>,
+    mut map_query: Query<(&Map, &mut Explored)>,
+) {
+    if ev_reveal.iter().next().is_none() {
+        return;
+    }
+    if let Ok((current_map, mut explored)) = map_query.single_mut() {
+        let map_data = &current_map.0;
+        for y in 0..map_data.height() as i32 {
+            for x in 0..map_data.width() as i32 {
+                if !map_data.is_walkable(x, y) {
+                    continue;
+                }
+                explored.0.set(y as usize, x as usize, true).ok();
+                for (nx, ny) in map_data.iter_neighbors(x, y) {
+                    explored.0.set(ny as usize, nx as usize, true).ok();
+                }
+            }
+        }
+    }
+}
+
+// re-colours every drawn tile when the view, the explored tiles or the map itself change,
+// and any tile update_map just drew
+fn shade_tiles(
+    fov: Res<FieldOfView>,
+    light_map: Res<LightMap>,
+    settings: Res<Settings>,
+    map_query: Query<(
+        &Map,
+        &Explored,
+        ChangeTrackers<Explored>,
+        ChangeTrackers<Map>,
+    )>,
+    mut tiles_query: Query<
+        (
+            &GridPos,
+            &mut TextureAtlasSprite,
+            ChangeTrackers<MapElement>,
+        ),
+        With<MapElement>,
+    >,
+) {
+    let (map_data, explored, explored_tracker, map_tracker) = match map_query.single() {
+        Ok((current_map, explored, tracker, map_tracker)) => {
+            (&current_map.0, explored, tracker, map_tracker)
+        }
+        Err(_) => return,
+    };
+    let redraw_all = fov.is_changed()
+        || explored_tracker.is_changed()
+        || map_tracker.is_changed()
+        || settings.is_changed();
+    for (loc, mut sprite, tracker) in tiles_query.iter_mut() {
+        if !redraw_all && !tracker.is_added() {
+            continue;
+        }
+        *sprite = tile_sprite(
+            map_data,
+            loc.x,
+            loc.y,
+            fov.light(loc.x, loc.y, &light_map),
+            explored.is_explored(loc.x, loc.y),
+            settings.palette,
+        );
+    }
+}
+
+// actors, items and fixtures out of sight aren't drawn
+fn hide_unseen(
+    fov: Res<FieldOfView>,
+    mut object_query: Query<
+        (&mut Visible, Option<&GridPos>, Option<&OnMap>),
+        (Or<(With<OnMap>, With<Faction>)>, Without<Player>),
+    >,
+) {
+    for (mut visible, loc, on_map) in object_query.iter_mut() {
+        let loc = match (loc, on_map) {
+            (Some(loc), _) => loc,
+            (None, Some(on_map)) => &on_map.0,
+            (None, None) => continue,
+        };
+        let seen = fov.is_visible(loc.x, loc.y);
+        // only touch it when it flips, the health bars watch for changes
+        if visible.is_visible != seen {
+            visible.is_visible = seen;
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::components::Direction;
+use crate::inventory::InventoryScreen;
+use crate::level::LevelUp;
+use crate::light::{LightSource, BRAZIER_LIGHT};
+use crate::magic::{Casting, Spell, Spellbook};
+use crate::map_view::MapView;
+use crate::message_log::MessageLog;
+use crate::npc::ActiveDialogue;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use crate::throwing::Aiming;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct FurniturePlugin;
+
+// odds of each room getting a piece of furniture
+const FURNITURE_CHANCE: f64 = 0.4;
+// a fountain's water is bad this often
+const POISONED_CHANCE: f64 = 0.3;
+const FOUNTAIN_HEAL: i32 = 8;
+const POISON_TURNS: u32 = 6;
+// spells a bookshelf can turn up, the rest only come from levelling and teachers
+const SHELF_SPELLS: [Spell; 4] = [
+    Spell::Firebolt,
+    Spell::Blink,
+    Spell::Heal,
+    Spell::MagicMapping,
+];
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Furniture {
+    // one drink, which either heals or poisons
+    Fountain { poisoned: bool, dry: bool },
+    // can be searched once for a spell
+    Bookshelf { searched: bool },
+    // can be lit and put out again
+    Brazier { lit: bool },
+}
+
+impl Furniture {
+    fn name(&self) -> &'static str {
+        match self {
+            Furniture::Fountain { .. } => "fountain",
+            Furniture::Bookshelf { .. } => "bookshelf",
+            Furniture::Brazier { .. } => "brazier",
+        }
+    }
+}
+
+impl Plugin for FurniturePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<InteractEvent>()
+            .add_system(
+                furnish_rooms
+                    .system()
+                    .label("furnish")
+                    .after("place_shrine"),
+            )
+            // before the inventory screen, so the E that equips from it doesn't also interact
+            .add_system(interact_input.system().after("index").before("inventory"))
+            .add_system(use_furniture.system().after("inventory"));
+    }
+}
+
+fn furnish_rooms(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "furniture");
+        for room in map_rooms.rooms.iter() {
+            if !rng.gen_bool(FURNITURE_CHANCE) {
+                continue;
+            }
+            let loc = match spawn_tiles.claim_in(room, &mut rng) {
+                Some(loc) => loc,
+                None => continue,
+            };
+            let furniture = match rng.gen_range(0..3) {
+                0 => Furniture::Fountain {
+                    poisoned: rng.gen_bool(POISONED_CHANCE),
+                    dry: false,
+                },
+                1 => Furniture::Bookshelf { searched: false },
+                _ => Furniture::Brazier { lit: false },
+            };
+            spawn_furniture(&mut commands, &materials, &window, furniture, loc);
+        }
+    }
+}
+
+pub fn spawn_furniture(
+    commands: &mut Commands,
+    materials: &Materials,
+    window: &WinSize,
+    furniture: Furniture,
+    loc: GridPos,
+) {
+    let (material, size) = match furniture {
+        Furniture::Fountain { .. } => (materials.fountain.clone(), Vec2::new(0.7, 0.7)),
+        Furniture::Bookshelf { .. } => (materials.bookshelf.clone(), Vec2::new(0.8, 0.4)),
+        Furniture::Brazier { lit: false } => (materials.brazier.clone(), Vec2::new(0.4, 0.4)),
+        Furniture::Brazier { lit: true } => (materials.brazier_lit.clone(), Vec2::new(0.4, 0.4)),
+    };
+    let entity = commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(size * window.tile),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(furniture)
+        .insert(Interactable)
+        .insert(Name::new(furniture.name()))
+        .insert(BlocksMovement)
+        .insert(OnMap(loc))
+        .insert(loc)
+        .id();
+    if furniture == (Furniture::Brazier { lit: true }) {
+        commands.entity(entity).insert(LightSource {
+            radius: BRAZIER_LIGHT,
+            carried: false,
+        });
+    }
+}
+
+// what E acts on: whatever the player is facing, or failing that the first thing next to
+// them going round in a fixed order, then anything on their own tile. the hud prompt in
+// context::context_actions names the same one
+pub fn interaction_target(
+    index: &SpatialIndex,
+    player_loc: &GridPos,
+    facing: &Direction,
+    is_interactable: impl Fn(Entity) -> bool,
+) -> Option<Entity> {
+    let neighbours = (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&step| step != (0, 0))
+        .map(|(dx, dy)| player_loc.add(dx, dy));
+    std::iter::once(player_loc.neighbor(facing))
+        .chain(neighbours)
+        .chain(std::iter::once(*player_loc))
+        .flat_map(|loc| index.at(&loc).to_vec())
+        .find(|&entity| is_interactable(entity))
+}
+
+// E interacts with whatever interaction_target picks
+fn interact_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    mut log: ResMut<MessageLog>,
+    mut ev_interact: EventWriter<InteractEvent>,
+    index: Res<SpatialIndex>,
+    player_query: Query<(Entity, &GridPos, &Direction), With<Player>>,
+    interactable_query: Query<(), With<Interactable>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::E)
+        || game_state.animating_actions
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    let (player, player_loc, facing) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let target = interaction_target(&index, player_loc, facing, |entity| {
+        interactable_query.get(entity).is_ok()
+    });
+    match target {
+        Some(target) => ev_interact.send(InteractEvent {
+            actor: player,
+            target,
+        }),
+        None => log.add("There's nothing here to interact with."),
+    }
+}
+
+fn use_furniture(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<Materials>,
+    mut log: ResMut<MessageLog>,
+    mut ev_interact: EventReader<InteractEvent>,
+    mut furniture_query: Query<(&mut Furniture, &mut Handle<ColorMaterial>)>,
+    mut player_query: Query<(&mut Stats, &mut StatusEffects, &mut Spellbook), With<Player>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("furniture");
+    for interact in ev_interact.iter() {
+        let (mut furniture, mut material) = match furniture_query.get_mut(interact.target) {
+            Ok(furniture) => furniture,
+            Err(_) => continue,
+        };
+        let (mut stats, mut effects, mut spellbook) = match player_query.get_mut(interact.actor) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        match *furniture {
+            Furniture::Fountain { dry: true, .. } => log.add("The fountain has run dry."),
+            Furniture::Fountain { poisoned, .. } => {
+                if poisoned {
+                    effects.add(Status::Poisoned, game_state.turn + POISON_TURNS);
+                    log.add("The water tastes foul. You feel sick.");
+                } else {
+                    stats.hp = (stats.hp + FOUNTAIN_HEAL).min(stats.max_hp);
+                    log.add("The cool water washes your wounds clean.");
+                }
+                *furniture = Furniture::Fountain {
+                    poisoned,
+                    dry: true,
+                };
+            }
+            Furniture::Bookshelf { searched: true } => {
+                log.add("You've already been through these books.")
+            }
+            Furniture::Bookshelf { searched: false } => {
+                *furniture = Furniture::Bookshelf { searched: true };
+                let unknown: Vec<Spell> = SHELF_SPELLS
+                    .iter()
+                    .copied()
+                    .filter(|spell| !spellbook.0.contains(spell))
+                    .collect();
+                match unknown.choose(&mut *rng) {
+                    Some(&spell) => {
+                        spellbook.learn(spell);
+                        log.add(format!(
+                            "Between the mouldering books is a spell you can use: {}.",
+                            spell.name()
+                        ));
+                    }
+                    None => log.add("Nothing on the shelves you don't already know."),
+                }
+            }
+            Furniture::Brazier { lit } => {
+                *furniture = Furniture::Brazier { lit: !lit };
+                *material = if lit {
+                    log.add("You smother the brazier.");
+                    commands.entity(interact.target).remove::<LightSource>();
+                    materials.brazier.clone()
+                } else {
+                    log.add("The brazier catches and flares to life.");
+                    commands.entity(interact.target).insert(LightSource {
+                        radius: BRAZIER_LIGHT,
+                        carried: false,
+                    });
+                    materials.brazier_lit.clone()
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::gold::Gold;
+use crate::prelude::*;
+use crate::scores::HighScores;
+use bevy::prelude::*;
+
+pub struct GameOverPlugin;
+
+struct GameOverScreen;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<VictoryEvent>()
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::InGame).with_system(end_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(show_game_over.system().after("record_score")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Victory)
+                    .with_system(show_victory.system().after("record_score")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::GameOver).with_system(restart_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::Victory).with_system(restart_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::GameOver).with_system(close_screen.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::Victory).with_system(close_screen.system()),
+            );
+    }
+}
+
+// the player dying or walking out of the dungeon ends the run
+fn end_run(
+    game_state: Res<GameState>,
+    mut ev_victory: EventReader<VictoryEvent>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if game_state.phase == TurnPhase::GameOver {
+        app_state.set(AppState::GameOver).ok();
+    } else if ev_victory.iter().next().is_some() {
+        app_state.set(AppState::Victory).ok();
+    }
+}
+
+fn show_game_over(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    run_stats: Res<RunStats>,
+    gold: Res<Gold>,
+    high_scores: Res<HighScores>,
+    game_rng: Res<GameRng>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+) {
+    let summary = format!(
+        "Floors reached: {}\nKills: {}\nGold: {}\nTurns: {}\nSeed: {}\n\n",
+        game_state.depth,
+        run_stats.kills,
+        gold.0,
+        game_state.turn,
+        game_rng.seed()
+    );
+    spawn_screen(
+        &mut commands,
+        &font,
+        &materials,
+        ("You have died", Color::rgb(0.85, 0.2, 0.2)),
+        summary,
+        &high_scores,
+    );
+}
+
+fn show_victory(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    run_stats: Res<RunStats>,
+    gold: Res<Gold>,
+    high_scores: Res<HighScores>,
+    game_rng: Res<GameRng>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+) {
+    let summary = format!(
+        "You climb out of the dungeon alive.\n\nKills: {}\nGold: {}\nTurns: {}\nSeed: {}\n\n",
+        run_stats.kills,
+        gold.0,
+        game_state.turn,
+        game_rng.seed()
+    );
+    spawn_screen(
+        &mut commands,
+        &font,
+        &materials,
+        ("Victory", Color::rgb(0.95, 0.85, 0.4)),
+        summary,
+        &high_scores,
+    );
+}
+
+// dims the whole screen and lists how the run went, and where it landed in the high scores
+fn spawn_screen(
+    commands: &mut Commands,
+    font: &UiFont,
+    materials: &Materials,
+    (title, color): (&str, Color),
+    summary: String,
+    high_scores: &HighScores,
+) {
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![
+        TextSection {
+            value: format!("{}\n\n", title),
+            style: style(40., color),
+        },
+        TextSection {
+            value: summary,
+            style: style(20., Color::WHITE),
+        },
+        TextSection {
+            value: "High scores\n".to_string(),
+            style: style(20., Color::rgb(0.95, 0.85, 0.4)),
+        },
+    ];
+    sections.extend(high_scores.table(font));
+    sections.push(TextSection {
+        value: "\n[R] start a new run".to_string(),
+        style: style(16., Color::GRAY),
+    });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(GameOverScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections,
+                        alignment: TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .insert(Narrated);
+        });
+}
+
+// R goes back to picking a class for a fresh run
+fn restart_run(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut game_state: ResMut<GameState>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::R) {
+        return;
+    }
+    keyboard_input.reset(KeyCode::R);
+    game_state.phase = TurnPhase::NewGame;
+    app_state.set(AppState::InGame).ok();
+}
+
+fn close_screen(mut commands: Commands, screen_query: Query<Entity, With<GameOverScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
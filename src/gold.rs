@@ -0,0 +1,105 @@
+use crate::inventory::PickUpEvent;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct GoldPlugin;
+
+// odds of a pile turning up in any given room
+const PILE_CHANCE: f64 = 0.35;
+// one room per floor is a treasure room, stacked with this many piles
+const TREASURE_PILES: usize = 4;
+
+// coins the player has picked up this run
+#[derive(Default)]
+pub struct Gold(pub u32);
+
+// the line in the hud, see hud::spawn_hud
+pub struct GoldText;
+
+impl Plugin for GoldPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Gold::default())
+            .add_system(
+                spawn_gold_piles
+                    .system()
+                    .label("place_gold")
+                    .after("place_chests"),
+            )
+            .add_system(collect_gold.system().after("resolve"))
+            .add_system(update_counter.system());
+    }
+}
+
+// a few piles scattered around, and one room where most of it is stashed.
+// piles get bigger the deeper the floor
+fn spawn_gold_piles(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "gold");
+        let treasure_room = rng.gen_range(0..map_rooms.rooms.len().max(1));
+        for (i, room) in map_rooms.rooms.iter().enumerate() {
+            let piles = if i == treasure_room && i != map_rooms.spawn_room {
+                TREASURE_PILES
+            } else if rng.gen_bool(PILE_CHANCE) {
+                1
+            } else {
+                0
+            };
+            for _ in 0..piles {
+                let loc = match spawn_tiles.claim_in(room, &mut rng) {
+                    Some(loc) => loc,
+                    None => continue,
+                };
+                let amount = rng.gen_range(2..=6) * game_state.depth.max(1);
+                let item = Item::new(ItemKind::Gold(amount));
+                spawn_item(&mut commands, &materials, &window, item, loc);
+            }
+        }
+    }
+}
+
+// walking onto gold picks it up, no key needed
+fn collect_gold(
+    mut commands: Commands,
+    mut gold: ResMut<Gold>,
+    mut log: ResMut<MessageLog>,
+    mut ev_pick_up: EventWriter<PickUpEvent>,
+    index: Res<SpatialIndex>,
+    player_query: Query<&GridPos, (With<Player>, Changed<GridPos>)>,
+    item_query: Query<&Item, With<OnMap>>,
+) {
+    if let Ok(player_loc) = player_query.single() {
+        for &entity in index.at(player_loc) {
+            if let Ok(Item {
+                kind: ItemKind::Gold(amount),
+                ..
+            }) = item_query.get(entity)
+            {
+                gold.0 += amount;
+                log.add(format!("You pick up {} gold.", amount));
+                ev_pick_up.send(PickUpEvent);
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+fn update_counter(gold: Res<Gold>, mut text_query: Query<&mut Text, With<GoldText>>) {
+    if !gold.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        text.sections[0].value = format!("Gold: {}", gold.0);
+    }
+}
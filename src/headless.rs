@@ -0,0 +1,165 @@
+use crate::gold::Gold;
+use crate::map::MapTask;
+use crate::message_log::MessageLog;
+use crate::movement::AnimationClock;
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::replay::{Replay, ReplayRun};
+use crate::settings::{disable_user_dirs, Settings};
+use crate::unlocks::Unlocks;
+use crate::GamePlugin;
+use bevy::asset::AssetPlugin;
+use bevy::core::DefaultTaskPoolOptions;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::window::WindowPlugin;
+use serde::{Deserialize, Serialize};
+
+// what a script needs to name from outside the crate
+pub use crate::class::PlayerClass;
+pub use crate::replay::ScriptedInput;
+pub use crate::resources::{Difficulty, FloorLayout, GameMode};
+
+// frames the game gets to act on each input before the run is given up on as stuck
+const FRAMES_PER_INPUT: usize = 600;
+
+// a run to play without a window: how it starts and what the player does, one input
+// each time the game waits on them
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub seed: u64,
+    #[serde(default)]
+    pub class: PlayerClass,
+    #[serde(default)]
+    pub layout: FloorLayout,
+    #[serde(default)]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub inputs: Vec<ScriptedInput>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ending {
+    // the script ran out with the player still going
+    Finished,
+    Died,
+    Escaped,
+    // the game stopped waiting on the player and never started again
+    Stuck,
+}
+
+// where a headless run got to
+#[derive(Clone, PartialEq, Debug)]
+pub struct Outcome {
+    pub ending: Ending,
+    pub depth: u32,
+    pub turn: u32,
+    pub hp: i32,
+    pub gold: u32,
+    // the message log from start to finish
+    pub log: Vec<String>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::de::from_str(&data).map_err(|e| e.to_string())
+    }
+}
+
+// plays the script through with no window, renderer or sound. the same script always
+// plays out the same way, it's a replay nobody watches
+pub fn run_script(script: &Script) -> Outcome {
+    // nothing a script does should touch the player's own settings, profiles or scores
+    disable_user_dirs();
+    let mut builder = App::build();
+    builder
+        // on one thread, systems that don't care which of them goes first still always
+        // go in the same order
+        .insert_resource(DefaultTaskPoolOptions::with_num_threads(1))
+        .insert_resource(Settings::default())
+        .insert_resource(Profile::load_last())
+        .insert_resource(Unlocks::default())
+        .add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin::default())
+        .add_plugin(AssetPlugin)
+        // the sprites and text are still made, there's just nothing to draw them
+        .add_asset::<Texture>()
+        .add_asset::<TextureAtlas>()
+        .add_asset::<ColorMaterial>()
+        .add_asset::<Font>()
+        .add_plugin(GamePlugin);
+    let mut app = builder.app;
+
+    // the main menu comes up first, then the script starts like a replay picked from it
+    app.update();
+    let run = ReplayRun::scripted(
+        script.seed,
+        script.class,
+        script.layout,
+        script.mode,
+        script.difficulty,
+        &script.inputs,
+    );
+    app.world
+        .get_resource_mut::<Replay>()
+        .expect("the replay plugin is in")
+        .play_unpaced(run);
+    app.world
+        .get_resource_mut::<AnimationClock>()
+        .expect("the movement plugin is in")
+        .unpaced = true;
+    app.world
+        .get_resource_mut::<MapTask>()
+        .expect("the map plugin is in")
+        .wait = true;
+    app.world
+        .get_resource_mut::<State<AppState>>()
+        .expect("the app state is in")
+        .set(AppState::InGame)
+        .ok();
+
+    let mut ending = Ending::Stuck;
+    for _ in 0..FRAMES_PER_INPUT * (script.inputs.len() + 1) {
+        app.update();
+        let world = &app.world;
+        ending = match world.get_resource::<State<AppState>>().map(|s| s.current()) {
+            Some(AppState::GameOver) => Ending::Died,
+            Some(AppState::Victory) => Ending::Escaped,
+            _ if world
+                .get_resource::<Replay>()
+                .is_some_and(|r| r.has_ended()) =>
+            {
+                Ending::Finished
+            }
+            _ => continue,
+        };
+        break;
+    }
+    outcome(&mut app.world, ending)
+}
+
+fn outcome(world: &mut World, ending: Ending) -> Outcome {
+    let game_state = world.get_resource::<GameState>().expect("setup has run");
+    let (depth, turn) = (game_state.depth, game_state.turn);
+    let hp = world
+        .query_filtered::<&Stats, With<Player>>()
+        .iter(world)
+        .next()
+        .map_or(0, |stats| stats.hp);
+    let gold = world.get_resource::<Gold>().map_or(0, |gold| gold.0);
+    let log = world
+        .get_resource::<MessageLog>()
+        .map_or_else(Vec::new, |log| log.messages().to_vec());
+    Outcome {
+        ending,
+        depth,
+        turn,
+        hp,
+        gold,
+        log,
+    }
+}
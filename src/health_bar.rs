@@ -0,0 +1,80 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct HealthBarPlugin;
+
+// the bar is as wide as an enemy sprite and sits just above it
+const BAR_WIDTH: f32 = 2. / 3.;
+const BAR_HEIGHT: f32 = 0.08;
+const BAR_OFFSET: f32 = 0.45;
+
+// child sprites of an enemy, the fill shrinks as it loses hp
+struct HealthBar {
+    fill: bool,
+}
+
+impl Plugin for HealthBarPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(attach_health_bars.system())
+            .add_system(update_health_bars.system().after("combat"));
+    }
+}
+
+fn attach_health_bars(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    window: Res<WinSize>,
+    enemy_query: Query<Entity, Added<Enemy>>,
+) {
+    for enemy in enemy_query.iter() {
+        commands.entity(enemy).with_children(|parent| {
+            for (fill, material, z) in [
+                (false, materials.health_back.clone(), 1.),
+                (true, materials.health_fill.clone(), 2.),
+            ] {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        material,
+                        sprite: Sprite::new(Vec2::new(
+                            window.tile * BAR_WIDTH,
+                            window.tile * BAR_HEIGHT,
+                        )),
+                        transform: Transform::from_xyz(0., window.tile * BAR_OFFSET, z),
+                        // hidden until the enemy takes a hit
+                        visible: Visible {
+                            is_visible: false,
+                            is_transparent: false,
+                        },
+                        ..Default::default()
+                    })
+                    .insert(HealthBar { fill });
+            }
+        });
+    }
+}
+
+// resizes the fill from the left whenever an enemy's hp changes,
+// the bar goes away again at full health or when the enemy is out of sight
+fn update_health_bars(
+    window: Res<WinSize>,
+    enemy_query: Query<
+        (&Stats, &Children, &Visible),
+        (With<Enemy>, Or<(Changed<Stats>, Changed<Visible>)>),
+    >,
+    mut bar_query: Query<(&HealthBar, &mut Sprite, &mut Transform, &mut Visible), Without<Enemy>>,
+) {
+    for (stats, children, enemy_visible) in enemy_query.iter() {
+        let ratio = (stats.hp as f32 / stats.max_hp.max(1) as f32).clamp(0., 1.);
+        let full_width = window.tile * BAR_WIDTH;
+        for &child in children.iter() {
+            if let Ok((bar, mut sprite, mut tf, mut visible)) = bar_query.get_mut(child) {
+                visible.is_visible =
+                    enemy_visible.is_visible && stats.hp > 0 && stats.hp < stats.max_hp;
+                if bar.fill {
+                    sprite.size.x = full_width * ratio;
+                    tf.translation.x = -(full_width - sprite.size.x) / 2.;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::collapse::CollapseText;
+use crate::context::ContextText;
+use crate::gold::GoldText;
+use crate::hunger::HungerText;
+use crate::prelude::*;
+use crate::quest::QuestText;
+use crate::status::StatusEffects;
+use crate::stealth::StealthText;
+use bevy::prelude::*;
+
+pub struct HudPlugin;
+
+const HP_BAR_WIDTH: f32 = 140.;
+const HP_BAR_HEIGHT: f32 = 8.;
+
+struct HpText;
+// the red part of the hp bar, its width is the share of hp left
+struct HpBarFill;
+struct DepthText;
+struct StatusText;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system_to_stage("game_setup_actors", spawn_hud.system())
+            .add_system(update_hp.system().after("combat"))
+            .add_system(update_depth.system())
+            .add_system(update_status.system().after("expire"));
+    }
+}
+
+// one panel down the top left holds everything the player needs at a glance. the other
+// plugins keep their own lines up to date through the text markers they export
+fn spawn_hud(mut commands: Commands, font: Res<UiFont>, materials: Res<Materials>) {
+    let text = |value: &str, color: Color| TextBundle {
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: font.0.clone(),
+                font_size: 16.,
+                color,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(6.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(text("Hidden", Color::rgb(0.5, 0.7, 0.9)))
+                .insert(StealthText);
+            parent.spawn_bundle(text("HP", Color::WHITE)).insert(HpText);
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(HP_BAR_WIDTH), Val::Px(HP_BAR_HEIGHT)),
+                        margin: Rect {
+                            top: Val::Px(2.),
+                            bottom: Val::Px(4.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    material: materials.health_back.clone(),
+                    ..Default::default()
+                })
+                .with_children(|bar| {
+                    bar.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                            ..Default::default()
+                        },
+                        material: materials.health_fill.clone(),
+                        ..Default::default()
+                    })
+                    .insert(HpBarFill);
+                });
+            parent
+                .spawn_bundle(text("Depth 1  Turn 0", Color::rgb(0.75, 0.75, 0.8)))
+                .insert(DepthText);
+            parent
+                .spawn_bundle(text("Gold: 0", Color::rgb(0.95, 0.8, 0.2)))
+                .insert(GoldText);
+            parent
+                .spawn_bundle(text("Fed", Color::rgb(0.6, 0.85, 0.5)))
+                .insert(HungerText);
+            parent
+                .spawn_bundle(text("", Color::rgb(0.95, 0.6, 0.9)))
+                .insert(StatusText);
+            parent
+                .spawn_bundle(text("", Color::rgb(0.85, 0.75, 0.95)))
+                .insert(QuestText);
+            parent
+                .spawn_bundle(text("", Color::rgb(0.95, 0.45, 0.3)))
+                .insert(CollapseText);
+            parent
+                .spawn_bundle(text("", Color::rgb(0.9, 0.9, 0.7)))
+                .insert(ContextText);
+        });
+}
+
+fn update_hp(
+    player_query: Query<&Stats, (With<Player>, Changed<Stats>)>,
+    mut text_query: Query<&mut Text, With<HpText>>,
+    mut fill_query: Query<&mut Style, With<HpBarFill>>,
+) {
+    let stats = match player_query.single() {
+        Ok(stats) => stats,
+        Err(_) => return,
+    };
+    if let Ok(mut text) = text_query.single_mut() {
+        text.sections[0].value = format!("HP {}/{}", stats.hp, stats.max_hp);
+    }
+    if let Ok(mut style) = fill_query.single_mut() {
+        let share = stats.hp.max(0) as f32 / stats.max_hp.max(1) as f32;
+        style.size.width = Val::Percent(share * 100.);
+    }
+}
+
+// GameState changes every time something animates, only touch the text when it would read differently
+fn update_depth(game_state: Res<GameState>, mut text_query: Query<&mut Text, With<DepthText>>) {
+    if !game_state.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let value = format!("Depth {}  Turn {}", game_state.depth, game_state.turn);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// each active effect with the turns it has left, which count down as the turns go by
+fn update_status(
+    game_state: Res<GameState>,
+    player_query: Query<(&StatusEffects, ChangeTrackers<StatusEffects>), With<Player>>,
+    mut text_query: Query<&mut Text, With<StatusText>>,
+) {
+    let (effects, tracker) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if !tracker.is_changed() && !game_state.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let value = effects
+            .0
+            .iter()
+            .map(|(status, until)| {
+                format!(
+                    "{} ({})",
+                    status.name(),
+                    until.saturating_sub(game_state.turn)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::inventory::ItemUsedEvent;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct HungerPlugin;
+
+// a full stomach lasts this many turns
+pub const MAX_FULLNESS: u32 = 600;
+pub const RATION_FULLNESS: u32 = 300;
+// below these the player gets warned, at 0 they start to starve
+const HUNGRY_AT: u32 = 150;
+const WEAK_AT: u32 = 50;
+// starving takes 1 hp every this many turns
+const STARVE_INTERVAL: u32 = 3;
+// rations left lying around on each floor, in rooms the player doesn't start in
+const RATIONS_PER_FLOOR: (usize, usize) = (1, 2);
+
+// how many turns the player can go before they start starving
+pub struct Hunger {
+    pub turns: u32,
+    // the part of a point owed from earlier turns, for the difficulties that don't take
+    // whole ones. kept with the rest so a new run, a load or a replay don't inherit it
+    pub owed: f32,
+}
+
+impl Hunger {
+    pub fn full() -> Self {
+        Hunger {
+            turns: MAX_FULLNESS,
+            owed: 0.,
+        }
+    }
+
+    fn label(&self) -> (&'static str, Color) {
+        if self.turns == 0 {
+            ("Starving", Color::rgb(0.9, 0.2, 0.2))
+        } else if self.turns < WEAK_AT {
+            ("Weak", Color::rgb(0.95, 0.5, 0.2))
+        } else if self.turns < HUNGRY_AT {
+            ("Hungry", Color::rgb(0.95, 0.85, 0.3))
+        } else {
+            ("Fed", Color::rgb(0.6, 0.85, 0.5))
+        }
+    }
+}
+
+// the line in the hud, see hud::spawn_hud
+pub struct HungerText;
+
+impl Plugin for HungerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            spawn_rations
+                .system()
+                .label("place_rations")
+                .after("place_gold"),
+        )
+        .add_system(tick_hunger.system())
+        .add_system(eat_food.system().after("act"))
+        .add_system(update_meter.system());
+    }
+}
+
+fn spawn_rations(
+    mut commands: Commands,
+    materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "rations");
+        let count = rng.gen_range(RATIONS_PER_FLOOR.0..=RATIONS_PER_FLOOR.1);
+        for _ in 0..count {
+            let i = rng.gen_range(0..map_rooms.rooms.len());
+            if i == map_rooms.spawn_room && map_rooms.rooms.len() > 1 {
+                continue;
+            }
+            if let Some(loc) = spawn_tiles.claim_in(&map_rooms.rooms[i], &mut rng) {
+                spawn_item(
+                    &mut commands,
+                    &materials,
+                    &window,
+                    Item::new(ItemKind::Ration),
+                    loc,
+                );
+            }
+        }
+    }
+}
+
+// one point per turn, give or take for the difficulty, and once it's gone the player's
+// health goes instead
+fn tick_hunger(
+    game_state: Res<GameState>,
+    modifiers: Res<DifficultyModifiers>,
+    mut last_turn: Local<u32>,
+    mut log: ResMut<MessageLog>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut player_query: Query<(Entity, &mut Hunger, &mut Stats, &GridPos), With<Player>>,
+) {
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    if game_state.phase == TurnPhase::GameOver {
+        return;
+    }
+    let (player, mut hunger, mut stats, loc) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    hunger.owed += modifiers.hunger;
+    let points = hunger.owed.floor();
+    hunger.owed -= points;
+    if hunger.turns > 0 {
+        // a point at a time, so none of the warnings get skipped over
+        for _ in 0..(points as u32).min(hunger.turns) {
+            hunger.turns -= 1;
+            match hunger.turns {
+                HUNGRY_AT => log.add("You are getting hungry."),
+                WEAK_AT => log.add("You are weak with hunger."),
+                0 => log.add("You are starving!"),
+                _ => {}
+            }
+        }
+        return;
+    }
+    if !game_state.turn.is_multiple_of(STARVE_INTERVAL) || stats.hp <= 0 {
+        return;
+    }
+    stats.hp -= 1;
+    if stats.hp == 0 {
+        log.add("You starve to death.");
+        ev_death.send(DeathEvent {
+            entity: player,
+            killer: player,
+            location: *loc,
+        });
+    }
+}
+
+fn eat_food(
+    mut log: ResMut<MessageLog>,
+    mut ev_item_used: EventReader<ItemUsedEvent>,
+    mut hunger_query: Query<&mut Hunger>,
+) {
+    for used in ev_item_used.iter() {
+        if used.item.kind != ItemKind::Ration {
+            continue;
+        }
+        if let Ok(mut hunger) = hunger_query.get_mut(used.user) {
+            hunger.turns = (hunger.turns + RATION_FULLNESS).min(MAX_FULLNESS);
+            log.add("You eat a ration. That hit the spot.");
+        }
+    }
+}
+
+fn update_meter(
+    hunger_query: Query<&Hunger, (With<Player>, Changed<Hunger>)>,
+    mut text_query: Query<&mut Text, With<HungerText>>,
+) {
+    if let (Ok(hunger), Ok(mut text)) = (hunger_query.single(), text_query.single_mut()) {
+        let (label, color) = hunger.label();
+        text.sections[0].value = label.to_string();
+        text.sections[0].style.color = color;
+    }
+}
@@ -0,0 +1,584 @@
+use crate::item::{spawn_item, Identification, Item, ItemKind, ItemMaterials, Potion, Scroll};
+use crate::level::LevelUp;
+use crate::magic::{Casting, RevealMapEvent};
+use crate::map_view::MapView;
+use crate::message_log::{capitalize, MessageLog};
+use crate::npc::{ActiveDialogue, CHOICE_KEYS};
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use crate::throwing::{Aiming, Shot};
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+pub struct InventoryPlugin;
+
+// hp a potion of healing gives back
+const POTION_HEAL: i32 = 10;
+// how many turns the other potions last
+const STRENGTH_TURNS: u32 = 20;
+const INVISIBILITY_TURNS: u32 = 15;
+
+// what the player is carrying
+pub struct Inventory {
+    pub items: Vec<Item>,
+    pub capacity: usize,
+}
+impl Inventory {
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    // puts the item in the pack, handing it back if there's no room.
+    // arrows go in with the ones already there and don't need a free slot
+    pub fn add(&mut self, item: Item) -> Result<(), Item> {
+        if let (ItemKind::Arrows(count), Some(quiver)) = (item.kind, self.quiver()) {
+            *quiver += count;
+        } else if self.is_full() {
+            return Err(item);
+        } else {
+            self.items.push(item);
+        }
+        Ok(())
+    }
+
+    // how many arrows are left, all of them share one slot in the pack
+    pub fn quiver(&mut self) -> Option<&mut u32> {
+        self.items.iter_mut().find_map(|item| match &mut item.kind {
+            ItemKind::Arrows(count) => Some(count),
+            _ => None,
+        })
+    }
+}
+
+// one slot per kind of gear, combat adds up the bonuses on top of the actor's Stats
+#[derive(Default)]
+pub struct Equipment {
+    pub weapon: Option<Item>,
+    pub armor: Option<Item>,
+    pub ring: Option<Item>,
+}
+impl Equipment {
+    // the slot an item goes in, None if it can't be equipped
+    pub fn slot_mut(&mut self, kind: ItemKind) -> Option<&mut Option<Item>> {
+        match kind {
+            ItemKind::Weapon | ItemKind::Bow => Some(&mut self.weapon),
+            ItemKind::Armor => Some(&mut self.armor),
+            ItemKind::Ring => Some(&mut self.ring),
+            _ => None,
+        }
+    }
+
+    // total attack and defense from everything worn
+    pub fn bonus(&self) -> (i32, i32) {
+        [&self.weapon, &self.armor, &self.ring]
+            .iter()
+            .filter_map(|slot| slot.as_ref())
+            .fold((0, 0), |(attack, defense), item| {
+                let (a, d) = item.bonus();
+                (attack + a, defense + d)
+            })
+    }
+
+    // the element the weapon brands its hits with, if any
+    pub(crate) fn element(&self) -> Option<Element> {
+        self.weapon.as_ref().and_then(|item| item.element())
+    }
+
+    pub fn speed_bonus(&self) -> f32 {
+        [&self.weapon, &self.armor, &self.ring]
+            .iter()
+            .filter_map(|slot| slot.as_ref())
+            .map(|item| item.speed_bonus())
+            .sum()
+    }
+
+    // true if anything worn was actually cursed
+    pub fn uncurse(&mut self) -> bool {
+        let Equipment {
+            weapon,
+            armor,
+            ring,
+        } = self;
+        let mut lifted = false;
+        for item in [weapon, armor, ring].into_iter().flatten() {
+            lifted |= item.cursed;
+            item.cursed = false;
+        }
+        lifted
+    }
+
+    // equips the item, handing back whatever was in the slot before
+    pub fn equip(&mut self, item: Item) -> Result<Option<Item>, Item> {
+        match self.slot_mut(item.kind) {
+            Some(slot) => Ok(slot.replace(item)),
+            None => Err(item),
+        }
+    }
+}
+
+// the inventory screen, player input is blocked while it's open
+#[derive(Default)]
+pub struct InventoryScreen {
+    pub open: bool,
+    selected: usize,
+}
+
+// the player spent their turn using or equipping something from their pack
+pub struct ItemUsedEvent {
+    pub user: Entity,
+    pub item: Item,
+}
+
+// something went into the player's pack or purse
+pub struct PickUpEvent;
+
+struct InventoryPanel;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(InventoryScreen::default())
+            .add_event::<ItemUsedEvent>()
+            .add_event::<PickUpEvent>()
+            .add_system(pick_up_items.system().before("input"))
+            .add_system(inventory_input.system().label("inventory").before("input"))
+            .add_system(use_consumables.system().after("act"))
+            .add_system(update_inventory_panel.system().after("inventory"));
+    }
+}
+
+// G picks up the top item on the player's tile, it doesn't cost a turn
+fn pick_up_items(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    identification: Res<Identification>,
+    index: Res<SpatialIndex>,
+    mut log: ResMut<MessageLog>,
+    mut ev_pick_up: EventWriter<PickUpEvent>,
+    mut player_query: Query<(&GridPos, &mut Inventory), With<Player>>,
+    item_query: Query<&Item, With<OnMap>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::G)
+        || game_state.animating_actions
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    if let Ok((player_loc, mut inventory)) = player_query.single_mut() {
+        // gold is scooped up just by walking over it
+        let item = index.at(player_loc).iter().find_map(|&entity| {
+            item_query
+                .get(entity)
+                .ok()
+                .filter(|item| !matches!(item.kind, ItemKind::Gold(_)))
+                .map(|item| (entity, item))
+        });
+        let (entity, item) = match item {
+            Some(item) => item,
+            None => {
+                log.add("There's nothing here to pick up.");
+                return;
+            }
+        };
+        if inventory.add(item.clone()).is_err() {
+            log.add("Your pack is full.");
+            return;
+        }
+        log.add(format!("You pick up {}.", identification.describe(item)));
+        ev_pick_up.send(PickUpEvent);
+        commands.entity(entity).despawn();
+    }
+}
+
+// I opens the pack, up and down or a number picks an item, U uses it,
+// E equips it, T throws it, D drops it on the floor, escape or I closes the pack.
+// Q drinks a healing potion without opening the pack
+fn inventory_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<MapView>,
+    ),
+    (item_materials, window, identification): (
+        Res<ItemMaterials>,
+        Res<WinSize>,
+        Res<Identification>,
+    ),
+    mut screen: ResMut<InventoryScreen>,
+    mut aiming: ResMut<Aiming>,
+    mut log: ResMut<MessageLog>,
+    mut ev_item_used: EventWriter<ItemUsedEvent>,
+    mut player_query: Query<
+        (
+            Entity,
+            &GridPos,
+            &mut Inventory,
+            &mut Equipment,
+            &mut NextAction,
+        ),
+        With<Player>,
+    >,
+) {
+    if !screen.open {
+        let can_open = !game_state.animating_actions
+            && game_state.phase == TurnPhase::PlayerInput
+            && !dialogue.is_open()
+            && !casting.is_busy()
+            && !level_up.is_choosing()
+            && !aiming.is_busy()
+            && !map_view.open;
+        if can_open && keyboard_input.just_pressed(KeyCode::I) {
+            screen.open = true;
+            screen.selected = 0;
+        } else if can_open && keyboard_input.just_pressed(KeyCode::Q) {
+            if let Ok((_, _, inventory, _, mut action)) = player_query.single_mut() {
+                // only once the player knows which potion that is
+                let healing = ItemKind::Potion(Potion::Healing);
+                let found = inventory.items.iter().position(|item| item.kind == healing);
+                match found.filter(|_| identification.is_known(healing)) {
+                    Some(i) => action.0 = Some(Action::UseItem(i)),
+                    None => log.add("You don't have a potion of healing."),
+                }
+            }
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) || keyboard_input.just_pressed(KeyCode::I) {
+        screen.open = false;
+        return;
+    }
+    let (player, player_loc, mut inventory, mut equipment, mut action) =
+        match player_query.single_mut() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+    if inventory.items.is_empty() {
+        return;
+    }
+    let last = inventory.items.len() - 1;
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        screen.selected = screen.selected.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        screen.selected = (screen.selected + 1).min(last);
+    }
+    if let Some(i) = CHOICE_KEYS
+        .iter()
+        .take(inventory.items.len())
+        .position(|key| keyboard_input.just_pressed(*key))
+    {
+        screen.selected = i;
+    }
+    screen.selected = screen.selected.min(last);
+    let selected = screen.selected;
+
+    if keyboard_input.just_pressed(KeyCode::D) {
+        let item = inventory.items.remove(selected);
+        log.add(format!("You drop {}.", identification.describe(&item)));
+        spawn_item(&mut commands, &item_materials, &window, item, *player_loc);
+    } else if keyboard_input.just_pressed(KeyCode::U) {
+        match inventory.items[selected].kind {
+            ItemKind::Potion(_) | ItemKind::Scroll(_) | ItemKind::Ration => {
+                // using something takes the player's turn
+                screen.open = false;
+                action.0 = Some(Action::UseItem(selected));
+            }
+            _ => log.add("You can't use that."),
+        }
+    } else if keyboard_input.just_pressed(KeyCode::T) {
+        if inventory.items[selected].is_throwable() {
+            // the aiming cursor takes over from here
+            screen.open = false;
+            *aiming = Aiming::Targeting(Shot::Throw(selected));
+        } else {
+            log.add("You can't throw that.");
+        }
+    } else if keyboard_input.just_pressed(KeyCode::E) {
+        let kind = inventory.items[selected].kind;
+        if let Some(Some(worn)) = equipment.slot_mut(kind).map(|slot| &*slot) {
+            if worn.cursed {
+                log.add(format!(
+                    "{} won't come off. It's cursed!",
+                    capitalize(&worn.describe())
+                ));
+                return;
+            }
+        }
+        let item = inventory.items.remove(selected);
+        let name = item.describe();
+        let used = item.clone();
+        match equipment.equip(item) {
+            Ok(old) => {
+                // whatever was worn before takes the new item's place in the pack
+                match old {
+                    Some(old) => {
+                        log.add(format!("You swap {} for {}.", old.describe(), name));
+                        inventory.items.insert(selected, old);
+                    }
+                    None => log.add(format!("You equip {}.", name)),
+                }
+                if used.cursed {
+                    log.add("It clamps on tight. It's cursed!");
+                }
+                screen.open = false;
+                ev_item_used.send(ItemUsedEvent {
+                    user: player,
+                    item: used,
+                });
+            }
+            Err(item) => {
+                inventory.items.insert(selected, item);
+                log.add("You can't equip that.");
+            }
+        }
+    }
+}
+
+// potions and scrolls take effect once they're used up, equipment is handled on the spot
+fn use_consumables(
+    game_state: Res<GameState>,
+    window: Res<WinSize>,
+    mut identification: ResMut<Identification>,
+    mut log: ResMut<MessageLog>,
+    mut ev_item_used: EventReader<ItemUsedEvent>,
+    mut ev_reveal: EventWriter<RevealMapEvent>,
+    map_query: Query<&Map>,
+    mut user_query: Query<
+        (
+            &mut Stats,
+            &mut StatusEffects,
+            &mut GridPos,
+            &mut Transform,
+            &Inventory,
+        ),
+        With<Player>,
+    >,
+    blocker_query: Query<&GridPos, (With<BlocksMovement>, Without<Player>)>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("consumables");
+    for used in ev_item_used.iter() {
+        let (mut stats, mut effects, mut loc, mut tf, inventory) =
+            match user_query.get_mut(used.user) {
+                Ok(user) => user,
+                Err(_) => continue,
+            };
+        // using something is the surest way to find out what it is
+        identification.learn(used.item.kind);
+        let name = used.item.describe();
+        match used.item.kind {
+            ItemKind::Potion(Potion::Healing) => {
+                stats.hp = (stats.hp + POTION_HEAL).min(stats.max_hp);
+                log.add(format!("You drink {} and feel better.", name));
+            }
+            ItemKind::Potion(Potion::Strength) => {
+                effects.add(Status::Strength, game_state.turn + STRENGTH_TURNS);
+                log.add(format!("You drink {} and power surges through you.", name));
+            }
+            ItemKind::Potion(Potion::Invisibility) => {
+                effects.add(Status::Invisible, game_state.turn + INVISIBILITY_TURNS);
+                log.add(format!("You drink {} and fade from sight.", name));
+            }
+            ItemKind::Scroll(Scroll::Teleportation) => {
+                let map_data = match map_query.single() {
+                    Ok(current_map) => &current_map.0,
+                    Err(_) => continue,
+                };
+                // anywhere on open ground that nobody is standing on
+                let mut free_tiles = Vec::new();
+                for y in 0..map_data.height() {
+                    for x in 0..map_data.width() {
+                        let (x, y) = (x as i32, y as i32);
+                        let taken = (x, y) == (loc.x, loc.y)
+                            || blocker_query.iter().any(|b| (b.x, b.y) == (x, y));
+                        if map_data.is_walkable(x, y) && !taken {
+                            free_tiles.push(GridPos::new(x, y));
+                        }
+                    }
+                }
+                log.add(format!("You read {}.", name));
+                match free_tiles.choose(&mut *rng) {
+                    Some(target) => {
+                        tf.translation.x = target.x as f32 * window.tile;
+                        tf.translation.y = target.y as f32 * window.tile;
+                        *loc = *target;
+                        log.add("The world lurches and you find yourself elsewhere.");
+                    }
+                    None => log.add("Nothing happens."),
+                }
+            }
+            ItemKind::Scroll(Scroll::Identify) => {
+                log.add(format!("You read {}.", name));
+                // works out the first thing in the pack that's still a mystery
+                let unknown = inventory
+                    .items
+                    .iter()
+                    .find(|item| !identification.is_known(item.kind));
+                match unknown {
+                    Some(item) => {
+                        let looks = identification.describe(item);
+                        identification.learn(item.kind);
+                        log.add(format!(
+                            "{} turns out to be {}.",
+                            capitalize(&looks),
+                            item.describe()
+                        ));
+                    }
+                    None => log.add("You already know everything you're carrying."),
+                }
+            }
+            ItemKind::Scroll(Scroll::MagicMapping) => {
+                ev_reveal.send(RevealMapEvent);
+                log.add(format!(
+                    "You read {}. The layout of the floor floods into your mind.",
+                    name
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+// rebuilt whenever the screen, the pack or the gear changes
+fn update_inventory_panel(
+    mut commands: Commands,
+    screen: Res<InventoryScreen>,
+    identification: Res<Identification>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    panel_query: Query<Entity, With<InventoryPanel>>,
+    player_query: Query<(&Inventory, &Equipment, &Stats), With<Player>>,
+    changed_query: Query<
+        (),
+        (
+            With<Player>,
+            Or<(Changed<Inventory>, Changed<Equipment>, Changed<Stats>)>,
+        ),
+    >,
+) {
+    if !screen.is_changed() && !identification.is_changed() && changed_query.iter().next().is_none()
+    {
+        return;
+    }
+    for panel in panel_query.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    let (inventory, equipment, stats) = match player_query.single() {
+        Ok(player) if screen.open => player,
+        _ => return,
+    };
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    // base stats, what the gear adds, and what combat actually uses
+    let (attack_bonus, defense_bonus) = equipment.bonus();
+    let mut sections = vec![TextSection {
+        value: "Character\n".to_string(),
+        style: style(22., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    for (name, base, bonus) in [
+        ("Attack", stats.attack, attack_bonus),
+        ("Defense", stats.defense, defense_bonus),
+    ] {
+        sections.push(TextSection {
+            value: format!("{}: {} + {} = {}\n", name, base, bonus, base + bonus),
+            style: style(18., Color::WHITE),
+        });
+    }
+    for (name, slot) in [
+        ("Weapon", &equipment.weapon),
+        ("Armor", &equipment.armor),
+        ("Ring", &equipment.ring),
+    ] {
+        sections.push(TextSection {
+            value: format!("{}: ", name),
+            style: style(16., Color::rgb(0.7, 0.8, 0.9)),
+        });
+        let (worn, color) = slot
+            .as_ref()
+            .map_or(("-".to_string(), Color::GRAY), |item| {
+                let curse = if item.cursed { " (cursed)" } else { "" };
+                (format!("{}{}", item.describe(), curse), item.rarity.color())
+            });
+        sections.push(TextSection {
+            value: format!("{}\n", worn),
+            style: style(16., color),
+        });
+    }
+    sections.push(TextSection {
+        value: format!(
+            "\nPack ({}/{})\n",
+            inventory.items.len(),
+            inventory.capacity
+        ),
+        style: style(22., Color::rgb(0.95, 0.85, 0.4)),
+    });
+    if inventory.items.is_empty() {
+        sections.push(TextSection {
+            value: "\n(empty)".to_string(),
+            style: style(18., Color::GRAY),
+        });
+    }
+    // names are drawn in their rarity's colour, so the selection gets a marker instead
+    for (i, item) in inventory.items.iter().enumerate() {
+        let marker = if i == screen.selected { ">" } else { " " };
+        sections.push(TextSection {
+            value: format!("\n{} {}. ", marker, i + 1),
+            style: style(18., Color::WHITE),
+        });
+        sections.push(TextSection {
+            value: identification.describe(item),
+            style: style(18., item.rarity.color()),
+        });
+    }
+    sections.push(TextSection {
+        value: "\n\n[U] use  [E] equip  [T] throw  [D] drop  [Esc] close".to_string(),
+        style: style(14., Color::GRAY),
+    });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.),
+                    top: Val::Percent(20.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(12.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(InventoryPanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
@@ -0,0 +1,651 @@
+use crate::enemy::{EnemyKind, EnemyTemplates};
+use crate::message_log::{capitalize, with_article, MessageLog};
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub struct ItemPlugin;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ItemKind {
+    Gold(u32),
+    Potion(Potion),
+    Scroll(Scroll),
+    Weapon,
+    Armor,
+    Ring,
+    // thrown, and left on the floor where it lands
+    Dagger,
+    // goes in the weapon slot and shoots arrows from the pack
+    Bow,
+    Arrows(u32),
+    // opens a locked chest, used up when it does
+    Key,
+    // eaten to stave off hunger
+    Ration,
+    // a floor objective, handed in as soon as it's picked up
+    Amulet,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Potion {
+    Healing,
+    Strength,
+    Invisibility,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Scroll {
+    Teleportation,
+    MagicMapping,
+    Identify,
+}
+
+// only equipment cares about rarity, the rarer it is the more affixes it rolls
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Legendary,
+}
+
+impl Rarity {
+    // what the item's name is drawn in, and the colour of its sprite
+    pub fn color(&self) -> Color {
+        match self {
+            Rarity::Common => Color::rgb(0.7, 0.7, 0.7),
+            Rarity::Uncommon => Color::rgb(0.3, 0.85, 0.35),
+            Rarity::Rare => Color::rgb(0.3, 0.5, 0.95),
+            Rarity::Legendary => Color::rgb(1., 0.55, 0.1),
+        }
+    }
+}
+
+// extra properties rolled onto equipment. prefixes go in front of the name
+// and suffixes after it, as in "flaming sword of speed"
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Affix {
+    // prefixes. the first three are weapon brands, their hits deal the element
+    Flaming,
+    Freezing,
+    Venomous,
+    Keen,
+    Sturdy,
+    // suffixes
+    Speed,
+    Might,
+    Warding,
+}
+
+// how much faster "of speed" makes the wearer, dodging and moving
+pub const SPEED_AFFIX_BONUS: f32 = 2.;
+
+impl Affix {
+    const WEAPON_PREFIXES: [Affix; 4] = [
+        Affix::Flaming,
+        Affix::Freezing,
+        Affix::Venomous,
+        Affix::Keen,
+    ];
+    const ARMOR_PREFIXES: [Affix; 1] = [Affix::Sturdy];
+    const RING_PREFIXES: [Affix; 2] = [Affix::Keen, Affix::Sturdy];
+    const SUFFIXES: [Affix; 3] = [Affix::Speed, Affix::Might, Affix::Warding];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Affix::Flaming => "flaming",
+            Affix::Freezing => "freezing",
+            Affix::Venomous => "venomous",
+            Affix::Keen => "keen",
+            Affix::Sturdy => "sturdy",
+            Affix::Speed => "speed",
+            Affix::Might => "might",
+            Affix::Warding => "warding",
+        }
+    }
+
+    // attack and defense on top of the item's own
+    fn bonus(&self) -> (i32, i32) {
+        match self {
+            Affix::Flaming | Affix::Freezing | Affix::Venomous | Affix::Keen => (1, 0),
+            Affix::Sturdy => (0, 1),
+            Affix::Might => (2, 0),
+            Affix::Warding => (0, 2),
+            Affix::Speed => (0, 0),
+        }
+    }
+
+    fn element(&self) -> Option<Element> {
+        match self {
+            Affix::Flaming => Some(Element::Fire),
+            Affix::Freezing => Some(Element::Ice),
+            Affix::Venomous => Some(Element::Poison),
+            _ => None,
+        }
+    }
+}
+
+// something lying on the floor, or carried in an Inventory
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub rarity: Rarity,
+    pub prefix: Option<Affix>,
+    pub suffix: Option<Affix>,
+    // turns the bonus against the wearer and won't come off, only shows once it's worn
+    pub cursed: bool,
+}
+
+impl Item {
+    // a plain common item with nothing rolled on it
+    pub fn new(kind: ItemKind) -> Self {
+        Self {
+            kind,
+            rarity: Rarity::Common,
+            prefix: None,
+            suffix: None,
+            cursed: false,
+        }
+    }
+
+    // how the item reads in the message log
+    pub fn describe(&self) -> String {
+        match self.kind {
+            ItemKind::Gold(amount) => format!("{} gold", amount),
+            ItemKind::Potion(potion) => format!("a potion of {}", potion.name()),
+            ItemKind::Scroll(scroll) => format!("a scroll of {}", scroll.name()),
+            ItemKind::Weapon => self.gear_name("sword"),
+            ItemKind::Armor => self.gear_name("breastplate"),
+            ItemKind::Ring => self.gear_name("ring"),
+            ItemKind::Dagger => "a throwing dagger".to_string(),
+            ItemKind::Bow => self.gear_name("bow"),
+            ItemKind::Arrows(1) => "an arrow".to_string(),
+            ItemKind::Arrows(count) => format!("{} arrows", count),
+            ItemKind::Key => "a key".to_string(),
+            ItemKind::Ration => "a ration".to_string(),
+            ItemKind::Amulet => "the lost amulet".to_string(),
+        }
+    }
+
+    // "a rare flaming sword of speed"
+    fn gear_name(&self, base: &str) -> String {
+        let rarity = match self.rarity {
+            Rarity::Common => "a ",
+            Rarity::Uncommon => "an uncommon ",
+            Rarity::Rare => "a rare ",
+            Rarity::Legendary => "a legendary ",
+        };
+        let prefix = self
+            .prefix
+            .map_or(String::new(), |a| format!("{} ", a.name()));
+        let suffix = self
+            .suffix
+            .map_or(String::new(), |a| format!(" of {}", a.name()));
+        format!("{}{}{}{}", rarity, prefix, base, suffix)
+    }
+
+    // what can be picked with T from the pack
+    pub fn is_throwable(&self) -> bool {
+        matches!(self.kind, ItemKind::Potion(_) | ItemKind::Dagger)
+    }
+
+    // potions break open where they land instead of hitting anyone
+    pub fn shatters(&self) -> bool {
+        matches!(self.kind, ItemKind::Potion(_))
+    }
+
+    // the attack and defense it adds while equipped
+    pub fn bonus(&self) -> (i32, i32) {
+        let tier = match self.rarity {
+            Rarity::Common => 1,
+            Rarity::Uncommon => 2,
+            Rarity::Rare => 3,
+            Rarity::Legendary => 4,
+        };
+        let (attack, defense) = match self.kind {
+            // a bow's bonus goes behind its arrows
+            ItemKind::Weapon | ItemKind::Bow => (tier, 0),
+            ItemKind::Armor => (0, tier),
+            ItemKind::Ring => ((tier + 1) / 2, tier / 2),
+            _ => (0, 0),
+        };
+        let (attack, defense) =
+            [self.prefix, self.suffix]
+                .iter()
+                .flatten()
+                .fold((attack, defense), |(a, d), affix| {
+                    let (affix_a, affix_d) = affix.bonus();
+                    (a + affix_a, d + affix_d)
+                });
+        if self.cursed {
+            (-attack, -defense)
+        } else {
+            (attack, defense)
+        }
+    }
+
+    // the element its hits deal, if it's branded with one
+    pub(crate) fn element(&self) -> Option<Element> {
+        self.prefix.and_then(|affix| affix.element())
+    }
+
+    pub fn speed_bonus(&self) -> f32 {
+        if self.suffix == Some(Affix::Speed) {
+            SPEED_AFFIX_BONUS
+        } else {
+            0.
+        }
+    }
+
+    // uncommon gear gets one affix, rare and legendary get one of each
+    fn roll_affixes(&mut self, rng: &mut impl Rng) {
+        let prefixes: &[Affix] = match self.kind {
+            ItemKind::Weapon | ItemKind::Bow => &Affix::WEAPON_PREFIXES,
+            ItemKind::Armor => &Affix::ARMOR_PREFIXES,
+            ItemKind::Ring => &Affix::RING_PREFIXES,
+            _ => return,
+        };
+        let (prefix, suffix) = match self.rarity {
+            Rarity::Common => (false, false),
+            Rarity::Uncommon => {
+                let prefix = rng.gen_bool(0.5);
+                (prefix, !prefix)
+            }
+            Rarity::Rare | Rarity::Legendary => (true, true),
+        };
+        if prefix {
+            self.prefix = prefixes.choose(rng).copied();
+        }
+        if suffix {
+            self.suffix = Affix::SUFFIXES.choose(rng).copied();
+        }
+    }
+}
+
+impl ItemKind {
+    // what a typed name means, for the debug console: "dagger", "potion healing",
+    // "scroll magic mapping", "gold 50". counts default to a handful
+    pub fn parse(text: &str) -> Option<ItemKind> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (first, rest) = words.split_first()?;
+        let rest = rest.join(" ");
+        let count = || rest.parse().ok().or(rest.is_empty().then_some(10));
+        Some(match *first {
+            "gold" => ItemKind::Gold(count()?),
+            "arrows" => ItemKind::Arrows(count()?),
+            "potion" => ItemKind::Potion(*Potion::ALL.iter().find(|p| p.name() == rest)?),
+            "scroll" => ItemKind::Scroll(*Scroll::ALL.iter().find(|s| s.name() == rest)?),
+            "weapon" => ItemKind::Weapon,
+            "armor" => ItemKind::Armor,
+            "ring" => ItemKind::Ring,
+            "dagger" => ItemKind::Dagger,
+            "bow" => ItemKind::Bow,
+            "key" => ItemKind::Key,
+            "ration" => ItemKind::Ration,
+            "amulet" => ItemKind::Amulet,
+            _ => return None,
+        })
+    }
+}
+
+impl Potion {
+    const ALL: [Potion; 3] = [Potion::Healing, Potion::Strength, Potion::Invisibility];
+    // healing turns up twice as often as the others
+    const DROPS: [Potion; 4] = [
+        Potion::Healing,
+        Potion::Healing,
+        Potion::Strength,
+        Potion::Invisibility,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Potion::Healing => "healing",
+            Potion::Strength => "strength",
+            Potion::Invisibility => "invisibility",
+        }
+    }
+}
+
+impl Scroll {
+    const ALL: [Scroll; 3] = [
+        Scroll::Teleportation,
+        Scroll::MagicMapping,
+        Scroll::Identify,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scroll::Teleportation => "teleportation",
+            Scroll::MagicMapping => "magic mapping",
+            Scroll::Identify => "identify",
+        }
+    }
+}
+
+// what potions and scrolls can look like before they're identified,
+// there's always at least one spare so the last kind can't be guessed
+const POTION_LOOKS: [&str; 6] = [
+    "bubbling red",
+    "murky green",
+    "fizzing blue",
+    "cloudy white",
+    "smoky black",
+    "glittering gold",
+];
+const SCROLL_LABELS: [&str; 6] = [
+    "ZELGO MOR",
+    "KIRJE VAS",
+    "ORN THUL",
+    "PRATYAVAYAH",
+    "XIXAXA",
+    "VELOX NEB",
+];
+
+// which look goes with which potion or scroll this run, and which ones the player has worked out.
+// shuffled again for every run
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "SavedIdentification", into = "SavedIdentification")]
+pub struct Identification {
+    potions: HashMap<Potion, &'static str>,
+    scrolls: HashMap<Scroll, &'static str>,
+    known: Vec<ItemKind>,
+}
+
+impl Identification {
+    pub fn shuffled(rng: &mut impl Rng) -> Self {
+        let mut looks = POTION_LOOKS.to_vec();
+        looks.shuffle(rng);
+        let mut labels = SCROLL_LABELS.to_vec();
+        labels.shuffle(rng);
+        Self {
+            potions: Potion::ALL.iter().copied().zip(looks).collect(),
+            scrolls: Scroll::ALL.iter().copied().zip(labels).collect(),
+            known: Vec::new(),
+        }
+    }
+
+    // looks are only ever taken from the tables, so a saved one is found there by name.
+    // anything that isn't there anymore gets the same fixed stand-in every load
+    fn from_looks(
+        potions: HashMap<Potion, String>,
+        scrolls: HashMap<Scroll, String>,
+        known: Vec<ItemKind>,
+    ) -> Self {
+        let fresh = Identification::shuffled(&mut StdRng::seed_from_u64(0));
+        let find = |table: &[&'static str], look: Option<&String>| {
+            table
+                .iter()
+                .copied()
+                .find(|t| Some(*t) == look.map(|l| l.as_str()))
+        };
+        let potions_found: Option<HashMap<Potion, &'static str>> = Potion::ALL
+            .iter()
+            .map(|p| find(&POTION_LOOKS, potions.get(p)).map(|look| (*p, look)))
+            .collect();
+        let scrolls_found: Option<HashMap<Scroll, &'static str>> = Scroll::ALL
+            .iter()
+            .map(|s| find(&SCROLL_LABELS, scrolls.get(s)).map(|label| (*s, label)))
+            .collect();
+        Self {
+            potions: potions_found.unwrap_or(fresh.potions),
+            scrolls: scrolls_found.unwrap_or(fresh.scrolls),
+            known,
+        }
+    }
+
+    // only potions and scrolls ever need identifying
+    pub fn is_known(&self, kind: ItemKind) -> bool {
+        match kind {
+            ItemKind::Potion(_) | ItemKind::Scroll(_) => self.known.contains(&kind),
+            _ => true,
+        }
+    }
+
+    // true if the player didn't already know it
+    pub fn learn(&mut self, kind: ItemKind) -> bool {
+        if self.is_known(kind) {
+            return false;
+        }
+        self.known.push(kind);
+        true
+    }
+
+    // the item's real name once it's known, otherwise what it looks like
+    pub fn describe(&self, item: &Item) -> String {
+        if self.is_known(item.kind) {
+            return item.describe();
+        }
+        match item.kind {
+            ItemKind::Potion(potion) => format!("a {} potion", self.potions[&potion]),
+            ItemKind::Scroll(scroll) => format!("a scroll labelled {}", self.scrolls[&scroll]),
+            _ => item.describe(),
+        }
+    }
+}
+
+// how Identification goes into a save file
+#[derive(Serialize, Deserialize)]
+struct SavedIdentification {
+    potions: HashMap<Potion, String>,
+    scrolls: HashMap<Scroll, String>,
+    known: Vec<ItemKind>,
+}
+
+impl From<SavedIdentification> for Identification {
+    fn from(saved: SavedIdentification) -> Self {
+        Identification::from_looks(saved.potions, saved.scrolls, saved.known)
+    }
+}
+
+impl From<Identification> for SavedIdentification {
+    fn from(identification: Identification) -> Self {
+        SavedIdentification {
+            potions: (identification.potions.into_iter())
+                .map(|(potion, look)| (potion, look.to_string()))
+                .collect(),
+            scrolls: (identification.scrolls.into_iter())
+                .map(|(scroll, label)| (scroll, label.to_string()))
+                .collect(),
+            known: identification.known,
+        }
+    }
+}
+
+// one line of a loot table, gold rolls an amount between the two numbers,
+// potions and scrolls roll which kind they are
+#[derive(Deserialize, Clone, Copy)]
+pub enum LootDrop {
+    Gold(u32, u32),
+    Potion,
+    Scroll,
+    Weapon,
+    Armor,
+    Ring,
+    Dagger,
+    Bow,
+    Arrows(u32, u32),
+    Ration,
+}
+
+impl LootDrop {
+    // turns the table entry into an actual item, equipment gets better with depth
+    pub fn roll(&self, depth: u32, rng: &mut impl Rng) -> Item {
+        let kind = match *self {
+            LootDrop::Gold(min, max) => ItemKind::Gold(rng.gen_range(min..=max.max(min))),
+            LootDrop::Potion => ItemKind::Potion(*Potion::DROPS.choose(rng).unwrap()),
+            LootDrop::Scroll => ItemKind::Scroll(*Scroll::ALL.choose(rng).unwrap()),
+            LootDrop::Weapon => ItemKind::Weapon,
+            LootDrop::Armor => ItemKind::Armor,
+            LootDrop::Ring => ItemKind::Ring,
+            LootDrop::Dagger => ItemKind::Dagger,
+            LootDrop::Bow => ItemKind::Bow,
+            LootDrop::Arrows(min, max) => ItemKind::Arrows(rng.gen_range(min..=max.max(min))),
+            LootDrop::Ration => ItemKind::Ration,
+        };
+        let mut item = Item::new(kind);
+        if let ItemKind::Weapon | ItemKind::Armor | ItemKind::Ring | ItemKind::Bow = kind {
+            item.rarity = roll_rarity(depth, rng);
+            item.roll_affixes(rng);
+            item.cursed = rng.gen_bool(CURSE_CHANCE);
+        }
+        item
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LootEntry {
+    pub drop: LootDrop,
+    pub chance: f64,
+}
+
+pub struct ItemMaterials {
+    gold: Handle<ColorMaterial>,
+    potion: Handle<ColorMaterial>,
+    scroll: Handle<ColorMaterial>,
+    food: Handle<ColorMaterial>,
+    common: Handle<ColorMaterial>,
+    uncommon: Handle<ColorMaterial>,
+    rare: Handle<ColorMaterial>,
+    legendary: Handle<ColorMaterial>,
+}
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // a placeholder until a class is picked, which shuffles it from the run's seed
+        app.insert_resource(Identification::shuffled(&mut rand::thread_rng()))
+            .add_startup_system(item_materials.system())
+            .add_system(drop_loot.system().after("combat").after("log"));
+    }
+}
+
+fn item_materials(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(ItemMaterials {
+        gold: materials.add(Color::rgb(0.95, 0.8, 0.2).into()),
+        potion: materials.add(Color::rgb(0.9, 0.3, 0.5).into()),
+        scroll: materials.add(Color::rgb(0.9, 0.85, 0.7).into()),
+        food: materials.add(Color::rgb(0.6, 0.4, 0.2).into()),
+        common: materials.add(Rarity::Common.color().into()),
+        uncommon: materials.add(Rarity::Uncommon.color().into()),
+        rare: materials.add(Rarity::Rare.color().into()),
+        legendary: materials.add(Rarity::Legendary.color().into()),
+    });
+}
+
+// odds of any piece of rolled equipment being cursed
+const CURSE_CHANCE: f64 = 0.1;
+
+// better gear shows up more often the deeper the player gets
+pub fn roll_rarity(depth: u32, rng: &mut impl Rng) -> Rarity {
+    let roll: f64 = rng.gen();
+    let legendary = (0.005 * depth as f64).min(0.05);
+    let rare = (0.02 * depth as f64).min(0.3);
+    let uncommon = (0.1 + 0.04 * depth as f64).min(0.5);
+    if roll < legendary {
+        Rarity::Legendary
+    } else if roll < legendary + rare {
+        Rarity::Rare
+    } else if roll < legendary + rare + uncommon {
+        Rarity::Uncommon
+    } else {
+        Rarity::Common
+    }
+}
+
+pub fn spawn_item(
+    commands: &mut Commands,
+    materials: &ItemMaterials,
+    window: &WinSize,
+    item: Item,
+    loc: GridPos,
+) -> Entity {
+    // gold and potions are small, scrolls are flat, weapons are long and thin,
+    // armor is chunky, rings are tiny
+    let (material, size) = match item.kind {
+        ItemKind::Gold(_) => (materials.gold.clone(), Vec2::new(0.25, 0.25)),
+        ItemKind::Potion(_) => (materials.potion.clone(), Vec2::new(0.25, 0.35)),
+        ItemKind::Scroll(_) => (materials.scroll.clone(), Vec2::new(0.35, 0.2)),
+        ItemKind::Dagger => (materials.common.clone(), Vec2::new(0.1, 0.3)),
+        ItemKind::Arrows(_) => (materials.common.clone(), Vec2::new(0.3, 0.1)),
+        ItemKind::Key => (materials.gold.clone(), Vec2::new(0.25, 0.12)),
+        ItemKind::Ration => (materials.food.clone(), Vec2::new(0.3, 0.25)),
+        ItemKind::Amulet => (materials.gold.clone(), Vec2::new(0.2, 0.3)),
+        ItemKind::Weapon | ItemKind::Armor | ItemKind::Ring | ItemKind::Bow => {
+            let material = match item.rarity {
+                Rarity::Common => materials.common.clone(),
+                Rarity::Uncommon => materials.uncommon.clone(),
+                Rarity::Rare => materials.rare.clone(),
+                Rarity::Legendary => materials.legendary.clone(),
+            };
+            let size = match item.kind {
+                ItemKind::Weapon => Vec2::new(0.15, 0.5),
+                ItemKind::Bow => Vec2::new(0.2, 0.5),
+                ItemKind::Ring => Vec2::new(0.2, 0.2),
+                _ => Vec2::new(0.4, 0.4),
+            };
+            (material, size)
+        }
+    };
+    commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(size * window.tile),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(item)
+        .insert(OnMap(loc))
+        .insert(loc)
+        .id()
+}
+
+// dead enemies roll their loot table and leave the results on the tile they died on
+fn drop_loot(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    modifiers: Res<DifficultyModifiers>,
+    templates: Res<EnemyTemplates>,
+    materials: Res<ItemMaterials>,
+    window: Res<WinSize>,
+    identification: Res<Identification>,
+    mut log: ResMut<MessageLog>,
+    mut ev_death: EventReader<DeathEvent>,
+    enemy_query: Query<&EnemyKind, With<Enemy>>,
+    name_query: Query<&Name>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("loot");
+    for death in ev_death.iter() {
+        let template = match enemy_query
+            .get(death.entity)
+            .map(|kind| templates.get(kind))
+        {
+            Ok(Some(template)) => template,
+            _ => continue,
+        };
+        for entry in template.loot.iter() {
+            if !rng.gen_bool(entry.chance.clamp(0., 1.)) {
+                continue;
+            }
+            let item = entry
+                .drop
+                .roll(modifiers.loot_depth(game_state.depth), &mut *rng);
+            if let Ok(name) = name_query.get(death.entity) {
+                log.add(format!(
+                    "{} drops {}.",
+                    capitalize(&with_article(name)),
+                    identification.describe(&item)
+                ));
+            }
+            spawn_item(&mut commands, &materials, &window, item, death.location);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::inventory::InventoryScreen;
+use crate::magic::{Casting, Mana};
+use crate::map_view::MapView;
+use crate::message_log::MessageLog;
+use crate::npc::{ActiveDialogue, CHOICE_KEYS};
+use crate::prelude::*;
+use crate::throwing::Aiming;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+pub struct LevelPlugin;
+
+// max hp gained on every level, on top of whatever upgrade gets picked
+const HP_PER_LEVEL: i32 = 4;
+
+// total xp needed to go from `level` to the next one, 10, 30, 60, 100...
+pub fn xp_for_next(level: u32) -> u32 {
+    10 * level * (level + 1) / 2
+}
+
+// one of the upgrades offered on level up
+#[derive(Clone, Copy, PartialEq)]
+pub enum Perk {
+    Toughness,
+    Strength,
+    Guard,
+    Focus,
+    Fleetness,
+    FireWard,
+    IceWard,
+}
+
+impl Perk {
+    const ALL: [Perk; 7] = [
+        Perk::Toughness,
+        Perk::Strength,
+        Perk::Guard,
+        Perk::Focus,
+        Perk::Fleetness,
+        Perk::FireWard,
+        Perk::IceWard,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Perk::Toughness => "Toughness",
+            Perk::Strength => "Strength",
+            Perk::Guard => "Guard",
+            Perk::Focus => "Focus",
+            Perk::Fleetness => "Fleetness",
+            Perk::FireWard => "Fire Ward",
+            Perk::IceWard => "Ice Ward",
+        }
+    }
+
+    // what the upgrade does
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Perk::Toughness => "+6 max hp",
+            Perk::Strength => "+1 attack",
+            Perk::Guard => "+1 defense",
+            Perk::Focus => "+4 max mana",
+            Perk::Fleetness => "harder to hit",
+            Perk::FireWard => "+25% fire resistance",
+            Perk::IceWard => "+25% ice resistance",
+        }
+    }
+}
+
+// levels the player has gained but not picked an upgrade for yet,
+// player input is blocked while the choice is on screen
+#[derive(Default)]
+pub struct LevelUp {
+    pub pending: u32,
+    choices: Vec<Perk>,
+}
+impl LevelUp {
+    pub fn is_choosing(&self) -> bool {
+        !self.choices.is_empty()
+    }
+}
+
+struct LevelUpPanel;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(LevelUp::default())
+            .add_system(gain_levels.system().after("combat").after("log"))
+            .add_system(offer_perks.system().before("input"))
+            .add_system(pick_perk.system().label("perk").before("input"));
+    }
+}
+
+fn gain_levels(
+    mut level_up: ResMut<LevelUp>,
+    mut log: ResMut<MessageLog>,
+    mut player_query: Query<(&Experience, &mut Level, &mut Stats), With<Player>>,
+) {
+    if let Ok((xp, mut level, mut stats)) = player_query.single_mut() {
+        while xp.0 >= xp_for_next(level.0) {
+            level.0 += 1;
+            stats.max_hp += HP_PER_LEVEL;
+            stats.hp += HP_PER_LEVEL;
+            level_up.pending += 1;
+            log.add(format!("You reach level {}!", level.0));
+        }
+    }
+}
+
+// waits for a quiet moment on the player's turn to put three upgrades up for picking
+fn offer_perks(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    dialogue: Res<ActiveDialogue>,
+    casting: Res<Casting>,
+    inventory_screen: Res<InventoryScreen>,
+    (aiming, map_view): (Res<Aiming>, Res<MapView>),
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    mut level_up: ResMut<LevelUp>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("perks");
+    if level_up.pending == 0
+        || level_up.is_choosing()
+        || game_state.animating_actions
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    level_up.choices = Perk::ALL.choose_multiple(&mut *rng, 3).copied().collect();
+
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: "Level up! Choose an upgrade:\n".to_string(),
+        style: style(22., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    for (i, perk) in level_up.choices.iter().enumerate() {
+        sections.push(TextSection {
+            value: format!("\n{}. {}: {}", i + 1, perk.name(), perk.describe()),
+            style: style(18., Color::WHITE),
+        });
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(30.),
+                    top: Val::Percent(30.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(12.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(LevelUpPanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
+
+fn pick_perk(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut level_up: ResMut<LevelUp>,
+    mut log: ResMut<MessageLog>,
+    panel_query: Query<Entity, With<LevelUpPanel>>,
+    mut player_query: Query<(&mut Stats, &mut Mana, &mut Speed, &mut Resistances), With<Player>>,
+) {
+    if !level_up.is_choosing() {
+        return;
+    }
+    let perk = match CHOICE_KEYS
+        .iter()
+        .take(level_up.choices.len())
+        .position(|key| keyboard_input.just_pressed(*key))
+    {
+        Some(i) => level_up.choices[i],
+        None => return,
+    };
+    if let Ok((mut stats, mut mana, mut speed, mut resistances)) = player_query.single_mut() {
+        match perk {
+            Perk::Toughness => {
+                stats.max_hp += 6;
+                stats.hp += 6;
+            }
+            Perk::Strength => stats.attack += 1,
+            Perk::Guard => stats.defense += 1,
+            Perk::Focus => {
+                mana.max += 4;
+                mana.current += 4;
+            }
+            Perk::Fleetness => speed.0 += 1.,
+            Perk::FireWard => *resistances.0.entry(Element::Fire).or_insert(0) += 25,
+            Perk::IceWard => *resistances.0.entry(Element::Ice).or_insert(0) += 25,
+        }
+    }
+    log.add(format!("You gain {}.", perk.name()));
+    for panel in panel_query.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    level_up.choices.clear();
+    level_up.pending -= 1;
+}
@@ -0,0 +1,566 @@
+#![allow(unused)]
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+mod action;
+mod ai;
+mod altar;
+mod animation;
+mod aoe;
+mod audio;
+mod boss;
+mod boulder;
+mod chest;
+mod circuit;
+mod class;
+mod collapse;
+mod combat;
+mod combat_text;
+mod companion;
+mod components;
+mod console;
+pub mod content;
+mod context;
+mod corpse;
+mod data;
+mod diagnostics;
+mod enemy;
+mod events;
+mod fov;
+mod furniture;
+mod game_over;
+mod gold;
+pub mod headless;
+mod health_bar;
+mod hud;
+mod hunger;
+mod inventory;
+mod item;
+mod level;
+mod light;
+mod magic;
+mod map;
+mod map_view;
+mod menu;
+mod message_log;
+mod mouse;
+mod movement;
+mod narration;
+mod npc;
+mod palette;
+mod pathfinding;
+mod player;
+mod prelude;
+mod profile;
+mod projectile;
+mod quest;
+mod replay;
+mod resources;
+mod save;
+mod scent;
+mod scores;
+mod script;
+mod settings;
+mod spatial;
+mod status;
+mod stealth;
+mod summoner;
+mod targeting;
+mod throwing;
+mod tile;
+mod transition;
+mod turn;
+mod tutorial;
+mod unlocks;
+mod visibility;
+
+use action::ActionPlugin;
+use ai::AiPlugin;
+use altar::AltarPlugin;
+use animation::AnimationPlugin;
+use audio::AudioPlugin;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::render::camera::{Camera, CameraProjection, OrthographicProjection};
+use bevy::window::{WindowMode, WindowResized};
+use boss::BossPlugin;
+use boulder::BoulderPlugin;
+use chest::ChestPlugin;
+use circuit::CircuitPlugin;
+use class::ClassPlugin;
+use collapse::CollapsePlugin;
+use combat::CombatPlugin;
+use combat_text::CombatTextPlugin;
+use companion::CompanionPlugin;
+use console::ConsolePlugin;
+use content::{ContentPacks, ContentPlugin, DungeonContentPlugin};
+use context::ContextPlugin;
+use corpse::CorpsePlugin;
+use diagnostics::DiagnosticsOverlayPlugin;
+use enemy::EnemyPlugin;
+use fov::{tile_sprite, Explored, FieldOfView, FovPlugin};
+use furniture::FurniturePlugin;
+use game_over::GameOverPlugin;
+use gold::GoldPlugin;
+use health_bar::HealthBarPlugin;
+use hud::HudPlugin;
+use hunger::HungerPlugin;
+use inventory::InventoryPlugin;
+use item::ItemPlugin;
+use level::LevelPlugin;
+use light::{LightMap, LightPlugin};
+use magic::MagicPlugin;
+use map::MapPlugin;
+use map_view::MapViewPlugin;
+use menu::MenuPlugin;
+use message_log::MessageLogPlugin;
+use mouse::MousePlugin;
+use movement::MovementPlugin;
+use narration::NarrationPlugin;
+use npc::NpcPlugin;
+use palette::PalettePlugin;
+use player::PlayerPlugin;
+use prelude::*;
+use profile::{Profile, ProfilePlugin};
+use projectile::ProjectilePlugin;
+use quest::QuestPlugin;
+use replay::ReplayPlugin;
+use save::SavePlugin;
+use scent::ScentPlugin;
+use scores::ScoresPlugin;
+use script::ScriptPlugin;
+use settings::{Settings, SettingsPlugin};
+use spatial::SpatialPlugin;
+use status::StatusPlugin;
+use stealth::StealthPlugin;
+use summoner::SummonerPlugin;
+use targeting::TargetingPlugin;
+use throwing::ThrowingPlugin;
+use transition::TransitionPlugin;
+use turn::TurnPlugin;
+use tutorial::TutorialPlugin;
+use unlocks::{Unlocks, UnlocksPlugin};
+
+const WINDOW_HEIGHT: f32 = 600.;
+const WINDOW_WIDTH: f32 = 800.;
+const TILESET_FILE: &str = "textures/tileset.png";
+// the tileset is a grid of TILE_SIZE cells
+const TILESET_COLUMNS: usize = 8;
+const TILESET_ROWS: usize = 5;
+// animations move in steps of this many seconds, see movement::AnimationClock
+const TIME_STEP: f64 = 1. / 60.;
+// how far the map view can zoom in and out, and how much each notch or keypress zooms
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.;
+const ZOOM_STEP: f32 = 1.25;
+// how quickly a smoothed camera closes the gap to where it should be, per second
+const CAMERA_SMOOTHING: f32 = 12.;
+
+// opens the window and plays, the way the game is normally run. headless::run_script plays
+// a run without one
+pub fn run() {
+    run_with(Vec::new());
+}
+
+// the game with more content packs on top of its own, in the order given
+pub fn run_with(packs: Vec<Box<dyn DungeonContentPlugin>>) {
+    // read before the window opens, so it opens the way the player left it
+    let profile = Profile::load_last();
+    let settings = Settings::load(&profile);
+    let unlocks = Unlocks::load(&profile);
+    App::build()
+        .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.04)))
+        .insert_resource(WindowDescriptor {
+            title: "Rust Dungeon".to_string(),
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            vsync: settings.vsync,
+            mode: settings.window_mode(),
+            ..Default::default()
+        })
+        .insert_resource(settings)
+        .insert_resource(profile)
+        .insert_resource(unlocks)
+        .insert_resource(ContentPacks(packs))
+        .add_plugins(DefaultPlugins)
+        .add_plugin(GamePlugin)
+        .add_plugin(AudioPlugin)
+        .add_plugin(DiagnosticsOverlayPlugin)
+        .run();
+}
+
+// everything but the window, the renderer and the speakers, which the game logic can run
+// without. expects Settings, Profile and Unlocks to be in already
+struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(CameraCenter::default())
+            .insert_resource(CameraZoom::default())
+            .add_stage_before(CoreStage::Update, "app_state", SystemStage::parallel())
+            .add_state_to_stage("app_state", AppState::MainMenu)
+            .stage(CoreStage::Update, |stage: &mut SystemStage| {
+                stage.set_run_criteria(in_game.system())
+            })
+            .add_plugin(ContentPlugin)
+            .add_plugin(MapPlugin)
+            .add_plugin(SpatialPlugin)
+            .add_plugin(ActionPlugin)
+            .add_plugin(MovementPlugin)
+            .add_plugin(MousePlugin)
+            .add_plugin(TargetingPlugin)
+            .add_plugin(AnimationPlugin)
+            .add_plugin(LightPlugin)
+            .add_plugin(FovPlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(EnemyPlugin)
+            .add_plugin(AiPlugin)
+            .add_plugin(ScentPlugin)
+            .add_plugin(StealthPlugin)
+            .add_plugin(BossPlugin)
+            .add_plugin(SummonerPlugin)
+            .add_plugin(CompanionPlugin)
+            .add_plugin(CombatPlugin)
+            .add_plugin(CorpsePlugin)
+            .add_plugin(ItemPlugin)
+            .add_plugin(InventoryPlugin)
+            .add_plugin(GoldPlugin)
+            .add_plugin(ChestPlugin)
+            .add_plugin(CollapsePlugin)
+            .add_plugin(CircuitPlugin)
+            .add_plugin(BoulderPlugin)
+            .add_plugin(HungerPlugin)
+            .add_plugin(StatusPlugin)
+            .add_plugin(ThrowingPlugin)
+            .add_plugin(LevelPlugin)
+            .add_plugin(MagicPlugin)
+            .add_plugin(HealthBarPlugin)
+            .add_plugin(HudPlugin)
+            .add_plugin(ContextPlugin)
+            .add_plugin(CombatTextPlugin)
+            .add_plugin(MessageLogPlugin)
+            .add_plugin(ProjectilePlugin)
+            .add_plugin(TurnPlugin)
+            .add_plugin(TransitionPlugin)
+            .add_plugin(TutorialPlugin)
+            .add_plugin(NpcPlugin)
+            .add_plugin(AltarPlugin)
+            .add_plugin(ScriptPlugin)
+            .add_plugin(FurniturePlugin)
+            .add_plugin(QuestPlugin)
+            .add_plugin(MapViewPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(ProfilePlugin)
+            .add_plugin(ScoresPlugin)
+            .add_plugin(UnlocksPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(SettingsPlugin)
+            .add_plugin(PalettePlugin)
+            .add_plugin(NarrationPlugin)
+            .add_plugin(GameOverPlugin)
+            .add_plugin(ClassPlugin)
+            .add_startup_system(setup.system())
+            .add_system(zoom_input.system().before("actions"))
+            // the window still has to work from the menus
+            .add_system_to_stage("app_state", fullscreen_input.system())
+            .add_system_to_stage("app_state", resize_window.system())
+            .add_system(clamp_camera.system().label("clamp").after("actions"))
+            .add_system(update_camera.system().after("clamp"))
+            .add_system(update_map.system().after("clamp"));
+        // cheats for trying things out, not for players
+        if cfg!(debug_assertions) {
+            app.add_plugin(ConsolePlugin);
+        }
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    windows: Res<Windows>,
+) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(IsCamera);
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands.insert_resource(UiFont(asset_server.load("fonts/DejaVuSans.ttf")));
+    let tileset = TextureAtlas::from_grid(
+        asset_server.load(TILESET_FILE),
+        Vec2::new(TILE_SIZE, TILE_SIZE),
+        TILESET_COLUMNS,
+        TILESET_ROWS,
+    );
+    commands.insert_resource(Tileset(texture_atlases.add(tileset)));
+
+    commands.insert_resource(Materials {
+        projectile: materials.add(Color::rgb(0.9, 0.8, 0.5).into()),
+        danger: materials.add(Color::rgba(0.9, 0.1, 0.1, 0.45).into()),
+        panel: materials.add(Color::rgba(0.05, 0.05, 0.08, 0.85).into()),
+        spell: materials.add(Color::rgb(1., 0.45, 0.1).into()),
+        target: materials.add(Color::rgba(0.3, 0.6, 1., 0.45).into()),
+        health_back: materials.add(Color::rgb(0.25, 0.05, 0.05).into()),
+        health_fill: materials.add(Color::rgb(0.85, 0.15, 0.15).into()),
+        corpse: materials.add(Color::rgb(0.35, 0.12, 0.1).into()),
+        fountain: materials.add(Color::rgb(0.3, 0.6, 0.9).into()),
+        bookshelf: materials.add(Color::rgb(0.45, 0.3, 0.2).into()),
+        brazier: materials.add(Color::rgb(0.3, 0.3, 0.3).into()),
+        brazier_lit: materials.add(Color::rgb(1., 0.6, 0.15).into()),
+        lever: materials.add(Color::rgb(0.55, 0.5, 0.45).into()),
+        lever_pulled: materials.add(Color::rgb(0.85, 0.7, 0.3).into()),
+        plate: materials.add(Color::rgb(0.4, 0.4, 0.45).into()),
+        spikes: materials.add(Color::rgb(0.6, 0.6, 0.65).into()),
+        boulder: materials.add(Color::rgb(0.5, 0.45, 0.4).into()),
+    });
+
+    // a headless run has no window, it gets the size one would open at
+    let (w, h) = windows
+        .get_primary()
+        .map_or((WINDOW_WIDTH, WINDOW_HEIGHT), |window| {
+            (window.width(), window.height())
+        });
+    commands.insert_resource(WinSize {
+        w,
+        h,
+        tile: TILE_SIZE,
+    });
+    // window.set_position(IVec2::new(1620, 100));
+    commands.insert_resource(GameState::default());
+    commands.insert_resource(RunStats::default());
+    // picking a class reseeds it for the run
+    commands.insert_resource(GameRng::random());
+    //create empty map
+    // let mut new_map: Array2D<Tile> = Array2D::filled_with(Tile::Ground, MAP_HEIGHT, MAP_WIDTH);
+    // //line edges of map with walls
+    // for x in 0..MAP_WIDTH {
+    //     new_map.set(0, x, Tile::Wall);
+    //     new_map.set(MAP_HEIGHT - 1, x, Tile::Wall);
+    // }
+
+    // for y in 1..(MAP_HEIGHT - 1) {
+    //     new_map.set(y, 0, Tile::Wall);
+    //     new_map.set(y, MAP_WIDTH - 1, Tile::Wall);
+    // }
+
+    // commands.spawn().insert(Map(new_map));
+}
+
+// gameplay only runs while a run is in progress and not paused
+fn in_game(app_state: Res<State<AppState>>) -> ShouldRun {
+    if *app_state.current() == AppState::InGame {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+// F11 or Alt+Enter switches between a window and borderless fullscreen,
+// the settings plugin applies it and remembers it for next time
+fn fullscreen_input(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    let alt = keyboard_input.pressed(KeyCode::LAlt) || keyboard_input.pressed(KeyCode::RAlt);
+    let toggle = keyboard_input.just_pressed(KeyCode::F11)
+        || (alt && keyboard_input.just_pressed(KeyCode::Return));
+    if !toggle {
+        return;
+    }
+    settings.fullscreen = !settings.fullscreen;
+}
+
+// the tile culling works off WinSize, so it has to follow the window around
+fn resize_window(mut ev_resized: EventReader<WindowResized>, mut window: ResMut<WinSize>) {
+    if let Some(resized) = ev_resized.iter().last() {
+        if (resized.width, resized.height) != (window.w, window.h) {
+            window.w = resized.width;
+            window.h = resized.height;
+        }
+    }
+}
+
+// the mouse wheel, or - and =, zoom the map view out and in
+fn zoom_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut ev_wheel: EventReader<MouseWheel>,
+    mut zoom: ResMut<CameraZoom>,
+) {
+    let mut steps = 0.;
+    for wheel in ev_wheel.iter() {
+        steps -= wheel.y.signum();
+    }
+    if keyboard_input.just_pressed(KeyCode::Equals)
+        || keyboard_input.just_pressed(KeyCode::NumpadAdd)
+    {
+        steps -= 1.;
+    }
+    if keyboard_input.just_pressed(KeyCode::Minus)
+        || keyboard_input.just_pressed(KeyCode::NumpadSubtract)
+    {
+        steps += 1.;
+    }
+    let scale = (zoom.0 * ZOOM_STEP.powf(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+    if scale != zoom.0 {
+        zoom.0 = scale;
+    }
+}
+
+// keeps the view from scrolling past the edges of the map, and centres a map that's
+// smaller than the view
+fn clamp_camera(
+    mut camera_center: ResMut<CameraCenter>,
+    zoom: Res<CameraZoom>,
+    window: Res<WinSize>,
+    map_query: Query<&Map>,
+) {
+    if !camera_center.is_changed() && !zoom.is_changed() && !window.is_changed() {
+        return;
+    }
+    let map_data = match map_query.single() {
+        Ok(current_map) => &current_map.0,
+        Err(_) => return,
+    };
+    let clamp_axis = |center: f32, tiles: usize, view: f32| {
+        // tiles are centred on their coordinates, so the map starts half a tile before 0
+        let (low, high) = (-window.tile / 2., (tiles as f32 - 0.5) * window.tile);
+        if high - low <= view {
+            (low + high) / 2.
+        } else {
+            center.clamp(low + view / 2., high - view / 2.)
+        }
+    };
+    let x = clamp_axis(camera_center.0, map_data.width(), window.w * zoom.0);
+    let y = clamp_axis(camera_center.1, map_data.height(), window.h * zoom.0);
+    if (x, y) != (camera_center.0, camera_center.1) {
+        *camera_center = CameraCenter(x, y);
+    }
+}
+
+// with camera smoothing on the camera eases towards the centre instead of jumping to it,
+// unless it's so far off (a new floor) that gliding across would look wrong
+fn update_camera(
+    mut camera_query: Query<
+        (&mut Transform, &mut OrthographicProjection, &mut Camera),
+        With<IsCamera>,
+    >,
+    camera_center: Res<CameraCenter>,
+    zoom: Res<CameraZoom>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    window: Res<WinSize>,
+) {
+    if let Ok((mut camera_tf, mut projection, mut camera)) = camera_query.single_mut() {
+        let target = Vec2::new(camera_center.0, camera_center.1);
+        let current = camera_tf.translation.truncate();
+        if current != target {
+            let gap = target - current;
+            let next = if !settings.camera_smoothing
+                || gap.length() < 0.5
+                || gap.length() > window.w.max(window.h)
+            {
+                target
+            } else {
+                current + gap * (1. - (-CAMERA_SMOOTHING * time.delta_seconds()).exp())
+            };
+            camera_tf.translation.x = next.x;
+            camera_tf.translation.y = next.y;
+        }
+        if zoom.is_changed() {
+            // bevy only rebuilds the projection matrix when the window changes size
+            projection.scale = zoom.0;
+            camera.projection_matrix = projection.get_projection_matrix();
+        }
+    }
+}
+
+// shows the chunks around the camera and hides the rest, spawning a chunk's tiles the
+// first time it comes into view
+fn update_map(
+    mut commands: Commands,
+    camera_center: Res<CameraCenter>,
+    zoom: Res<CameraZoom>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    fov: Res<FieldOfView>,
+    light_map: Res<LightMap>,
+    settings: Res<Settings>,
+    game_state: ResMut<GameState>,
+    mut map_query: Query<(&Map, &Explored, &mut MapChunks)>,
+    mut tiles_query: Query<&mut Visible, With<MapElement>>,
+) {
+    if !game_state.has_map
+        || !(camera_center.is_changed() || zoom.is_changed() || window.is_changed())
+    {
+        return;
+    }
+    let (current_map, explored, mut chunks) = match map_query.single_mut() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    // get range of chunks to draw, the view covers more of the map when zoomed out
+    let (half_w, half_h) = (window.w * zoom.0 / 2., window.h * zoom.0 / 2.);
+    let left_bound = ((camera_center.0 - half_w) / window.tile).floor() as i32;
+    let right_bound = ((camera_center.0 + half_w) / window.tile).ceil() as i32;
+    let top_bound = ((camera_center.1 + half_h) / window.tile).ceil() as i32;
+    let bottom_bound = ((camera_center.1 - half_h) / window.tile).floor() as i32;
+    let (left_chunk, right_chunk) = (
+        left_bound.div_euclid(CHUNK_SIZE),
+        right_bound.div_euclid(CHUNK_SIZE),
+    );
+    let (bottom_chunk, top_chunk) = (
+        bottom_bound.div_euclid(CHUNK_SIZE),
+        top_bound.div_euclid(CHUNK_SIZE),
+    );
+
+    for (&(cx, cy), chunk) in chunks.0.iter_mut() {
+        let in_view =
+            (left_chunk..=right_chunk).contains(&cx) && (bottom_chunk..=top_chunk).contains(&cy);
+        if chunk.shown == in_view {
+            continue;
+        }
+        chunk.shown = in_view;
+        for &tile in chunk.tiles.iter() {
+            if let Ok(mut visible) = tiles_query.get_mut(tile) {
+                visible.is_visible = in_view;
+            }
+        }
+    }
+
+    for cy in bottom_chunk..=top_chunk {
+        for cx in left_chunk..=right_chunk {
+            if chunks.0.contains_key(&(cx, cy)) {
+                continue;
+            }
+            let mut tiles = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+            for y in cy * CHUNK_SIZE..(cy + 1) * CHUNK_SIZE {
+                for x in cx * CHUNK_SIZE..(cx + 1) * CHUNK_SIZE {
+                    let sprite = tile_sprite(
+                        &current_map.0,
+                        x,
+                        y,
+                        fov.light(x, y, &light_map),
+                        explored.is_explored(x, y),
+                        settings.palette,
+                    );
+                    let tile = commands
+                        .spawn_bundle(SpriteSheetBundle {
+                            sprite,
+                            texture_atlas: tileset.0.clone(),
+                            transform: Transform {
+                                translation: Vec3::new(
+                                    x as f32 * window.tile,
+                                    y as f32 * window.tile,
+                                    5.,
+                                ),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(MapElement)
+                        .insert(GridPos::new(x, y))
+                        .id();
+                    tiles.push(tile);
+                }
+            }
+            chunks.0.insert((cx, cy), Chunk { tiles, shown: true });
+        }
+    }
+}
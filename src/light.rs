@@ -0,0 +1,180 @@
+use crate::animation::TileAnimation;
+use crate::enemy::{spawn_enemy, EnemyKind, EnemyTemplates};
+use crate::prelude::*;
+use crate::visibility::field_of_view;
+use bevy::prelude::*;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+pub struct LightPlugin;
+
+// how far the player's own lantern reaches
+pub const PLAYER_LIGHT: i32 = 4;
+const TORCH_LIGHT: i32 = 4;
+pub const BRAZIER_LIGHT: i32 = 5;
+// odds of a room having a torch in it, the rest are left dark
+const TORCH_CHANCE: f64 = 0.5;
+
+// gives off light that falls off towards the edge of its radius
+pub struct LightSource {
+    pub radius: i32,
+    // the player's lantern doesn't count as a fixed light, shadow creatures don't mind it
+    pub carried: bool,
+}
+
+// a torch standing in a room, lights it for the whole floor
+pub struct Torch;
+
+// how brightly lit each tile is, from 0 to 1, and which tiles a fixed light reaches
+#[derive(Default)]
+pub struct LightMap {
+    levels: HashMap<(i32, i32), f32>,
+    fixed: HashSet<(i32, i32)>,
+}
+impl LightMap {
+    pub fn level(&self, x: i32, y: i32) -> f32 {
+        self.levels.get(&(x, y)).copied().unwrap_or(0.)
+    }
+
+    // no torch or brazier reaches the tile
+    pub fn is_dark(&self, loc: &GridPos) -> bool {
+        !self.fixed.contains(&(loc.x, loc.y))
+    }
+}
+
+impl Plugin for LightPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(LightMap::default())
+            .add_system(
+                spawn_torches
+                    .system()
+                    .label("place_torches")
+                    .after("furnish"),
+            )
+            .add_system(
+                update_light
+                    .system()
+                    .label("light")
+                    .after("resolve")
+                    .before("fov"),
+            );
+    }
+}
+
+pub fn spawn_torch(commands: &mut Commands, tileset: &Tileset, window: &WinSize, loc: GridPos) {
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TileSprite::Torch.sprite(),
+            texture_atlas: tileset.0.clone(),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(7.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Torch)
+        .insert(TileAnimation::torch())
+        .insert(LightSource {
+            radius: TORCH_LIGHT,
+            carried: false,
+        })
+        .insert(Name::new("torch"))
+        .insert(OnMap(loc))
+        .insert(loc);
+}
+
+// torches go in about half the rooms, and the dark ones are where shadow creatures wait
+fn spawn_torches(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    game_rng: Res<GameRng>,
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "torches");
+        let mut dark_rooms = Vec::new();
+        for (i, room) in map_rooms.rooms.iter().enumerate() {
+            // the starting room is always lit
+            if i != map_rooms.spawn_room && !rng.gen_bool(TORCH_CHANCE) {
+                dark_rooms.push(room);
+                continue;
+            }
+            let loc = match spawn_tiles.claim_in(room, &mut rng) {
+                Some(loc) => loc,
+                None => continue,
+            };
+            spawn_torch(&mut commands, &tileset, &window, loc);
+        }
+
+        let shade_depth = templates
+            .get(&EnemyKind::Shade)
+            .map_or(u32::MAX, |shade| shade.min_depth);
+        if dark_rooms.is_empty() || game_state.depth < shade_depth {
+            return;
+        }
+        let count = 1 + game_state.depth as usize / 3;
+        for _ in 0..count {
+            let room = dark_rooms[rng.gen_range(0..dark_rooms.len())];
+            if let Some(loc) = spawn_tiles.claim_in(room, &mut rng) {
+                spawn_enemy(
+                    &mut commands,
+                    &templates,
+                    &modifiers,
+                    &tileset,
+                    &window,
+                    EnemyKind::Shade,
+                    loc,
+                );
+            }
+        }
+    }
+}
+
+// recomputed whenever a light moves, appears or goes out
+fn update_light(
+    mut light_map: ResMut<LightMap>,
+    map_query: Query<&Map>,
+    new_map_query: Query<(), Added<Map>>,
+    moved_query: Query<
+        (),
+        (
+            With<LightSource>,
+            Or<(Changed<GridPos>, Added<LightSource>)>,
+        ),
+    >,
+    removed: RemovedComponents<LightSource>,
+    light_query: Query<(&LightSource, &GridPos)>,
+) {
+    if new_map_query.iter().next().is_none()
+        && moved_query.iter().next().is_none()
+        && removed.iter().next().is_none()
+    {
+        return;
+    }
+    let map_data = match map_query.single() {
+        Ok(current_map) => &current_map.0,
+        Err(_) => return,
+    };
+    let mut levels: HashMap<(i32, i32), f32> = HashMap::new();
+    let mut fixed = HashSet::new();
+    for (light, source) in light_query.iter() {
+        let r = light.radius;
+        for loc in field_of_view(map_data, source, r) {
+            let (dx, dy) = (loc.x - source.x, loc.y - source.y);
+            let distance = ((dx * dx + dy * dy) as f32).sqrt();
+            // full brightness at the source, fading to a glimmer at the edge
+            let level = (1. - distance / (r as f32 + 1.)).max(0.1);
+            let entry = levels.entry((loc.x, loc.y)).or_insert(0.);
+            *entry = entry.max(level);
+            if !light.carried {
+                fixed.insert((loc.x, loc.y));
+            }
+        }
+    }
+    *light_map = LightMap { levels, fixed };
+}
@@ -0,0 +1,451 @@
+use crate::aoe::{spawn_highlight, AoeShape};
+use crate::inventory::InventoryScreen;
+use crate::level::LevelUp;
+use crate::map_view::MapView;
+use crate::message_log::MessageLog;
+use crate::npc::{ActiveDialogue, CHOICE_KEYS};
+use crate::prelude::*;
+use crate::targeting::TargetCursor;
+use crate::throwing::Aiming;
+use crate::visibility::is_visible;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct MagicPlugin;
+
+// a point of mana comes back every this many turns
+const MANA_REGEN_TURNS: u32 = 3;
+const FIREBOLT_POWER: i32 = 7;
+const FIREBALL_POWER: i32 = 5;
+const LIGHTNING_POWER: i32 = 6;
+const FROST_CONE_POWER: i32 = 4;
+const HEAL_AMOUNT: i32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Spell {
+    Firebolt,
+    Fireball,
+    Lightning,
+    FrostCone,
+    Blink,
+    Heal,
+    MagicMapping,
+}
+
+impl Spell {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Spell::Firebolt => "Firebolt",
+            Spell::Fireball => "Fireball",
+            Spell::Lightning => "Lightning",
+            Spell::FrostCone => "Cone of Cold",
+            Spell::Blink => "Blink",
+            Spell::Heal => "Heal",
+            Spell::MagicMapping => "Magic Mapping",
+        }
+    }
+
+    pub fn cost(&self) -> i32 {
+        match self {
+            Spell::Firebolt => 3,
+            Spell::Fireball => 6,
+            Spell::Lightning => 5,
+            Spell::FrostCone => 5,
+            Spell::Blink => 4,
+            Spell::Heal => 5,
+            Spell::MagicMapping => 8,
+        }
+    }
+
+    // how far away a tile can be picked, None for spells that don't need a target
+    pub fn range(&self) -> Option<i32> {
+        match self {
+            Spell::Firebolt | Spell::Fireball | Spell::Lightning => Some(6),
+            Spell::FrostCone => Some(3),
+            Spell::Blink => Some(5),
+            Spell::Heal | Spell::MagicMapping => None,
+        }
+    }
+
+    pub fn area(&self) -> Option<AoeShape> {
+        match self {
+            Spell::Fireball => Some(AoeShape::Circle(1)),
+            Spell::Lightning => Some(AoeShape::Line(6)),
+            Spell::FrostCone => Some(AoeShape::Cone(3)),
+            _ => None,
+        }
+    }
+
+    // the kind of damage it deals, None for spells that don't hurt anything
+    pub fn element(&self) -> Option<Element> {
+        match self {
+            Spell::Firebolt | Spell::Fireball => Some(Element::Fire),
+            Spell::Lightning => Some(Element::Arcane),
+            Spell::FrostCone => Some(Element::Ice),
+            _ => None,
+        }
+    }
+
+    // the tiles to tint while aiming, and the ones an area spell hits
+    pub fn affected_tiles(
+        &self,
+        map_data: &DungeonMap,
+        caster: &GridPos,
+        target: &GridPos,
+    ) -> Vec<GridPos> {
+        match self.area() {
+            Some(shape) => shape.tiles(map_data, caster, target),
+            None => vec![*target],
+        }
+    }
+}
+
+pub struct Mana {
+    pub max: i32,
+    pub current: i32,
+}
+
+// spells the player knows, in the order they were learned
+pub struct Spellbook(pub Vec<Spell>);
+
+impl Spellbook {
+    // false if it was already known
+    pub fn learn(&mut self, spell: Spell) -> bool {
+        if self.0.contains(&spell) {
+            return false;
+        }
+        self.0.push(spell);
+        true
+    }
+}
+
+// the spell menu and targeting cursor, player input is blocked while either is up
+pub enum Casting {
+    Idle,
+    Choosing,
+    // aiming with the shared TargetCursor
+    Targeting(Spell),
+}
+impl Casting {
+    pub fn is_busy(&self) -> bool {
+        !matches!(self, Casting::Idle)
+    }
+}
+
+// the player used up their turn on a spell
+pub struct SpellCastEvent {
+    pub caster: Entity,
+    pub spell: Spell,
+}
+
+// magic mapping went off, the whole floor should be marked as explored
+pub struct RevealMapEvent;
+
+struct SpellMenu;
+// one tinted tile of the targeting overlay
+struct SpellTarget;
+
+impl Plugin for MagicPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Casting::Idle)
+            .add_event::<SpellCastEvent>()
+            .add_event::<RevealMapEvent>()
+            // after player input, so the key that confirms a spell can't also move the player
+            .add_system(spell_input.system().label("cast").after("input"))
+            .add_system(regen_mana.system());
+    }
+}
+
+fn spawn_menu(
+    commands: &mut Commands,
+    font: &UiFont,
+    materials: &Materials,
+    mana: &Mana,
+    spellbook: &Spellbook,
+) {
+    let style = |color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: 18.,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: format!("Mana {}/{}\n", mana.current, mana.max),
+        style: style(Color::rgb(0.4, 0.6, 1.)),
+    }];
+    for (i, spell) in spellbook.0.iter().enumerate() {
+        // spells the player can't afford are greyed out
+        let color = if spell.cost() <= mana.current {
+            Color::WHITE
+        } else {
+            Color::GRAY
+        };
+        sections.push(TextSection {
+            value: format!("\n{}. {} ({})", i + 1, spell.name(), spell.cost()),
+            style: style(color),
+        });
+    }
+    sections.push(TextSection {
+        value: "\n\n[Esc] cancel".to_string(),
+        style: TextStyle {
+            font_size: 14.,
+            ..style(Color::GRAY)
+        },
+    });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(12.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(SpellMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
+
+// C opens the spellbook, a number picks a spell, and spells with a range
+// need a tile picked with the arrow keys and confirmed with enter or space
+fn spell_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    (font, materials, window): (Res<UiFont>, Res<Materials>, Res<WinSize>),
+    mut casting: ResMut<Casting>,
+    mut target_cursor: ResMut<TargetCursor>,
+    mut log: ResMut<MessageLog>,
+    (mut ev_fire, mut ev_attack, mut ev_cast, mut ev_reveal): (
+        EventWriter<FireProjectileEvent>,
+        EventWriter<AttackEvent>,
+        EventWriter<SpellCastEvent>,
+        EventWriter<RevealMapEvent>,
+    ),
+    map_query: Query<&Map>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut GridPos,
+            &mut Transform,
+            &mut Stats,
+            &mut Mana,
+            &Spellbook,
+        ),
+        With<Player>,
+    >,
+    blocker_query: Query<(Entity, &GridPos), (With<BlocksMovement>, Without<Player>)>,
+    menu_query: Query<Entity, With<SpellMenu>>,
+    overlay_query: Query<Entity, With<SpellTarget>>,
+) {
+    let (player, mut player_loc, mut player_tf, mut stats, mut mana, spellbook) =
+        match player_query.single_mut() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+    let map_data = match map_query.single() {
+        Ok(current_map) => &current_map.0,
+        Err(_) => return,
+    };
+    let close_menu = |commands: &mut Commands| {
+        for menu in menu_query.iter() {
+            commands.entity(menu).despawn_recursive();
+        }
+    };
+    let clear_overlay = |commands: &mut Commands| {
+        for tile in overlay_query.iter() {
+            commands.entity(tile).despawn();
+        }
+    };
+    // tint every tile the spell would hit if it went off at the cursor
+    let draw_overlay = |commands: &mut Commands, spell: Spell, cursor: &GridPos| {
+        for loc in spell.affected_tiles(map_data, &player_loc, cursor) {
+            let tile = spawn_highlight(commands, materials.target.clone(), &window, &loc, 12.);
+            commands.entity(tile).insert(SpellTarget);
+        }
+    };
+
+    // the spell that should go off this frame, and where
+    let mut cast: Option<(Spell, Option<GridPos>)> = None;
+    match &mut *casting {
+        Casting::Idle => {
+            let can_act = !game_state.animating_actions
+                && game_state.has_map
+                && game_state.phase == TurnPhase::PlayerInput
+                && !dialogue.is_open()
+                && !level_up.is_choosing()
+                && !inventory_screen.open
+                && !aiming.is_busy()
+                && !map_view.open;
+            if can_act && keyboard_input.just_pressed(KeyCode::C) {
+                spawn_menu(&mut commands, &font, &materials, &mana, spellbook);
+                *casting = Casting::Choosing;
+            }
+            return;
+        }
+        Casting::Choosing => {
+            if keyboard_input.just_pressed(KeyCode::Escape) {
+                close_menu(&mut commands);
+                *casting = Casting::Idle;
+                return;
+            }
+            let spell = match CHOICE_KEYS
+                .iter()
+                .take(spellbook.0.len())
+                .position(|key| keyboard_input.just_pressed(*key))
+            {
+                Some(i) => spellbook.0[i],
+                None => return,
+            };
+            if spell.cost() > mana.current {
+                log.add(format!("Not enough mana for {}.", spell.name()));
+                return;
+            }
+            close_menu(&mut commands);
+            if spell.range().is_some() {
+                draw_overlay(&mut commands, spell, &player_loc);
+                target_cursor.show(&player_loc, spell.range());
+                *casting = Casting::Targeting(spell);
+            } else {
+                cast = Some((spell, None));
+            }
+        }
+        Casting::Targeting(spell) => {
+            let cursor = match target_cursor.tile() {
+                Some(cursor) => *cursor,
+                None => return,
+            };
+            if keyboard_input.just_pressed(KeyCode::Escape) {
+                clear_overlay(&mut commands);
+                target_cursor.hide();
+                *casting = Casting::Idle;
+                return;
+            }
+            if keyboard_input.just_pressed(KeyCode::Return)
+                || keyboard_input.just_pressed(KeyCode::Space)
+            {
+                cast = Some((*spell, Some(cursor)));
+            } else {
+                // the cursor keeps itself within the spell's range
+                if target_cursor.is_changed() {
+                    clear_overlay(&mut commands);
+                    draw_overlay(&mut commands, *spell, &cursor);
+                }
+                return;
+            }
+        }
+    }
+
+    let (spell, target) = match cast {
+        Some(cast) => cast,
+        None => return,
+    };
+    clear_overlay(&mut commands);
+    target_cursor.hide();
+    *casting = Casting::Idle;
+    match (spell, target) {
+        (Spell::Firebolt, Some(target)) => {
+            if (target.x, target.y) == (player_loc.x, player_loc.y) {
+                return;
+            }
+            ev_fire.send(FireProjectileEvent {
+                source: player,
+                from: *player_loc,
+                to: target,
+                power: Some(FIREBOLT_POWER),
+                element: spell.element(),
+                payload: None,
+            });
+        }
+        (Spell::Fireball, Some(target))
+        | (Spell::Lightning, Some(target))
+        | (Spell::FrostCone, Some(target)) => {
+            let tiles = spell.affected_tiles(map_data, &player_loc, &target);
+            if tiles.is_empty() {
+                return;
+            }
+            let power = match spell {
+                Spell::Fireball => FIREBALL_POWER,
+                Spell::Lightning => LIGHTNING_POWER,
+                _ => FROST_CONE_POWER,
+            };
+            // anything standing in the area gets hit, companions included
+            for (entity, loc) in blocker_query.iter() {
+                if tiles.iter().any(|tile| (tile.x, tile.y) == (loc.x, loc.y)) {
+                    ev_attack.send(AttackEvent {
+                        attacker: player,
+                        target: entity,
+                        ranged: true,
+                        power: Some(power),
+                        element: spell.element(),
+                    });
+                }
+            }
+        }
+        (Spell::Blink, Some(target)) => {
+            // only onto open ground the player can see, with nobody standing on it
+            let is_ground = map_data.is_walkable(target.x, target.y);
+            let is_free = !blocker_query
+                .iter()
+                .any(|(_, loc)| (loc.x, loc.y) == (target.x, target.y));
+            if !is_ground || !is_free || !is_visible(map_data, &player_loc, &target) {
+                log.add("You can't blink there.");
+                return;
+            }
+            player_tf.translation.x = target.x as f32 * window.tile;
+            player_tf.translation.y = target.y as f32 * window.tile;
+            *player_loc = target;
+        }
+        (Spell::Heal, _) => {
+            stats.hp = (stats.hp + HEAL_AMOUNT).min(stats.max_hp);
+            log.add("Warmth spreads through your wounds.");
+        }
+        (Spell::MagicMapping, _) => {
+            ev_reveal.send(RevealMapEvent);
+            log.add("The layout of the floor floods into your mind.");
+        }
+        _ => return,
+    }
+    mana.current -= spell.cost();
+    ev_cast.send(SpellCastEvent {
+        caster: player,
+        spell,
+    });
+}
+
+// mana trickles back as turns go by
+fn regen_mana(
+    game_state: Res<GameState>,
+    mut last_turn: Local<u32>,
+    mut mana_query: Query<&mut Mana>,
+) {
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    if !game_state.turn.is_multiple_of(MANA_REGEN_TURNS) {
+        return;
+    }
+    for mut mana in mana_query.iter_mut() {
+        mana.current = (mana.current + 1).min(mana.max);
+    }
+}
@@ -1,10 +1,13 @@
-use crate::{
-    FinishedMapEvent, GameState, Location, Map, MapElement, MapStyle, Materials, OnMap, Stairs,
-    Tile, WinSize,
-};
-use array2d::Array2D;
+use crate::animation::TileAnimation;
+use crate::fov::Explored;
+use crate::prelude::*;
+use crate::tutorial::TutorialFloor;
 use bevy::prelude::*;
-use rand::{thread_rng, Rng};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use rand::Rng;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 pub struct MapPlugin;
 
@@ -18,6 +21,7 @@ struct Room {
     height: u32,
 }
 
+#[derive(Clone)]
 struct MapMaker {
     columns: u32,
     rows: u32,
@@ -27,15 +31,13 @@ struct MapMaker {
     // style: MapStyle,
 }
 
-// REMINDER: Array2D get/set is rows then columns (y, x)
 impl MapMaker {
-    fn make(&mut self) -> (Map, Location) {
-        let mut new_map: Array2D<Tile> = Array2D::filled_with(
+    fn make(&mut self, rng: &mut impl Rng) -> (Map, GridPos, MapRooms) {
+        let mut new_map = DungeonMap::filled(
             Tile::Wall,
-            self.map_height as usize,
             self.map_width as usize,
+            self.map_height as usize,
         );
-        let mut rng = thread_rng();
         let mut all_rooms: Vec<Room> = Vec::new();
         let mut connections: Vec<(u32, u32)> = Vec::new();
         let sector_width: u32 = self.map_width / self.columns;
@@ -80,7 +82,7 @@ impl MapMaker {
         for y in 0..self.rows {
             for x in 0..self.columns {
                 let curr_id = x + self.columns * y;
-                if real_rooms.iter().any(|&id| id == curr_id) {
+                if real_rooms.contains(&curr_id) {
                     let room_width = rng.gen_range(5..sector_width - 2);
                     let room_height = rng.gen_range(4..sector_height - 2);
                     let room_left = rng.gen_range(2..sector_width - room_width);
@@ -170,34 +172,34 @@ impl MapMaker {
             for &id in cluster.iter() {
                 if (id + 1) % self.columns != 0 {
                     let right = id + 1;
-                    if !already_has_connection(&connections, id, right) {
-                        if cluster.iter().all(|&id| id != right) {
-                            potential_connection.push((id, right));
-                        }
+                    if !already_has_connection(&connections, id, right)
+                        && cluster.iter().all(|&id| id != right)
+                    {
+                        potential_connection.push((id, right));
                     }
                 }
                 if id % self.columns != 0 {
                     let left = id - 1;
-                    if !already_has_connection(&connections, id, left) {
-                        if cluster.iter().all(|&id| id != left) {
-                            potential_connection.push((id, left));
-                        }
+                    if !already_has_connection(&connections, id, left)
+                        && cluster.iter().all(|&id| id != left)
+                    {
+                        potential_connection.push((id, left));
                     }
                 }
                 if id < self.columns * (self.rows - 1) {
                     let up = id + self.columns;
-                    if !already_has_connection(&connections, id, up) {
-                        if cluster.iter().all(|&id| id != up) {
-                            potential_connection.push((id, up));
-                        }
+                    if !already_has_connection(&connections, id, up)
+                        && cluster.iter().all(|&id| id != up)
+                    {
+                        potential_connection.push((id, up));
                     }
                 }
                 if id >= self.columns {
                     let down = id - self.columns;
-                    if !already_has_connection(&connections, id, down) {
-                        if cluster.iter().all(|&id| id != down) {
-                            potential_connection.push((id, down));
-                        }
+                    if !already_has_connection(&connections, id, down)
+                        && cluster.iter().all(|&id| id != down)
+                    {
+                        potential_connection.push((id, down));
                     }
                 }
             }
@@ -218,8 +220,8 @@ impl MapMaker {
 
         // now, draw all the rooms that are in the complete cluster
         for room in all_rooms.iter() {
-            if cluster.iter().any(|&id| id == room.id) {
-                make_room(&mut new_map, &room);
+            if cluster.contains(&room.id) {
+                make_room(&mut new_map, room);
                 // } else {
                 //     println!(
                 //         "Skipping room {} because it's not connected to anything",
@@ -242,7 +244,7 @@ impl MapMaker {
                     // 10% chance of merging if they aren't already merged elsewhere
                     if can_merge_id[id1 as usize] && can_merge_id[id2 as usize] && rng.gen_bool(0.1)
                     {
-                        merge_rooms(&mut new_map, &room1, &room2);
+                        merge_rooms(&mut new_map, room1, room2);
                         can_merge_id[id1 as usize] = false;
                         can_merge_id[id2 as usize] = false;
                     }
@@ -254,8 +256,8 @@ impl MapMaker {
                         let xright: i32 = room2.left as i32;
                         let random_yright: i32 =
                             (room2.bottom + rng.gen_range(0..room2.height)) as i32;
-                        let point1: Location = Location(xleft, random_yleft);
-                        let point2: Location = Location(xright, random_yright);
+                        let point1: GridPos = GridPos::new(xleft, random_yleft);
+                        let point2: GridPos = GridPos::new(xright, random_yright);
                         // println!(
                         //     "Drawing horizontal connection between {}, {} and {}, {}",
                         //     point1.0, point1.1, point2.0, point2.1
@@ -268,8 +270,8 @@ impl MapMaker {
                             (room1.left + rng.gen_range(0..room1.width)) as i32;
                         let ytop: i32 = room2.bottom as i32;
                         let random_xtop: i32 = (room2.left + rng.gen_range(0..room2.width)) as i32;
-                        let point1: Location = Location(random_xbottom, ybottom);
-                        let point2: Location = Location(random_xtop, ytop);
+                        let point1: GridPos = GridPos::new(random_xbottom, ybottom);
+                        let point2: GridPos = GridPos::new(random_xtop, ytop);
                         // println!(
                         //     "Drawing vertical connection between {}, {} and {}, {}",
                         //     point1.0, point1.1, point2.0, point2.1
@@ -286,6 +288,20 @@ impl MapMaker {
         //     real_rooms.len() - 1,
         //     spawn_room_id
         // );
+        // hand the real rooms back so other plugins can place things inside them
+        let map_rooms = MapRooms {
+            rooms: all_rooms
+                .iter()
+                .filter(|r| !r.dummy)
+                .map(|r| RoomArea {
+                    left: r.left as i32,
+                    bottom: r.bottom as i32,
+                    width: r.width as i32,
+                    height: r.height as i32,
+                })
+                .collect(),
+            spawn_room: pick_spawn,
+        };
         if let Some(spawn_room) = all_rooms.iter().find(|&r| r.id == spawn_room_id) {
             let random_spawn_x = spawn_room.left + rng.gen_range(0..spawn_room.width);
             let random_spawn_y = spawn_room.bottom + rng.gen_range(0..spawn_room.height);
@@ -296,32 +312,38 @@ impl MapMaker {
                 (
                     Map(
                         new_map,
-                        Location(random_spawn_x as i32, random_spawn_y as i32),
+                        GridPos::new(random_spawn_x as i32, random_spawn_y as i32),
                     ),
-                    Location(random_exit_x as i32, random_exit_y as i32),
+                    GridPos::new(random_exit_x as i32, random_exit_y as i32),
+                    map_rooms,
                 )
             } else {
                 (
                     Map(
                         new_map,
-                        Location(random_spawn_x as i32, random_spawn_y as i32),
+                        GridPos::new(random_spawn_x as i32, random_spawn_y as i32),
                     ),
-                    Location(random_spawn_x as i32, random_spawn_y as i32),
+                    GridPos::new(random_spawn_x as i32, random_spawn_y as i32),
+                    map_rooms,
                 )
             }
         } else {
-            (Map(new_map, Location::default()), Location::default())
+            (
+                Map(new_map, GridPos::default()),
+                GridPos::default(),
+                map_rooms,
+            )
         }
     }
 }
 
-fn already_has_connection(conn_list: &Vec<(u32, u32)>, id1: u32, id2: u32) -> bool {
+fn already_has_connection(conn_list: &[(u32, u32)], id1: u32, id2: u32) -> bool {
     conn_list
         .iter()
         .any(|&(e1, e2)| (e1 == id1 && e2 == id2) || (e1 == id2 && e2 == id1))
 }
 
-fn get_cluster(conn_list: &Vec<(u32, u32)>, start: u32) -> Vec<u32> {
+fn get_cluster(conn_list: &[(u32, u32)], start: u32) -> Vec<u32> {
     let mut cluster: Vec<u32> = vec![start];
     let mut cluster_size: usize = 0;
     let mut max_id: u32 = start;
@@ -341,7 +363,7 @@ fn get_cluster(conn_list: &Vec<(u32, u32)>, start: u32) -> Vec<u32> {
     cluster
 }
 
-fn has_all(cluster: &Vec<u32>, rooms: &Vec<u32>) -> bool {
+fn has_all(cluster: &[u32], rooms: &[u32]) -> bool {
     // println!("Testing cluster:");
     // for i in cluster.iter() {
     //     print!("{}, ", i);
@@ -356,12 +378,14 @@ fn has_all(cluster: &Vec<u32>, rooms: &Vec<u32>) -> bool {
     rooms.iter().all(|&id| cluster_iter.any(|&rid| rid == id))
 }
 
-fn make_room(map: &mut Array2D<Tile>, room: &Room) {
+fn make_room(map: &mut DungeonMap, room: &Room) {
     for y in 0..room.height {
         for x in 0..room.width {
-            let real_x: usize = (x + room.left) as usize;
-            let real_y: usize = (y + room.bottom) as usize;
-            map.set(real_y, real_x, Tile::Ground);
+            map.set_tile(
+                (x + room.left) as i32,
+                (y + room.bottom) as i32,
+                Tile::Ground,
+            );
         }
     }
     // println!(
@@ -370,63 +394,82 @@ fn make_room(map: &mut Array2D<Tile>, room: &Room) {
     // );
 }
 
-fn merge_rooms(map: &mut Array2D<Tile>, room1: &Room, room2: &Room) {
+fn merge_rooms(map: &mut DungeonMap, room1: &Room, room2: &Room) {
     let big_left = room1.left.min(room2.left);
     let big_bottom = room1.bottom.min(room2.bottom);
     let big_right = (room1.left + room1.width).max(room2.left + room2.width);
     let big_top = (room1.bottom + room1.height).max(room2.bottom + room2.height);
     for y in big_bottom..big_top {
         for x in big_left..big_right {
-            map.set(y as usize, x as usize, Tile::Ground);
+            map.set_tile(x as i32, y as i32, Tile::Ground);
         }
     }
 }
 
 // make sure to pass point arguments left to right, and bridge_x is between the two points
 fn make_corridor_horizontal(
-    map: &mut Array2D<Tile>,
-    point1: &Location,
-    point2: &Location,
+    map: &mut DungeonMap,
+    point1: &GridPos,
+    point2: &GridPos,
     bridge_x: i32,
 ) {
-    for x in point1.0..=bridge_x {
-        map.set(point1.1 as usize, x as usize, Tile::Ground);
+    for x in point1.x..=bridge_x {
+        map.set_tile(x, point1.y, Tile::Ground);
     }
-    for x in bridge_x..=point2.0 {
-        map.set(point2.1 as usize, x as usize, Tile::Ground);
+    for x in bridge_x..=point2.x {
+        map.set_tile(x, point2.y, Tile::Ground);
     }
-    if point1.1 < point2.1 {
-        for y in point1.1..=point2.1 {
-            map.set(y as usize, bridge_x as usize, Tile::Ground);
+    if point1.y < point2.y {
+        for y in point1.y..=point2.y {
+            map.set_tile(bridge_x, y, Tile::Ground);
         }
-    } else if point1.1 > point2.1 {
-        for y in point2.1..=point1.1 {
-            map.set(y as usize, bridge_x as usize, Tile::Ground);
+    } else if point1.y > point2.y {
+        for y in point2.y..=point1.y {
+            map.set_tile(bridge_x, y, Tile::Ground);
         }
     }
 }
 
 // make sure to pass point arguments bottom to top, and bridge_y is between the two points
-fn make_corridor_vertical(
-    map: &mut Array2D<Tile>,
-    point1: &Location,
-    point2: &Location,
-    bridge_y: i32,
-) {
-    for y in point1.1..=bridge_y {
-        map.set(y as usize, point1.0 as usize, Tile::Ground);
+fn make_corridor_vertical(map: &mut DungeonMap, point1: &GridPos, point2: &GridPos, bridge_y: i32) {
+    for y in point1.y..=bridge_y {
+        map.set_tile(point1.x, y, Tile::Ground);
     }
-    for y in bridge_y..=point2.1 {
-        map.set(y as usize, point2.0 as usize, Tile::Ground);
+    for y in bridge_y..=point2.y {
+        map.set_tile(point2.x, y, Tile::Ground);
     }
-    if point1.0 < point2.0 {
-        for x in point1.0..=point2.0 {
-            map.set(bridge_y as usize, x as usize, Tile::Ground);
+    if point1.x < point2.x {
+        for x in point1.x..=point2.x {
+            map.set_tile(x, bridge_y, Tile::Ground);
+        }
+    } else if point1.x > point2.x {
+        for x in point2.x..=point1.x {
+            map.set_tile(x, bridge_y, Tile::Ground);
         }
-    } else if point1.0 > point2.0 {
-        for x in point2.0..=point1.0 {
-            map.set(bridge_y as usize, x as usize, Tile::Ground);
+    }
+}
+
+// how big a floor is and how it's cut up into rooms, one for each FloorLayout
+#[derive(Clone, Debug)]
+pub struct LayoutStyle {
+    pub width: u32,
+    pub height: u32,
+    // the grid of sectors that each hold at most one room
+    pub columns: RangeInclusive<u32>,
+    pub rows: RangeInclusive<u32>,
+}
+
+// built from the content packs (see content.rs)
+pub struct FloorStyles(HashMap<FloorLayout, LayoutStyle>);
+
+impl FloorStyles {
+    pub fn new(styles: HashMap<FloorLayout, LayoutStyle>) -> Result<Self, String> {
+        for layout in FloorLayout::ALL.iter() {
+            if !styles.contains_key(layout) {
+                return Err(format!("no style for {} floors", layout.name()));
+            }
         }
+        Ok(FloorStyles(styles))
     }
 }
 
@@ -439,47 +482,95 @@ impl Plugin for MapPlugin {
             map_height: 32,
             map_width: 56,
         })
-        .add_startup_stage("game_setup_map", SystemStage::single(create_map.system()))
+        .insert_resource(SpawnTiles::default())
+        .insert_resource(MapTask::default())
+        .insert_resource(FloorLayout::default())
         .add_event::<FinishedMapEvent>()
         .add_system(cleanup_map.system().label("cleanup").after("actions"))
         .add_system(create_map.system().after("cleanup"));
     }
 }
 
+// the next floor, built off the main thread so the game keeps drawing in the meantime
+#[derive(Default)]
+pub struct MapTask {
+    // the seed and depth it's for, a new run or a load can make it stale before it's done
+    building: Option<((u64, u32), Task<(Map, GridPos, MapRooms)>)>,
+    // a headless run waits for the floor in the frame it's asked for, so it plays out the
+    // same however long building it takes
+    pub wait: bool,
+}
+
 fn create_map(
     mut commands: Commands,
     mut map_maker: ResMut<MapMaker>,
     mut game_state: ResMut<GameState>,
-    materials: Res<Materials>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    tileset: Res<Tileset>,
     window: Res<WinSize>,
+    (layout, floor_styles): (Res<FloorLayout>, Res<FloorStyles>),
+    game_rng: Res<GameRng>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut task: ResMut<MapTask>,
 ) {
-    if !game_state.has_map {
-        let mut rng = thread_rng();
-        let c: u32 = rng.gen_range(3..=4);
-        let r: u32 = rng.gen_range(2..=4);
+    // the tutorial floor is laid out by hand, see tutorial.rs
+    if game_state.has_map || game_state.tutorial {
+        task.building = None;
+        return;
+    }
+    let floor = (game_rng.seed(), game_state.depth);
+    if task.building.as_ref().map(|(f, _)| *f) != Some(floor) {
+        let mut rng = game_rng.floor(game_state.depth, "map");
+        let style = &floor_styles.0[&*layout];
+        map_maker.map_width = style.width;
+        map_maker.map_height = style.height;
+        let c = rng.gen_range(style.columns.clone());
+        let r = rng.gen_range(style.rows.clone());
         map_maker.columns = c;
         map_maker.rows = r;
         map_maker.rooms = rng.gen_range(2..=c * r);
-        let (map, exit) = map_maker.make();
-        commands.spawn().insert(map);
-        commands
-            .spawn_bundle(SpriteBundle {
-                material: materials.exit.clone(),
-                sprite: Sprite::new(Vec2::new(window.tile * 7. / 8., window.tile * 7. / 8.)),
-                transform: Transform {
-                    translation: Vec3::new(
-                        exit.0 as f32 * window.tile,
-                        exit.1 as f32 * window.tile,
-                        6.,
-                    ),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(Stairs)
-            .insert(OnMap(exit));
-        game_state.has_map = true;
+        let mut maker = map_maker.clone();
+        let building = pool.spawn(async move { maker.make(&mut rng) });
+        task.building = Some((floor, building));
     }
+    let wait = task.wait;
+    let built = match &mut task.building {
+        Some((_, building)) if wait => Some(future::block_on(building)),
+        Some((_, building)) => future::block_on(future::poll_once(building)),
+        None => None,
+    };
+    let (map, exit, map_rooms) = match built {
+        Some(built) => built,
+        None => return,
+    };
+    task.building = None;
+    // nothing else gets placed on the player's spawn or the stairs
+    *spawn_tiles = SpawnTiles(vec![map.1, exit]);
+    let explored = Explored::new(&map.0);
+    commands
+        .spawn()
+        .insert(map)
+        .insert(map_rooms)
+        .insert(explored)
+        .insert(MapChunks::default());
+    spawn_stairs(&mut commands, &tileset, &window, exit);
+    game_state.has_map = true;
+}
+
+pub fn spawn_stairs(commands: &mut Commands, tileset: &Tileset, window: &WinSize, loc: GridPos) {
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TileSprite::Stairs.sprite(),
+            texture_atlas: tileset.0.clone(),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(6.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Stairs)
+        .insert(TileAnimation::stairs())
+        .insert(OnMap(loc));
 }
 
 fn cleanup_map(
@@ -487,11 +578,19 @@ fn cleanup_map(
     mut ev_finished_map: EventReader<FinishedMapEvent>,
     mut game_state: ResMut<GameState>,
     map_query: Query<Entity, With<Map>>,
+    tutorial_query: Query<(), With<TutorialFloor>>,
     object_query: Query<Entity, With<OnMap>>,
     tiles_query: Query<Entity, With<MapElement>>,
 ) {
     for ev in ev_finished_map.iter() {
         game_state.has_map = false;
+        // the tutorial comes before the first floor and doesn't count as one. a new run
+        // started from it goes back to depth 0 first
+        if game_state.depth > 0 && tutorial_query.iter().next().is_some() {
+            game_state.tutorial = false;
+        } else {
+            game_state.depth += 1;
+        }
         for obj_entity in object_query.iter() {
             commands.entity(obj_entity).despawn();
         }
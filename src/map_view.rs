@@ -0,0 +1,372 @@
+use crate::altar::Altar;
+use crate::fov::Explored;
+use crate::inventory::InventoryScreen;
+use crate::level::LevelUp;
+use crate::light::Torch;
+use crate::magic::Casting;
+use crate::npc::{ActiveDialogue, Npc, NpcLibrary};
+use crate::prelude::*;
+use crate::settings::{Palette, Settings};
+use crate::throwing::Aiming;
+use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, FilterMode, TextureDimension, TextureFormat};
+
+pub struct MapViewPlugin;
+
+// each tile is a square of this many pixels in the map texture, enough room to frame the cursor
+const PX_PER_TILE: usize = 4;
+// share of the window the map is allowed to fill
+const FIT_WIDTH: f32 = 0.9;
+const FIT_HEIGHT: f32 = 0.75;
+
+const FLOOR_COLOR: [u8; 4] = [70, 70, 78, 255];
+const WALL_COLOR: [u8; 4] = [150, 45, 45, 255];
+const CHASM_COLOR: [u8; 4] = [15, 12, 20, 255];
+const DOOR_COLOR: [u8; 4] = [120, 95, 60, 255];
+const BRIDGE_COLOR: [u8; 4] = [150, 110, 70, 255];
+const PLAYER_COLOR: [u8; 4] = [80, 230, 80, 255];
+const CURSOR_COLOR: [u8; 4] = [255, 240, 120, 255];
+
+// the whole floor at once, scaled to fit the window. input is blocked while it's open,
+// and a cursor picks out remembered tiles to look at
+#[derive(Default)]
+pub struct MapView {
+    pub open: bool,
+    cursor: GridPos,
+    texture: Option<Handle<Texture>>,
+}
+
+struct MapViewPanel;
+struct MapViewText;
+
+// something on the floor worth marking on the map, once its tile has been explored
+#[derive(Clone, Copy, PartialEq)]
+enum Feature {
+    Stairs,
+    Shop,
+    Npc,
+    Altar,
+    Chest,
+    Fixture,
+    Torch,
+}
+
+impl Feature {
+    fn color(&self) -> [u8; 4] {
+        match self {
+            Feature::Stairs => [235, 235, 235, 255],
+            Feature::Shop => [255, 210, 50, 255],
+            Feature::Npc => [90, 200, 230, 255],
+            Feature::Altar => [190, 110, 240, 255],
+            Feature::Chest => [170, 110, 40, 255],
+            Feature::Fixture => [90, 140, 230, 255],
+            Feature::Torch => [255, 150, 40, 255],
+        }
+    }
+}
+
+impl Plugin for MapViewPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(MapView::default())
+            .add_system(map_view_input.system().before("input"))
+            .add_system(draw_map_view.system().after("input"));
+    }
+}
+
+// M opens the map, the arrow keys move the cursor, M or escape close it again
+fn map_view_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+    ),
+    mut map_view: ResMut<MapView>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, With<Player>>,
+) {
+    if !map_view.open {
+        if keyboard_input.just_pressed(KeyCode::M)
+            && !game_state.animating_actions
+            && game_state.phase == TurnPhase::PlayerInput
+            && !dialogue.is_open()
+            && !casting.is_busy()
+            && !level_up.is_choosing()
+            && !inventory_screen.open
+            && !aiming.is_busy()
+        {
+            if let Ok(player_loc) = player_query.single() {
+                map_view.open = true;
+                map_view.cursor = *player_loc;
+            }
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::M) || keyboard_input.just_pressed(KeyCode::Escape) {
+        map_view.open = false;
+        return;
+    }
+    let (mut dx, mut dy) = (0, 0);
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        dx -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) {
+        dx += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        dy += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        dy -= 1;
+    }
+    if (dx, dy) == (0, 0) {
+        return;
+    }
+    if let Ok(current_map) = map_query.single() {
+        let map_data = &current_map.0;
+        let x = (map_view.cursor.x + dx).clamp(0, map_data.width() as i32 - 1);
+        let y = (map_view.cursor.y + dy).clamp(0, map_data.height() as i32 - 1);
+        map_view.cursor = GridPos::new(x, y);
+    }
+}
+
+// builds the panel when the map opens, redraws it when the cursor moves,
+// and takes it down again when it closes
+fn draw_map_view(
+    mut commands: Commands,
+    mut map_view: ResMut<MapView>,
+    (font, materials, window, library, settings): (
+        Res<UiFont>,
+        Res<Materials>,
+        Res<WinSize>,
+        Res<NpcLibrary>,
+        Res<Settings>,
+    ),
+    mut textures: ResMut<Assets<Texture>>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    map_query: Query<(&Map, &Explored)>,
+    player_query: Query<&GridPos, With<Player>>,
+    feature_query: Query<
+        (
+            &OnMap,
+            Option<&Name>,
+            Option<&Stairs>,
+            Option<&Npc>,
+            Option<&Altar>,
+            Option<&Torch>,
+        ),
+        Or<(With<Stairs>, With<Interactable>, With<Torch>)>,
+    >,
+    panel_query: Query<Entity, With<MapViewPanel>>,
+    mut text_query: Query<&mut Text, With<MapViewText>>,
+) {
+    if !map_view.is_changed() {
+        return;
+    }
+    if !map_view.open {
+        for panel in panel_query.iter() {
+            commands.entity(panel).despawn_recursive();
+        }
+        map_view.texture = None;
+        return;
+    }
+    let (current_map, explored) = match map_query.single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let map_data = &current_map.0;
+
+    // features on explored tiles, named for the cursor readout
+    let features: Vec<(GridPos, Feature, String)> = feature_query
+        .iter()
+        .filter(|(on_map, ..)| explored.is_explored(on_map.0.x, on_map.0.y))
+        .map(|(on_map, name, stairs, npc, altar, torch)| {
+            let feature = match (stairs, npc, altar, torch) {
+                (Some(_), ..) => Feature::Stairs,
+                (_, _, Some(_), _) => Feature::Altar,
+                (_, Some(npc), ..) if library.0[npc.0].is_shop() => Feature::Shop,
+                (_, Some(_), ..) => Feature::Npc,
+                (.., Some(_)) => Feature::Torch,
+                _ if name.is_some_and(|n| n.as_str() == "chest") => Feature::Chest,
+                _ => Feature::Fixture,
+            };
+            let label = match (feature, name) {
+                (Feature::Stairs, _) => "the stairs down".to_string(),
+                // npcs go by proper names
+                (_, Some(name)) if name.as_str().starts_with(char::is_uppercase) => {
+                    name.as_str().to_string()
+                }
+                (_, Some(name)) => format!("a {}", name.as_str()),
+                (_, None) => "something".to_string(),
+            };
+            (on_map.0, feature, label)
+        })
+        .collect();
+    let player_loc = player_query.single().ok();
+    let data = paint_map(
+        map_data,
+        explored,
+        &features,
+        player_loc,
+        &map_view.cursor,
+        settings.palette,
+    );
+
+    let cursor = &map_view.cursor;
+    let readout = if !explored.is_explored(cursor.x, cursor.y) {
+        "Unexplored.".to_string()
+    } else {
+        let tile = map_data
+            .tile_at(cursor.x, cursor.y)
+            .map_or("Floor", Tile::describe);
+        let here: Vec<&str> = features
+            .iter()
+            .filter(|(loc, ..)| (loc.x, loc.y) == (cursor.x, cursor.y))
+            .map(|(_, _, label)| label.as_str())
+            .collect();
+        if here.is_empty() {
+            format!("{}.", tile)
+        } else {
+            format!("{}, with {}.", tile, here.join(" and "))
+        }
+    };
+
+    // already up, just swap in the new pixels
+    if let Some(texture) = map_view.texture.as_ref().and_then(|h| textures.get_mut(h)) {
+        texture.data = data;
+        if let Ok(mut text) = text_query.single_mut() {
+            text.sections[0].value = readout;
+        }
+        return;
+    }
+
+    let (columns, rows) = (map_data.width(), map_data.height());
+    let mut texture = Texture::new(
+        Extent3d::new(
+            (columns * PX_PER_TILE) as u32,
+            (rows * PX_PER_TILE) as u32,
+            1,
+        ),
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    texture.sampler.mag_filter = FilterMode::Nearest;
+    let texture = textures.add(texture);
+    map_view.texture = Some(texture.clone());
+    let scale = (window.w * FIT_WIDTH / columns as f32).min(window.h * FIT_HEIGHT / rows as f32);
+    let text_style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(MapViewPanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(ImageBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Px(columns as f32 * scale),
+                        Val::Px(rows as f32 * scale),
+                    ),
+                    ..Default::default()
+                },
+                material: color_materials.add(texture.into()),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        readout,
+                        text_style(18., Color::WHITE),
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MapViewText);
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "[Arrows] move cursor  [M/Esc] close",
+                    text_style(14., Color::GRAY),
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+// one square of PX_PER_TILE pixels per tile, top row first. unexplored tiles are left clear
+fn paint_map(
+    map_data: &DungeonMap,
+    explored: &Explored,
+    features: &[(GridPos, Feature, String)],
+    player_loc: Option<&GridPos>,
+    cursor: &GridPos,
+    palette: Palette,
+) -> Vec<u8> {
+    let (columns, rows) = (map_data.width(), map_data.height());
+    let width = columns * PX_PER_TILE;
+    let mut data = vec![0; width * rows * PX_PER_TILE * 4];
+    let mut fill = |x: i32, y: i32, color: [u8; 4], border_only: bool| {
+        if !map_data.in_bounds(x, y) {
+            return;
+        }
+        let color = recolor(palette, color);
+        let top = (rows - 1 - y as usize) * PX_PER_TILE;
+        let left = x as usize * PX_PER_TILE;
+        for py in 0..PX_PER_TILE {
+            for px in 0..PX_PER_TILE {
+                let edge = py == 0 || px == 0 || py == PX_PER_TILE - 1 || px == PX_PER_TILE - 1;
+                if border_only && !edge {
+                    continue;
+                }
+                let i = ((top + py) * width + left + px) * 4;
+                data[i..i + 4].copy_from_slice(&color);
+            }
+        }
+    };
+    for y in 0..rows as i32 {
+        for x in 0..columns as i32 {
+            if !explored.is_explored(x, y) {
+                continue;
+            }
+            match map_data.tile_at(x, y) {
+                Some(Tile::Ground) => fill(x, y, FLOOR_COLOR, false),
+                Some(Tile::Wall) => fill(x, y, WALL_COLOR, false),
+                Some(Tile::Chasm) => fill(x, y, CHASM_COLOR, false),
+                Some(Tile::Door) => fill(x, y, DOOR_COLOR, false),
+                Some(Tile::Bridge) => fill(x, y, BRIDGE_COLOR, false),
+                None => {}
+            }
+        }
+    }
+    for (loc, feature, _) in features.iter() {
+        fill(loc.x, loc.y, feature.color(), false);
+    }
+    if let Some(loc) = player_loc {
+        fill(loc.x, loc.y, PLAYER_COLOR, false);
+    }
+    fill(cursor.x, cursor.y, CURSOR_COLOR, true);
+    data
+}
+
+// the palette works in colours, the texture in bytes
+fn recolor(palette: Palette, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    let color = palette.tint(Color::rgba_u8(r, g, b, a));
+    [color.r(), color.g(), color.b(), color.a()].map(|c| (c * 255.).round() as u8)
+}
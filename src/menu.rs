@@ -0,0 +1,285 @@
+use crate::class::SeedEntry;
+use crate::enemy::EnemyTemplates;
+use crate::inventory::InventoryScreen;
+use crate::level::LevelUp;
+use crate::magic::Casting;
+use crate::map_view::MapView;
+use crate::npc::ActiveDialogue;
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::replay::{load_replay, Replay};
+use crate::save::{has_save, load_save, LoadRequest};
+use crate::settings::Settings;
+use crate::throwing::Aiming;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+pub struct MenuPlugin;
+
+// the title screen and the pause screen, both full-screen panels
+struct MenuScreen;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_enter(AppState::MainMenu).with_system(show_main_menu.system()),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_update(AppState::MainMenu).with_system(main_menu_input.system()),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_exit(AppState::MainMenu).with_system(close_menu.system()),
+        )
+        // back from the profile screen, possibly on another profile, or the high scores
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_resume(AppState::MainMenu)
+                .with_system(close_menu.system().label("close_menu"))
+                .with_system(show_main_menu.system().after("close_menu")),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_update(AppState::InGame).with_system(pause_input.system()),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_enter(AppState::Paused).with_system(show_pause_menu.system()),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_update(AppState::Paused).with_system(paused_input.system()),
+        )
+        .add_system_set_to_stage(
+            "app_state",
+            SystemSet::on_exit(AppState::Paused).with_system(close_menu.system()),
+        );
+    }
+}
+
+fn show_main_menu(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    profile: Res<Profile>,
+    settings: Res<Settings>,
+    templates: Res<EnemyTemplates>,
+) {
+    spawn_main_menu(
+        &mut commands,
+        &font,
+        &materials,
+        &profile,
+        &settings,
+        &templates,
+    );
+}
+
+fn spawn_main_menu(
+    commands: &mut Commands,
+    font: &UiFont,
+    materials: &Materials,
+    profile: &Profile,
+    settings: &Settings,
+    templates: &EnemyTemplates,
+) {
+    let continue_option = if !has_save(profile) {
+        String::new()
+    } else {
+        match load_save(profile, templates) {
+            Ok(_) => "[C] continue\n".to_string(),
+            Err(e) => format!("The save can't be loaded, {}.\n", e),
+        }
+    };
+    // the last run, fresh or quit part way, can be watched again
+    let replay_option = if load_replay(profile).is_ok() {
+        "[R] watch the last run\n"
+    } else {
+        ""
+    };
+    let tutorial = if settings.tutorial { "on" } else { "off" };
+    let options = format!(
+        "Profile: {}\n\n{}[Enter] new game\n[T] tutorial: {}\n{}[P] profiles\n[H] high scores\n[S] settings\n[Q] quit",
+        profile.name, continue_option, tutorial, replay_option
+    );
+    spawn_menu(commands, font, materials, "Rust Dungeon", &options);
+}
+
+// the seed is there to be written down and played again
+fn show_pause_menu(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    game_rng: Res<GameRng>,
+) {
+    let options = format!(
+        "Seed: {}\n\n[Esc] resume\n[S] settings\n[Q] quit to the main menu",
+        game_rng.seed()
+    );
+    spawn_menu(&mut commands, &font, &materials, "Paused", &options);
+}
+
+fn spawn_menu(
+    commands: &mut Commands,
+    font: &UiFont,
+    materials: &Materials,
+    title: &str,
+    options: &str,
+) {
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let sections = vec![
+        TextSection {
+            value: format!("{}\n\n", title),
+            style: style(40., Color::rgb(0.95, 0.85, 0.4)),
+        },
+        TextSection {
+            value: options.to_string(),
+            style: style(20., Color::WHITE),
+        },
+    ];
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections,
+                        alignment: TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .insert(Narrated);
+        });
+}
+
+// a new game starts on the class screen, which is part of the run. continuing hands the
+// save over to be restored as the run starts
+fn main_menu_input(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut load_request: ResMut<LoadRequest>,
+    mut replay: ResMut<Replay>,
+    profile: Res<Profile>,
+    mut settings: ResMut<Settings>,
+    (font, materials, templates): (Res<UiFont>, Res<Materials>, Res<EnemyTemplates>),
+    mut ev_exit: EventWriter<AppExit>,
+    screen_query: Query<Entity, With<MenuScreen>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        // the same switch as the one on the settings screen
+        settings.tutorial = !settings.tutorial;
+        for screen in screen_query.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+        spawn_main_menu(
+            &mut commands,
+            &font,
+            &materials,
+            &profile,
+            &settings,
+            &templates,
+        );
+    } else if keyboard_input.just_pressed(KeyCode::C) {
+        if let Ok(save) = load_save(&profile, &templates) {
+            keyboard_input.reset(KeyCode::C);
+            load_request.0 = Some(save);
+            app_state.set(AppState::InGame).ok();
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        keyboard_input.reset(KeyCode::Return);
+        app_state.set(AppState::InGame).ok();
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        if let Ok(run) = load_replay(&profile) {
+            keyboard_input.reset(KeyCode::R);
+            replay.play(run);
+            app_state.set(AppState::InGame).ok();
+        }
+    } else if keyboard_input.just_pressed(KeyCode::P) {
+        app_state.push(AppState::Profiles).ok();
+    } else if keyboard_input.just_pressed(KeyCode::H) {
+        app_state.push(AppState::HighScores).ok();
+    } else if keyboard_input.just_pressed(KeyCode::S) {
+        app_state.push(AppState::Settings).ok();
+    } else if keyboard_input.just_pressed(KeyCode::Q) {
+        ev_exit.send(AppExit);
+    }
+}
+
+// escape pauses, unless it's about to close whatever menu or prompt is open instead.
+// the state change runs the next state's systems in the same frame, so a key that
+// switches state is used up to keep it from switching straight back
+fn pause_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    (seed_entry, replay): (Res<SeedEntry>, Res<Replay>),
+    mut app_state: ResMut<State<AppState>>,
+) {
+    // a replay plays back the Escape that paused it, but never pauses itself
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        && game_state.phase != TurnPhase::GameOver
+        && !replay.is_playing()
+        && !seed_entry.is_typing()
+        && !dialogue.is_open()
+        && !casting.is_busy()
+        && !level_up.is_choosing()
+        && !inventory_screen.open
+        && !aiming.is_busy()
+        && !map_view.open
+    {
+        keyboard_input.reset(KeyCode::Escape);
+        app_state.push(AppState::Paused).ok();
+    }
+}
+
+// quitting abandons the run, the next new game starts over from the class screen
+fn paused_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut game_state: ResMut<GameState>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        app_state.pop().ok();
+    } else if keyboard_input.just_pressed(KeyCode::S) {
+        app_state.push(AppState::Settings).ok();
+    } else if keyboard_input.just_pressed(KeyCode::Q) {
+        keyboard_input.reset(KeyCode::Q);
+        game_state.phase = TurnPhase::NewGame;
+        app_state.replace(AppState::MainMenu).ok();
+    }
+}
+
+fn close_menu(mut commands: Commands, screen_query: Query<Entity, With<MenuScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
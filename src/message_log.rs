@@ -0,0 +1,197 @@
+use crate::companion::Companion;
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct MessageLogPlugin;
+
+// lines shown at once in the panel
+const VISIBLE_LINES: usize = 6;
+
+// everything that has happened this run, newest last
+#[derive(Default)]
+pub struct MessageLog {
+    messages: Vec<String>,
+    // how many lines back from the newest the panel is scrolled
+    scroll: usize,
+}
+
+impl MessageLog {
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+
+    // new messages snap the panel back to the bottom
+    pub fn add(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+        self.scroll = 0;
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.messages.len().saturating_sub(VISIBLE_LINES)
+    }
+}
+
+struct MessageLogText;
+
+impl Plugin for MessageLogPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(MessageLog::default())
+            .add_startup_system_to_stage("game_setup_actors", spawn_log_panel.system())
+            .add_system(log_combat.system().label("log").after("combat"))
+            .add_system(scroll_log.system())
+            .add_system(update_log_panel.system());
+    }
+}
+
+// "rat" becomes "the rat", names that are already capitalized are left alone
+pub fn with_article(name: &Name) -> String {
+    let name = name.as_str();
+    if name == "you" || name.starts_with(|c: char| c.is_uppercase()) {
+        name.to_string()
+    } else {
+        format!("the {}", name)
+    }
+}
+
+pub fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn log_combat(
+    mut log: ResMut<MessageLog>,
+    mut ev_hit: EventReader<HitEvent>,
+    mut ev_death: EventReader<DeathEvent>,
+    name_query: Query<&Name>,
+    player_query: Query<(), With<Player>>,
+    companion_query: Query<(), With<Companion>>,
+) {
+    for hit in ev_hit.iter() {
+        if let (Ok(attacker), Ok(target)) =
+            (name_query.get(hit.attacker), name_query.get(hit.target))
+        {
+            // "you hit" but "the rat hits"
+            let you = player_query.get(hit.attacker).is_ok();
+            let verb = |own: &'static str, other: &'static str| if you { own } else { other };
+            let attacker = capitalize(&with_article(attacker));
+            let target = with_article(target);
+            // "for 4" on a plain hit, "for 2 fire damage (resisted 50%)" when elements come into it,
+            // with a note when the target caught it on their guard
+            let mut amount = hit.damage.to_string();
+            if hit.element != Element::Physical {
+                amount = format!("{} {} damage", amount, hit.element.name());
+            }
+            if hit.resisted > 0 {
+                amount = format!("{} (resisted {}%)", amount, hit.resisted);
+            } else if hit.resisted < 0 {
+                amount = format!("{} (vulnerable +{}%)", amount, -hit.resisted);
+            }
+            if hit.blocked {
+                amount = format!("{} (blocked)", amount);
+            }
+            if hit.backstab {
+                amount = format!("{} (sneak attack)", amount);
+            } else if hit.flanked {
+                amount = format!("{} (flanked)", amount);
+            }
+            log.add(match hit.outcome {
+                HitOutcome::Miss => format!("{} {} {}.", attacker, verb("miss", "misses"), target),
+                HitOutcome::Hit => format!(
+                    "{} {} {} for {}.",
+                    attacker,
+                    verb("hit", "hits"),
+                    target,
+                    amount
+                ),
+                HitOutcome::Critical => format!(
+                    "{} {} a critical hit on {} for {}!",
+                    attacker,
+                    verb("land", "lands"),
+                    target,
+                    amount
+                ),
+            });
+        }
+    }
+    for death in ev_death.iter() {
+        if let Ok(name) = name_query.get(death.entity) {
+            if player_query.get(death.entity).is_ok() {
+                log.add("You collapse.");
+            } else if companion_query.get(death.entity).is_ok() {
+                log.add(format!(
+                    "{} is knocked out!",
+                    capitalize(&with_article(name))
+                ));
+            } else {
+                log.add(format!("{} dies.", capitalize(&with_article(name))));
+            }
+        }
+    }
+}
+
+// page up goes back through older messages, page down comes back towards the newest
+fn scroll_log(keyboard_input: Res<Input<KeyCode>>, mut log: ResMut<MessageLog>) {
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        log.scroll = (log.scroll + VISIBLE_LINES).min(log.max_scroll());
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        log.scroll = log.scroll.saturating_sub(VISIBLE_LINES);
+    }
+}
+
+fn spawn_log_panel(mut commands: Commands, font: Res<UiFont>, materials: Res<Materials>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(60.), Val::Auto),
+                padding: Rect::all(Val::Px(6.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        max_size: Size::new(Val::Percent(100.), Val::Undefined),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 14.,
+                            color: Color::rgb(0.85, 0.85, 0.85),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MessageLogText);
+        });
+}
+
+fn update_log_panel(log: Res<MessageLog>, mut text_query: Query<&mut Text, With<MessageLogText>>) {
+    if !log.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let end = log.messages.len() - log.scroll;
+        let start = end.saturating_sub(VISIBLE_LINES);
+        let mut lines = log.messages[start..end].join("\n");
+        if log.scroll > 0 {
+            lines.push_str(&format!("\n-- {} newer, page down --", log.scroll));
+        }
+        text.sections[0].value = lines;
+    }
+}
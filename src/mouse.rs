@@ -0,0 +1,371 @@
+use crate::aoe::spawn_highlight;
+use crate::components::Direction;
+use crate::enemy::Disguised;
+use crate::fov::{Explored, FieldOfView};
+use crate::inventory::InventoryScreen;
+use crate::level::LevelUp;
+use crate::magic::Casting;
+use crate::map_view::MapView;
+use crate::message_log::MessageLog;
+use crate::npc::ActiveDialogue;
+use crate::pathfinding::{find_path, DijkstraMap};
+use crate::prelude::*;
+use crate::replay::Replay;
+use crate::spatial::SpatialIndex;
+use crate::throwing::Aiming;
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+pub struct MousePlugin;
+
+// the dots marking the way to the hovered tile, as a share of a tile
+const PATH_DOT_SIZE: f32 = 0.2;
+
+// the map tile under the mouse pointer, if the pointer is over the window
+#[derive(Default)]
+pub struct HoveredTile(pub Option<GridPos>);
+
+// the rest of a path the player clicked, walked one step per turn until it runs out
+// or something worth stopping for happens
+#[derive(Default)]
+pub struct AutoWalk {
+    path: Vec<GridPos>,
+    // auto-explore plans the next leg each time a path runs out, until there's nowhere new
+    exploring: bool,
+    // hp when the last step was taken, losing any stops the walk
+    hp: i32,
+    // enemies already in view when the walk started, only new ones stop it
+    seen: HashSet<Entity>,
+}
+impl AutoWalk {
+    pub fn is_walking(&self) -> bool {
+        !self.path.is_empty()
+    }
+
+    fn stop(&mut self) {
+        self.path.clear();
+        self.exploring = false;
+    }
+}
+
+// the hover highlight and the path preview
+struct HoverMarker;
+
+impl Plugin for MousePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(HoveredTile::default())
+            .insert_resource(AutoWalk::default())
+            .add_system(hover_tile.system().label("hover").before("input"))
+            .add_system(click_to_move.system().after("hover").before("auto_walk"))
+            .add_system(auto_walk.system().label("auto_walk").before("input"))
+            // after the walk has seen the key that started it, so it doesn't stop it
+            .add_system(auto_explore.system().after("auto_walk").before("input"))
+            .add_system(draw_hover.system().after("hover").after("resolve"));
+    }
+}
+
+fn hover_tile(
+    windows: Res<Windows>,
+    window: Res<WinSize>,
+    zoom: Res<CameraZoom>,
+    mut hovered: ResMut<HoveredTile>,
+    camera_query: Query<&Transform, With<IsCamera>>,
+) {
+    let tile = match (windows.get_primary(), camera_query.single()) {
+        (Some(primary), Ok(camera_tf)) => primary.cursor_position().map(|cursor| {
+            // the cursor is measured from the bottom left corner of the window
+            let offset = cursor - Vec2::new(primary.width(), primary.height()) / 2.;
+            let world = camera_tf.translation.truncate() + offset * zoom.0;
+            GridPos::new(
+                (world.x / window.tile).round() as i32,
+                (world.y / window.tile).round() as i32,
+            )
+        }),
+        _ => None,
+    };
+    if hovered.0 != tile {
+        hovered.0 = tile;
+    }
+}
+
+// the map as the player knows it, anything they haven't explored counts as wall so
+// paths don't give away the layout
+fn known_map(map_data: &DungeonMap, explored: &Explored) -> DungeonMap {
+    let mut known = map_data.clone();
+    for y in 0..map_data.height() as i32 {
+        for x in 0..map_data.width() as i32 {
+            if !explored.is_explored(x, y) {
+                known.set_tile(x, y, Tile::Wall);
+            }
+        }
+    }
+    known
+}
+
+fn click_to_move(
+    mouse_input: Res<Input<MouseButton>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    hovered: Res<HoveredTile>,
+    fov: Res<FieldOfView>,
+    mut walk: ResMut<AutoWalk>,
+    map_query: Query<(&Map, &Explored)>,
+    player_query: Query<(&GridPos, &Stats), With<Player>>,
+    enemy_query: Query<(Entity, &GridPos), (With<Enemy>, Without<Disguised>)>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left)
+        || !game_state.has_map
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    let goal = match &hovered.0 {
+        Some(goal) => goal,
+        None => return,
+    };
+    if let (Ok((current_map, explored)), Ok((player_loc, stats))) =
+        (map_query.single(), player_query.single())
+    {
+        if !explored.is_explored(goal.x, goal.y) {
+            return;
+        }
+        let path = find_path(&known_map(&current_map.0, explored), player_loc, goal);
+        *walk = AutoWalk {
+            path,
+            exploring: false,
+            hp: stats.hp,
+            seen: enemy_query
+                .iter()
+                .filter(|(_, loc)| fov.is_visible(loc.x, loc.y))
+                .map(|(enemy, _)| enemy)
+                .collect(),
+        };
+    }
+}
+
+// takes the next step of a clicked path whenever it's the player's turn. any key,
+// getting hurt, a new enemy coming into view or something in the way stops the walk
+fn auto_walk(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    fov: Res<FieldOfView>,
+    index: Res<SpatialIndex>,
+    mut walk: ResMut<AutoWalk>,
+    mut replay: ResMut<Replay>,
+    mut log: ResMut<MessageLog>,
+    new_map_query: Query<(), Added<Map>>,
+    mut player_query: Query<(&GridPos, &Stats, &mut NextAction), With<Player>>,
+    enemy_query: Query<(Entity, &GridPos, Option<&Name>), (With<Enemy>, Without<Disguised>)>,
+    blocker_query: Query<(), With<BlocksMovement>>,
+) {
+    if !walk.is_walking() {
+        return;
+    }
+    // a replay has the steps of the walk already
+    if replay.is_playing() {
+        walk.stop();
+        return;
+    }
+    if keyboard_input.get_just_pressed().next().is_some()
+        || new_map_query.iter().next().is_some()
+        || game_state.phase == TurnPhase::GameOver
+    {
+        walk.stop();
+        return;
+    }
+    if game_state.animating_actions
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    let (player_loc, stats, mut action) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if stats.hp < walk.hp {
+        log.add("You stop, hurt.");
+        walk.stop();
+        return;
+    }
+    let spotted = enemy_query
+        .iter()
+        .find(|(enemy, loc, _)| fov.is_visible(loc.x, loc.y) && !walk.seen.contains(enemy));
+    if let Some((_, _, name)) = spotted {
+        match name {
+            Some(name) => log.add(format!("You spot a {} and stop.", name.as_str())),
+            None => log.add("You spot something and stop."),
+        }
+        walk.stop();
+        return;
+    }
+    let next = walk.path.remove(0);
+    let (dx, dy) = (next.x - player_loc.x, next.y - player_loc.y);
+    // the last step didn't happen, or someone stepped into the way
+    if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) || index.is_blocked(&next, &blocker_query)
+    {
+        walk.stop();
+        return;
+    }
+    walk.hp = stats.hp;
+    replay.note_step((dx, dy));
+    action.0 = Some(Action::Move(Direction(dx, dy)));
+}
+
+// O walks to the nearest spot next to somewhere unexplored, and keeps going from there
+// until the floor is explored or the walk is stopped the usual way
+fn auto_explore(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    fov: Res<FieldOfView>,
+    replay: Res<Replay>,
+    mut walk: ResMut<AutoWalk>,
+    mut log: ResMut<MessageLog>,
+    map_query: Query<(&Map, &Explored)>,
+    player_query: Query<(&GridPos, &Stats), With<Player>>,
+    enemy_query: Query<(Entity, &GridPos), (With<Enemy>, Without<Disguised>)>,
+) {
+    if replay.is_playing()
+        || !game_state.has_map
+        || game_state.animating_actions
+        || game_state.phase != TurnPhase::PlayerInput
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+    {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::O) {
+        walk.exploring = true;
+    }
+    if !walk.exploring || walk.is_walking() {
+        return;
+    }
+    let (current_map, explored) = match map_query.single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let (player_loc, stats) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let map_data = &current_map.0;
+    // explored floor with an unexplored tile next to it
+    let mut frontier = Vec::new();
+    for y in 0..map_data.height() as i32 {
+        for x in 0..map_data.width() as i32 {
+            if map_data.is_walkable(x, y)
+                && explored.is_explored(x, y)
+                && map_data
+                    .iter_neighbors(x, y)
+                    .any(|(nx, ny)| !explored.is_explored(nx, ny))
+            {
+                frontier.push(GridPos::new(x, y));
+            }
+        }
+    }
+    let known = known_map(map_data, explored);
+    let dijkstra = DijkstraMap::new(&known, frontier);
+    let path = dijkstra.path_from(&known, player_loc);
+    if path.is_empty() {
+        if dijkstra.distance(player_loc).is_none() {
+            log.add("There's nowhere left to explore.");
+        }
+        walk.stop();
+        return;
+    }
+    *walk = AutoWalk {
+        path,
+        exploring: true,
+        hp: stats.hp,
+        seen: enemy_query
+            .iter()
+            .filter(|(_, loc)| fov.is_visible(loc.x, loc.y))
+            .map(|(enemy, _)| enemy)
+            .collect(),
+    };
+}
+
+// marks the hovered tile, and the way there if the player can walk to it
+fn draw_hover(
+    mut commands: Commands,
+    hovered: Res<HoveredTile>,
+    (materials, window, map_view): (Res<Materials>, Res<WinSize>, Res<MapView>),
+    map_query: Query<(&Map, &Explored)>,
+    player_query: Query<(&GridPos, ChangeTrackers<GridPos>), With<Player>>,
+    marker_query: Query<Entity, With<HoverMarker>>,
+) {
+    let (player_loc, tracker) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if !hovered.is_changed() && !tracker.is_changed() && !map_view.is_changed() {
+        return;
+    }
+    for marker in marker_query.iter() {
+        commands.entity(marker).despawn();
+    }
+    let (current_map, explored) = match map_query.single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let goal = match &hovered.0 {
+        Some(goal) if !map_view.open && explored.is_explored(goal.x, goal.y) => goal,
+        _ => return,
+    };
+    let highlight = spawn_highlight(&mut commands, materials.target.clone(), &window, goal, 12.);
+    commands.entity(highlight).insert(HoverMarker);
+    let path = find_path(&known_map(&current_map.0, explored), player_loc, goal);
+    // the goal itself already has the highlight
+    for loc in path.iter().take(path.len().saturating_sub(1)) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.target.clone(),
+                sprite: Sprite::new(Vec2::splat(window.tile * PATH_DOT_SIZE)),
+                transform: Transform::from_xyz(
+                    loc.x as f32 * window.tile,
+                    loc.y as f32 * window.tile,
+                    12.,
+                ),
+                ..Default::default()
+            })
+            .insert(HoverMarker);
+    }
+}
@@ -0,0 +1,519 @@
+use crate::boulder::{Boulder, CRUSH_HP};
+use crate::circuit::in_corridor;
+use crate::components::Direction;
+use crate::inventory::Equipment;
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::TIME_STEP;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+pub struct MovementPlugin;
+
+// the most time the animations will catch up on after a long frame
+const MAX_CATCH_UP: f64 = 0.25;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<MoveIntentEvent>()
+            .add_event::<MoveResolvedEvent>()
+            .add_system(resolve_moves.system().label("resolve").after("act"))
+            .insert_resource(AnimationClock::default())
+            .add_system(
+                animate_movement
+                    .system()
+                    .label("animate")
+                    .after("resolve")
+                    .with_run_criteria(animation_clock.system()),
+            );
+    }
+}
+
+// checks a single step from a location against the map:
+// no walls, no leaving the map, and no cutting corners on diagonals
+pub fn can_move(map_data: &DungeonMap, from: &GridPos, xdir: i32, ydir: i32) -> bool {
+    if xdir == 0 && ydir == 0 {
+        return false;
+    }
+    let xnew = from.x + xdir;
+    let ynew = from.y + ydir;
+    // into a wall, or off the map
+    if !map_data.is_walkable(xnew, ynew) {
+        return false;
+    }
+    // moving diagonally, trying to cut a corner!
+    if xdir != 0 && ydir != 0 {
+        return map_data.is_walkable(xnew, from.y) && map_data.is_walkable(from.x, ynew);
+    }
+    true
+}
+
+// where a boulder at loc goes when it's shoved one tile in a direction
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Shove {
+    Rolls(GridPos),
+    // into a chasm, filling it in
+    Plugs(GridPos),
+    // over someone weak enough to go under it
+    Crushes(Entity, GridPos),
+    Stuck,
+}
+
+// straight pushes only, and one boulder at a time, it won't shove another along. it never
+// goes into a hallway, where the first bend would wedge it for good, or over the stairs
+fn shove(
+    map_data: &DungeonMap,
+    rooms: &[RoomArea],
+    stairs: Option<&GridPos>,
+    loc: &GridPos,
+    direction: Direction,
+    occupants: &[Entity],
+    crushable: impl Fn(Entity) -> Option<bool>,
+) -> Shove {
+    let Direction(xdir, ydir) = direction;
+    if xdir != 0 && ydir != 0 {
+        return Shove::Stuck;
+    }
+    let dest = loc.add(xdir, ydir);
+    if map_data.tile_at(dest.x, dest.y) == Some(&Tile::Chasm) {
+        return Shove::Plugs(dest);
+    }
+    if !can_move(map_data, loc, xdir, ydir)
+        || in_corridor(map_data, rooms, &dest)
+        || stairs == Some(&dest)
+    {
+        return Shove::Stuck;
+    }
+    // crushable says whether a blocker would go under, None for anything that isn't in the way
+    let mut crushed = None;
+    for &entity in occupants {
+        match crushable(entity) {
+            Some(true) => crushed = Some(entity),
+            Some(false) => return Shove::Stuck,
+            None => {}
+        }
+    }
+    match crushed {
+        Some(entity) => Shove::Crushes(entity, dest),
+        None => Shove::Rolls(dest),
+    }
+}
+
+fn resolve_moves(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    mut ev_move_intent: EventReader<MoveIntentEvent>,
+    mut ev_move_resolved: EventWriter<MoveResolvedEvent>,
+    mut ev_attack: EventWriter<AttackEvent>,
+    mut ev_talk: EventWriter<TalkEvent>,
+    (mut ev_death, mut log): (EventWriter<DeathEvent>, ResMut<MessageLog>),
+    mut index: ResMut<SpatialIndex>,
+    mut map_query: Query<(&mut Map, &MapRooms)>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+    blocker_query: Query<Option<&Faction>, With<BlocksMovement>>,
+    mut actor_query: Query<(
+        Entity,
+        &mut GridPos,
+        &mut Direction,
+        Option<&BlocksMovement>,
+        Option<&Faction>,
+    )>,
+    mut boulder_query: Query<&mut GridPos, (With<Boulder>, Without<Direction>)>,
+    mut crush_query: Query<(&mut Stats, Option<&Name>)>,
+    player_query: Query<(), With<Player>>,
+) {
+    if let Ok((mut current_map, map_rooms)) = map_query.single_mut() {
+        let stairs = stairs_query.single().ok().map(|on_map| on_map.0);
+        // chasms filled in this frame, they only become floor once the moves are done
+        let mut plugged = Vec::new();
+        let map_data = &current_map.0;
+        for intent in ev_move_intent.iter() {
+            // an ally the actor is trading places with, moved once the actor's borrow is done
+            let mut swap: Option<(Entity, GridPos)> = None;
+            // the same for a boulder the player is pushing ahead of them
+            let mut pushed: Option<(Entity, Shove)> = None;
+            if let Ok((_, mut location, mut facing, blocks, faction)) =
+                actor_query.get_mut(intent.actor)
+            {
+                let Direction(xdir, ydir) = intent.direction;
+                // actors turn to face where they tried to go, even into a wall
+                *facing = intent.direction;
+                let dest = location.add(xdir, ydir);
+                // whoever is standing in the way, along with the side they're on
+                let occupant = index.at(&dest).iter().find_map(|&entity| {
+                    blocker_query
+                        .get(entity)
+                        .ok()
+                        .map(|faction| (entity, faction.copied()))
+                });
+                let mut swapping = false;
+                if let Some((target, None)) = occupant {
+                    if boulder_query.get_mut(target).is_ok()
+                        && player_query.get(intent.actor).is_ok()
+                    {
+                        // only the monsters go under, never the player's own side
+                        let crushable = |entity: Entity| {
+                            let faction = blocker_query.get(entity).ok()?;
+                            let hp = crush_query
+                                .get_component::<Stats>(entity)
+                                .map_or(0, |s| s.hp);
+                            Some(faction == Some(&Faction::Monster) && hp <= CRUSH_HP)
+                        };
+                        let result = shove(
+                            map_data,
+                            &map_rooms.rooms,
+                            stairs.as_ref(),
+                            &dest,
+                            intent.direction,
+                            index.at(&dest.add(xdir, ydir)),
+                            crushable,
+                        );
+                        if result == Shove::Stuck {
+                            continue;
+                        }
+                        pushed = Some((target, result));
+                    }
+                }
+                if let (Some((target, Some(target_faction))), Some(faction)) = (occupant, faction) {
+                    // bumping into someone on the other side is an attack, not a move
+                    if faction.is_hostile_to(&target_faction)
+                        && can_move(map_data, &location, xdir, ydir)
+                    {
+                        ev_attack.send(AttackEvent {
+                            attacker: intent.actor,
+                            target,
+                            ranged: false,
+                            power: None,
+                            element: None,
+                        });
+                    } else if *faction == Faction::Player && target_faction == Faction::Neutral {
+                        ev_talk.send(TalkEvent { speaker: target });
+                    }
+                    // the player's side trades places instead of getting stuck behind each other
+                    swapping = *faction == Faction::Player && target_faction == Faction::Player;
+                    if !swapping {
+                        continue;
+                    }
+                }
+                let is_free =
+                    blocks.is_none() || occupant.is_none() || swapping || pushed.is_some();
+                if is_free && can_move(map_data, &location, xdir, ydir) {
+                    let from = *location;
+                    index.place(intent.actor, &dest);
+                    if swapping {
+                        if let Some((target, _)) = occupant {
+                            index.place(target, &from);
+                            swap = Some((target, from));
+                        }
+                    }
+                    *location = dest;
+                    // the turn system will unset animating_actions once everything lands
+                    commands.entity(intent.actor).insert(MovingTo(*location));
+                    game_state.animating_actions = true;
+                    ev_move_resolved.send(MoveResolvedEvent {
+                        actor: intent.actor,
+                        from,
+                        to: *location,
+                    });
+                }
+            }
+            if let Some((boulder, result)) = pushed {
+                let to = match result {
+                    Shove::Rolls(to) => to,
+                    Shove::Plugs(to) => {
+                        log.add("The boulder tumbles into the chasm and plugs it.");
+                        plugged.push(to);
+                        commands.entity(boulder).despawn_recursive();
+                        continue;
+                    }
+                    Shove::Crushes(victim, to) => {
+                        if let Ok((mut stats, name)) = crush_query.get_mut(victim) {
+                            stats.hp = 0;
+                            let name = name.map_or("something", |name| name.as_str());
+                            log.add(format!("The boulder rolls over the {}!", name));
+                            ev_death.send(DeathEvent {
+                                entity: victim,
+                                killer: intent.actor,
+                                location: to,
+                            });
+                        }
+                        to
+                    }
+                    Shove::Stuck => continue,
+                };
+                if let Ok(mut location) = boulder_query.get_mut(boulder) {
+                    *location = to;
+                    index.place(boulder, &to);
+                    commands.entity(boulder).insert(MovingTo(to));
+                }
+            }
+            if let Some((target, to)) = swap {
+                if let Ok((_, mut location, _, _, _)) = actor_query.get_mut(target) {
+                    let from = *location;
+                    *location = to;
+                    commands.entity(target).insert(MovingTo(*location));
+                    ev_move_resolved.send(MoveResolvedEvent {
+                        actor: target,
+                        from,
+                        to: *location,
+                    });
+                }
+            }
+        }
+        for loc in plugged {
+            current_map.0.set_tile(loc.x, loc.y, Tile::Ground);
+        }
+    }
+}
+
+// like f32::signum, but zero stays zero so straight moves don't drift sideways
+fn step_sign(delta: f32) -> f32 {
+    if delta > 0. {
+        1.
+    } else if delta < 0. {
+        -1.
+    } else {
+        0.
+    }
+}
+
+// the animations step TIME_STEP at a time, as many steps as the time since the last frame
+// covers, so how fast things slide doesn't depend on the frame rate
+#[derive(Default)]
+pub struct AnimationClock {
+    accumulator: f64,
+    looping: bool,
+    // a headless run takes exactly one step a frame, so it plays out the same however fast
+    // the machine running it is
+    pub unpaced: bool,
+}
+
+impl AnimationClock {
+    // seconds gone by that haven't been stepped yet
+    pub fn overstep(&self) -> f32 {
+        self.accumulator as f32
+    }
+
+    // whether to take another step this frame, asked again after each one
+    fn tick(&mut self, delta: f64) -> ShouldRun {
+        if !self.looping {
+            let elapsed = if self.unpaced { TIME_STEP } else { delta };
+            // after a long hitch, catch up a little and skip the rest
+            self.accumulator = (self.accumulator + elapsed).min(MAX_CATCH_UP);
+        }
+        if self.accumulator >= TIME_STEP {
+            self.accumulator -= TIME_STEP;
+            self.looping = true;
+            ShouldRun::YesAndCheckAgain
+        } else {
+            self.looping = false;
+            ShouldRun::No
+        }
+    }
+}
+
+fn animation_clock(time: Res<Time>, mut clock: ResMut<AnimationClock>) -> ShouldRun {
+    clock.tick(time.delta_seconds_f64())
+}
+
+// one TIME_STEP of sliding towards dest at speed tiles per second, true once it's there
+fn slide(pos: &mut Vec3, dest: Vec2, speed: f32, tile: f32) -> bool {
+    let step = speed * tile * TIME_STEP as f32;
+    let (dx, dy) = (dest.x - pos.x, dest.y - pos.y);
+    // lock to the tile once it's within a step, give or take the hair rounding leaves
+    let reach = step + 0.01;
+    if dx.abs() <= reach && dy.abs() <= reach {
+        pos.x = dest.x;
+        pos.y = dest.y;
+        return true;
+    }
+    let next = lead(*pos, dest, speed, tile, TIME_STEP as f32);
+    pos.x = next.x;
+    pos.y = next.y;
+    false
+}
+
+// where a slide towards dest will have got to after secs more, without snapping
+pub fn lead(pos: Vec3, dest: Vec2, speed: f32, tile: f32, secs: f32) -> Vec2 {
+    let reach = speed * tile * secs;
+    let (dx, dy) = (dest.x - pos.x, dest.y - pos.y);
+    Vec2::new(
+        pos.x + step_sign(dx) * reach.min(dx.abs()),
+        pos.y + step_sign(dy) * reach.min(dy.abs()),
+    )
+}
+
+// slides any actor with a MovingTo towards its destination tile, once per animation step
+fn animate_movement(
+    mut commands: Commands,
+    window: Res<WinSize>,
+    mut moving_query: Query<(
+        Entity,
+        &Speed,
+        &MovingTo,
+        &mut Transform,
+        Option<&Equipment>,
+    )>,
+) {
+    for (entity, speed, moving_to, mut tf, equipment) in moving_query.iter_mut() {
+        let speed = speed.0 + equipment.map_or(0., |e| e.speed_bonus());
+        let dest = moving_to.0.to_world(window.tile);
+        if slide(&mut tf.translation, dest, speed, window.tile) {
+            commands.entity(entity).remove::<MovingTo>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plays frames of the given length until a one tile move finishes, returning how long
+    // it took
+    fn time_to_cross_a_tile(speed: f32, frame_time: f64) -> f64 {
+        let tile = 32.;
+        let mut clock = AnimationClock::default();
+        let mut pos = Vec3::ZERO;
+        let mut elapsed = 0.;
+        loop {
+            elapsed += frame_time;
+            while clock.tick(frame_time) == ShouldRun::YesAndCheckAgain {
+                if slide(&mut pos, Vec2::new(tile, 0.), speed, tile) {
+                    return elapsed;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn five_tiles_a_second_takes_a_fifth_of_a_second_per_tile() {
+        for fps in [30., 60., 75., 144., 240.].iter() {
+            let frame_time = 1. / fps;
+            let took = time_to_cross_a_tile(5., frame_time);
+            // it can only finish on a frame, so it's up to a frame late
+            assert!(
+                took >= 0.2 - 0.001 && took <= 0.2 + frame_time + 0.001,
+                "{} fps took {}",
+                fps,
+                took
+            );
+        }
+    }
+
+    #[test]
+    fn unpaced_clock_steps_once_a_frame() {
+        let mut clock = AnimationClock {
+            unpaced: true,
+            ..Default::default()
+        };
+        for delta in [0., 0.5, 1. / 500.].iter() {
+            assert_eq!(clock.tick(*delta), ShouldRun::YesAndCheckAgain);
+            assert_eq!(clock.tick(*delta), ShouldRun::No);
+        }
+    }
+
+    // a strip of floor along y = 1 with a chasm at its east end, all of it one room
+    fn strip() -> (DungeonMap, Vec<RoomArea>) {
+        let mut map_data = DungeonMap::filled(Tile::Wall, 6, 3);
+        for x in 1..5 {
+            map_data.set_tile(x, 1, Tile::Ground);
+        }
+        map_data.set_tile(4, 1, Tile::Chasm);
+        let room = RoomArea {
+            left: 1,
+            bottom: 1,
+            width: 4,
+            height: 1,
+        };
+        (map_data, vec![room])
+    }
+
+    #[test]
+    fn boulders_roll_into_free_floor_and_fill_chasms() {
+        let (map_data, rooms) = strip();
+        let nobody = |_| None;
+        let east = Direction(1, 0);
+        let push =
+            |loc: GridPos, direction| shove(&map_data, &rooms, None, &loc, direction, &[], nobody);
+        assert_eq!(
+            push(GridPos::new(2, 1), east),
+            Shove::Rolls(GridPos::new(3, 1))
+        );
+        assert_eq!(
+            push(GridPos::new(3, 1), east),
+            Shove::Plugs(GridPos::new(4, 1))
+        );
+        assert_eq!(push(GridPos::new(1, 1), Direction(-1, 0)), Shove::Stuck);
+        assert_eq!(push(GridPos::new(2, 1), Direction(1, 1)), Shove::Stuck);
+    }
+
+    #[test]
+    fn boulders_crush_the_weak_and_stop_at_the_rest() {
+        let (map_data, rooms) = strip();
+        let mut world = World::new();
+        let (weak, strong) = (world.spawn().id(), world.spawn().id());
+        let crushable = |entity| Some(entity == weak);
+        let loc = GridPos::new(2, 1);
+        let east = Direction(1, 0);
+        let over = shove(&map_data, &rooms, None, &loc, east, &[weak], crushable);
+        assert_eq!(over, Shove::Crushes(weak, GridPos::new(3, 1)));
+        let stopped = shove(
+            &map_data,
+            &rooms,
+            None,
+            &loc,
+            east,
+            &[weak, strong],
+            crushable,
+        );
+        assert_eq!(stopped, Shove::Stuck);
+    }
+
+    #[test]
+    fn boulders_stay_out_of_hallways_and_off_the_stairs() {
+        // the room ends at x = 2, and the hallway leaving it turns north at x = 3
+        let mut map_data = DungeonMap::filled(Tile::Wall, 6, 4);
+        for x in 1..4 {
+            map_data.set_tile(x, 1, Tile::Ground);
+        }
+        map_data.set_tile(3, 2, Tile::Ground);
+        let room = RoomArea {
+            left: 1,
+            bottom: 1,
+            width: 2,
+            height: 1,
+        };
+        let rooms = vec![room];
+        let nobody = |_| None;
+        let loc = GridPos::new(1, 1);
+        let east = Direction(1, 0);
+        let into_hallway = shove(
+            &map_data,
+            &rooms,
+            None,
+            &GridPos::new(2, 1),
+            east,
+            &[],
+            nobody,
+        );
+        assert_eq!(into_hallway, Shove::Stuck);
+        let stairs = GridPos::new(2, 1);
+        let onto_stairs = shove(&map_data, &rooms, Some(&stairs), &loc, east, &[], nobody);
+        assert_eq!(onto_stairs, Shove::Stuck);
+        let in_room = shove(&map_data, &rooms, None, &loc, east, &[], nobody);
+        assert_eq!(in_room, Shove::Rolls(GridPos::new(2, 1)));
+    }
+
+    #[test]
+    fn diagonal_moves_arrive_on_both_axes_together() {
+        let mut pos = Vec3::ZERO;
+        let mut steps = 0;
+        while !slide(&mut pos, Vec2::new(32., -32.), 10., 32.) {
+            steps += 1;
+            assert_eq!(pos.x, -pos.y);
+        }
+        assert_eq!((pos.x, pos.y), (32., -32.));
+        assert_eq!(steps, 5);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::enemy::Disguised;
+use crate::fov::FieldOfView;
+use crate::message_log::{capitalize, with_article, MessageLog};
+use crate::prelude::*;
+use crate::settings::{Narration, Settings};
+use bevy::prelude::*;
+use std::collections::HashSet;
+use std::process::{Child, Command};
+
+pub struct NarrationPlugin;
+
+// somewhere for narrated lines to go. replace the ones in Announcers to send them elsewhere
+pub trait TextAnnouncer: Send + Sync {
+    fn announce(&mut self, text: &str);
+}
+
+// a line each on standard output, for a screen reader following the terminal
+pub struct StdoutAnnouncer;
+
+impl TextAnnouncer for StdoutAnnouncer {
+    fn announce(&mut self, text: &str) {
+        println!("{}", text);
+    }
+}
+
+// hands each line to the platform's speech command
+pub struct SpeechAnnouncer {
+    // None where there isn't one, or once it has failed to run
+    program: Option<&'static str>,
+    speaking: Vec<Child>,
+}
+
+impl Default for SpeechAnnouncer {
+    fn default() -> Self {
+        let program = if cfg!(target_os = "macos") {
+            Some("say")
+        } else if cfg!(target_os = "windows") {
+            None
+        } else {
+            Some("spd-say")
+        };
+        SpeechAnnouncer {
+            program,
+            speaking: Vec::new(),
+        }
+    }
+}
+
+impl TextAnnouncer for SpeechAnnouncer {
+    fn announce(&mut self, text: &str) {
+        // reaps the ones that have finished
+        self.speaking
+            .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+        let program = match self.program {
+            Some(program) => program,
+            None => return,
+        };
+        match Command::new(program).arg(text).spawn() {
+            Ok(child) => self.speaking.push(child),
+            Err(e) => {
+                warn!("couldn't run {} for speech: {}", program, e);
+                self.program = None;
+            }
+        }
+    }
+}
+
+// which of these get a line depends on the narration setting
+pub struct Announcers {
+    pub text: Box<dyn TextAnnouncer>,
+    pub speech: Box<dyn TextAnnouncer>,
+}
+
+impl Default for Announcers {
+    fn default() -> Self {
+        Announcers {
+            text: Box::new(StdoutAnnouncer),
+            speech: Box::new(SpeechAnnouncer::default()),
+        }
+    }
+}
+
+impl Plugin for NarrationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Announcers::default())
+            .add_event::<AnnounceEvent>()
+            .add_system(narrate_step.system().after("resolve"))
+            .add_system(narrate_sightings.system().after("fov"))
+            .add_system(narrate_log.system())
+            // the menus are up while the game is paused
+            .add_system_to_stage(CoreStage::PostUpdate, narrate_screens.system())
+            .add_system_to_stage(CoreStage::PostUpdate, deliver.system());
+    }
+}
+
+// "3 east and 2 north"
+fn bearing(dx: i32, dy: i32) -> String {
+    let part = |n: i32, plus: &str, minus: &str| match n {
+        0 => None,
+        n if n > 0 => Some(format!("{} {}", n, plus)),
+        n => Some(format!("{} {}", -n, minus)),
+    };
+    let parts: Vec<String> = [part(dx, "east", "west"), part(dy, "north", "south")]
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+    parts.join(" and ")
+}
+
+// the tile the player steps onto and whatever is lying on it
+fn narrate_step(
+    mut ev_moved: EventReader<MoveResolvedEvent>,
+    mut ev_announce: EventWriter<AnnounceEvent>,
+    map_query: Query<&Map>,
+    player_query: Query<(), With<Player>>,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+    thing_query: Query<(&GridPos, &Name, Option<&Disguised>), Without<Player>>,
+) {
+    let current_map = match map_query.single() {
+        Ok(current_map) => current_map,
+        Err(_) => return,
+    };
+    for moved in ev_moved.iter() {
+        if player_query.get(moved.actor).is_err() {
+            continue;
+        }
+        let loc = moved.to;
+        let tile = current_map
+            .0
+            .tile_at(loc.x, loc.y)
+            .map_or("Floor", Tile::describe);
+        let mut here: Vec<String> = thing_query
+            .iter()
+            .filter(|(thing_loc, ..)| **thing_loc == loc)
+            .map(|(_, name, disguised)| match disguised {
+                // a mimic passes for a chest here too
+                Some(_) => "a chest".to_string(),
+                None => with_article(name),
+            })
+            .collect();
+        if stairs_query.iter().any(|on_map| on_map.0 == loc) {
+            here.push("the stairs down".to_string());
+        }
+        let line = if here.is_empty() {
+            format!("{}.", tile)
+        } else {
+            format!("{}, with {}.", tile, here.join(" and "))
+        };
+        ev_announce.send(AnnounceEvent(line));
+    }
+}
+
+// each enemy as it comes into view, and where it is from the player
+fn narrate_sightings(
+    fov: Res<FieldOfView>,
+    mut seen: Local<HashSet<Entity>>,
+    mut ev_announce: EventWriter<AnnounceEvent>,
+    player_query: Query<&GridPos, With<Player>>,
+    enemy_query: Query<(Entity, &GridPos, &Name), (With<Enemy>, Without<Disguised>)>,
+) {
+    if !fov.is_changed() {
+        return;
+    }
+    let player_loc = match player_query.single() {
+        Ok(loc) => loc,
+        Err(_) => return,
+    };
+    let mut visible = HashSet::new();
+    for (entity, loc, name) in enemy_query.iter() {
+        if !fov.is_visible(loc.x, loc.y) {
+            continue;
+        }
+        visible.insert(entity);
+        if !seen.contains(&entity) {
+            ev_announce.send(AnnounceEvent(format!(
+                "{} comes into view, {}.",
+                capitalize(&with_article(name)),
+                bearing(loc.x - player_loc.x, loc.y - player_loc.y)
+            )));
+        }
+    }
+    // one that drops out of sight gets announced again when it comes back
+    *seen = visible;
+}
+
+// everything that goes into the message log
+fn narrate_log(
+    log: Res<MessageLog>,
+    mut read: Local<usize>,
+    mut ev_announce: EventWriter<AnnounceEvent>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+    let messages = log.messages();
+    // a new run starts a new log
+    if *read > messages.len() {
+        *read = 0;
+    }
+    for message in messages[*read..].iter() {
+        ev_announce.send(AnnounceEvent(message.clone()));
+    }
+    *read = messages.len();
+}
+
+fn narrate_screens(
+    mut ev_announce: EventWriter<AnnounceEvent>,
+    text_query: Query<&Text, (With<Narrated>, Changed<Text>)>,
+) {
+    for text in text_query.iter() {
+        let value: String = text.sections.iter().map(|s| s.value.as_str()).collect();
+        let lines: Vec<&str> = value
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        ev_announce.send(AnnounceEvent(lines.join("\n")));
+    }
+}
+
+// speech gets a frame's lines in one go, so they don't talk over each other
+fn deliver(
+    settings: Res<Settings>,
+    mut announcers: ResMut<Announcers>,
+    mut ev_announce: EventReader<AnnounceEvent>,
+) {
+    let lines: Vec<&str> = ev_announce.iter().map(|line| line.0.as_str()).collect();
+    if lines.is_empty() || settings.narration == Narration::Off {
+        return;
+    }
+    if settings.narration.text() {
+        for line in lines.iter() {
+            announcers.text.announce(line);
+        }
+    }
+    if settings.narration.speech() {
+        announcers.speech.announce(&lines.join(" "));
+    }
+}
@@ -0,0 +1,467 @@
+use crate::companion;
+use crate::components::Direction;
+use crate::gold::Gold;
+use crate::inventory::{Equipment, Inventory};
+use crate::item::{Identification, LootDrop};
+use crate::magic::{Spell, Spellbook};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::script::RunScriptEvent;
+use crate::status::{Status, StatusEffects};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub struct NpcPlugin;
+
+// odds of a friendly character waiting near the start of a floor
+const NPC_CHANCE: f64 = 0.4;
+// how long an altar's blessing lasts
+const BLESSING_TURNS: u32 = 100;
+// odds of a gamble at an altar paying out
+const GAMBLE_ODDS: f64 = 0.45;
+pub const CHOICE_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+// region: Data
+#[derive(Deserialize)]
+struct NpcFile {
+    npcs: Vec<NpcDef>,
+}
+
+#[derive(Deserialize)]
+pub struct NpcDef {
+    pub id: String,
+    pub name: String,
+    pub color: (f32, f32, f32),
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+    // altars and the like, placed in shrine rooms instead of waiting at the start
+    #[serde(default)]
+    pub shrine: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueNode {
+    pub text: String,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    // None ends the conversation
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub effect: Option<DialogueEffect>,
+    // gold the player has to hand over to pick this, for shops
+    #[serde(default)]
+    pub cost: u32,
+}
+
+// side effects of picking a choice, applied before moving to the next node
+#[derive(Deserialize, Clone)]
+pub enum DialogueEffect {
+    // the speaker walks off the map
+    Leave,
+    // restores some of the player's hp
+    Heal(i32),
+    // the speaker joins the player as a companion
+    Recruit,
+    // adds a spell to the player's spellbook
+    Teach(Spell),
+    // puts a freshly rolled item in the player's pack
+    Give(LootDrop),
+    // takes this much hp for a while of being Blessed
+    Bless(i32),
+    // pays out this much gold, sometimes
+    Gamble(u32),
+    // lifts the curse from everything the player is wearing
+    Cleanse,
+    // runs a script from assets/scripts, by the speaker and on the player
+    Script(String),
+}
+
+impl NpcDef {
+    // sells something, anywhere in the conversation
+    pub fn is_shop(&self) -> bool {
+        self.nodes
+            .values()
+            .any(|node| node.choices.iter().any(|choice| choice.cost > 0))
+    }
+}
+
+// every npc definition, built from the content packs (see content.rs)
+#[derive(Default)]
+pub struct NpcLibrary(pub Vec<NpcDef>);
+// endregion: Data
+
+// a friendly character, indexes into the NpcLibrary
+pub struct Npc(pub usize);
+
+// the conversation currently on screen, player input is blocked while it's open
+#[derive(Default)]
+pub struct ActiveDialogue {
+    pub speaker: Option<Entity>,
+    npc: usize,
+    node: String,
+}
+impl ActiveDialogue {
+    pub fn is_open(&self) -> bool {
+        self.speaker.is_some()
+    }
+}
+
+struct DialoguePanel;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<TalkEvent>()
+            .insert_resource(ActiveDialogue::default())
+            .add_system(
+                place_wanderer
+                    .system()
+                    .label("place_wanderer")
+                    .after("place_boss"),
+            )
+            .add_system(start_dialogue.system().label("dialogue").after("resolve"))
+            .add_system(
+                dialogue_input
+                    .system()
+                    .label("dialogue_input")
+                    .before("input"),
+            );
+    }
+}
+
+pub fn read_npc_file(path: &str) -> Result<Vec<NpcDef>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: NpcFile = ron::de::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(file.npcs)
+}
+
+fn place_wanderer(
+    mut commands: Commands,
+    library: Res<NpcLibrary>,
+    window: Res<WinSize>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    map_query: Query<&MapRooms, (Added<Map>, Without<Restored>)>,
+) {
+    if let Ok(map_rooms) = map_query.single() {
+        let mut rng = game_rng.floor(game_state.depth, "wanderer");
+        let wanderers: Vec<usize> = (0..library.0.len())
+            .filter(|i| !library.0[*i].shrine)
+            .collect();
+        if wanderers.is_empty() || !rng.gen_bool(NPC_CHANCE) {
+            return;
+        }
+        // enemies never spawn in the starting room, so npcs wait there
+        let room = match map_rooms.rooms.get(map_rooms.spawn_room) {
+            Some(room) => room,
+            None => return,
+        };
+        let loc = match spawn_tiles.claim_in(room, &mut rng) {
+            Some(loc) => loc,
+            None => return,
+        };
+        let index = wanderers[rng.gen_range(0..wanderers.len())];
+        spawn_npc(&mut commands, &library, &window, &mut materials, index, loc);
+    }
+}
+
+pub fn spawn_npc(
+    commands: &mut Commands,
+    library: &NpcLibrary,
+    window: &WinSize,
+    materials: &mut Assets<ColorMaterial>,
+    index: usize,
+    loc: GridPos,
+) -> Entity {
+    let (r, g, b) = library.0[index].color;
+    let color = Color::rgb(r, g, b);
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(color.into()),
+            sprite: Sprite::new(Vec2::new(window.tile * 2. / 3., window.tile * 2. / 3.)),
+            transform: Transform {
+                translation: loc.to_world(window.tile).extend(9.),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Npc(index))
+        .insert(Name::new(library.0[index].name.clone()))
+        .insert(Interactable)
+        .insert(Direction::default())
+        .insert(Tint(color))
+        .insert(BlocksMovement)
+        .insert(Faction::Neutral)
+        .insert(OnMap(loc))
+        .insert(loc)
+        .id()
+}
+
+fn start_dialogue(
+    mut commands: Commands,
+    library: Res<NpcLibrary>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    mut dialogue: ResMut<ActiveDialogue>,
+    mut ev_talk: EventReader<TalkEvent>,
+    mut ev_interact: EventReader<InteractEvent>,
+    npc_query: Query<&Npc>,
+) {
+    // bumping into someone and pressing Interact at them both start a conversation
+    let speakers: Vec<Entity> = ev_talk
+        .iter()
+        .map(|talk| talk.speaker)
+        .chain(ev_interact.iter().map(|interact| interact.target))
+        .collect();
+    for speaker in speakers {
+        if dialogue.is_open() {
+            continue;
+        }
+        if let Ok(npc) = npc_query.get(speaker) {
+            let def = &library.0[npc.0];
+            dialogue.speaker = Some(speaker);
+            dialogue.npc = npc.0;
+            dialogue.node = def.start.clone();
+            spawn_panel(&mut commands, &font, &materials, def, &dialogue.node);
+        }
+    }
+}
+
+// number keys pick a choice, escape walks away mid-conversation
+fn dialogue_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    library: Res<NpcLibrary>,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    (game_state, modifiers, identification): (
+        Res<GameState>,
+        Res<DifficultyModifiers>,
+        Res<Identification>,
+    ),
+    mut dialogue: ResMut<ActiveDialogue>,
+    mut gold: ResMut<Gold>,
+    mut log: ResMut<MessageLog>,
+    mut rng: ResMut<GameRng>,
+    mut ev_script: EventWriter<RunScriptEvent>,
+    panel_query: Query<Entity, With<DialoguePanel>>,
+    player_entity: Query<Entity, With<Player>>,
+    mut player_query: Query<
+        (
+            &mut Stats,
+            &mut Spellbook,
+            &mut Inventory,
+            &mut StatusEffects,
+            &mut Equipment,
+        ),
+        With<Player>,
+    >,
+) {
+    let rng = rng.stream("dialogue");
+    let speaker = match dialogue.speaker {
+        Some(speaker) => speaker,
+        None => return,
+    };
+    let def = &library.0[dialogue.npc];
+    let choice = match def.nodes.get(&dialogue.node) {
+        Some(node) => CHOICE_KEYS
+            .iter()
+            .take(node.choices.len())
+            .position(|key| keyboard_input.just_pressed(*key))
+            .map(|i| &node.choices[i]),
+        // a dangling node id in the data file just ends the conversation
+        None => None,
+    };
+    if choice.is_none()
+        && !keyboard_input.just_pressed(KeyCode::Escape)
+        && def.nodes.contains_key(&dialogue.node)
+    {
+        return;
+    }
+    // the conversation stays where it is if the player can't pay, or has nowhere to put it
+    if let Some(choice) = choice {
+        if choice.cost > gold.0 {
+            log.add("You can't afford that.");
+            return;
+        }
+        if let Ok((stats, _, inventory, _, _)) = player_query.single_mut() {
+            match &choice.effect {
+                Some(DialogueEffect::Give(_)) if inventory.is_full() => {
+                    log.add("Your pack is full.");
+                    return;
+                }
+                // an offering can't be the thing that kills the player
+                Some(DialogueEffect::Bless(amount)) if stats.hp <= *amount => {
+                    log.add("You're too weak to offer that much.");
+                    return;
+                }
+                _ => {}
+            }
+        }
+        gold.0 -= choice.cost;
+    }
+
+    for panel in panel_query.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    let next = choice.and_then(|choice| {
+        match choice.effect.clone() {
+            Some(DialogueEffect::Leave) => commands.entity(speaker).despawn(),
+            Some(DialogueEffect::Recruit) => {
+                companion::recruit(&mut commands, speaker, dialogue.npc)
+            }
+            Some(DialogueEffect::Heal(amount)) => {
+                if let Ok((mut stats, _, _, _, _)) = player_query.single_mut() {
+                    stats.hp = (stats.hp + amount).min(stats.max_hp);
+                }
+            }
+            Some(DialogueEffect::Teach(spell)) => {
+                if let Ok((_, mut spellbook, _, _, _)) = player_query.single_mut() {
+                    if spellbook.learn(spell) {
+                        log.add(format!("You learn {}.", spell.name()));
+                    }
+                }
+            }
+            Some(DialogueEffect::Give(drop)) => {
+                if let Ok((_, _, mut inventory, _, _)) = player_query.single_mut() {
+                    let item = drop.roll(modifiers.loot_depth(game_state.depth), &mut *rng);
+                    log.add(format!("You receive {}.", identification.describe(&item)));
+                    // already checked there's room
+                    let _ = inventory.add(item);
+                }
+            }
+            Some(DialogueEffect::Bless(amount)) => {
+                if let Ok((mut stats, _, _, mut effects, _)) = player_query.single_mut() {
+                    // already checked it leaves the player standing
+                    stats.hp -= amount;
+                    effects.add(Status::Blessed, game_state.turn + BLESSING_TURNS);
+                    log.add("Your blood runs into the stone, and warmth floods back.");
+                }
+            }
+            Some(DialogueEffect::Gamble(payout)) => {
+                if rng.gen_bool(GAMBLE_ODDS) {
+                    gold.0 += payout;
+                    log.add(format!("The altar spills out {} gold!", payout));
+                } else {
+                    log.add("The coins vanish into the stone.");
+                }
+            }
+            Some(DialogueEffect::Cleanse) => {
+                if let Ok((_, _, _, _, mut equipment)) = player_query.single_mut() {
+                    let lifted = equipment.uncurse();
+                    log.add(if lifted {
+                        "A weight lifts from your gear."
+                    } else {
+                        "Light washes over you, but nothing you wear is cursed."
+                    });
+                }
+            }
+            Some(DialogueEffect::Script(script)) => {
+                if let Ok(player) = player_entity.single() {
+                    ev_script.send(RunScriptEvent {
+                        script,
+                        source: speaker,
+                        target: player,
+                    });
+                }
+            }
+            None => {}
+        }
+        choice.next.clone()
+    });
+    match next {
+        Some(node) => {
+            spawn_panel(&mut commands, &font, &materials, def, &node);
+            dialogue.node = node;
+        }
+        None => *dialogue = ActiveDialogue::default(),
+    }
+}
+
+// box along the bottom of the screen with the speaker's name, their line, and numbered choices
+fn spawn_panel(
+    commands: &mut Commands,
+    font: &UiFont,
+    materials: &Materials,
+    def: &NpcDef,
+    node_id: &str,
+) {
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: format!("{}\n", def.name),
+        style: style(22., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    if let Some(node) = def.nodes.get(node_id) {
+        sections.push(TextSection {
+            value: format!("{}\n", node.text),
+            style: style(18., Color::WHITE),
+        });
+        for (i, choice) in node.choices.iter().enumerate() {
+            let price = match choice.cost {
+                0 => String::new(),
+                cost => format!(" ({} gold)", cost),
+            };
+            sections.push(TextSection {
+                value: format!("\n{}. {}{}", i + 1, choice.text, price),
+                style: style(18., Color::rgb(0.7, 0.8, 0.9)),
+            });
+        }
+    }
+    sections.push(TextSection {
+        value: "\n\n[Esc] leave".to_string(),
+        style: style(14., Color::GRAY),
+    });
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                padding: Rect::all(Val::Px(12.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(DialoguePanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    max_size: Size::new(Val::Percent(100.), Val::Undefined),
+                    ..Default::default()
+                },
+                text: Text {
+                    sections,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
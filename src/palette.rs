@@ -0,0 +1,72 @@
+use crate::enemy::Disguised;
+use crate::prelude::*;
+use crate::settings::Settings;
+use bevy::prelude::*;
+
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // after the spawners' commands are in, so nothing shows in the wrong colour for a
+        // frame. runs while paused too, so the settings screen shows a change straight away
+        app.add_system_to_stage(CoreStage::PostUpdate, recolor_materials.system())
+            .add_system_to_stage(CoreStage::PostUpdate, recolor_sprites.system())
+            .add_system_to_stage(CoreStage::PostUpdate, recolor_own_materials.system());
+    }
+}
+
+// the first time through it notes the colours the materials were made in, and from then
+// on works from those whenever the palette changes
+fn recolor_materials(
+    settings: Res<Settings>,
+    materials: Res<Materials>,
+    mut assets: ResMut<Assets<ColorMaterial>>,
+    mut standard: Local<Vec<Color>>,
+) {
+    if standard.is_empty() {
+        standard.extend(
+            materials
+                .tinted()
+                .iter()
+                .filter_map(|handle| assets.get(*handle).map(|material| material.color)),
+        );
+    } else if !settings.is_changed() {
+        return;
+    }
+    for (handle, color) in materials.tinted().iter().zip(standard.iter()) {
+        if let Some(material) = assets.get_mut(*handle) {
+            material.color = settings.palette.tint(*color);
+        }
+    }
+}
+
+// a disguised mimic keeps the chest's own colours until it shows itself
+fn recolor_sprites(
+    settings: Res<Settings>,
+    mut sprite_query: Query<
+        (&Tint, &mut TextureAtlasSprite, ChangeTrackers<Tint>),
+        Without<Disguised>,
+    >,
+) {
+    for (tint, mut sprite, tracker) in sprite_query.iter_mut() {
+        if tracker.is_changed() || settings.is_changed() {
+            sprite.color = settings.palette.tint(tint.0);
+        }
+    }
+}
+
+// npcs and altars each get a material of their own in their library colour, rather than
+// one of the shared ones, so they're tinted one at a time like the sprites
+fn recolor_own_materials(
+    settings: Res<Settings>,
+    mut assets: ResMut<Assets<ColorMaterial>>,
+    material_query: Query<(&Tint, &Handle<ColorMaterial>, ChangeTrackers<Tint>)>,
+) {
+    for (tint, handle, tracker) in material_query.iter() {
+        if tracker.is_changed() || settings.is_changed() {
+            if let Some(material) = assets.get_mut(handle) {
+                material.color = settings.palette.tint(tint.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use crate::movement::can_move;
+use crate::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+pub const NEIGHBORS: [(i32, i32); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+    (1, 1),
+];
+
+// A* over the map grid, using the same step rules as the movement system
+// returns the tiles to walk through, not including the start
+pub fn find_path(map_data: &DungeonMap, start: &GridPos, goal: &GridPos) -> Vec<GridPos> {
+    let mut open: BinaryHeap<Reverse<(i32, i32, i32, i32)>> = BinaryHeap::new();
+    let mut came_from: HashMap<GridPos, GridPos> = HashMap::new();
+    let mut cost: HashMap<GridPos, i32> = HashMap::new();
+    if start == goal {
+        return Vec::new();
+    }
+
+    cost.insert(*start, 0);
+    open.push(Reverse((start.chebyshev(goal), 0, start.x, start.y)));
+    while let Some(Reverse((_, g, x, y))) = open.pop() {
+        let here = GridPos::new(x, y);
+        if here == *goal {
+            let mut path = vec![here];
+            let mut current = here;
+            while let Some(&prev) = came_from.get(&current) {
+                if prev == *start {
+                    break;
+                }
+                path.push(prev);
+                current = prev;
+            }
+            path.reverse();
+            return path;
+        }
+        // skip stale heap entries
+        if g > cost[&here] {
+            continue;
+        }
+        for &(dx, dy) in NEIGHBORS.iter() {
+            if !can_move(map_data, &here, dx, dy) {
+                continue;
+            }
+            let next = here.add(dx, dy);
+            let next_cost = g + 1;
+            if cost.get(&next).is_none_or(|&c| next_cost < c) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, here);
+                let h = next.chebyshev(goal);
+                open.push(Reverse((next_cost + h, next_cost, next.x, next.y)));
+            }
+        }
+    }
+    Vec::new()
+}
+
+// how many steps every reachable tile is from the nearest of some goals, worked out once
+// so any number of walkers can follow it downhill: everyone chasing the player, or the
+// player heading for the nearest unexplored corner
+pub struct DijkstraMap {
+    distances: HashMap<GridPos, i32>,
+}
+
+impl DijkstraMap {
+    pub fn new(map_data: &DungeonMap, goals: impl IntoIterator<Item = GridPos>) -> Self {
+        let mut distances: HashMap<GridPos, i32> = HashMap::new();
+        let mut frontier: VecDeque<GridPos> = VecDeque::new();
+        for goal in goals {
+            if distances.insert(goal, 0).is_none() {
+                frontier.push_back(goal);
+            }
+        }
+        // steps are the same both ways, so spreading out from the goals finds the way in
+        while let Some(here) = frontier.pop_front() {
+            let dist = distances[&here];
+            for &(dx, dy) in NEIGHBORS.iter() {
+                let next = here.add(dx, dy);
+                if !distances.contains_key(&next) && can_move(map_data, &here, dx, dy) {
+                    distances.insert(next, dist + 1);
+                    frontier.push_back(next);
+                }
+            }
+        }
+        DijkstraMap { distances }
+    }
+
+    // None if no goal can be reached from the tile
+    pub fn distance(&self, loc: &GridPos) -> Option<i32> {
+        self.distances.get(loc).copied()
+    }
+
+    // the neighbouring tile a step closer to the nearest goal, None once on one or if
+    // there's no way there. ties go to the first in NEIGHBORS, straight before diagonal
+    pub fn step_towards(&self, map_data: &DungeonMap, from: &GridPos) -> Option<GridPos> {
+        let here = self.distance(from)?;
+        NEIGHBORS
+            .iter()
+            .filter(|&&(dx, dy)| can_move(map_data, from, dx, dy))
+            .map(|&(dx, dy)| from.add(dx, dy))
+            .filter_map(|next| Some((self.distance(&next)?, next)))
+            .filter(|&(dist, _)| dist < here)
+            .min_by_key(|&(dist, _)| dist)
+            .map(|(_, next)| next)
+    }
+
+    // the whole way to the nearest goal, not including the start
+    pub fn path_from(&self, map_data: &DungeonMap, from: &GridPos) -> Vec<GridPos> {
+        let mut path = Vec::new();
+        let mut here = *from;
+        while let Some(next) = self.step_towards(map_data, &here) {
+            path.push(next);
+            here = next;
+        }
+        path
+    }
+
+    // the reachable tile that takes the most steps to get to, ties broken the same way
+    // every time so a seed plays out the same
+    pub fn farthest(&self) -> Option<GridPos> {
+        self.distances
+            .iter()
+            .max_by_key(|&(tile, &dist)| (dist, tile.x, tile.y))
+            .map(|(tile, _)| *tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a map from rows of text, top row first: '.' is floor, anything else wall
+    fn map(text: &str) -> DungeonMap {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        let height = rows.len();
+        let width = rows[0].len();
+        let mut map_data = DungeonMap::filled(Tile::Wall, width, height);
+        for (row, line) in rows.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '.' {
+                    map_data.set_tile(x as i32, (height - 1 - row) as i32, Tile::Ground);
+                }
+            }
+        }
+        map_data
+    }
+
+    #[test]
+    fn find_path_goes_around_walls() {
+        let map_data = map("
+            #####
+            #...#
+            #.#.#
+            #.#.#
+            #####
+            ");
+        let path = find_path(&map_data, &GridPos::new(1, 1), &GridPos::new(3, 1));
+        assert_eq!(path.last(), Some(&GridPos::new(3, 1)));
+        assert_eq!(path.len(), 6);
+        assert!(path.iter().all(|loc| map_data.is_walkable(loc.x, loc.y)));
+    }
+
+    #[test]
+    fn find_path_does_not_cut_corners() {
+        let map_data = map("
+            ####
+            #..#
+            #.##
+            ####
+            ");
+        // the diagonal from (1, 1) to (2, 2) clips the wall at (2, 1)
+        let path = find_path(&map_data, &GridPos::new(1, 1), &GridPos::new(2, 2));
+        assert_eq!(path, vec![GridPos::new(1, 2), GridPos::new(2, 2)]);
+    }
+
+    #[test]
+    fn find_path_is_empty_when_unreachable_or_already_there() {
+        let map_data = map("
+            #####
+            #.#.#
+            #####
+            ");
+        let start = GridPos::new(1, 1);
+        assert!(find_path(&map_data, &start, &GridPos::new(3, 1)).is_empty());
+        assert!(find_path(&map_data, &start, &start).is_empty());
+    }
+
+    #[test]
+    fn dijkstra_map_measures_from_the_nearest_goal() {
+        let map_data = map("
+            #######
+            #.....#
+            #######
+            ");
+        let goals = [GridPos::new(1, 1), GridPos::new(5, 1)];
+        let dijkstra = DijkstraMap::new(&map_data, goals.iter().copied());
+        assert_eq!(dijkstra.distance(&GridPos::new(1, 1)), Some(0));
+        assert_eq!(dijkstra.distance(&GridPos::new(2, 1)), Some(1));
+        assert_eq!(dijkstra.distance(&GridPos::new(3, 1)), Some(2));
+        assert_eq!(dijkstra.distance(&GridPos::new(4, 1)), Some(1));
+        assert_eq!(dijkstra.distance(&GridPos::new(0, 0)), None);
+    }
+
+    #[test]
+    fn dijkstra_paths_match_a_star_lengths() {
+        let map_data = map("
+            ########
+            #......#
+            #.####.#
+            #.#..#.#
+            #...#..#
+            ########
+            ");
+        let goal = GridPos::new(3, 2);
+        let dijkstra = DijkstraMap::new(&map_data, [goal]);
+        for y in 0..map_data.height() as i32 {
+            for x in 0..map_data.width() as i32 {
+                let from = GridPos::new(x, y);
+                if !map_data.is_walkable(x, y) {
+                    continue;
+                }
+                let walked = dijkstra.path_from(&map_data, &from);
+                let searched = find_path(&map_data, &from, &goal);
+                assert_eq!(walked.len(), searched.len(), "from {:?}", from);
+                assert_eq!(dijkstra.distance(&from), Some(searched.len() as i32));
+                if let Some(last) = walked.last() {
+                    assert_eq!(*last, goal);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn farthest_is_the_far_end_of_a_corridor() {
+        let map_data = map("
+            ######
+            #....#
+            ######
+            ");
+        let dijkstra = DijkstraMap::new(&map_data, [GridPos::new(1, 1)]);
+        assert_eq!(dijkstra.farthest(), Some(GridPos::new(4, 1)));
+        assert_eq!(
+            dijkstra.step_towards(&map_data, &GridPos::new(4, 1)),
+            Some(GridPos::new(3, 1))
+        );
+        assert_eq!(dijkstra.step_towards(&map_data, &GridPos::new(1, 1)), None);
+    }
+}
@@ -1,33 +1,67 @@
-use crate::{
-    ActionToPerform, CameraCenter, Direction, FinishedMapEvent, GameState, Location, Map,
-    Materials, OnMap, Player, Speed, Stairs, Tile, WinSize, TIME_STEP,
-};
+use crate::class::{ClassKit, PlayerClass};
+use crate::components::Direction;
+use crate::hunger::Hunger;
+use crate::inventory::{Equipment, Inventory, InventoryScreen};
+use crate::level::LevelUp;
+use crate::light::{LightSource, PLAYER_LIGHT};
+use crate::magic::{Casting, Mana, Spell, Spellbook};
+use crate::map_view::MapView;
+use crate::movement::{lead, AnimationClock};
+use crate::npc::ActiveDialogue;
+use crate::prelude::*;
+use crate::replay::Replay;
+use crate::settings::Settings;
+use crate::status::StatusEffects;
+use crate::throwing::Aiming;
 use bevy::prelude::*;
 
 pub struct PlayerPlugin;
 
+// how many items fit in the player's pack
+const PACK_CAPACITY: usize = 12;
+// the player's colour in the standard palette
+const PLAYER_TINT: Color = Color::rgb(0.3, 0.9, 0.3);
+
+// a step the arrow keys asked for that hasn't been taken yet. a tap always gets exactly
+// one step, even mid-animation, and a held key repeats after the delay in the settings
+#[derive(Default)]
+pub struct MoveBuffer {
+    step: Option<(i32, i32)>,
+    // arrows being held, oldest press first
+    held: Vec<KeyCode>,
+    // counts down to the next repeat while an arrow is held
+    repeat: Timer,
+}
+
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_stage_after(
-            "game_setup_map",
+        app.add_startup_stage(
             "game_setup_actors",
             SystemStage::single(player_spawn.system()),
         )
+        .insert_resource(MoveBuffer::default())
         .add_system(player_jump_to_spawn.system().before("input"))
+        .add_system(buffer_moves.system().before("input"))
         .add_system(player_input.system().label("input"))
-        .add_system(player_actions.system().label("actions").after("input"));
+        .add_system(
+            player_camera_follow
+                .system()
+                .label("actions")
+                .after("animate"),
+        );
     }
 }
 
 fn player_spawn(
     mut commands: Commands,
-    materials: Res<Materials>,
+    player_class: Res<PlayerClass>,
+    tileset: Res<Tileset>,
     window: Res<WinSize>,
     mut camera_center: ResMut<CameraCenter>,
     // map_query: Query<(&Map)>,
 ) {
     // Create player sprite at map's spawn point (currently defaults to (1, 1))
-    let mut spawn_point: Location = Location::default();
+    let mut spawn_point: GridPos = GridPos::default();
     // if let Ok((current_map)) = map_query.single() {
     //     let map_spawn = &current_map.1;
     //     spawn_point.0 = map_spawn.0;
@@ -38,26 +72,70 @@ fn player_spawn(
     //     // );
     // }
     // move camera to center on player
-    camera_center.0 = spawn_point.0 as f32 * window.tile;
-    camera_center.1 = spawn_point.1 as f32 * window.tile;
+    camera_center.0 = spawn_point.x as f32 * window.tile;
+    camera_center.1 = spawn_point.y as f32 * window.tile;
 
-    commands
-        .spawn_bundle(SpriteBundle {
-            material: materials.player.clone(),
-            sprite: Sprite::new(Vec2::new(window.tile * 2. / 3., window.tile * 2. / 3.)),
+    let player = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                color: PLAYER_TINT,
+                ..TileSprite::Player.sprite()
+            },
+            texture_atlas: tileset.0.clone(),
             transform: Transform {
-                translation: Vec3::new(
-                    spawn_point.0 as f32 * window.tile,
-                    spawn_point.1 as f32 * window.tile,
-                    10.,
-                ),
+                translation: spawn_point.to_world(window.tile).extend(10.),
                 ..Default::default()
             },
             ..Default::default()
         })
         .insert(Player)
-        .insert(Speed::default())
-        .insert(spawn_point);
+        .insert(Tint(PLAYER_TINT))
+        .insert(Name::new("you"))
+        .insert(Direction::default())
+        .insert(BlocksMovement)
+        .insert(Faction::Player)
+        .insert(NextAction::default())
+        .insert(spawn_point)
+        .id();
+    give_starting_kit(&mut commands, player, player_class.kit());
+}
+
+// everything that progresses over a run, handed out again when a new run starts
+pub fn give_starting_kit(commands: &mut Commands, player: Entity, kit: ClassKit) {
+    let mut equipment = Equipment::default();
+    for item in kit.gear {
+        // a kit never holds two things for the same slot
+        let _ = equipment.equip(item);
+    }
+    commands
+        .entity(player)
+        .insert(Stats {
+            max_hp: kit.max_hp,
+            hp: kit.max_hp,
+            attack: kit.attack,
+            defense: kit.defense,
+        })
+        .insert(Speed(kit.speed))
+        .insert(Experience(0))
+        .insert(Level(1))
+        .insert(Resistances::default())
+        .insert(Mana {
+            max: kit.mana,
+            current: kit.mana,
+        })
+        .insert(Spellbook(kit.spells))
+        .insert(Inventory {
+            items: kit.pack,
+            capacity: PACK_CAPACITY,
+        })
+        .insert(equipment)
+        .insert(StatusEffects::default())
+        .insert(Hunger::full())
+        .insert(LightSource {
+            radius: PLAYER_LIGHT,
+            carried: true,
+        })
+        .remove::<Blocking>();
 }
 
 fn player_jump_to_spawn(
@@ -65,17 +143,17 @@ fn player_jump_to_spawn(
     window: Res<WinSize>,
     game_state: ResMut<GameState>,
     map_query: Query<(&Map), Added<Map>>,
-    mut player_query: Query<(&mut Transform, &mut Location), With<Player>>,
+    mut player_query: Query<(&mut Transform, &mut GridPos), With<Player>>,
 ) {
     if let Ok((mut player_tf, mut player_loc)) = player_query.single_mut() {
         if let Ok((current_map)) = map_query.single() {
             let map_spawn = &current_map.1;
 
             // set player location to map spawn point
-            player_loc.0 = map_spawn.0;
-            player_loc.1 = map_spawn.1;
-            player_tf.translation.x = player_loc.0 as f32 * window.tile;
-            player_tf.translation.y = player_loc.1 as f32 * window.tile;
+            *player_loc = *map_spawn;
+            let world = player_loc.to_world(window.tile);
+            player_tf.translation.x = world.x;
+            player_tf.translation.y = world.y;
             //keep the camera on the player
             camera_center.0 = player_tf.translation.x;
             camera_center.1 = player_tf.translation.y;
@@ -83,140 +161,182 @@ fn player_jump_to_spawn(
     }
 }
 
+const ARROWS: [KeyCode; 4] = [KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down];
+
+fn arrow_direction(key: KeyCode) -> (i32, i32) {
+    match key {
+        KeyCode::Left => (-1, 0),
+        KeyCode::Right => (1, 0),
+        KeyCode::Down => (0, -1),
+        _ => (0, 1),
+    }
+}
+
+// the direction the held arrows point in. the most recent press wins between opposite
+// arrows, and between the two axes when diagonals are turned off
+fn held_direction(held: &[KeyCode], settings: &Settings) -> (i32, i32) {
+    let latest = |horizontal: bool| {
+        held.iter()
+            .rev()
+            .map(|key| arrow_direction(*key))
+            .find(|(x, _)| (*x != 0) == horizontal)
+    };
+    if !settings.diagonal_movement {
+        return held.last().map_or((0, 0), |key| arrow_direction(*key));
+    }
+    let xdir = latest(true).map_or(0, |(x, _)| x);
+    let ydir = latest(false).map_or(0, |(_, y)| y);
+    (xdir, ydir)
+}
+
+// a fresh press queues a step straight away, replacing one that hasn't been taken so a
+// second arrow pressed a moment later makes it a diagonal. holding on queues another
+// after the repeat delay and then at the repeat rate, but never more than one at a time
+fn buffer_moves(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    game_state: Res<GameState>,
+    settings: Res<Settings>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    replay: Res<Replay>,
+    mut buffer: ResMut<MoveBuffer>,
+) {
+    // the arrows belong to whatever menu is open, and a replay brings its own steps
+    if replay.is_playing()
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+        || matches!(game_state.phase, TurnPhase::NewGame | TurnPhase::GameOver)
+    {
+        buffer.step = None;
+        buffer.held.clear();
+        return;
+    }
+    buffer.held.retain(|key| keyboard_input.pressed(*key));
+    for key in ARROWS.iter() {
+        if keyboard_input.just_pressed(*key) {
+            buffer.held.push(*key);
+        }
+    }
+    let direction = held_direction(&buffer.held, &settings);
+    if direction == (0, 0) {
+        return;
+    }
+    if ARROWS.iter().any(|key| keyboard_input.just_pressed(*key)) {
+        buffer.step = Some(direction);
+        buffer.repeat = Timer::from_seconds(settings.repeat_delay, false);
+        return;
+    }
+    if buffer.repeat.tick(time.delta()).just_finished() {
+        if buffer.step.is_none() {
+            buffer.step = Some(direction);
+        }
+        buffer.repeat = Timer::from_seconds(1. / settings.repeat_rate.max(1.), false);
+    }
+}
+
 fn player_input(
-    mut commands: Commands,
     keyboard_input: Res<Input<KeyCode>>,
-    mut game_state: ResMut<GameState>,
-    mut ev_finished_map: EventWriter<FinishedMapEvent>,
-    map_query: Query<&Map>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, aiming, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<Aiming>,
+        Res<MapView>,
+    ),
+    mut buffer: ResMut<MoveBuffer>,
+    mut replay: ResMut<Replay>,
     stairs_query: Query<(&OnMap), With<Stairs>>,
-    mut player_query: Query<(&mut Location), With<Player>>,
+    mut player_query: Query<(&GridPos, &mut NextAction), With<Player>>,
 ) {
     // in the middle of a move, ignore inputs until finished
-    // alternatively, if the map doesn't exist
-    if game_state.animating_actions || !game_state.has_map {
+    // alternatively, if the map doesn't exist, someone is talking, or a menu is open
+    if game_state.animating_actions
+        || dialogue.is_open()
+        || casting.is_busy()
+        || level_up.is_choosing()
+        || inventory_screen.open
+        || aiming.is_busy()
+        || map_view.open
+        || !game_state.has_map
+        || game_state.phase != TurnPhase::PlayerInput
+    {
         return;
     }
 
-    if let Ok((mut location)) = player_query.single_mut() {
-        if let Ok(current_map) = map_query.single() {
-            let map_data = &current_map.0;
-            // pressing SPACE on stairs finishes the current map
-            if keyboard_input.pressed(KeyCode::Space) {
-                for (loc_data) in stairs_query.iter() {
-                    let stair_loc = &loc_data.0;
-                    if stair_loc.0 == location.0 && stair_loc.1 == location.1 {
-                        ev_finished_map.send(FinishedMapEvent);
-                    }
-                }
-            }
-            // allows 8 way movement
-            let mut xdir: i32 = if keyboard_input.pressed(KeyCode::Left) {
-                -1
-            } else if keyboard_input.pressed(KeyCode::Right) {
-                1
-            } else {
-                0
-            };
-            let mut ydir: i32 = if keyboard_input.pressed(KeyCode::Down) {
-                -1
-            } else if keyboard_input.pressed(KeyCode::Up) {
-                1
-            } else {
-                0
-            };
-            let xnew = location.0 + xdir;
-            let ynew = location.1 + ydir;
-            // later track player's facing direction and set here
-            // check for valid move
-            if xnew < 0 || ynew < 0 {
-                // moving out of bounds somehow?
-                xdir = 0;
-                ydir = 0;
-            } else if let Some(tile) = map_data.get(ynew as usize, xnew as usize) {
-                if tile == &Tile::Wall {
-                    // moving into a wall tile
-                    xdir = 0;
-                    ydir = 0;
-                } else if xdir != 0 && ydir != 0 {
-                    // moving diagonally
-                    if let (Some(xmove), Some(ymove)) = (
-                        map_data.get(location.1 as usize, xnew as usize),
-                        map_data.get(ynew as usize, location.0 as usize),
-                    ) {
-                        if xmove == &Tile::Wall || ymove == &Tile::Wall {
-                            // trying to cut a corner!
-                            xdir = 0;
-                            ydir = 0;
-                        }
-                    }
-                }
-            } else {
-                // moving out of bounds somehow?
-                xdir = 0;
-                ydir = 0;
-            }
-
-            // set animating_actions, mark location to move to, let other system handle animation
-            // other system will also unset animating_actions
-            if xdir != 0 || ydir != 0 {
-                location.0 = xnew;
-                location.1 = ynew;
-                // println!("Intending to move to {}, {}", location.0, location.1);
-                commands
-                    .spawn()
-                    .insert(ActionToPerform)
-                    .insert(Direction(xdir, ydir));
-                game_state.animating_actions = true;
-            }
+    if let Ok((location, mut next)) = player_query.single_mut() {
+        // the pack or the mouse already picked something to do this frame
+        if next.0.is_some() {
+            return;
+        }
+        // pressing SPACE on stairs finishes the current map, unless something seals them
+        if keyboard_input.pressed(KeyCode::Space)
+            && stairs_query.iter().any(|stairs| stairs.0 == *location)
+        {
+            next.0 = Some(Action::Descend);
+            return;
+        }
+        // B spends the turn guarding the direction the player is facing
+        if keyboard_input.just_pressed(KeyCode::B) {
+            next.0 = Some(Action::Block);
+            return;
+        }
+        // . lets the turn pass without moving
+        if keyboard_input.just_pressed(KeyCode::Period) {
+            next.0 = Some(Action::Wait);
+            return;
+        }
+        let step = if replay.is_playing() {
+            replay.take_step()
+        } else {
+            buffer.step.take()
+        };
+        if let Some((xdir, ydir)) = step {
+            replay.note_step((xdir, ydir));
+            next.0 = Some(Action::Move(Direction(xdir, ydir)));
         }
     }
 }
 
-fn player_actions(
-    mut commands: Commands,
-    mut game_state: ResMut<GameState>,
-    mut action_query: Query<(Entity, &Direction), With<ActionToPerform>>,
+// keeps the camera on the player every frame, not just on the frames the animations step.
+// mid-slide it aims where the player will be once the time not yet stepped is, so the view
+// glides however the steps fall across frames
+fn player_camera_follow(
     mut camera_center: ResMut<CameraCenter>,
+    mut last_target: Local<Option<Vec2>>,
+    clock: Res<AnimationClock>,
     window: Res<WinSize>,
-    mut player_query: Query<(&Speed, &mut Transform, &Location), With<Player>>,
+    player_query: Query<(&Transform, &Speed, Option<&MovingTo>, Option<&Equipment>), With<Player>>,
 ) {
-    if !game_state.animating_actions {
-        return;
-    }
-    if let Ok((speed, mut player_tf, player_loc)) = player_query.single_mut() {
-        if let Ok((move_entity, dir)) = action_query.single() {
-            //get direction to move
-            let move_x = dir.0 as f32;
-            let move_y = dir.1 as f32;
-
-            //get destination
-            let dest_x = player_loc.0 as f32 * window.tile;
-            let dest_y = player_loc.1 as f32 * window.tile;
-
-            //prospective step
-            let step_x = player_tf.translation.x + move_x * speed.0 * window.tile * TIME_STEP;
-            let step_y = player_tf.translation.y + move_y * speed.0 * window.tile * TIME_STEP;
-
-            //lock to next tile position if close enough and allow for input again
-            let curr_dist_x = (dest_x - player_tf.translation.x).abs();
-            let curr_dist_y = (dest_y - player_tf.translation.y).abs();
-            let step_dist_x = (dest_x - step_x).abs();
-            let step_dist_y = (dest_y - step_y).abs();
-
-            if curr_dist_x <= step_dist_x && curr_dist_y <= step_dist_y {
-                player_tf.translation.x = dest_x;
-                player_tf.translation.y = dest_y;
-                commands.entity(move_entity).despawn();
-                game_state.animating_actions = false;
-            } else {
-                // otherwise, take the step
-                player_tf.translation.x = step_x;
-                player_tf.translation.y = step_y;
-            }
-            //keep the camera on the player
-            camera_center.0 = player_tf.translation.x;
-            camera_center.1 = player_tf.translation.y;
-        }
+    let (player_tf, speed, moving_to, equipment) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let target = match moving_to {
+        Some(moving_to) => lead(
+            player_tf.translation,
+            moving_to.0.to_world(window.tile),
+            speed.0 + equipment.map_or(0., |e| e.speed_bonus()),
+            window.tile,
+            clock.overstep(),
+        ),
+        None => player_tf.translation.truncate(),
+    };
+    // only when it moves, so the camera can be clamped to the map without a tug of war
+    if *last_target != Some(target) {
+        *last_target = Some(target);
+        *camera_center = CameraCenter(target.x, target.y);
     }
 }
@@ -0,0 +1,5 @@
+// the types every plugin shares, so they can all be had with one import
+pub use crate::components::*;
+pub use crate::events::*;
+pub use crate::resources::*;
+pub use crate::tile::*;
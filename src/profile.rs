@@ -0,0 +1,371 @@
+use crate::gold::Gold;
+use crate::prelude::*;
+use crate::replay::Replay;
+use crate::settings::{data_dir, Settings};
+use crate::unlocks::Unlocks;
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub struct ProfilePlugin;
+
+const PROFILES_FOLDER: &str = "profiles";
+const STATS_FILE: &str = "stats.ron";
+// the name of the profile picked last, kept in the data dir
+const LAST_PROFILE_FILE: &str = "last_profile";
+const DEFAULT_PROFILE: &str = "Default";
+const MAX_NAME_LENGTH: usize = 16;
+
+// whose run, settings and statistics are in use. each profile is a folder under the data dir
+pub struct Profile {
+    pub name: String,
+}
+
+impl Profile {
+    // the one picked last time, or the default one on a first run
+    pub fn load_last() -> Self {
+        let name = data_dir()
+            .and_then(|dir| std::fs::read_to_string(dir.join(LAST_PROFILE_FILE)).ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| is_valid_name(name))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+        Profile { name }
+    }
+
+    fn remember(&self) {
+        let written = data_dir().map_or(Ok(()), |dir| {
+            std::fs::create_dir_all(&dir)
+                .and_then(|_| std::fs::write(dir.join(LAST_PROFILE_FILE), &self.name))
+        });
+        if let Err(e) = written {
+            warn!("couldn't remember the profile: {}", e);
+        }
+    }
+
+    pub fn dir(&self) -> Option<PathBuf> {
+        Some(data_dir()?.join(PROFILES_FOLDER).join(&self.name))
+    }
+
+    pub fn stats(&self) -> ProfileStats {
+        self.dir()
+            .and_then(|dir| std::fs::read_to_string(dir.join(STATS_FILE)).ok())
+            .and_then(|data| ron::de::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_stats(&self, stats: &ProfileStats) {
+        let path = match self.dir() {
+            Some(dir) => dir.join(STATS_FILE),
+            None => return,
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| ron::ser::to_string(stats).map_err(|e| e.to_string()))
+            .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!("couldn't save {}: {}", path.display(), e);
+        }
+    }
+}
+
+// names double as folder names, so they stick to characters every filesystem takes
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_')
+        && !name.starts_with(' ')
+}
+
+// every profile with a folder, and the active one even if it hasn't got one yet
+fn list_profiles(active: &str) -> Vec<String> {
+    let mut names: Vec<String> = data_dir()
+        .and_then(|dir| std::fs::read_dir(dir.join(PROFILES_FOLDER)).ok())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| is_valid_name(name))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !names.iter().any(|name| name == active) {
+        names.push(active.to_string());
+    }
+    names.sort();
+    names
+}
+
+// totals over every run the profile has finished, by dying or by winning
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStats {
+    pub runs: u32,
+    pub victories: u32,
+    pub deepest: u32,
+    pub kills: u32,
+    pub gold: u32,
+}
+
+// the profile screen's list, and the new name being typed in if there is one
+#[derive(Default)]
+struct ProfileList {
+    names: Vec<String>,
+    cursor: usize,
+    naming: Option<String>,
+}
+
+struct ProfileScreen;
+struct ProfileText;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(ProfileList::default())
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::GameOver).with_system(record_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Victory).with_system(record_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Profiles).with_system(show_profiles.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::Profiles)
+                    .with_system(profiles_input.system().label("profiles_input"))
+                    .with_system(update_profiles_text.system().after("profiles_input")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::Profiles).with_system(close_profiles.system()),
+            );
+    }
+}
+
+fn record_run(
+    app_state: Res<State<AppState>>,
+    profile: Res<Profile>,
+    game_state: Res<GameState>,
+    run_stats: Res<RunStats>,
+    gold: Res<Gold>,
+    replay: Res<Replay>,
+) {
+    // watching a run back doesn't count as playing one
+    if replay.is_playing() {
+        return;
+    }
+    let mut stats = profile.stats();
+    stats.runs += 1;
+    if *app_state.current() == AppState::Victory {
+        stats.victories += 1;
+    }
+    stats.deepest = stats.deepest.max(game_state.depth);
+    stats.kills += run_stats.kills;
+    stats.gold += gold.0;
+    profile.save_stats(&stats);
+}
+
+fn show_profiles(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    profile: Res<Profile>,
+    mut list: ResMut<ProfileList>,
+) {
+    let names = list_profiles(&profile.name);
+    *list = ProfileList {
+        cursor: names
+            .iter()
+            .position(|name| *name == profile.name)
+            .unwrap_or(0),
+        names,
+        naming: None,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(ProfileScreen)
+        .with_children(|parent| {
+            // filled in by update_profiles_text
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 20.,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ProfileText);
+        });
+}
+
+// up and down pick a profile and enter switches to it, N names a new one.
+// picking one brings its settings and unlocks with it
+fn profiles_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    mut list: ResMut<ProfileList>,
+    mut profile: ResMut<Profile>,
+    mut settings: ResMut<Settings>,
+    mut unlocks: ResMut<Unlocks>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    // read every frame so letters typed before naming started don't turn up in the name
+    let typed: Vec<char> = ev_chars.iter().map(|ev| ev.char).collect();
+    // the list is only borrowed mutably on a key, the screen redraws whenever it changes
+    if let Some(name) = list.naming.clone() {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            keyboard_input.reset(KeyCode::Escape);
+            list.naming = None;
+        } else if keyboard_input.just_pressed(KeyCode::Back) {
+            list.naming = Some(name[..name.len().saturating_sub(1)].to_string());
+        } else if keyboard_input.just_pressed(KeyCode::Return) {
+            let name = name.trim().to_string();
+            if is_valid_name(&name) {
+                if !list.names.contains(&name) {
+                    list.names.push(name.clone());
+                    list.names.sort();
+                }
+                list.cursor = list.names.iter().position(|n| *n == name).unwrap_or(0);
+                list.naming = None;
+            }
+        } else if !typed.is_empty() {
+            let mut name = name;
+            for c in typed {
+                let mut longer = name.clone();
+                longer.push(c);
+                if is_valid_name(longer.trim_start()) {
+                    name = longer;
+                }
+            }
+            list.naming = Some(name);
+        }
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // the main menu underneath would see it too
+        keyboard_input.reset(KeyCode::Escape);
+        app_state.pop().ok();
+        return;
+    }
+    let count = list.names.len().max(1);
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        list.cursor = (list.cursor + count - 1) % count;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        list.cursor = (list.cursor + 1) % count;
+    }
+    if keyboard_input.just_pressed(KeyCode::N) {
+        list.naming = Some(String::new());
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        // enter on the main menu starts a new game
+        keyboard_input.reset(KeyCode::Return);
+        if let Some(name) = list.names.get(list.cursor) {
+            if *name != profile.name {
+                profile.name = name.clone();
+                *settings = Settings::load(&profile);
+                *unlocks = Unlocks::load(&profile);
+            }
+            profile.remember();
+        }
+        app_state.pop().ok();
+    }
+}
+
+fn update_profiles_text(
+    font: Res<UiFont>,
+    profile: Res<Profile>,
+    list: Res<ProfileList>,
+    mut text_query: Query<&mut Text, With<ProfileText>>,
+    new_text_query: Query<(), Added<ProfileText>>,
+) {
+    if !list.is_changed() && new_text_query.iter().next().is_none() {
+        return;
+    }
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: "Profiles\n".to_string(),
+        style: style(36., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    for (i, name) in list.names.iter().enumerate() {
+        let selected = i == list.cursor && list.naming.is_none();
+        let color = if selected {
+            Color::rgb(1., 0.9, 0.5)
+        } else {
+            Color::WHITE
+        };
+        let marker = if selected { ">" } else { " " };
+        let active = if *name == profile.name {
+            "  (current)"
+        } else {
+            ""
+        };
+        sections.push(TextSection {
+            value: format!("\n{} {}{}", marker, name, active),
+            style: style(20., color),
+        });
+    }
+    let footer = match &list.naming {
+        Some(name) => {
+            sections.push(TextSection {
+                value: format!("\n> {}_", name),
+                style: style(20., Color::rgb(1., 0.9, 0.5)),
+            });
+            "[Enter] add  [Esc] cancel".to_string()
+        }
+        None => {
+            if let Some(name) = list.names.get(list.cursor) {
+                let stats = Profile { name: name.clone() }.stats();
+                sections.push(TextSection {
+                    value: format!(
+                        "\n\nRuns: {}  Victories: {}\nDeepest floor: {}\nKills: {}  Gold: {}",
+                        stats.runs, stats.victories, stats.deepest, stats.kills, stats.gold
+                    ),
+                    style: style(16., Color::rgb(0.7, 0.8, 0.9)),
+                });
+            }
+            "[Up/Down] select  [Enter] use  [N] new profile  [Esc] back".to_string()
+        }
+    };
+    sections.push(TextSection {
+        value: format!("\n\n{}", footer),
+        style: style(14., Color::GRAY),
+    });
+    if let Ok(mut text) = text_query.single_mut() {
+        text.sections = sections;
+    }
+}
+
+fn close_profiles(mut commands: Commands, screen_query: Query<Entity, With<ProfileScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
@@ -0,0 +1,113 @@
+use crate::prelude::*;
+use crate::visibility::line;
+use bevy::prelude::*;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<FireProjectileEvent>()
+            .add_event::<ProjectileLandedEvent>()
+            .add_system(spawn_projectiles.system().after("ai"))
+            .add_system(advance_projectiles.system().after("animate"));
+    }
+}
+
+fn spawn_projectiles(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    window: Res<WinSize>,
+    mut ev_fire: EventReader<FireProjectileEvent>,
+) {
+    for fire in ev_fire.iter() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                // spells glow, arrows don't
+                material: if fire.power.is_some() {
+                    materials.spell.clone()
+                } else {
+                    materials.projectile.clone()
+                },
+                sprite: Sprite::new(Vec2::new(window.tile / 4., window.tile / 4.)),
+                transform: Transform {
+                    translation: fire.from.to_world(window.tile).extend(11.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Projectile {
+                source: fire.source,
+                path: line(&fire.from, &fire.to),
+                next: 0,
+                hit: None,
+                power: fire.power,
+                element: fire.element,
+                payload: fire.payload.clone(),
+            })
+            .insert(Speed(20.))
+            .insert(fire.from);
+    }
+}
+
+// once a projectile lands on a tile, either hit what's there or fly on to the next one
+fn advance_projectiles(
+    mut commands: Commands,
+    mut ev_attack: EventWriter<AttackEvent>,
+    mut ev_landed: EventWriter<ProjectileLandedEvent>,
+    map_query: Query<&Map>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut GridPos), Without<MovingTo>>,
+    actor_query: Query<(Entity, &GridPos), (With<BlocksMovement>, Without<Projectile>)>,
+) {
+    if let Ok(current_map) = map_query.single() {
+        for (entity, mut projectile, mut location) in projectile_query.iter_mut() {
+            // whatever it was carrying comes down where it stops
+            let mut land = |commands: &mut Commands, projectile: &Projectile| {
+                if let Some(item) = &projectile.payload {
+                    ev_landed.send(ProjectileLandedEvent {
+                        source: projectile.source,
+                        item: item.clone(),
+                        location: *location,
+                    });
+                }
+                commands.entity(entity).despawn();
+            };
+            // arrived on top of its target
+            if let Some(target) = projectile.hit {
+                if !projectile
+                    .payload
+                    .as_ref()
+                    .is_some_and(|item| item.shatters())
+                {
+                    ev_attack.send(AttackEvent {
+                        attacker: projectile.source,
+                        target,
+                        ranged: true,
+                        power: projectile.power,
+                        element: projectile.element,
+                    });
+                }
+                land(&mut commands, &projectile);
+                continue;
+            }
+            let next = match projectile.path.get(projectile.next) {
+                Some(next) => *next,
+                None => {
+                    // flew its whole path without hitting anything
+                    land(&mut commands, &projectile);
+                    continue;
+                }
+            };
+            if !current_map.0.is_walkable(next.x, next.y) {
+                land(&mut commands, &projectile);
+                continue;
+            }
+            projectile.hit = actor_query
+                .iter()
+                .find(|(e, loc)| *e != projectile.source && loc.x == next.x && loc.y == next.y)
+                .map(|(e, _)| e);
+            projectile.next += 1;
+            *location = next;
+            commands.entity(entity).insert(MovingTo(next));
+        }
+    }
+}
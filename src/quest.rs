@@ -0,0 +1,276 @@
+use crate::enemy::{EnemyKind, EnemyTemplates};
+use crate::gold::Gold;
+use crate::inventory::Inventory;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct QuestPlugin;
+
+// a hunt asks for this share of the floor's enemies, at least one
+const HUNT_SHARE: f32 = 0.5;
+// rewards scale with how deep the floor is
+const GOLD_REWARD: u32 = 15;
+const XP_REWARD: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Objective {
+    // kill one enemy of this kind
+    Slay(EnemyKind),
+    // find the amulet somewhere on the floor and pick it up
+    Recover,
+    // kill this many enemies, and how many are down so far
+    Hunt { target: u32, killed: u32 },
+}
+
+// the current floor's objective, optional and rewarded on completion
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct QuestLog {
+    pub objective: Option<Objective>,
+    pub done: bool,
+    // the target went some way other than by the player's hand, done without the reward
+    #[serde(default)]
+    pub failed: bool,
+    // objectives finished this run
+    pub completed: u32,
+    // a new floor is up and waiting for its objective. it's rolled the frame after
+    // the map appears, once the floor's enemies have been spawned
+    pending: bool,
+}
+
+// the line in the hud, see hud::spawn_hud
+pub struct QuestText;
+
+impl Plugin for QuestPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(QuestLog::default())
+            .add_system(seed_objective.system().label("objective").after("cleanup"))
+            // before a respawn swaps the objective out from under the floor it was set on
+            .add_system(
+                track_kills
+                    .system()
+                    .after("combat")
+                    .after("objective")
+                    .before("respawn"),
+            )
+            .add_system(track_amulet.system().after("inventory"))
+            .add_system(update_tracker.system());
+    }
+}
+
+// picks something to do from what the floor actually has in it
+fn seed_objective(
+    mut commands: Commands,
+    templates: Res<EnemyTemplates>,
+    (item_materials, window): (Res<ItemMaterials>, Res<WinSize>),
+    mut quest_log: ResMut<QuestLog>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    mut log: ResMut<MessageLog>,
+    (game_state, game_rng): (Res<GameState>, Res<GameRng>),
+    new_map_query: Query<(), (Added<Map>, Without<Restored>)>,
+    map_query: Query<&MapRooms, With<Map>>,
+    enemy_query: Query<&EnemyKind, With<Enemy>>,
+) {
+    if new_map_query.iter().next().is_some() {
+        quest_log.objective = None;
+        quest_log.done = false;
+        quest_log.failed = false;
+        quest_log.pending = true;
+        return;
+    }
+    if !quest_log.pending {
+        return;
+    }
+    let map_rooms = match map_query.single() {
+        Ok(map_rooms) => map_rooms,
+        Err(_) => return,
+    };
+    quest_log.pending = false;
+    let mut rng = game_rng.floor(game_state.depth, "objective");
+    // sorted, the query hands them back in whatever order they were stored
+    let mut kinds: Vec<EnemyKind> = enemy_query.iter().copied().collect();
+    kinds.sort();
+    let objective = match rng.gen_range(0..3) {
+        0 if !kinds.is_empty() => Objective::Slay(*kinds.choose(&mut rng).unwrap()),
+        1 if !kinds.is_empty() => Objective::Hunt {
+            target: ((kinds.len() as f32 * HUNT_SHARE).ceil() as u32).max(1),
+            killed: 0,
+        },
+        _ => {
+            // the amulet goes anywhere but the starting room
+            let room = map_rooms
+                .rooms
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != map_rooms.spawn_room)
+                .map(|(_, room)| room)
+                .collect::<Vec<_>>()
+                .choose(&mut rng)
+                .copied();
+            match room.and_then(|room| spawn_tiles.claim_in(room, &mut rng)) {
+                Some(loc) => {
+                    let amulet = Item::new(ItemKind::Amulet);
+                    spawn_item(&mut commands, &item_materials, &window, amulet, loc);
+                    Objective::Recover
+                }
+                None => return,
+            }
+        }
+    };
+    quest_log.objective = Some(objective);
+    log.add(format!(
+        "New objective: {}.",
+        describe(&objective, &templates)
+    ));
+}
+
+// "Slay the orc", "Recover the amulet", "Kill 4 enemies (1/4)"
+fn describe(objective: &Objective, templates: &EnemyTemplates) -> String {
+    match objective {
+        Objective::Slay(kind) => format!(
+            "Slay the {}",
+            templates.get(kind).map_or("foe", |template| &template.name)
+        ),
+        Objective::Recover => "Recover the amulet".to_string(),
+        Objective::Hunt { target, killed } => {
+            format!(
+                "Kill {} enemies ({}/{})",
+                target,
+                killed.min(target),
+                target
+            )
+        }
+    }
+}
+
+fn complete(
+    quest_log: &mut QuestLog,
+    depth: u32,
+    gold: &mut Gold,
+    xp: &mut Experience,
+    log: &mut MessageLog,
+) {
+    let (gold_reward, xp_reward) = (GOLD_REWARD * depth.max(1), XP_REWARD * depth.max(1));
+    quest_log.done = true;
+    quest_log.completed += 1;
+    gold.0 += gold_reward;
+    xp.0 += xp_reward;
+    log.add(format!(
+        "Objective complete! You earn {} gold and {} xp.",
+        gold_reward, xp_reward
+    ));
+}
+
+// only the player's own kills count. a slay target that goes any other way, killed by
+// something else or lost down a collapse, fails the objective
+fn track_kills(
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    mut quest_log: ResMut<QuestLog>,
+    mut gold: ResMut<Gold>,
+    mut log: ResMut<MessageLog>,
+    mut ev_death: EventReader<DeathEvent>,
+    map_query: Query<(), With<Map>>,
+    new_map_query: Query<(), Added<Map>>,
+    enemy_query: Query<&EnemyKind, With<Enemy>>,
+    mut player_query: Query<&mut Experience, With<Player>>,
+) {
+    for death in ev_death.iter() {
+        let kind = match enemy_query.get(death.entity) {
+            Ok(kind) => *kind,
+            Err(_) => continue,
+        };
+        if quest_log.done || player_query.get_mut(death.killer).is_err() {
+            continue;
+        }
+        let finished = match &mut quest_log.objective {
+            Some(Objective::Slay(target)) => *target == kind,
+            Some(Objective::Hunt { target, killed }) => {
+                *killed += 1;
+                killed >= target
+            }
+            _ => false,
+        };
+        if let (true, Ok(mut xp)) = (finished, player_query.single_mut()) {
+            complete(
+                &mut quest_log,
+                game_state.depth,
+                &mut gold,
+                &mut xp,
+                &mut log,
+            );
+        }
+    }
+
+    // a floor that's only just come up hasn't had its enemies spawned yet
+    let settled = map_query.iter().next().is_some() && new_map_query.iter().next().is_none();
+    if let (Some(Objective::Slay(target)), false, true) =
+        (quest_log.objective, quest_log.done, settled)
+    {
+        if !enemy_query.iter().any(|kind| *kind == target) {
+            quest_log.done = true;
+            quest_log.failed = true;
+            log.add(format!(
+                "The {} is gone. Objective failed.",
+                templates
+                    .get(&target)
+                    .map_or("foe", |template| &template.name)
+            ));
+        }
+    }
+}
+
+// the amulet is handed in as soon as it's in the pack
+fn track_amulet(
+    game_state: Res<GameState>,
+    mut quest_log: ResMut<QuestLog>,
+    mut gold: ResMut<Gold>,
+    mut log: ResMut<MessageLog>,
+    mut player_query: Query<(&mut Inventory, &mut Experience), (With<Player>, Changed<Inventory>)>,
+) {
+    if quest_log.done || quest_log.objective != Some(Objective::Recover) {
+        return;
+    }
+    if let Ok((mut inventory, mut xp)) = player_query.single_mut() {
+        let amulet = inventory
+            .items
+            .iter()
+            .position(|item| item.kind == ItemKind::Amulet);
+        if let Some(i) = amulet {
+            inventory.items.remove(i);
+            complete(
+                &mut quest_log,
+                game_state.depth,
+                &mut gold,
+                &mut xp,
+                &mut log,
+            );
+        }
+    }
+}
+
+fn update_tracker(
+    quest_log: Res<QuestLog>,
+    templates: Res<EnemyTemplates>,
+    mut text_query: Query<&mut Text, With<QuestText>>,
+) {
+    if !quest_log.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        text.sections[0].value = match &quest_log.objective {
+            Some(objective) if quest_log.failed => {
+                format!("{} (failed)", describe(objective, &templates))
+            }
+            Some(objective) if quest_log.done => {
+                format!("{} (done)", describe(objective, &templates))
+            }
+            Some(objective) => describe(objective, &templates),
+            None => "No objective".to_string(),
+        };
+    }
+}
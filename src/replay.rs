@@ -0,0 +1,482 @@
+use crate::class::PlayerClass;
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::unlocks::Unlocks;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub struct ReplayPlugin;
+
+// the last run the profile played, overwritten by the next one
+const REPLAY_FILE: &str = "replay.ron";
+// bumped whenever a change to the game would make old replays play out differently
+const REPLAY_VERSION: u32 = 8;
+// seconds between recorded inputs at normal speed, however long the player took over them
+const PLAYBACK_DELAY: f32 = 0.15;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum ReplayKey {
+    Press(KeyCode),
+    Release(KeyCode),
+}
+
+// the inputs for one frame the game was waiting on the player. keys pressed while it
+// wasn't are saved up for the next one that is, so playback doesn't depend on how long
+// animations took
+#[derive(Clone, Serialize, Deserialize)]
+struct ReplayFrame {
+    // which waiting frame of the run this was, only needed while recording
+    #[serde(skip)]
+    ready: u32,
+    // the turn it was on, a replay that disagrees has drifted from the recording
+    turn: u32,
+    keys: Vec<ReplayKey>,
+    // a step taken by a held arrow or a clicked path, neither of which shows up as keys
+    #[serde(default)]
+    step: Option<(i32, i32)>,
+}
+
+// one waiting frame of a scripted run: a step in some direction, or a key tapped
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ScriptedInput {
+    Step(i32, i32),
+    Tap(KeyCode),
+}
+
+// everything it takes to play a run out again: how it started and what the player did
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ReplayRun {
+    version: u32,
+    seed: u64,
+    class: PlayerClass,
+    layout: FloorLayout,
+    #[serde(default)]
+    mode: GameMode,
+    #[serde(default)]
+    difficulty: Difficulty,
+    // started on the tutorial floor
+    #[serde(default)]
+    tutorial: bool,
+    // the starting kit depends on what the profile had unlocked at the time
+    unlocks: Unlocks,
+    frames: Vec<ReplayFrame>,
+    // written by hand rather than recorded, so there are no turns to check against
+    #[serde(skip)]
+    scripted: bool,
+}
+
+impl ReplayRun {
+    // a run that plays a script instead of a recording, with nothing unlocked. each input
+    // goes in on a waiting frame of its own, and a tapped key is let go on the next one
+    pub fn scripted(
+        seed: u64,
+        class: PlayerClass,
+        layout: FloorLayout,
+        mode: GameMode,
+        difficulty: Difficulty,
+        inputs: &[ScriptedInput],
+    ) -> Self {
+        let mut frames = Vec::new();
+        let mut tapped: Option<KeyCode> = None;
+        for input in inputs {
+            let mut keys: Vec<ReplayKey> =
+                tapped.take().map(ReplayKey::Release).into_iter().collect();
+            let step = match *input {
+                ScriptedInput::Step(dx, dy) => Some((dx, dy)),
+                ScriptedInput::Tap(key) => {
+                    keys.push(ReplayKey::Press(key));
+                    tapped = Some(key);
+                    None
+                }
+            };
+            frames.push(ReplayFrame {
+                ready: 0,
+                turn: 0,
+                keys,
+                step,
+            });
+        }
+        ReplayRun {
+            version: REPLAY_VERSION,
+            seed,
+            class,
+            layout,
+            mode,
+            difficulty,
+            tutorial: false,
+            unlocks: Unlocks::default(),
+            frames,
+            scripted: true,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn class(&self) -> PlayerClass {
+        self.class
+    }
+
+    pub fn layout(&self) -> FloorLayout {
+        self.layout
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn tutorial(&self) -> bool {
+        self.tutorial
+    }
+
+    pub fn unlocks(&self) -> &Unlocks {
+        &self.unlocks
+    }
+}
+
+#[derive(PartialEq, Default)]
+enum ReplayMode {
+    #[default]
+    Off,
+    Recording,
+    Playing,
+}
+
+// records every fresh run, or plays a recorded one back in its place
+#[derive(Default)]
+pub struct Replay {
+    mode: ReplayMode,
+    run: ReplayRun,
+    // keys pressed or let go since the last waiting frame
+    pending: Vec<ReplayKey>,
+    // keys down as far as the recording or playback knows
+    held: Vec<KeyCode>,
+    // waiting frames so far, and the turn the latest one was on
+    ready: u32,
+    turn: u32,
+    // playback: the next frame to play, the step it hands the player systems and the pace
+    next: usize,
+    step: Option<(i32, i32)>,
+    speed: f32,
+    wait: f32,
+    drifted: bool,
+    ended: bool,
+    // hands over each frame as soon as the game is waiting, for a run nobody is watching
+    unpaced: bool,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Replay::default())
+            // straight after the keyboard is read, before anything gets to see it
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                record_input.system().label("replay").after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                play_back.system().label("replay").after(InputSystem),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::GameOver).with_system(save_recording.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Victory).with_system(save_recording.system()),
+            )
+            // a replay carries on through its own end screen, leaving it stops it
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::GameOver).with_system(end_replay.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::Victory).with_system(end_replay.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::MainMenu).with_system(end_replay.system()),
+            );
+    }
+}
+
+fn replay_path(profile: &Profile) -> Option<PathBuf> {
+    Some(profile.dir()?.join(REPLAY_FILE))
+}
+
+pub fn load_replay(profile: &Profile) -> Result<ReplayRun, String> {
+    let path = replay_path(profile).ok_or("there's no data folder")?;
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let run: ReplayRun = ron::de::from_str(&data).map_err(|e| e.to_string())?;
+    if run.version != REPLAY_VERSION {
+        return Err("it was recorded by another version of the game".to_string());
+    }
+    Ok(run)
+}
+
+fn save_replay(profile: &Profile, run: &ReplayRun) {
+    let path = match replay_path(profile) {
+        Some(path) => path,
+        None => return,
+    };
+    // not pretty, a long run is a lot of frames
+    let saved = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .map_err(|e| e.to_string())
+        .and_then(|_| ron::ser::to_string(run).map_err(|e| e.to_string()))
+        .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        warn!("couldn't save {}: {}", path.display(), e);
+    }
+}
+
+impl Replay {
+    pub fn is_playing(&self) -> bool {
+        self.mode == ReplayMode::Playing
+    }
+
+    // the run being played back, which the class screen starts instead of asking
+    pub fn playing(&self) -> Option<&ReplayRun> {
+        match self.mode {
+            ReplayMode::Playing => Some(&self.run),
+            _ => None,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        seed: u64,
+        class: PlayerClass,
+        layout: FloorLayout,
+        mode: GameMode,
+        difficulty: Difficulty,
+        tutorial: bool,
+        unlocks: &Unlocks,
+    ) {
+        *self = Replay {
+            mode: ReplayMode::Recording,
+            run: ReplayRun {
+                version: REPLAY_VERSION,
+                seed,
+                class,
+                layout,
+                mode,
+                difficulty,
+                tutorial,
+                unlocks: unlocks.clone(),
+                frames: Vec::new(),
+                scripted: false,
+            },
+            ..Default::default()
+        };
+    }
+
+    pub fn play(&mut self, run: ReplayRun) {
+        *self = Replay {
+            mode: ReplayMode::Playing,
+            run,
+            speed: 1.,
+            ..Default::default()
+        };
+    }
+
+    // the same, without waiting between frames
+    pub fn play_unpaced(&mut self, run: ReplayRun) {
+        self.play(run);
+        self.unpaced = true;
+    }
+
+    // playback has run out of frames to play
+    pub fn has_ended(&self) -> bool {
+        self.ended
+    }
+
+    // a step the player systems took on this waiting frame
+    pub fn note_step(&mut self, step: (i32, i32)) {
+        if self.mode != ReplayMode::Recording {
+            return;
+        }
+        let (ready, turn) = (self.ready, self.turn);
+        match self.run.frames.last_mut() {
+            Some(frame) if frame.ready == ready => frame.step = Some(step),
+            _ => self.run.frames.push(ReplayFrame {
+                ready,
+                turn,
+                keys: Vec::new(),
+                step: Some(step),
+            }),
+        }
+    }
+
+    // the recorded step for this frame, taken in place of the move buffer's
+    pub fn take_step(&mut self) -> Option<(i32, i32)> {
+        self.step.take()
+    }
+}
+
+// the player systems only act on these frames
+fn is_waiting(game_state: &GameState) -> bool {
+    game_state.has_map
+        && !game_state.animating_actions
+        && game_state.phase == TurnPhase::PlayerInput
+}
+
+fn record_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<GameState>,
+    mut replay: ResMut<Replay>,
+) {
+    if replay.mode != ReplayMode::Recording
+        || *app_state.current() != AppState::InGame
+        || matches!(game_state.phase, TurnPhase::NewGame | TurnPhase::GameOver)
+    {
+        return;
+    }
+    let replay = &mut *replay;
+    // keys let go of while paused are caught here too
+    for key in replay.held.iter() {
+        if !keyboard_input.pressed(*key) {
+            replay.pending.push(ReplayKey::Release(*key));
+        }
+    }
+    replay.held.retain(|key| keyboard_input.pressed(*key));
+    for key in keyboard_input.get_just_pressed() {
+        replay.pending.push(ReplayKey::Press(*key));
+        if !replay.held.contains(key) {
+            replay.held.push(*key);
+        }
+    }
+    if !is_waiting(&game_state) {
+        return;
+    }
+    replay.ready += 1;
+    replay.turn = game_state.turn;
+    if !replay.pending.is_empty() {
+        replay.run.frames.push(ReplayFrame {
+            ready: replay.ready,
+            turn: replay.turn,
+            keys: std::mem::take(&mut replay.pending),
+            step: None,
+        });
+    }
+}
+
+// the keyboard only steers the playback: [ and ] change the speed, Esc stops watching.
+// the recorded keys are fed in one waiting frame at a time
+fn play_back(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    time: Res<Time>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_state: ResMut<GameState>,
+    mut log: ResMut<MessageLog>,
+    mut replay: ResMut<Replay>,
+) {
+    if replay.mode != ReplayMode::Playing {
+        return;
+    }
+    let replay = &mut *replay;
+    let pressed: Vec<KeyCode> = keyboard_input
+        .get_just_pressed()
+        .copied()
+        .filter(|key| !replay.held.contains(key))
+        .collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+        match key {
+            KeyCode::Escape => {
+                game_state.phase = TurnPhase::NewGame;
+                app_state.set(AppState::MainMenu).ok();
+                return;
+            }
+            KeyCode::LBracket | KeyCode::RBracket => {
+                replay.speed = if key == KeyCode::LBracket {
+                    (replay.speed / 2.).max(MIN_SPEED)
+                } else {
+                    (replay.speed * 2.).min(MAX_SPEED)
+                };
+                log.add(format!("Replay speed: {}x", replay.speed));
+            }
+            _ => {}
+        }
+    }
+    if *app_state.current() != AppState::InGame || !is_waiting(&game_state) {
+        return;
+    }
+    if !replay.unpaced {
+        replay.wait -= time.delta_seconds() * replay.speed;
+        if replay.wait > 0. {
+            return;
+        }
+        replay.wait = PLAYBACK_DELAY;
+    }
+    let frame = match replay.run.frames.get(replay.next) {
+        Some(frame) => frame.clone(),
+        None => {
+            if !replay.ended && !replay.unpaced {
+                log.add("The replay ends here. [Esc] stops watching.");
+            }
+            replay.ended = true;
+            return;
+        }
+    };
+    replay.next += 1;
+    if !replay.run.scripted && frame.turn != game_state.turn && !replay.drifted {
+        replay.drifted = true;
+        log.add(format!(
+            "The replay has drifted from the recording on turn {}.",
+            frame.turn
+        ));
+    }
+    for key in frame.keys {
+        match key {
+            ReplayKey::Press(key) => {
+                keyboard_input.press(key);
+                replay.held.push(key);
+            }
+            ReplayKey::Release(key) => {
+                keyboard_input.release(key);
+                replay.held.retain(|held| *held != key);
+            }
+        }
+    }
+    replay.step = frame.step;
+}
+
+// kept as soon as the run ends, in case the game is closed on the end screen
+fn save_recording(profile: Res<Profile>, mut replay: ResMut<Replay>) {
+    if replay.mode == ReplayMode::Recording {
+        save_replay(&profile, &replay.run);
+        replay.mode = ReplayMode::Off;
+    }
+}
+
+// quitting a run part way through still leaves a replay of it
+fn end_replay(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    profile: Res<Profile>,
+    mut replay: ResMut<Replay>,
+) {
+    if replay.mode == ReplayMode::Recording {
+        save_replay(&profile, &replay.run);
+    }
+    // the keys the replay was holding down would stay stuck otherwise
+    if replay.mode == ReplayMode::Playing {
+        for key in replay.held.drain(..) {
+            keyboard_input.release(key);
+        }
+    }
+    replay.mode = ReplayMode::Off;
+}
@@ -0,0 +1,403 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// taking the stairs on this floor wins the run
+pub const FINAL_DEPTH: u32 = 10;
+
+pub struct Materials {
+    pub projectile: Handle<ColorMaterial>,
+    pub danger: Handle<ColorMaterial>,
+    pub panel: Handle<ColorMaterial>,
+    pub spell: Handle<ColorMaterial>,
+    pub target: Handle<ColorMaterial>,
+    pub health_back: Handle<ColorMaterial>,
+    pub health_fill: Handle<ColorMaterial>,
+    pub corpse: Handle<ColorMaterial>,
+    pub fountain: Handle<ColorMaterial>,
+    pub bookshelf: Handle<ColorMaterial>,
+    pub brazier: Handle<ColorMaterial>,
+    pub brazier_lit: Handle<ColorMaterial>,
+    pub lever: Handle<ColorMaterial>,
+    pub lever_pulled: Handle<ColorMaterial>,
+    pub plate: Handle<ColorMaterial>,
+    pub spikes: Handle<ColorMaterial>,
+    pub boulder: Handle<ColorMaterial>,
+}
+
+impl Materials {
+    // the panel is left out, it's already as dark as it goes
+    pub fn tinted(&self) -> [&Handle<ColorMaterial>; 16] {
+        [
+            &self.projectile,
+            &self.danger,
+            &self.spell,
+            &self.target,
+            &self.health_back,
+            &self.health_fill,
+            &self.corpse,
+            &self.fountain,
+            &self.bookshelf,
+            &self.brazier,
+            &self.brazier_lit,
+            &self.lever,
+            &self.lever_pulled,
+            &self.plate,
+            &self.spikes,
+            &self.boulder,
+        ]
+    }
+}
+
+// font shared by every piece of on-screen text
+pub struct UiFont(pub Handle<Font>);
+
+// how far a floor spreads, picked on the class screen once a profile has unlocked more than one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug, Serialize, Deserialize)]
+pub enum FloorLayout {
+    #[default]
+    Standard,
+    // more, smaller rooms over a bigger map
+    Sprawling,
+}
+
+impl FloorLayout {
+    pub const ALL: [FloorLayout; 2] = [FloorLayout::Standard, FloorLayout::Sprawling];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FloorLayout::Standard => "Standard",
+            FloorLayout::Sprawling => "Sprawling",
+        }
+    }
+}
+
+// each stretch of the dungeon has its own music, deeper ones are grimmer
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FloorTheme {
+    Cellars,
+    Catacombs,
+    Abyss,
+}
+
+impl FloorTheme {
+    pub const ALL: [FloorTheme; 3] = [
+        FloorTheme::Cellars,
+        FloorTheme::Catacombs,
+        FloorTheme::Abyss,
+    ];
+
+    pub fn at(depth: u32) -> FloorTheme {
+        match depth {
+            0..=3 => FloorTheme::Cellars,
+            4..=7 => FloorTheme::Catacombs,
+            _ => FloorTheme::Abyss,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FloorTheme::Cellars => "cellars",
+            FloorTheme::Catacombs => "catacombs",
+            FloorTheme::Abyss => "abyss",
+        }
+    }
+}
+
+// what dying costs, picked on the class screen and kept with the save
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub enum GameMode {
+    // the run ends and its save is deleted
+    #[default]
+    Classic,
+    // back to the start of the floor, minus some gold
+    Checkpoint,
+}
+
+impl GameMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Classic => "Permadeath",
+            GameMode::Checkpoint => "Checkpoints",
+        }
+    }
+
+    pub fn toggled(&self) -> GameMode {
+        match self {
+            GameMode::Classic => GameMode::Checkpoint,
+            GameMode::Checkpoint => GameMode::Classic,
+        }
+    }
+}
+
+// how hard the run is, picked on the class screen and kept with the save
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        let current = Difficulty::ALL.iter().position(|d| d == self).unwrap_or(0);
+        Difficulty::ALL[(current + 1) % Difficulty::ALL.len()]
+    }
+
+    pub fn modifiers(&self) -> DifficultyModifiers {
+        match self {
+            Difficulty::Easy => DifficultyModifiers {
+                enemy_stats: 0.8,
+                spawns: 0.75,
+                hunger: 0.75,
+                loot_depth: 2,
+            },
+            Difficulty::Normal => DifficultyModifiers::default(),
+            Difficulty::Hard => DifficultyModifiers {
+                enemy_stats: 1.25,
+                spawns: 1.35,
+                hunger: 1.25,
+                loot_depth: -1,
+            },
+        }
+    }
+}
+
+// what the run's Difficulty changes, for the systems it touches to look up
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DifficultyModifiers {
+    // enemy hp and attack are multiplied by this as they spawn
+    pub enemy_stats: f32,
+    // the same for how many enemies a floor starts with and how many wander in later
+    pub spawns: f32,
+    // fullness lost a turn
+    pub hunger: f32,
+    // floors added to the depth loot rolls its rarity for, fewer makes for worse gear
+    pub loot_depth: i32,
+}
+
+impl Default for DifficultyModifiers {
+    fn default() -> Self {
+        DifficultyModifiers {
+            enemy_stats: 1.,
+            spawns: 1.,
+            hunger: 1.,
+            loot_depth: 0,
+        }
+    }
+}
+
+impl DifficultyModifiers {
+    pub fn scale_stats(&self, stats: &mut Stats) {
+        let scale = |value: i32| ((value as f32 * self.enemy_stats).round() as i32).max(1);
+        stats.max_hp = scale(stats.max_hp);
+        stats.hp = stats.max_hp;
+        stats.attack = scale(stats.attack);
+    }
+
+    pub fn spawns(&self, count: u32) -> u32 {
+        (count as f32 * self.spawns).round() as u32
+    }
+
+    pub fn loot_depth(&self, depth: u32) -> u32 {
+        (depth as i32 + self.loot_depth).max(0) as u32
+    }
+}
+
+// the size of the window, kept up to date as it's resized
+pub struct WinSize {
+    pub w: f32,
+    pub h: f32,
+    pub tile: f32,
+}
+
+#[derive(Default)]
+pub struct CameraCenter(pub f32, pub f32);
+
+// the scale of the map camera's projection, above 1 shows more of the map
+pub struct CameraZoom(pub f32);
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+// which screen the game is on. the whole update stage only runs InGame, the menus and
+// the screens between runs are driven from the app_state stage that runs ahead of it
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AppState {
+    MainMenu,
+    InGame,
+    // pushed on top of InGame, popping it carries on where the run left off
+    Paused,
+    // pushed on top of the main menu or the pause screen
+    Settings,
+    // pushed on top of the main menu
+    Profiles,
+    HighScores,
+    GameOver,
+    Victory,
+}
+
+// a turn is the player acting, then their allies, then every enemy acting at once,
+// with each part waiting for its animations to finish
+#[derive(PartialEq)]
+pub enum TurnPhase {
+    PlayerInput,
+    PlayerAnimating,
+    AllyAction,
+    AllyAnimating,
+    EnemyAction,
+    EnemyAnimating,
+    // the player died, nothing acts until a new run is started
+    GameOver,
+    // a class is being picked for a new run, nothing acts until one is chosen
+    NewGame,
+}
+
+pub struct GameState {
+    pub has_map: bool,
+    // true while any actor still has a MovingTo
+    pub animating_actions: bool,
+    pub depth: u32,
+    pub turn: u32,
+    pub phase: TurnPhase,
+    // the run starts on the tutorial floor, until the player takes its stairs
+    pub tutorial: bool,
+}
+
+// the first floor is depth 1, and every run starts by picking a class
+impl Default for GameState {
+    fn default() -> Self {
+        Self {
+            has_map: false,
+            animating_actions: false,
+            depth: 1,
+            turn: 0,
+            phase: TurnPhase::NewGame,
+            tutorial: false,
+        }
+    }
+}
+
+// tallies for the game over screen
+#[derive(Default)]
+pub struct RunStats {
+    pub kills: u32,
+}
+
+// every roll of a run, seeded so the same seed plays out the same run. nothing draws from
+// one shared sequence: the floor spawners each take their own stream per floor, and every
+// system that rolls during play keeps a named stream of its own, so what comes out doesn't
+// depend on the order the scheduler happens to run them in
+pub struct GameRng {
+    pub seed: u64,
+    // where the streams start from, the seed itself or where a load picked the run up
+    origin: u64,
+    streams: HashMap<&'static str, StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            origin: seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    // the only roll that doesn't come from a seed
+    pub fn random() -> Self {
+        Self::new(rand::thread_rng().gen())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // a stream for one spawner on one floor, the same every time for the same seed
+    pub fn floor(&self, depth: u32, stream: &str) -> StdRng {
+        StdRng::seed_from_u64(mix_seed(self.seed, depth, stream))
+    }
+
+    // the stream one system rolls from for the rest of the run, started the first time
+    // it's asked for
+    pub fn stream(&mut self, name: &'static str) -> &mut StdRng {
+        let origin = self.origin;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| StdRng::seed_from_u64(mix_seed(origin, 0, name)))
+    }
+
+    // picks the run up again after a load. the rolls won't match the ones an unbroken run
+    // would have made, but they stay the same for every load of the same save
+    pub fn resume(seed: u64, turn: u32) -> Self {
+        Self {
+            seed,
+            origin: mix_seed(seed, turn, "resume"),
+            streams: HashMap::new(),
+        }
+    }
+}
+
+// fnv-1a, spelled out so a seed means the same floors on every build
+pub fn mix_seed(seed: u64, depth: u32, stream: &str) -> u64 {
+    let bytes = seed
+        .to_le_bytes()
+        .iter()
+        .chain(depth.to_le_bytes().iter())
+        .chain(stream.as_bytes())
+        .copied()
+        .collect::<Vec<u8>>();
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// tiles already handed out on the current floor, shared by everything that
+// places enemies, items or npcs so nothing gets stacked on the same tile. the spawners
+// take their turns in a fixed order (follow, place_boss, place_wanderer, place_shrine,
+// furnish, place_torches, place_chests, place_gold, place_rations, place_enemies) so the
+// same seed lays a floor out the same way every time
+#[derive(Default)]
+pub struct SpawnTiles(pub Vec<GridPos>);
+
+impl SpawnTiles {
+    pub fn is_free(&self, loc: &GridPos) -> bool {
+        !self.0.iter().any(|t| t.x == loc.x && t.y == loc.y)
+    }
+
+    pub fn claim(&mut self, loc: GridPos) -> GridPos {
+        self.0.push(loc);
+        loc
+    }
+
+    // random unclaimed tile in the room, gives up after a few tries
+    pub fn claim_in(&mut self, room: &RoomArea, rng: &mut impl Rng) -> Option<GridPos> {
+        for _ in 0..10 {
+            let loc = GridPos::new(
+                room.left + rng.gen_range(0..room.width),
+                room.bottom + rng.gen_range(0..room.height),
+            );
+            if self.is_free(&loc) {
+                return Some(self.claim(loc));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,774 @@
+use crate::ai::AiState;
+use crate::altar::spawn_altar;
+use crate::boss::Boss;
+use crate::boulder::{spawn_boulder, Boulder};
+use crate::chest::{spawn_chest, Chest};
+use crate::circuit::{spawn_mechanism, spawn_switch, Mechanism, Switch};
+use crate::class::PlayerClass;
+use crate::collapse::Collapse;
+use crate::companion::{recruit, Companion};
+use crate::enemy::{spawn_enemy, Disguised, EnemyKind, EnemyTemplates};
+use crate::fov::Explored;
+use crate::furniture::{spawn_furniture, Furniture};
+use crate::gold::Gold;
+use crate::hunger::Hunger;
+use crate::in_game;
+use crate::inventory::{Equipment, Inventory};
+use crate::item::{spawn_item, Identification, Item, ItemMaterials};
+use crate::level::LevelUp;
+use crate::light::{spawn_torch, Torch};
+use crate::magic::{Casting, Mana, Spell, Spellbook};
+use crate::map::spawn_stairs;
+use crate::message_log::MessageLog;
+use crate::npc::{spawn_npc, Npc, NpcLibrary};
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::quest::QuestLog;
+use crate::replay::Replay;
+use crate::status::{Status, StatusEffects};
+use array2d::Array2D;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub struct SavePlugin;
+
+const SAVE_FILE: &str = "save.ron";
+// bumped whenever the save layout changes, with a step in migrate for the old layout
+const SAVE_VERSION: u32 = 4;
+// dying in checkpoint mode costs this share of the gold carried
+const CHECKPOINT_GOLD_LOST: u32 = 2;
+
+// everything about a run in progress, as it's written to the save file
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    // saves from before versioning have none, see SaveHeader
+    #[serde(default)]
+    version: u32,
+    depth: u32,
+    turn: u32,
+    // older saves get a seed of 0, which only changes the rolls from here on
+    #[serde(default)]
+    seed: u64,
+    class: PlayerClass,
+    // version 1 saves were all made on standard floors
+    #[serde(default)]
+    layout: FloorLayout,
+    // version 3 and older saves are classic, they were never respawned from
+    #[serde(default)]
+    mode: GameMode,
+    // saves from before difficulties were all on normal
+    #[serde(default)]
+    difficulty: Difficulty,
+    gold: u32,
+    kills: u32,
+    identification: Identification,
+    quest_log: QuestLog,
+    player: SavedPlayer,
+    // older saves have none, the allies were lost on loading them
+    #[serde(default)]
+    companions: Vec<SavedCompanion>,
+    floor: SavedFloor,
+}
+
+// read on its own first, so the rest of the file can be parsed the way it was written
+#[derive(Deserialize)]
+struct SaveHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    location: GridPos,
+    stats: Stats,
+    speed: f32,
+    experience: u32,
+    level: u32,
+    mana: (i32, i32),
+    spells: Vec<Spell>,
+    pack: Vec<Item>,
+    capacity: usize,
+    equipment: (Option<Item>, Option<Item>, Option<Item>),
+    statuses: Vec<(Status, u32)>,
+    hunger: u32,
+    #[serde(default)]
+    hunger_owed: f32,
+}
+
+// by their place in the npc library, like the npcs on the floor
+#[derive(Serialize, Deserialize)]
+struct SavedCompanion {
+    npc: usize,
+    location: GridPos,
+    stats: Stats,
+    downed: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEnemy {
+    kind: EnemyKind,
+    location: GridPos,
+    stats: Stats,
+    asleep: bool,
+    disguised: bool,
+    boss: Option<Boss>,
+}
+
+// the current floor, laid out row by row the way Array2D stores it
+#[derive(Serialize, Deserialize)]
+struct SavedFloor {
+    tiles: Vec<Vec<Tile>>,
+    explored: Vec<Vec<bool>>,
+    rooms: Vec<RoomArea>,
+    spawn_room: usize,
+    stairs: Vec<GridPos>,
+    torches: Vec<GridPos>,
+    chests: Vec<(Chest, GridPos)>,
+    furniture: Vec<(Furniture, GridPos)>,
+    // npcs and altars by their place in the npc library
+    npcs: Vec<(usize, GridPos)>,
+    // levers and plates, and the gates, bridges and spikes they work. older saves have none
+    #[serde(default)]
+    switches: Vec<(Switch, Circuit, GridPos)>,
+    #[serde(default)]
+    mechanisms: Vec<(Mechanism, Circuit, GridPos)>,
+    #[serde(default)]
+    boulders: Vec<GridPos>,
+    items: Vec<(Item, GridPos)>,
+    enemies: Vec<SavedEnemy>,
+}
+
+// ctrl+s asks for a save, it's written once the frame's commands have gone through
+#[derive(Default)]
+struct SaveRequest(bool);
+
+// a save picked from the main menu, restored as the run starts
+#[derive(Default)]
+pub struct LoadRequest(pub Option<SaveData>);
+
+// the run as it stood when the player reached the current floor, kept as written to the
+// save file. checkpoint mode puts the player back to it on dying
+#[derive(Default)]
+struct Checkpoint {
+    save: Option<String>,
+    // the gold the last respawn cost, for restore_run to tell the player
+    respawned: Option<u32>,
+}
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SaveRequest::default())
+            .insert_resource(LoadRequest::default())
+            .insert_resource(Checkpoint::default())
+            .insert_resource(GameMode::default())
+            .add_system(save_input.system().before("input"))
+            .add_system(
+                respawn_at_checkpoint
+                    .system()
+                    .label("respawn")
+                    .after("deaths"),
+            )
+            // restores a respawn in the same frame, before the run ends
+            .add_system(restore_run.system().after("respawn"))
+            // after the update stage, so a new floor's enemies and items are in the world
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                write_save.system().with_run_criteria(in_game.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::InGame).with_system(restore_run.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::GameOver).with_system(delete_save.system()),
+            );
+    }
+}
+
+// every profile keeps its own run
+fn save_path(profile: &Profile) -> Option<PathBuf> {
+    Some(profile.dir()?.join(SAVE_FILE))
+}
+
+pub fn has_save(profile: &Profile) -> bool {
+    save_path(profile).is_some_and(|path| path.exists())
+}
+
+// the error reads as the end of "the save can't be loaded, ..." on the main menu
+pub fn load_save(profile: &Profile, templates: &EnemyTemplates) -> Result<SaveData, String> {
+    let path = save_path(profile).ok_or("there's nowhere to keep saves")?;
+    let loaded = std::fs::read_to_string(&path)
+        .map_err(|e| format!("it couldn't be read ({})", e))
+        .and_then(|data| parse_save(&data, templates));
+    if let Err(e) = &loaded {
+        warn!("couldn't load {}: {}", path.display(), e);
+    }
+    loaded
+}
+
+fn parse_save(data: &str, templates: &EnemyTemplates) -> Result<SaveData, String> {
+    let header = ron::de::from_str::<SaveHeader>(data)
+        .map_err(|e| format!("it isn't a save file ({})", e))?;
+    if header.version > SAVE_VERSION {
+        return Err(format!(
+            "it was made by a newer version of the game (save version {}, this one reads up to {})",
+            header.version, SAVE_VERSION
+        ));
+    }
+    let save = migrate(header.version, data).map_err(|e| format!("it's damaged ({})", e))?;
+    // Array2D::from_rows panics on ragged rows, so a hand-edited file is checked first
+    if !save.floor.is_valid() {
+        return Err("its map is damaged".to_string());
+    }
+    // an enemy from a content pack that isn't loaded any more can't be put back
+    if let Some(saved) = save
+        .floor
+        .enemies
+        .iter()
+        .find(|saved| templates.get(&saved.kind).is_none())
+    {
+        return Err(format!(
+            "it has enemies that aren't loaded ({:?})",
+            saved.kind
+        ));
+    }
+    Ok(save)
+}
+
+// parses a save in the layout of its version and brings it up to the current one
+fn migrate(version: u32, data: &str) -> Result<SaveData, String> {
+    let mut save = match version {
+        // the layout before versioning only lacked the version field, version 1 the floor
+        // layout, version 2 the seed and version 3 the game mode. all of them default
+        0..=3 | SAVE_VERSION => ron::de::from_str::<SaveData>(data).map_err(|e| e.to_string())?,
+        _ => return Err(format!("there's no way to read save version {}", version)),
+    };
+    save.version = SAVE_VERSION;
+    Ok(save)
+}
+
+fn write_file(profile: &Profile, save: &SaveData) -> Result<(), String> {
+    let path = save_path(profile).ok_or("no data dir")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let data = ron::ser::to_string(save).map_err(|e| e.to_string())?;
+    std::fs::write(&path, data).map_err(|e| e.to_string())
+}
+
+impl SavedFloor {
+    fn is_valid(&self) -> bool {
+        let columns = self.tiles.first().map_or(0, |row| row.len());
+        columns > 0
+            && self.tiles.iter().all(|row| row.len() == columns)
+            && self.explored.len() == self.tiles.len()
+            && self.explored.iter().all(|row| row.len() == columns)
+    }
+}
+
+fn save_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    collapse: Res<Collapse>,
+    mut request: ResMut<SaveRequest>,
+) {
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    if ctrl
+        && keyboard_input.just_pressed(KeyCode::S)
+        && game_state.has_map
+        && !game_state.tutorial
+        && !collapse.is_active()
+        && !game_state.animating_actions
+        && game_state.phase == TurnPhase::PlayerInput
+    {
+        request.0 = true;
+    }
+}
+
+// writes the save for ctrl+s, and the frame after a new floor goes up once the floor's
+// spawners have filled it in. nothing is saved while picking a class or after dying
+fn write_save(
+    (
+        game_state,
+        game_rng,
+        player_class,
+        layout,
+        gold,
+        run_stats,
+        identification,
+        quest_log,
+        profile,
+        mode,
+        difficulty,
+    ): (
+        Res<GameState>,
+        Res<GameRng>,
+        Res<PlayerClass>,
+        Res<FloorLayout>,
+        Res<Gold>,
+        Res<RunStats>,
+        Res<Identification>,
+        Res<QuestLog>,
+        Res<Profile>,
+        Res<GameMode>,
+        Res<Difficulty>,
+    ),
+    mut log: ResMut<MessageLog>,
+    mut request: ResMut<SaveRequest>,
+    mut checkpoint: ResMut<Checkpoint>,
+    replay: Res<Replay>,
+    mut autosave: Local<bool>,
+    new_map_query: Query<(), (Added<Map>, Without<Restored>)>,
+    map_query: Query<(&Map, &MapRooms, &Explored)>,
+    player_query: Query<
+        (
+            &GridPos,
+            &Stats,
+            &Speed,
+            &Experience,
+            &Level,
+            &Mana,
+            &Spellbook,
+            &Inventory,
+            &Equipment,
+            &StatusEffects,
+            &Hunger,
+        ),
+        With<Player>,
+    >,
+    (stairs_query, torch_query): (Query<&OnMap, With<Stairs>>, Query<&OnMap, With<Torch>>),
+    (
+        chest_query,
+        furniture_query,
+        npc_query,
+        item_query,
+        switch_query,
+        mechanism_query,
+        boulder_query,
+    ): (
+        Query<(&Chest, &GridPos)>,
+        Query<(&Furniture, &GridPos)>,
+        Query<(&Npc, &GridPos), With<OnMap>>,
+        Query<(&Item, &GridPos), With<OnMap>>,
+        Query<(&Switch, &Circuit, &OnMap)>,
+        Query<(&Mechanism, &Circuit, &OnMap)>,
+        Query<&GridPos, With<Boulder>>,
+    ),
+    enemy_query: Query<
+        (
+            &EnemyKind,
+            &GridPos,
+            &Stats,
+            &AiState,
+            Option<&Disguised>,
+            Option<&Boss>,
+        ),
+        With<Enemy>,
+    >,
+    companion_query: Query<(&Companion, &GridPos, &Stats)>,
+) {
+    if new_map_query.iter().next().is_some() {
+        *autosave = true;
+        return;
+    }
+    if !request.0 && !*autosave {
+        return;
+    }
+    let (manual, floor_start) = (request.0, *autosave);
+    request.0 = false;
+    *autosave = false;
+    if matches!(game_state.phase, TurnPhase::NewGame | TurnPhase::GameOver) {
+        return;
+    }
+    let ((current_map, map_rooms, explored), player) =
+        match (map_query.single(), player_query.single()) {
+            (Ok(map), Ok(player)) => (map, player),
+            _ => return,
+        };
+    let (
+        location,
+        stats,
+        speed,
+        experience,
+        level,
+        mana,
+        spellbook,
+        inventory,
+        equipment,
+        effects,
+        hunger,
+    ) = player;
+    let save = SaveData {
+        version: SAVE_VERSION,
+        depth: game_state.depth,
+        turn: game_state.turn,
+        seed: game_rng.seed(),
+        class: *player_class,
+        layout: *layout,
+        mode: *mode,
+        difficulty: *difficulty,
+        gold: gold.0,
+        kills: run_stats.kills,
+        identification: identification.clone(),
+        quest_log: quest_log.clone(),
+        player: SavedPlayer {
+            location: *location,
+            stats: stats.clone(),
+            speed: speed.0,
+            experience: experience.0,
+            level: level.0,
+            mana: (mana.max, mana.current),
+            spells: spellbook.0.clone(),
+            pack: inventory.items.clone(),
+            capacity: inventory.capacity,
+            equipment: (
+                equipment.weapon.clone(),
+                equipment.armor.clone(),
+                equipment.ring.clone(),
+            ),
+            statuses: effects.0.clone(),
+            hunger: hunger.turns,
+            hunger_owed: hunger.owed,
+        },
+        companions: (companion_query.iter())
+            .map(|(companion, loc, stats)| SavedCompanion {
+                npc: companion.npc,
+                location: *loc,
+                stats: stats.clone(),
+                downed: companion.downed,
+            })
+            .collect(),
+        floor: SavedFloor {
+            tiles: current_map.0.rows(),
+            explored: explored.0.as_rows(),
+            rooms: map_rooms.rooms.clone(),
+            spawn_room: map_rooms.spawn_room,
+            stairs: stairs_query.iter().map(|on_map| on_map.0).collect(),
+            torches: torch_query.iter().map(|on_map| on_map.0).collect(),
+            chests: (chest_query.iter())
+                .map(|(chest, loc)| (chest.clone(), *loc))
+                .collect(),
+            furniture: (furniture_query.iter())
+                .map(|(furniture, loc)| (*furniture, *loc))
+                .collect(),
+            npcs: (npc_query.iter()).map(|(npc, loc)| (npc.0, *loc)).collect(),
+            switches: (switch_query.iter())
+                .map(|(switch, circuit, on_map)| (*switch, *circuit, on_map.0))
+                .collect(),
+            mechanisms: (mechanism_query.iter())
+                .map(|(mechanism, circuit, on_map)| (*mechanism, *circuit, on_map.0))
+                .collect(),
+            boulders: boulder_query.iter().copied().collect(),
+            items: (item_query.iter())
+                .map(|(item, loc)| (item.clone(), *loc))
+                .collect(),
+            enemies: (enemy_query.iter())
+                .map(|(kind, loc, stats, ai, disguised, boss)| SavedEnemy {
+                    kind: *kind,
+                    location: *loc,
+                    stats: stats.clone(),
+                    asleep: matches!(ai, AiState::Sleeping),
+                    disguised: disguised.is_some(),
+                    boss: boss.cloned(),
+                })
+                .collect(),
+        },
+    };
+    if floor_start {
+        checkpoint.save = ron::ser::to_string(&save).ok();
+    }
+    // a replay would overwrite the profile's own run
+    if replay.is_playing() {
+        return;
+    }
+    match write_file(&profile, &save) {
+        Ok(()) if manual => log.add("Game saved."),
+        Ok(()) => {}
+        Err(e) => {
+            warn!("couldn't save the game: {}", e);
+            log.add("The game couldn't be saved.");
+        }
+    }
+}
+
+// permadeath: the run is over, so there's nothing left to continue
+fn delete_save(profile: Res<Profile>, mode: Res<GameMode>, replay: Res<Replay>) {
+    if *mode != GameMode::Classic || replay.is_playing() {
+        return;
+    }
+    if let Some(path) = save_path(&profile).filter(|path| path.exists()) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!("couldn't delete {}: {}", path.display(), e);
+        }
+    }
+}
+
+// checkpoint mode: dying loads the floor's checkpoint, less some gold, before the run
+// can end. without one the run ends as it would in classic mode
+fn respawn_at_checkpoint(
+    game_state: Res<GameState>,
+    mode: Res<GameMode>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut load_request: ResMut<LoadRequest>,
+    templates: Res<EnemyTemplates>,
+) {
+    if game_state.phase != TurnPhase::GameOver || *mode != GameMode::Checkpoint {
+        return;
+    }
+    let parsed = checkpoint
+        .save
+        .as_deref()
+        .map(|data| parse_save(data, &templates));
+    let mut save = match parsed {
+        Some(Ok(save)) => save,
+        _ => return,
+    };
+    let lost = save.gold / CHECKPOINT_GOLD_LOST;
+    save.gold -= lost;
+    checkpoint.respawned = Some(lost);
+    load_request.0 = Some(save);
+}
+
+// swaps whatever floor is in the world for the saved one. the map's spawn point is
+// where the player was standing, so player_jump_to_spawn puts them back there
+fn restore_run(
+    mut commands: Commands,
+    mut load_request: ResMut<LoadRequest>,
+    mut checkpoint: ResMut<Checkpoint>,
+    (
+        mut game_state,
+        mut game_rng,
+        mut player_class,
+        mut layout,
+        mut mode,
+        (mut difficulty, mut modifiers),
+        mut gold,
+        mut run_stats,
+        mut identification,
+        mut quest_log,
+    ): (
+        ResMut<GameState>,
+        ResMut<GameRng>,
+        ResMut<PlayerClass>,
+        ResMut<FloorLayout>,
+        ResMut<GameMode>,
+        (ResMut<Difficulty>, ResMut<DifficultyModifiers>),
+        ResMut<Gold>,
+        ResMut<RunStats>,
+        ResMut<Identification>,
+        ResMut<QuestLog>,
+    ),
+    (mut log, mut casting, mut level_up): (ResMut<MessageLog>, ResMut<Casting>, ResMut<LevelUp>),
+    (library, templates, tileset, window, materials, item_materials): (
+        Res<NpcLibrary>,
+        Res<EnemyTemplates>,
+        Res<Tileset>,
+        Res<WinSize>,
+        Res<Materials>,
+        Res<ItemMaterials>,
+    ),
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    old_query: Query<
+        Entity,
+        Or<(
+            With<Map>,
+            With<MapElement>,
+            With<OnMap>,
+            With<Enemy>,
+            With<Companion>,
+        )>,
+    >,
+    player_query: Query<Entity, With<Player>>,
+) {
+    let save = match load_request.0.take() {
+        Some(save) => save,
+        None => return,
+    };
+    let player = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    // a continued run respawns where it was continued from
+    checkpoint.save = ron::ser::to_string(&save).ok();
+    for entity in old_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let floor = save.floor;
+    let p = save.player;
+    commands
+        .spawn()
+        .insert(Map(DungeonMap::from_rows(&floor.tiles), p.location))
+        .insert(MapRooms {
+            rooms: floor.rooms,
+            spawn_room: floor.spawn_room,
+        })
+        .insert(Explored(Array2D::from_rows(&floor.explored)))
+        .insert(MapChunks::default())
+        .insert(Restored);
+    for loc in floor.stairs {
+        spawn_stairs(&mut commands, &tileset, &window, loc);
+    }
+    for loc in floor.torches {
+        spawn_torch(&mut commands, &tileset, &window, loc);
+    }
+    for (chest, loc) in floor.chests {
+        spawn_chest(&mut commands, &tileset, &window, chest, loc);
+    }
+    for (furniture, loc) in floor.furniture {
+        spawn_furniture(&mut commands, &materials, &window, furniture, loc);
+    }
+    for (index, loc) in floor.npcs {
+        match library.0.get(index) {
+            Some(def) if def.shrine => spawn_altar(
+                &mut commands,
+                &library,
+                &window,
+                &mut color_materials,
+                index,
+                loc,
+            ),
+            Some(_) => {
+                spawn_npc(
+                    &mut commands,
+                    &library,
+                    &window,
+                    &mut color_materials,
+                    index,
+                    loc,
+                );
+            }
+            None => {}
+        }
+    }
+    // the ones following the player were cleared out with the old floor
+    for saved in save.companions {
+        if saved.npc >= library.0.len() {
+            continue;
+        }
+        let companion = spawn_npc(
+            &mut commands,
+            &library,
+            &window,
+            &mut color_materials,
+            saved.npc,
+            saved.location,
+        );
+        recruit(&mut commands, companion, saved.npc);
+        commands
+            .entity(companion)
+            .insert(Companion {
+                downed: saved.downed,
+                npc: saved.npc,
+            })
+            .insert(saved.stats);
+        // lying down, the way down_companions leaves them
+        if saved.downed.is_some() {
+            commands
+                .entity(companion)
+                .insert(Sprite::new(Vec2::splat(window.tile / 3.)));
+        }
+    }
+    for (switch, circuit, loc) in floor.switches {
+        spawn_switch(&mut commands, &materials, &window, switch, circuit, loc);
+    }
+    for (mechanism, circuit, loc) in floor.mechanisms {
+        spawn_mechanism(&mut commands, &materials, &window, mechanism, circuit, loc);
+    }
+    for loc in floor.boulders {
+        spawn_boulder(&mut commands, &materials, &window, loc);
+    }
+    for (item, loc) in floor.items {
+        spawn_item(&mut commands, &item_materials, &window, item, loc);
+    }
+    for saved in floor.enemies {
+        // parse_save turned away any kind that isn't loaded
+        let enemy = match spawn_enemy(
+            &mut commands,
+            &templates,
+            &modifiers,
+            &tileset,
+            &window,
+            saved.kind,
+            saved.location,
+        ) {
+            Some(enemy) => enemy,
+            None => continue,
+        };
+        commands.entity(enemy).insert(saved.stats);
+        if saved.asleep {
+            commands.entity(enemy).insert(AiState::Sleeping);
+        }
+        if saved.disguised {
+            commands
+                .entity(enemy)
+                .insert(TileSprite::Chest.sprite())
+                .insert(Disguised);
+        }
+        if let Some(boss) = saved.boss {
+            commands.entity(enemy).insert(boss).insert(SealsStairs);
+        }
+    }
+
+    let mut equipment = Equipment::default();
+    let (weapon, armor, ring) = p.equipment;
+    equipment.weapon = weapon;
+    equipment.armor = armor;
+    equipment.ring = ring;
+    commands
+        .entity(player)
+        .insert(p.location)
+        .insert(p.stats)
+        .insert(Speed(p.speed))
+        .insert(Experience(p.experience))
+        .insert(Level(p.level))
+        .insert(Mana {
+            max: p.mana.0,
+            current: p.mana.1,
+        })
+        .insert(Spellbook(p.spells))
+        .insert(Inventory {
+            items: p.pack,
+            capacity: p.capacity,
+        })
+        .insert(equipment)
+        .insert(StatusEffects(p.statuses))
+        .insert(Hunger {
+            turns: p.hunger,
+            owed: p.hunger_owed,
+        });
+
+    *game_state = GameState {
+        has_map: true,
+        depth: save.depth,
+        turn: save.turn,
+        phase: TurnPhase::PlayerInput,
+        ..Default::default()
+    };
+    *player_class = save.class;
+    *game_rng = GameRng::resume(save.seed, save.turn);
+    *layout = save.layout;
+    *mode = save.mode;
+    *difficulty = save.difficulty;
+    *modifiers = difficulty.modifiers();
+    *gold = Gold(save.gold);
+    *run_stats = RunStats { kills: save.kills };
+    *identification = save.identification;
+    *quest_log = save.quest_log;
+    *log = MessageLog::default();
+    *casting = Casting::Idle;
+    *level_up = LevelUp::default();
+    match checkpoint.respawned.take() {
+        Some(lost) => log.add(format!(
+            "You come to at the start of depth {}, {} gold lighter.",
+            save.depth, lost
+        )),
+        None => log.add(format!(
+            "You pick up where you left off, on depth {}.",
+            save.depth
+        )),
+    }
+}
@@ -0,0 +1,91 @@
+use crate::movement::can_move;
+use crate::pathfinding::NEIGHBORS;
+use crate::prelude::*;
+use array2d::Array2D;
+use bevy::prelude::*;
+
+pub struct ScentPlugin;
+
+// scent laid on the player's tile each turn, it fades by one every turn after
+const SCENT_STRENGTH: u32 = 12;
+
+// how strongly the player's scent lingers on each tile of the current floor
+pub struct ScentMap {
+    scent: Array2D<u32>,
+    // the turn the scent was last laid down on
+    turn: Option<u32>,
+}
+
+impl ScentMap {
+    fn get(&self, loc: &GridPos) -> u32 {
+        if loc.x < 0 || loc.y < 0 {
+            return 0;
+        }
+        self.scent
+            .get(loc.y as usize, loc.x as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // the reachable neighbouring tile with a fresher scent than this one, if any
+    pub fn follow(&self, map_data: &DungeonMap, from: &GridPos) -> Option<GridPos> {
+        let here = self.get(from);
+        NEIGHBORS
+            .iter()
+            .filter(|&&(dx, dy)| can_move(map_data, from, dx, dy))
+            .map(|&(dx, dy)| from.add(dx, dy))
+            .filter(|loc| self.get(loc) > here)
+            .max_by_key(|loc| self.get(loc))
+    }
+}
+
+impl Plugin for ScentPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(ScentMap {
+            scent: Array2D::filled_with(0, 1, 1),
+            turn: None,
+        })
+        .add_system(update_scent.system().before("ai"));
+    }
+}
+
+// once per turn the old trail fades a little and a fresh dose goes down where the player stands,
+// spilling onto the tiles right next to them
+fn update_scent(
+    game_state: Res<GameState>,
+    mut scent_map: ResMut<ScentMap>,
+    new_map_query: Query<&Map, Added<Map>>,
+    map_query: Query<&Map>,
+    player_query: Query<&GridPos, With<Player>>,
+) {
+    // a fresh floor starts with no trail at all
+    if let Ok(current_map) = new_map_query.single() {
+        scent_map.scent = Array2D::filled_with(0, current_map.0.height(), current_map.0.width());
+        scent_map.turn = None;
+    }
+    if scent_map.turn == Some(game_state.turn) {
+        return;
+    }
+    if let (Ok(current_map), Ok(player_loc)) = (map_query.single(), player_query.single()) {
+        scent_map.turn = Some(game_state.turn);
+        let (rows, columns) = (scent_map.scent.num_rows(), scent_map.scent.num_columns());
+        for y in 0..rows {
+            for x in 0..columns {
+                if let Some(scent) = scent_map.scent.get_mut(y, x) {
+                    *scent = scent.saturating_sub(1);
+                }
+            }
+        }
+        let mut lay = |loc: &GridPos, strength: u32| {
+            if let Some(scent) = scent_map.scent.get_mut(loc.y as usize, loc.x as usize) {
+                *scent = (*scent).max(strength);
+            }
+        };
+        lay(player_loc, SCENT_STRENGTH);
+        for &(dx, dy) in NEIGHBORS.iter() {
+            if can_move(&current_map.0, player_loc, dx, dy) {
+                lay(&player_loc.add(dx, dy), SCENT_STRENGTH - 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::class::PlayerClass;
+use crate::gold::Gold;
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::replay::Replay;
+use crate::settings::data_dir;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub struct ScoresPlugin;
+
+// shared by every profile, each entry says whose run it was
+const SCORES_FILE: &str = "scores.ron";
+const TABLE_SIZE: usize = 10;
+// what each part of a run is worth, a floor counts for more than a kill or a coin
+const DEPTH_POINTS: u32 = 100;
+const KILL_POINTS: u32 = 10;
+const GOLD_POINTS: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub score: u32,
+    pub profile: String,
+    pub class: PlayerClass,
+    pub depth: u32,
+    pub kills: u32,
+    pub gold: u32,
+    pub victory: bool,
+}
+
+// the best runs on this machine, best first. latest is where the last run finished in the
+// table this session, if it made it in
+#[derive(Default)]
+pub struct HighScores {
+    pub entries: Vec<ScoreEntry>,
+    pub latest: Option<usize>,
+}
+
+struct ScoresScreen;
+
+impl Plugin for ScoresPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(HighScores::load())
+            // the game over and victory screens show the table, so it's filled in first
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(record_score.system().label("record_score")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Victory)
+                    .with_system(record_score.system().label("record_score")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::HighScores).with_system(show_scores.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::HighScores).with_system(scores_input.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::HighScores).with_system(close_scores.system()),
+            );
+    }
+}
+
+fn score(depth: u32, kills: u32, gold: u32) -> u32 {
+    depth * DEPTH_POINTS + kills * KILL_POINTS + gold * GOLD_POINTS
+}
+
+fn scores_path() -> Option<PathBuf> {
+    Some(data_dir()?.join(SCORES_FILE))
+}
+
+impl HighScores {
+    fn load() -> Self {
+        let entries = scores_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|data| ron::de::from_str(&data).ok())
+            .unwrap_or_default();
+        HighScores {
+            entries,
+            latest: None,
+        }
+    }
+
+    fn save(&self) {
+        let path = match scores_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| ron::ser::to_string(&self.entries).map_err(|e| e.to_string()))
+            .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!("couldn't save {}: {}", path.display(), e);
+        }
+    }
+
+    // a tie goes to the run that got there first
+    fn add(&mut self, entry: ScoreEntry) {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        if rank < TABLE_SIZE {
+            self.entries.insert(rank, entry);
+            self.entries.truncate(TABLE_SIZE);
+            self.latest = Some(rank);
+        } else {
+            self.latest = None;
+        }
+    }
+
+    // one line per run, the latest in gold
+    pub fn table(&self, font: &UiFont) -> Vec<TextSection> {
+        let style = |color: Color| TextStyle {
+            font: font.0.clone(),
+            font_size: 16.,
+            color,
+        };
+        if self.entries.is_empty() {
+            return vec![TextSection {
+                value: "No runs yet\n".to_string(),
+                style: style(Color::GRAY),
+            }];
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let color = if self.latest == Some(i) {
+                    Color::rgb(1., 0.9, 0.5)
+                } else {
+                    Color::WHITE
+                };
+                let ending = if entry.victory {
+                    "escaped".to_string()
+                } else {
+                    format!("died on floor {}", entry.depth)
+                };
+                TextSection {
+                    value: format!(
+                        "{}. {}  {} the {}, {}, {} kills, {} gold\n",
+                        i + 1,
+                        entry.score,
+                        entry.profile,
+                        entry.class.name(),
+                        ending,
+                        entry.kills,
+                        entry.gold
+                    ),
+                    style: style(color),
+                }
+            })
+            .collect()
+    }
+}
+
+fn record_score(
+    app_state: Res<State<AppState>>,
+    profile: Res<Profile>,
+    player_class: Res<PlayerClass>,
+    game_state: Res<GameState>,
+    run_stats: Res<RunStats>,
+    gold: Res<Gold>,
+    mut high_scores: ResMut<HighScores>,
+    replay: Res<Replay>,
+) {
+    if replay.is_playing() {
+        return;
+    }
+    high_scores.add(ScoreEntry {
+        score: score(game_state.depth, run_stats.kills, gold.0),
+        profile: profile.name.clone(),
+        class: *player_class,
+        depth: game_state.depth,
+        kills: run_stats.kills,
+        gold: gold.0,
+        victory: *app_state.current() == AppState::Victory,
+    });
+    high_scores.save();
+}
+
+fn show_scores(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    high_scores: Res<HighScores>,
+) {
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: "High scores\n\n".to_string(),
+        style: style(36., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    sections.extend(high_scores.table(&font));
+    sections.push(TextSection {
+        value: "\n[Esc] back".to_string(),
+        style: style(14., Color::GRAY),
+    });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(ScoresScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections,
+                    alignment: TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+        });
+}
+
+fn scores_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) || keyboard_input.just_pressed(KeyCode::H) {
+        // the main menu underneath would see them too
+        keyboard_input.reset(KeyCode::Escape);
+        keyboard_input.reset(KeyCode::H);
+        app_state.pop().ok();
+    }
+}
+
+fn close_scores(mut commands: Commands, screen_query: Query<Entity, With<ScoresScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
@@ -0,0 +1,1014 @@
+use crate::enemy::{spawn_enemy, EnemyKind, EnemyTemplates};
+use crate::gold::Gold;
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use bevy::prelude::*;
+use rand::Rng;
+use std::collections::HashMap;
+
+// a small scripting language for the content the data files can't say on their own: enemy
+// abilities, chest traps and shrine offerings. scripts live in assets/scripts and the data
+// files name them. a script can't reach into the game itself, it reads a snapshot of the
+// actors involved and the map, and hands back a list of effects for run_scripts to apply.
+// there are no loops, so every script finishes. one looks like
+//
+//     // the shade's touch
+//     let drained = roll(1, 3)
+//     heal(self, drained)
+//     if is_player(target) && chance(30) {
+//         status(target, "poisoned", 4)
+//         log("Cold spreads from the wound.")
+//     }
+//
+// values are whole numbers, true and false, "text" and actors. self is whatever the script
+// belongs to, target whoever it's aimed at, and player the player. depth and turn are the
+// floor and the turn. see call for everything else a script can ask for or do
+pub struct ScriptPlugin;
+
+pub const SCRIPT_FOLDER: &str = "assets/scripts";
+const SCRIPT_EXTENSION: &str = "script";
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<RunScriptEvent>()
+            .add_system(use_abilities.system().after("combat").before("scripts"))
+            .add_system(
+                run_scripts
+                    .system()
+                    .label("scripts")
+                    .after("combat")
+                    .after("open_chests")
+                    .after("dialogue_input"),
+            );
+    }
+}
+
+// runs a script from the library, by name, for source and aimed at target
+pub struct RunScriptEvent {
+    pub script: String,
+    pub source: Entity,
+    pub target: Entity,
+}
+
+// every script the content packs brought, by the name the data files use, and the ones
+// that can be rolled as chest traps
+#[derive(Default)]
+pub struct ScriptLibrary {
+    scripts: HashMap<String, Script>,
+    pub traps: Vec<String>,
+}
+
+impl ScriptLibrary {
+    pub fn new(scripts: HashMap<String, Script>, traps: Vec<String>) -> Self {
+        ScriptLibrary { scripts, traps }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Script> {
+        self.scripts.get(name)
+    }
+}
+
+// every .script file in a folder, named by its file name
+pub fn read_script_folder(folder: &str) -> Result<Vec<(String, String)>, String> {
+    let entries = std::fs::read_dir(folder).map_err(|e| e.to_string())?;
+    let mut paths: Vec<std::path::PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION))
+        .collect();
+    // read_dir hands them back in whatever order the file system keeps them
+    paths.sort();
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some((name, path.to_str()?.to_string()))
+        })
+        .collect())
+}
+
+// what a script does to the game, applied by run_scripts once it's finished
+#[derive(Clone, PartialEq, Debug)]
+pub enum ScriptEffect {
+    Damage {
+        target: Entity,
+        amount: i32,
+        element: Element,
+    },
+    Heal {
+        target: Entity,
+        amount: i32,
+    },
+    // for this many turns
+    Status {
+        target: Entity,
+        status: Status,
+        turns: u32,
+    },
+    // to the player, or from them when it's negative
+    Gold(i32),
+    Log(String),
+    Noise {
+        location: GridPos,
+        radius: i32,
+    },
+    Spawn {
+        kind: EnemyKind,
+        location: GridPos,
+    },
+}
+
+// an actor as a script sees it, copied out of the world before the script runs
+#[derive(Clone)]
+pub struct ActorView {
+    pub entity: Entity,
+    pub name: String,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub location: GridPos,
+    pub player: bool,
+}
+
+// everything a script can read. actors are handed to the script as their place in actors
+pub struct ScriptScope<'a> {
+    pub depth: u32,
+    pub turn: u32,
+    pub map: &'a DungeonMap,
+    pub actors: Vec<ActorView>,
+    pub source: Option<usize>,
+    pub target: Option<usize>,
+    pub player: Option<usize>,
+    // enemy kinds by the name spawn takes
+    pub kinds: HashMap<String, EnemyKind>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Symbol(&'static str),
+    // a line break or a ;
+    End,
+}
+
+// longest first, so <= isn't read as < then =
+const SYMBOLS: [&str; 20] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", "=", "<", ">", "+", "-", "*", "/",
+    "%", "!",
+];
+
+// each token with the line it's on, for the error messages
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut at = 0;
+        while at < chars.len() {
+            let c = chars[at];
+            let rest: String = chars[at..].iter().collect();
+            if rest.starts_with("//") {
+                break;
+            } else if c.is_whitespace() {
+                at += 1;
+            } else if c == ';' {
+                tokens.push((Token::End, number));
+                at += 1;
+            } else if c.is_ascii_digit() {
+                let digits: String = chars[at..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect();
+                at += digits.len();
+                let value = digits
+                    .parse()
+                    .map_err(|_| format!("line {}: {} is too big", number, digits))?;
+                tokens.push((Token::Int(value), number));
+            } else if c.is_alphabetic() || c == '_' {
+                let word: String = chars[at..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .collect();
+                at += word.chars().count();
+                tokens.push((Token::Ident(word), number));
+            } else if c == '"' {
+                let text: String = chars[at + 1..].iter().take_while(|c| **c != '"').collect();
+                at += text.chars().count() + 1;
+                if at >= chars.len() {
+                    return Err(format!("line {}: text that never ends", number));
+                }
+                at += 1;
+                tokens.push((Token::Str(text), number));
+            } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                at += symbol.len();
+                tokens.push((Token::Symbol(symbol), number));
+            } else {
+                return Err(format!("line {}: unexpected {}", number, c));
+            }
+        }
+        tokens.push((Token::End, number));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Expr(Expr),
+    If(Expr, Vec<(Stmt, usize)>, Vec<(Stmt, usize)>),
+}
+
+// operators from the loosest to the tightest binding
+const PRECEDENCE: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["+", "-"],
+];
+const PRODUCT: [&str; 3] = ["*", "/", "%"];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.at)
+            .or_else(|| self.tokens.last())
+            .map_or(0, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.at += 1;
+        token
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line(), message))
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.is_symbol(symbol) {
+            self.at += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected {}", symbol))
+        }
+    }
+
+    fn skip_ends(&mut self) {
+        while self.peek() == Some(&Token::End) {
+            self.at += 1;
+        }
+    }
+
+    // statements up to the end of the script, or the } closing a block
+    fn statements(&mut self, in_block: bool) -> Result<Vec<(Stmt, usize)>, String> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_ends();
+            match self.peek() {
+                None if in_block => return self.error("a { that's never closed"),
+                None => return Ok(statements),
+                Some(Token::Symbol("}")) if in_block => {
+                    self.at += 1;
+                    return Ok(statements);
+                }
+                _ => {}
+            }
+            let line = self.line();
+            let statement = self.statement()?;
+            match self.peek() {
+                None | Some(Token::End) => {}
+                Some(Token::Symbol("}")) if in_block => {}
+                _ => return self.error("expected the end of the line"),
+            }
+            statements.push((statement, line));
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        match self.peek() {
+            Some(Token::Ident(word)) if word == "let" => {
+                self.at += 1;
+                let name = match self.next() {
+                    Some(Token::Ident(name)) => name,
+                    _ => return self.error("let needs a name"),
+                };
+                self.expect("=")?;
+                Ok(Stmt::Let(name, self.expression(0)?))
+            }
+            Some(Token::Ident(word)) if word == "if" => self.if_statement(),
+            _ => Ok(Stmt::Expr(self.expression(0)?)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, String> {
+        self.at += 1;
+        let condition = self.expression(0)?;
+        self.expect("{")?;
+        let then = self.statements(true)?;
+        // else can go on the line after the }
+        let before = self.at;
+        self.skip_ends();
+        if self.peek() != Some(&Token::Ident("else".to_string())) {
+            self.at = before;
+            return Ok(Stmt::If(condition, then, Vec::new()));
+        }
+        self.at += 1;
+        let otherwise = if self.peek() == Some(&Token::Ident("if".to_string())) {
+            let line = self.line();
+            vec![(self.if_statement()?, line)]
+        } else {
+            self.expect("{")?;
+            self.statements(true)?
+        };
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+
+    fn expression(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.product();
+        }
+        let mut left = self.expression(level + 1)?;
+        while let Some(&op) = PRECEDENCE[level].iter().find(|op| self.is_symbol(op)) {
+            self.at += 1;
+            // a long condition can carry on onto the next line after an operator
+            self.skip_ends();
+            let right = self.expression(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(&op) = PRODUCT.iter().find(|op| self.is_symbol(op)) {
+            self.at += 1;
+            self.skip_ends();
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_symbol("!") {
+            self.at += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.is_symbol("-") {
+            self.at += 1;
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(Expr::Int(value)),
+            Some(Token::Str(text)) => Ok(Expr::Str(text)),
+            Some(Token::Ident(word)) if word == "true" => Ok(Expr::Bool(true)),
+            Some(Token::Ident(word)) if word == "false" => Ok(Expr::Bool(false)),
+            Some(Token::Ident(name)) if self.is_symbol("(") => {
+                self.at += 1;
+                let mut args = Vec::new();
+                self.skip_ends();
+                while !self.is_symbol(")") {
+                    args.push(self.expression(0)?);
+                    self.skip_ends();
+                    if !self.is_symbol(")") {
+                        self.expect(",")?;
+                        self.skip_ends();
+                    }
+                }
+                self.at += 1;
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            Some(Token::Symbol("(")) => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            _ => {
+                self.at -= 1;
+                self.error("expected a value")
+            }
+        }
+    }
+}
+
+// a parsed script, ready to run as many times as it's needed
+#[derive(Clone, Debug)]
+pub struct Script {
+    statements: Vec<(Stmt, usize)>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            at: 0,
+        };
+        Ok(Script {
+            statements: parser.statements(false)?,
+        })
+    }
+
+    // runs it against the scope, handing back what it wants done in the order it asked
+    pub fn run(
+        &self,
+        scope: &ScriptScope,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<ScriptEffect>, String> {
+        let mut run = Run {
+            scope,
+            rng,
+            vars: HashMap::new(),
+            effects: Vec::new(),
+        };
+        run.block(&self.statements)?;
+        Ok(run.effects)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Value {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    Actor(usize),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Int(value) => value.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Str(text) => text.clone(),
+            Value::Actor(_) => "an actor".to_string(),
+        }
+    }
+}
+
+struct Run<'a, 'b, R: Rng> {
+    scope: &'a ScriptScope<'b>,
+    rng: &'a mut R,
+    // one set for the whole script, a let inside an if is still there after it
+    vars: HashMap<String, Value>,
+    effects: Vec<ScriptEffect>,
+}
+
+impl<'a, 'b, R: Rng> Run<'a, 'b, R> {
+    fn block(&mut self, statements: &[(Stmt, usize)]) -> Result<(), String> {
+        for (statement, line) in statements {
+            self.statement(statement)
+                .map_err(|e| format!("line {}: {}", line, e))?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), String> {
+        match statement {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.vars.insert(name.clone(), value);
+            }
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::If(condition, then, otherwise) => match self.eval(condition)? {
+                Value::Bool(true) => self.block(then)?,
+                Value::Bool(false) => self.block(otherwise)?,
+                other => return Err(format!("if needs true or false, not {}", other.describe())),
+            },
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Int(value) => Value::Int(*value),
+            Expr::Bool(value) => Value::Bool(*value),
+            Expr::Str(text) => Value::Str(text.clone()),
+            Expr::Var(name) => self.var(name)?,
+            Expr::Not(inner) => match self.eval(inner)? {
+                Value::Bool(value) => Value::Bool(!value),
+                other => return Err(format!("! needs true or false, not {}", other.describe())),
+            },
+            Expr::Negate(inner) => Value::Int(-self.int(inner)?),
+            // the right side is only looked at when it matters
+            Expr::Binary("&&", left, right) => Value::Bool(self.bool(left)? && self.bool(right)?),
+            Expr::Binary("||", left, right) => Value::Bool(self.bool(left)? || self.bool(right)?),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                binary(op, left, right)?
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                self.call(name, args)?
+            }
+        })
+    }
+
+    fn int(&mut self, expr: &Expr) -> Result<i64, String> {
+        match self.eval(expr)? {
+            Value::Int(value) => Ok(value),
+            other => Err(format!("expected a number, not {}", other.describe())),
+        }
+    }
+
+    fn bool(&mut self, expr: &Expr) -> Result<bool, String> {
+        match self.eval(expr)? {
+            Value::Bool(value) => Ok(value),
+            other => Err(format!("expected true or false, not {}", other.describe())),
+        }
+    }
+
+    fn var(&self, name: &str) -> Result<Value, String> {
+        let actor = |index: Option<usize>| {
+            index
+                .map(Value::Actor)
+                .ok_or(format!("this script has no {}", name))
+        };
+        match name {
+            "self" => actor(self.scope.source),
+            "target" => actor(self.scope.target),
+            "player" => actor(self.scope.player),
+            "depth" => Ok(Value::Int(self.scope.depth as i64)),
+            "turn" => Ok(Value::Int(self.scope.turn as i64)),
+            _ => self
+                .vars
+                .get(name)
+                .cloned()
+                .ok_or(format!("nothing called {}", name)),
+        }
+    }
+
+    fn actor(&self, value: &Value) -> Result<&ActorView, String> {
+        match value {
+            Value::Actor(index) => self
+                .scope
+                .actors
+                .get(*index)
+                .ok_or_else(|| "an actor that isn't there".to_string()),
+            other => Err(format!("expected an actor, not {}", other.describe())),
+        }
+    }
+
+    // what a script can ask about the game, and what it can ask the game to do
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        use Value::{Actor, Bool, Int, Str};
+        let no_value = Bool(false);
+        Ok(match (name, args.as_slice()) {
+            // rolls
+            ("chance", [Int(percent)]) => Bool(self.rng.gen_range(0..100) < *percent),
+            ("roll", [Int(low), Int(high)]) => Int(self.rng.gen_range(*low..=(*high).max(*low))),
+            ("min", [Int(a), Int(b)]) => Int(*a.min(b)),
+            ("max", [Int(a), Int(b)]) => Int(*a.max(b)),
+            // the actors
+            ("hp", [actor]) => Int(self.actor(actor)?.hp as i64),
+            ("max_hp", [actor]) => Int(self.actor(actor)?.max_hp as i64),
+            ("x", [actor]) => Int(self.actor(actor)?.location.x as i64),
+            ("y", [actor]) => Int(self.actor(actor)?.location.y as i64),
+            ("name", [actor]) => Str(self.actor(actor)?.name.clone()),
+            ("is_player", [actor]) => Bool(self.actor(actor)?.player),
+            ("distance", [a, b]) => {
+                let (a, b) = (self.actor(a)?.location, self.actor(b)?.location);
+                Int(a.chebyshev(&b) as i64)
+            }
+            // the map
+            ("walkable", [Int(x), Int(y)]) => {
+                Bool(self.scope.map.is_walkable(*x as i32, *y as i32))
+            }
+            ("tile", [Int(x), Int(y)]) => {
+                Str(tile_name(self.scope.map.tile_at(*x as i32, *y as i32)).to_string())
+            }
+            // effects
+            ("damage", [actor, Int(amount)]) => {
+                let target = self.actor(actor)?.entity;
+                self.effects.push(ScriptEffect::Damage {
+                    target,
+                    amount: *amount as i32,
+                    element: Element::Physical,
+                });
+                no_value
+            }
+            ("damage", [actor, Int(amount), Str(element)]) => {
+                let target = self.actor(actor)?.entity;
+                self.effects.push(ScriptEffect::Damage {
+                    target,
+                    amount: *amount as i32,
+                    element: element_named(element)?,
+                });
+                no_value
+            }
+            ("heal", [actor, Int(amount)]) => {
+                let target = self.actor(actor)?.entity;
+                self.effects.push(ScriptEffect::Heal {
+                    target,
+                    amount: *amount as i32,
+                });
+                no_value
+            }
+            ("status", [actor, Str(status), Int(turns)]) => {
+                let target = self.actor(actor)?.entity;
+                self.effects.push(ScriptEffect::Status {
+                    target,
+                    status: status_named(status)?,
+                    turns: (*turns).max(0) as u32,
+                });
+                no_value
+            }
+            ("gold", [Int(amount)]) => {
+                self.effects.push(ScriptEffect::Gold(*amount as i32));
+                no_value
+            }
+            ("log", [text]) => {
+                self.effects.push(ScriptEffect::Log(text.describe()));
+                no_value
+            }
+            ("noise", [Int(x), Int(y), Int(radius)]) => {
+                self.effects.push(ScriptEffect::Noise {
+                    location: GridPos::new(*x as i32, *y as i32),
+                    radius: *radius as i32,
+                });
+                no_value
+            }
+            ("spawn", [Str(kind), Int(x), Int(y)]) => {
+                let kind = *self
+                    .scope
+                    .kinds
+                    .get(kind)
+                    .ok_or(format!("no enemy called {}", kind))?;
+                self.effects.push(ScriptEffect::Spawn {
+                    kind,
+                    location: GridPos::new(*x as i32, *y as i32),
+                });
+                no_value
+            }
+            _ => {
+                let given: Vec<String> = args.iter().map(Value::describe).collect();
+                return Err(format!("no {}({})", name, given.join(", ")));
+            }
+        })
+    }
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Result<Value, String> {
+    use Value::{Bool, Int, Str};
+    Ok(match (op, left, right) {
+        ("==", left, right) => Bool(left == right),
+        ("!=", left, right) => Bool(left != right),
+        // + joins text, with anything else written out
+        ("+", Str(left), right) => Str(left + &right.describe()),
+        ("+", left, Str(right)) => Str(left.describe() + &right),
+        ("+", Int(a), Int(b)) => Int(a.saturating_add(b)),
+        ("-", Int(a), Int(b)) => Int(a.saturating_sub(b)),
+        ("*", Int(a), Int(b)) => Int(a.saturating_mul(b)),
+        ("/", Int(_), Int(0)) | ("%", Int(_), Int(0)) => return Err("dividing by 0".to_string()),
+        ("/", Int(a), Int(b)) => Int(a / b),
+        ("%", Int(a), Int(b)) => Int(a % b),
+        ("<", Int(a), Int(b)) => Bool(a < b),
+        ("<=", Int(a), Int(b)) => Bool(a <= b),
+        (">", Int(a), Int(b)) => Bool(a > b),
+        (">=", Int(a), Int(b)) => Bool(a >= b),
+        (op, left, right) => {
+            return Err(format!(
+                "can't {} {} and {}",
+                op,
+                left.describe(),
+                right.describe()
+            ))
+        }
+    })
+}
+
+fn tile_name(tile: Option<&Tile>) -> &'static str {
+    match tile {
+        Some(Tile::Ground) => "floor",
+        Some(Tile::Chasm) => "chasm",
+        Some(Tile::Door) => "gate",
+        Some(Tile::Bridge) => "bridge",
+        Some(Tile::Wall) | None => "wall",
+    }
+}
+
+fn element_named(name: &str) -> Result<Element, String> {
+    [
+        Element::Physical,
+        Element::Fire,
+        Element::Ice,
+        Element::Poison,
+        Element::Arcane,
+    ]
+    .iter()
+    .find(|element| element.name() == name)
+    .copied()
+    .ok_or(format!("no element called {}", name))
+}
+
+fn status_named(name: &str) -> Result<Status, String> {
+    [
+        Status::Strength,
+        Status::Invisible,
+        Status::Blessed,
+        Status::Poisoned,
+    ]
+    .iter()
+    .find(|status| status.name().eq_ignore_ascii_case(name))
+    .copied()
+    .ok_or(format!("no status called {}", name))
+}
+
+// an enemy with an ability runs it on whoever it lands a hit on
+fn use_abilities(
+    templates: Res<EnemyTemplates>,
+    mut ev_hit: EventReader<HitEvent>,
+    mut ev_script: EventWriter<RunScriptEvent>,
+    enemy_query: Query<&EnemyKind, With<Enemy>>,
+) {
+    for hit in ev_hit.iter().filter(|hit| hit.outcome != HitOutcome::Miss) {
+        let ability = enemy_query
+            .get(hit.attacker)
+            .ok()
+            .and_then(|kind| templates.get(kind))
+            .and_then(|template| template.ability.clone());
+        if let Some(script) = ability {
+            ev_script.send(RunScriptEvent {
+                script,
+                source: hit.attacker,
+                target: hit.target,
+            });
+        }
+    }
+}
+
+// a script that goes wrong is skipped with a warning, the game carries on without it
+fn run_scripts(
+    mut commands: Commands,
+    (game_state, library, templates, modifiers): (
+        Res<GameState>,
+        Res<ScriptLibrary>,
+        Res<EnemyTemplates>,
+        Res<DifficultyModifiers>,
+    ),
+    (tileset, window, index): (Res<Tileset>, Res<WinSize>, Res<SpatialIndex>),
+    (mut log, mut gold, mut rng): (ResMut<MessageLog>, ResMut<Gold>, ResMut<GameRng>),
+    mut ev_script: EventReader<RunScriptEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
+    map_query: Query<&Map>,
+    mut actor_query: Query<(
+        Option<&mut Stats>,
+        &GridPos,
+        Option<&Name>,
+        Option<&Player>,
+        Option<&Resistances>,
+        Option<&mut StatusEffects>,
+    )>,
+    player_query: Query<Entity, With<Player>>,
+    blockers: Query<(), With<BlocksMovement>>,
+) {
+    let rng = rng.stream("scripts");
+    let current_map = match map_query.single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let kinds: HashMap<String, EnemyKind> = templates
+        .kinds()
+        .iter()
+        .filter_map(|kind| templates.get(kind).map(|t| (t.name.clone(), *kind)))
+        .collect();
+    for event in ev_script.iter() {
+        let script = match library.get(&event.script) {
+            Some(script) => script,
+            None => {
+                warn!("no script called {}", event.script);
+                continue;
+            }
+        };
+        let mut actors = Vec::new();
+        let mut view = |entity: Entity| -> Option<usize> {
+            if let Some(i) = actors.iter().position(|a: &ActorView| a.entity == entity) {
+                return Some(i);
+            }
+            let (stats, loc, name, player, _, _) = actor_query.get_mut(entity).ok()?;
+            actors.push(ActorView {
+                entity,
+                name: name.map_or("something", |name| name.as_str()).to_string(),
+                hp: stats.as_ref().map_or(0, |stats| stats.hp),
+                max_hp: stats.as_ref().map_or(0, |stats| stats.max_hp),
+                location: *loc,
+                player: player.is_some(),
+            });
+            Some(actors.len() - 1)
+        };
+        let (source, target) = (view(event.source), view(event.target));
+        let player = player_query.single().ok().and_then(&mut view);
+        let scope = ScriptScope {
+            depth: game_state.depth,
+            turn: game_state.turn,
+            map: &current_map.0,
+            actors,
+            source,
+            target,
+            player,
+            kinds: kinds.clone(),
+        };
+        let effects = match script.run(&scope, rng) {
+            Ok(effects) => effects,
+            Err(e) => {
+                warn!("script {} stopped at {}", event.script, e);
+                continue;
+            }
+        };
+        for effect in effects {
+            match effect {
+                ScriptEffect::Damage {
+                    target,
+                    amount,
+                    element,
+                } => {
+                    if let Ok((Some(mut stats), loc, _, _, resistances, _)) =
+                        actor_query.get_mut(target)
+                    {
+                        if stats.hp <= 0 {
+                            continue;
+                        }
+                        let resisted = resistances.map_or(0, |r| r.get(element));
+                        let amount = (amount * (100 - resisted) / 100).max(0);
+                        stats.hp = (stats.hp - amount).max(0);
+                        if stats.hp == 0 {
+                            ev_death.send(DeathEvent {
+                                entity: target,
+                                killer: event.source,
+                                location: *loc,
+                            });
+                        }
+                    }
+                }
+                ScriptEffect::Heal { target, amount } => {
+                    if let Ok((Some(mut stats), _, _, _, _, _)) = actor_query.get_mut(target) {
+                        if stats.hp > 0 {
+                            stats.hp = (stats.hp + amount.max(0)).min(stats.max_hp);
+                        }
+                    }
+                }
+                ScriptEffect::Status {
+                    target,
+                    status,
+                    turns,
+                } => {
+                    if let Ok((_, _, _, _, _, Some(mut effects))) = actor_query.get_mut(target) {
+                        effects.add(status, game_state.turn + turns);
+                    }
+                }
+                ScriptEffect::Gold(amount) => {
+                    gold.0 = (gold.0 as i64 + amount as i64).max(0) as u32;
+                }
+                ScriptEffect::Log(text) => log.add(text),
+                ScriptEffect::Noise { location, radius } => {
+                    ev_noise.send(NoiseEvent { location, radius });
+                }
+                ScriptEffect::Spawn { kind, location } => {
+                    if current_map.0.is_walkable(location.x, location.y)
+                        && !index.is_blocked(&location, &blockers)
+                    {
+                        spawn_enemy(
+                            &mut commands,
+                            &templates,
+                            &modifiers,
+                            &tileset,
+                            &window,
+                            kind,
+                            location,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn run(source: &str) -> Result<Vec<ScriptEffect>, String> {
+        let map = DungeonMap::filled(Tile::Ground, 5, 5);
+        let mut world = World::new();
+        let actor = |entity, hp, player| ActorView {
+            entity,
+            name: if player { "you" } else { "rat" }.to_string(),
+            hp,
+            max_hp: 10,
+            location: GridPos::new(1, 1),
+            player,
+        };
+        let scope = ScriptScope {
+            depth: 3,
+            turn: 12,
+            map: &map,
+            actors: vec![
+                actor(world.spawn().id(), 4, false),
+                actor(world.spawn().id(), 7, true),
+            ],
+            source: Some(0),
+            target: Some(1),
+            player: Some(1),
+            kinds: HashMap::new(),
+        };
+        let script = Script::parse(source)?;
+        script.run(&scope, &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn scripts_read_the_scope_and_hand_back_effects_in_order() {
+        let effects = run(r#"
+            // a cut that grows with the floor
+            let cut = depth * 2 + 1
+            if is_player(target) && hp(target) >= cut {
+                damage(target, cut, "fire")
+            } else {
+                heal(self, 1)
+            }
+            log(name(self) + " bites for " + cut)
+        "#)
+        .unwrap();
+        assert_eq!(effects.len(), 2);
+        assert!(matches!(
+            effects[0],
+            ScriptEffect::Damage {
+                amount: 7,
+                element: Element::Fire,
+                ..
+            }
+        ));
+        assert_eq!(effects[1], ScriptEffect::Log("rat bites for 7".to_string()));
+    }
+
+    #[test]
+    fn else_if_chains_and_operators_bind_the_usual_way() {
+        let effects = run(r#"
+            if 1 + 2 * 3 == 9 {
+                gold(1)
+            } else if -(10 % 4) == -2 && !false {
+                gold(2)
+            }
+            else {
+                gold(3)
+            }
+        "#)
+        .unwrap();
+        assert_eq!(effects, vec![ScriptEffect::Gold(2)]);
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_their_line() {
+        assert_eq!(
+            run("log(\"fine\")\nheal(self)").unwrap_err(),
+            "line 2: no heal(an actor)"
+        );
+        assert_eq!(
+            run("if true {\n  gold(1)\n").unwrap_err(),
+            "line 2: a { that's never closed"
+        );
+        assert_eq!(run("let = 4").unwrap_err(), "line 1: let needs a name");
+        assert!(run("gold(1 / 0)").is_err());
+        assert!(run("status(target, \"on fire\", 3)").is_err());
+        assert!(run("log(missing)").is_err());
+    }
+}
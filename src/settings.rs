@@ -0,0 +1,550 @@
+use crate::prelude::*;
+use crate::profile::Profile;
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct SettingsPlugin;
+
+const SETTINGS_FILE: &str = "settings.ron";
+// the folder under the platform's config dir the settings file lives in
+const CONFIG_FOLDER: &str = "rust_dungeon";
+const VOLUME_STEP: f32 = 0.1;
+// how far each press moves the key repeat delay and rate, and the range they stay in
+const REPEAT_DELAY_STEP: f32 = 0.05;
+const REPEAT_DELAY_RANGE: (f32, f32) = (0.1, 0.8);
+const REPEAT_RATE_STEP: f32 = 1.;
+const REPEAT_RATE_RANGE: (f32, f32) = (2., 20.);
+const ROWS: usize = 13;
+// the row that picks whether the settings are shared or kept for the profile alone
+const SCOPE_ROW: usize = 12;
+// notches on a volume slider
+const SLIDER_WIDTH: usize = 10;
+// how much of the gap between red and green each colourblind palette puts into blue
+const DEUTAN_LIFT: f32 = 0.5;
+const PROTAN_LIFT: f32 = 0.7;
+// how far the high contrast palette spreads colours out from grey
+const CONTRAST: f32 = 1.4;
+
+// colour schemes for players who can't tell the default reds and greens apart
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Palette {
+    Standard,
+    Deuteranopia,
+    Protanopia,
+    HighContrast,
+}
+
+impl Palette {
+    const ALL: [Palette; 4] = [
+        Palette::Standard,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+            Palette::HighContrast => "High contrast",
+        }
+    }
+
+    // the colour something drawn in a flat colour gets instead. the colourblind palettes
+    // move the gap between red and green over to blue and yellow, which both still tell
+    // apart, and protanopes, who see reds darker, get them lifted further. high contrast
+    // pushes every channel away from grey
+    pub fn tint(&self, color: Color) -> Color {
+        let (r, g, b) = (color.r(), color.g(), color.b());
+        let (r, g, b) = match self {
+            Palette::Standard => (r, g, b),
+            Palette::Deuteranopia | Palette::Protanopia => {
+                let lift = if *self == Palette::Protanopia {
+                    PROTAN_LIFT
+                } else {
+                    DEUTAN_LIFT
+                };
+                let (mid, gap) = ((r + g) / 2., r - g);
+                (mid - gap / 2., mid - gap / 2., b + gap * lift)
+            }
+            Palette::HighContrast => {
+                let stretch = |c: f32| (c - 0.5) * CONTRAST + 0.5;
+                (stretch(r), stretch(g), stretch(b))
+            }
+        };
+        Color::rgba(r.clamp(0., 1.), g.clamp(0., 1.), b.clamp(0., 1.), color.a())
+    }
+
+    // what to multiply a sprite drawn in the tileset's own colour by, to get the same
+    // recolouring as tint. can go over 1, the sprite shader doesn't clamp it
+    pub fn retint(&self, texture: Color) -> Color {
+        let tinted = self.tint(texture);
+        let ratio = |to: f32, from: f32| if from > 0. { to / from } else { 1. };
+        Color::rgba(
+            ratio(tinted.r(), texture.r()),
+            ratio(tinted.g(), texture.g()),
+            ratio(tinted.b(), texture.b()),
+            1.,
+        )
+    }
+}
+
+// where the narration goes, see narration::TextAnnouncer
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Narration {
+    Off,
+    Text,
+    Speech,
+    Both,
+}
+
+impl Narration {
+    const ALL: [Narration; 4] = [
+        Narration::Off,
+        Narration::Text,
+        Narration::Speech,
+        Narration::Both,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Narration::Off => "Off",
+            Narration::Text => "Text",
+            Narration::Speech => "Speech",
+            Narration::Both => "Text and speech",
+        }
+    }
+
+    pub fn text(&self) -> bool {
+        matches!(self, Narration::Text | Narration::Both)
+    }
+
+    pub fn speech(&self) -> bool {
+        matches!(self, Narration::Speech | Narration::Both)
+    }
+}
+
+// player options, read from the config file before the window opens and written back
+// whenever they change. missing fields fall back to their defaults so older files still load.
+// a profile can keep its own copy, which is used instead of the shared one
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // 0 to 1. the master volume scales the music and sound channels
+    pub volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    // the camera glides after the player instead of sticking to them
+    pub camera_smoothing: bool,
+    pub diagonal_movement: bool,
+    pub palette: Palette,
+    // seconds an arrow key has to be held before it starts repeating
+    pub repeat_delay: f32,
+    // steps a second once it does
+    pub repeat_rate: f32,
+    // new runs start on the tutorial floor. it turns itself off once a run gets through it
+    pub tutorial: bool,
+    // reads out what's going on, for players using a screen reader
+    pub narration: Narration,
+    // the file these were read from and get written back to
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            volume: 0.8,
+            music_volume: 1.,
+            sfx_volume: 1.,
+            fullscreen: false,
+            vsync: true,
+            camera_smoothing: true,
+            diagonal_movement: true,
+            palette: Palette::Standard,
+            repeat_delay: 0.3,
+            repeat_rate: 8.,
+            tutorial: true,
+            narration: Narration::Off,
+            file: config_path(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(profile: &Profile) -> Self {
+        let own = profile.dir().map(|dir| dir.join(SETTINGS_FILE));
+        match own.filter(|path| path.exists()) {
+            Some(path) => Settings::read(path),
+            None => Settings::shared(),
+        }
+    }
+
+    fn shared() -> Self {
+        config_path().map_or_else(Settings::default, Settings::read)
+    }
+
+    fn read(path: PathBuf) -> Self {
+        let mut settings = match std::fs::read_to_string(&path) {
+            Ok(data) => ron::de::from_str(&data).unwrap_or_else(|e| {
+                warn!("couldn't parse {}: {}", path.display(), e);
+                Settings::default()
+            }),
+            // no file yet is the usual first run, not worth a warning
+            Err(_) => Settings::default(),
+        };
+        settings.file = Some(path);
+        settings
+    }
+
+    fn save(&self) {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                ron::ser::to_string_pretty(self, Default::default()).map_err(|e| e.to_string())
+            })
+            .and_then(|data| std::fs::write(path, data).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!("couldn't save {}: {}", path.display(), e);
+        }
+    }
+
+    fn is_profile_only(&self) -> bool {
+        self.file != config_path()
+    }
+
+    // turning it off throws the profile's copy away and goes back to the shared settings
+    fn toggle_profile_only(&mut self, profile: &Profile) {
+        if self.is_profile_only() {
+            if let Some(path) = &self.file {
+                std::fs::remove_file(path).ok();
+            }
+            *self = Settings::shared();
+        } else {
+            self.file = profile.dir().map(|dir| dir.join(SETTINGS_FILE));
+        }
+    }
+
+    pub fn music_level(&self) -> f32 {
+        self.volume * self.music_volume
+    }
+
+    pub fn sfx_level(&self) -> f32 {
+        self.volume * self.sfx_volume
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    // the label and current value of each row on the settings screen
+    fn rows(&self) -> [(&'static str, String); ROWS] {
+        let on_off = |on: bool| if on { "On" } else { "Off" }.to_string();
+        [
+            ("Master volume", slider(self.volume)),
+            ("Music volume", slider(self.music_volume)),
+            ("Sound volume", slider(self.sfx_volume)),
+            ("Fullscreen", on_off(self.fullscreen)),
+            ("VSync", on_off(self.vsync)),
+            ("Camera smoothing", on_off(self.camera_smoothing)),
+            ("Diagonal movement", on_off(self.diagonal_movement)),
+            ("Colour palette", self.palette.name().to_string()),
+            ("Key repeat delay", format!("{:.2}s", self.repeat_delay)),
+            ("Key repeat rate", format!("{:.0}/s", self.repeat_rate)),
+            ("Tutorial", on_off(self.tutorial)),
+            ("Narration", self.narration.name().to_string()),
+            (
+                "Saved for",
+                if self.is_profile_only() {
+                    "This profile"
+                } else {
+                    "All profiles"
+                }
+                .to_string(),
+            ),
+        ]
+    }
+
+    // left and right step a row's value down or up, toggles just flip
+    fn adjust(&mut self, row: usize, step: i32) {
+        match row {
+            0 => {
+                self.volume = step_value(self.volume, step, VOLUME_STEP, (0., 1.));
+            }
+            1 => {
+                self.music_volume = step_value(self.music_volume, step, VOLUME_STEP, (0., 1.));
+            }
+            2 => {
+                self.sfx_volume = step_value(self.sfx_volume, step, VOLUME_STEP, (0., 1.));
+            }
+            3 => self.fullscreen = !self.fullscreen,
+            4 => self.vsync = !self.vsync,
+            5 => self.camera_smoothing = !self.camera_smoothing,
+            6 => self.diagonal_movement = !self.diagonal_movement,
+            8 => {
+                self.repeat_delay = step_value(
+                    self.repeat_delay,
+                    step,
+                    REPEAT_DELAY_STEP,
+                    REPEAT_DELAY_RANGE,
+                )
+            }
+            9 => {
+                self.repeat_rate =
+                    step_value(self.repeat_rate, step, REPEAT_RATE_STEP, REPEAT_RATE_RANGE)
+            }
+            10 => self.tutorial = !self.tutorial,
+            7 => self.palette = cycle(&Palette::ALL, self.palette, step),
+            11 => self.narration = cycle(&Narration::ALL, self.narration, step),
+            _ => {}
+        }
+    }
+}
+
+// the option step places along from current, wrapping round at either end
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T, step: i32) -> T {
+    let i = options.iter().position(|o| *o == current).unwrap_or(0) as i32;
+    options[(i + step).rem_euclid(options.len() as i32) as usize]
+}
+
+// a bar that fills up with the volume, followed by the exact figure
+fn slider(value: f32) -> String {
+    let filled = ((value * SLIDER_WIDTH as f32).round() as usize).min(SLIDER_WIDTH);
+    format!(
+        "[{}{}] {:.0}%",
+        "|".repeat(filled),
+        ".".repeat(SLIDER_WIDTH - filled),
+        value * 100.
+    )
+}
+
+// snaps to whole steps so repeated presses don't drift
+fn step_value(value: f32, step: i32, size: f32, (min, max): (f32, f32)) -> f32 {
+    (((value / size).round() + step as f32) * size).clamp(min, max)
+}
+
+fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(SETTINGS_FILE))
+}
+
+// the game's folder in the platform's per-user config dir, worked out the same way most apps do
+pub fn config_dir() -> Option<PathBuf> {
+    user_dir("XDG_CONFIG_HOME", &[".config"])
+}
+
+// the same for the data dir, where profiles and their saves live
+pub fn data_dir() -> Option<PathBuf> {
+    user_dir("XDG_DATA_HOME", &[".local", "share"])
+}
+
+// off for headless runs, which shouldn't read the player's settings or write over their
+// profiles and scores. everything that saves already copes with there being no folder
+static USER_DIRS: AtomicBool = AtomicBool::new(true);
+
+pub fn disable_user_dirs() {
+    USER_DIRS.store(false, Ordering::Relaxed);
+}
+
+// windows and macos keep config and data together, other unixes split them up
+fn user_dir(xdg_var: &str, xdg_default: &[&str]) -> Option<PathBuf> {
+    if !USER_DIRS.load(Ordering::Relaxed) {
+        return None;
+    }
+    let env_path = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        env_path("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_path(xdg_var).or_else(|| {
+            env_path("HOME").map(|home| xdg_default.iter().fold(home, |path, dir| path.join(dir)))
+        })
+    }?;
+    Some(base.join(CONFIG_FOLDER))
+}
+
+struct SettingsScreen;
+struct SettingsText;
+
+// the highlighted row on the settings screen
+#[derive(Default)]
+struct SettingsCursor(usize);
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SettingsCursor::default())
+            .add_system_to_stage("app_state", apply_settings.system())
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Settings).with_system(show_settings.system()),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_update(AppState::Settings)
+                    .with_system(settings_input.system().label("settings_input"))
+                    .with_system(update_settings_text.system().after("settings_input")),
+            )
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_exit(AppState::Settings).with_system(close_settings.system()),
+            );
+    }
+}
+
+// pushes changes out to the window and the config file. the window was opened with
+// the loaded settings, so nothing needs doing until they first change
+fn apply_settings(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        if window.mode() != settings.window_mode() {
+            window.set_mode(settings.window_mode());
+        }
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+    settings.save();
+}
+
+fn show_settings(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    mut cursor: ResMut<SettingsCursor>,
+) {
+    cursor.0 = 0;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(SettingsScreen)
+        .with_children(|parent| {
+            // filled in by update_settings_text
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 20.,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(SettingsText);
+        });
+}
+
+// up and down pick a row, left and right or enter change it, escape goes back
+fn settings_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    profile: Res<Profile>,
+    mut cursor: ResMut<SettingsCursor>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // the screen underneath would see it too and close as well
+        keyboard_input.reset(KeyCode::Escape);
+        app_state.pop().ok();
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        cursor.0 = (cursor.0 + ROWS - 1) % ROWS;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        cursor.0 = (cursor.0 + 1) % ROWS;
+    }
+    let step = if keyboard_input.just_pressed(KeyCode::Left) {
+        -1
+    } else if keyboard_input.just_pressed(KeyCode::Right)
+        || keyboard_input.just_pressed(KeyCode::Return)
+    {
+        1
+    } else {
+        return;
+    };
+    if cursor.0 == SCOPE_ROW {
+        settings.toggle_profile_only(&profile);
+    } else {
+        settings.adjust(cursor.0, step);
+    }
+}
+
+fn update_settings_text(
+    font: Res<UiFont>,
+    settings: Res<Settings>,
+    cursor: Res<SettingsCursor>,
+    mut ev_announce: EventWriter<AnnounceEvent>,
+    mut text_query: Query<&mut Text, With<SettingsText>>,
+    new_text_query: Query<(), Added<SettingsText>>,
+) {
+    if !settings.is_changed() && !cursor.is_changed() && new_text_query.iter().next().is_none() {
+        return;
+    }
+    // the narration reads out just the row that's highlighted
+    let (label, value) = &settings.rows()[cursor.0];
+    ev_announce.send(AnnounceEvent(format!("{}: {}", label, value)));
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    let mut sections = vec![TextSection {
+        value: "Settings\n".to_string(),
+        style: style(36., Color::rgb(0.95, 0.85, 0.4)),
+    }];
+    for (i, (label, value)) in settings.rows().iter().enumerate() {
+        let color = if i == cursor.0 {
+            Color::rgb(1., 0.9, 0.5)
+        } else {
+            Color::WHITE
+        };
+        let marker = if i == cursor.0 { ">" } else { " " };
+        sections.push(TextSection {
+            value: format!("\n{} {}: {}", marker, label, value),
+            style: style(20., color),
+        });
+    }
+    sections.push(TextSection {
+        value: "\n\n[Up/Down] select  [Left/Right] change  [Esc] back".to_string(),
+        style: style(14., Color::GRAY),
+    });
+    if let Ok(mut text) = text_query.single_mut() {
+        text.sections = sections;
+    }
+}
+
+fn close_settings(mut commands: Commands, screen_query: Query<Entity, With<SettingsScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
@@ -0,0 +1,95 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+pub struct SpatialPlugin;
+
+// every entity with a GridPos but the map's own tile sprites, by tile. kept in sync at the
+// start of each frame from changed GridPos values, and by resolve_moves as it moves actors
+// around mid-frame
+#[derive(Default)]
+pub struct SpatialIndex {
+    tiles: HashMap<(i32, i32), Vec<Entity>>,
+    positions: HashMap<Entity, (i32, i32)>,
+}
+impl SpatialIndex {
+    pub fn at(&self, loc: &GridPos) -> &[Entity] {
+        self.tiles
+            .get(&(loc.x, loc.y))
+            .map_or(&[], |v| v.as_slice())
+    }
+
+    // whether anything that gets in the way of movement is standing on the tile
+    pub fn is_blocked(&self, loc: &GridPos, blockers: &Query<(), With<BlocksMovement>>) -> bool {
+        self.at(loc).iter().any(|&e| blockers.get(e).is_ok())
+    }
+
+    // adds an entity, or moves it if it's already indexed somewhere else
+    pub fn place(&mut self, entity: Entity, loc: &GridPos) {
+        let tile = (loc.x, loc.y);
+        if self.positions.get(&entity) == Some(&tile) {
+            return;
+        }
+        self.remove(entity);
+        self.positions.insert(entity, tile);
+        self.tiles.entry(tile).or_default().push(entity);
+    }
+
+    fn clear(&mut self) {
+        self.tiles.clear();
+        self.positions.clear();
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(tile) = self.positions.remove(&entity) {
+            if let Some(entities) = self.tiles.get_mut(&tile) {
+                entities.retain(|&e| e != entity);
+                if entities.is_empty() {
+                    self.tiles.remove(&tile);
+                }
+            }
+        }
+    }
+}
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SpatialIndex::default()).add_system(
+            index_locations
+                .system()
+                .label("index")
+                .before("input")
+                .before("ai")
+                .before("resolve"),
+        );
+    }
+}
+
+// picks up anything spawned, moved or despawned since last frame. despawns are found by
+// looking rather than through RemovedComponents, whose record of the ones applied at the end
+// of the update stage is gone by the next frame. a new floor starts the index over
+fn index_locations(
+    mut index: ResMut<SpatialIndex>,
+    mut ev_finished_map: EventReader<FinishedMapEvent>,
+    located_query: Query<(Entity, &GridPos), Without<MapElement>>,
+    moved_query: Query<(Entity, &GridPos), (Changed<GridPos>, Without<MapElement>)>,
+) {
+    if ev_finished_map.iter().next().is_some() {
+        index.clear();
+        for (entity, loc) in located_query.iter() {
+            index.place(entity, loc);
+        }
+    }
+    let gone: Vec<Entity> = index
+        .positions
+        .keys()
+        .copied()
+        .filter(|&entity| located_query.get(entity).is_err())
+        .collect();
+    for entity in gone {
+        index.remove(entity);
+    }
+    for (entity, loc) in moved_query.iter() {
+        index.place(entity, loc);
+    }
+}
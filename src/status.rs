@@ -0,0 +1,129 @@
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct StatusPlugin;
+
+// extra attack while Strength is active
+pub const STRENGTH_BONUS: i32 = 3;
+// extra attack and defense while Blessed
+pub const BLESSED_BONUS: i32 = 2;
+// hp lost every turn while Poisoned
+const POISON_DAMAGE: i32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Status {
+    // hits harder
+    Strength,
+    // enemies can't see the wearer, though they can still hear and smell them
+    Invisible,
+    // an altar's favour, fights better
+    Blessed,
+    // loses a little hp every turn
+    Poisoned,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Strength => "Strength",
+            Status::Invisible => "Invisible",
+            Status::Blessed => "Blessed",
+            Status::Poisoned => "Poisoned",
+        }
+    }
+
+    // logged when it runs out on the player
+    fn fade_message(&self) -> &'static str {
+        match self {
+            Status::Strength => "Your strength drains away.",
+            Status::Invisible => "You flicker back into view.",
+            Status::Blessed => "The altar's blessing leaves you.",
+            Status::Poisoned => "The poison works its way out of you.",
+        }
+    }
+}
+
+// timed effects on an actor, each one lasts until the turn stored with it
+#[derive(Default)]
+pub struct StatusEffects(pub Vec<(Status, u32)>);
+impl StatusEffects {
+    pub fn has(&self, status: Status) -> bool {
+        self.0.iter().any(|(s, _)| *s == status)
+    }
+
+    // getting the same effect again keeps whichever runs out later
+    pub fn add(&mut self, status: Status, until: u32) {
+        match self.0.iter_mut().find(|(s, _)| *s == status) {
+            Some((_, old)) => *old = (*old).max(until),
+            None => self.0.push((status, until)),
+        }
+    }
+}
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(poison_damage.system().before("expire"))
+            .add_system(expire_effects.system().label("expire"));
+    }
+}
+
+fn expire_effects(
+    game_state: Res<GameState>,
+    mut last_turn: Local<u32>,
+    mut log: ResMut<MessageLog>,
+    mut effects_query: Query<(&mut StatusEffects, Option<&Player>)>,
+) {
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    for (mut effects, player) in effects_query.iter_mut() {
+        // leave the component alone unless something actually ran out
+        if !effects.0.iter().any(|(_, until)| *until <= game_state.turn) {
+            continue;
+        }
+        for (status, until) in effects.0.iter() {
+            if *until <= game_state.turn && player.is_some() {
+                log.add(status.fade_message());
+            }
+        }
+        effects.0.retain(|(_, until)| *until > game_state.turn);
+    }
+}
+
+fn poison_damage(
+    game_state: Res<GameState>,
+    mut last_turn: Local<u32>,
+    mut log: ResMut<MessageLog>,
+    mut ev_death: EventWriter<DeathEvent>,
+    mut effects_query: Query<(
+        Entity,
+        &StatusEffects,
+        &mut Stats,
+        &GridPos,
+        Option<&Player>,
+    )>,
+) {
+    if *last_turn == game_state.turn {
+        return;
+    }
+    *last_turn = game_state.turn;
+    for (entity, effects, mut stats, loc, player) in effects_query.iter_mut() {
+        if !effects.has(Status::Poisoned) || stats.hp <= 0 {
+            continue;
+        }
+        stats.hp = (stats.hp - POISON_DAMAGE).max(0);
+        if stats.hp == 0 {
+            if player.is_some() {
+                log.add("The poison finishes you off.");
+            }
+            ev_death.send(DeathEvent {
+                entity,
+                killer: entity,
+                location: *loc,
+            });
+        }
+    }
+}
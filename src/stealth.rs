@@ -0,0 +1,61 @@
+use crate::ai::{can_see, AiState};
+use crate::enemy::Disguised;
+use crate::prelude::*;
+use crate::status::{Status, StatusEffects};
+use bevy::prelude::*;
+
+pub struct StealthPlugin;
+
+// whether any awake enemy has the player in sight, drives the sneak indicator
+#[derive(Default)]
+pub struct Stealth {
+    pub seen: bool,
+}
+
+// the line in the hud, see hud::spawn_hud
+pub struct StealthText;
+
+impl Plugin for StealthPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Stealth::default())
+            .add_system(update_stealth.system().after("ai"))
+            .add_system(update_indicator.system());
+    }
+}
+
+// sleeping enemies and mimics still pretending to be chests don't count as watching
+fn update_stealth(
+    mut stealth: ResMut<Stealth>,
+    map_query: Query<&Map>,
+    player_query: Query<(&GridPos, Option<&StatusEffects>), With<Player>>,
+    enemy_query: Query<(&AiState, &GridPos), (With<Enemy>, Without<Disguised>)>,
+) {
+    if let (Ok(current_map), Ok((player_loc, effects))) =
+        (map_query.single(), player_query.single())
+    {
+        let invisible = effects.is_some_and(|e| e.has(Status::Invisible));
+        let seen = !invisible
+            && enemy_query.iter().any(|(state, loc)| {
+                !matches!(state, AiState::Sleeping) && can_see(&current_map.0, loc, player_loc)
+            });
+        if stealth.seen != seen {
+            stealth.seen = seen;
+        }
+    }
+}
+
+fn update_indicator(stealth: Res<Stealth>, mut text_query: Query<&mut Text, With<StealthText>>) {
+    if !stealth.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let section = &mut text.sections[0];
+        if stealth.seen {
+            section.value = "Seen".to_string();
+            section.style.color = Color::rgb(0.9, 0.3, 0.25);
+        } else {
+            section.value = "Hidden".to_string();
+            section.style.color = Color::rgb(0.5, 0.7, 0.9);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::ai::{can_see, AiState};
+use crate::components::Direction;
+use crate::enemy::{spawn_enemy, EnemyKind, EnemyTemplates};
+use crate::movement::can_move;
+use crate::pathfinding::NEIGHBORS;
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::{Status, StatusEffects};
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct SummonerPlugin;
+
+// summoners back off when the player gets this close
+const KEEP_DISTANCE: i32 = 2;
+// turns between each call for help
+const SUMMON_INTERVAL: u32 = 3;
+// most minions a single summoner can have out at once
+const MAX_SUMMONS: usize = 3;
+
+pub struct Summoner {
+    // turns spent fighting the player, summons go out on a fixed beat
+    pub turns: u32,
+}
+
+// a minion that was called in by a particular summoner
+pub struct SummonedBy(pub Entity);
+
+impl Plugin for SummonerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(summoner_turn.system().after("input").before("ai"));
+    }
+}
+
+// summoners keep their distance and fill their room with rats, so the player has to push through
+fn summoner_turn(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    map_query: Query<(&Map, &MapRooms)>,
+    player_query: Query<(&GridPos, Option<&StatusEffects>), With<Player>>,
+    mut summoner_query: Query<(
+        Entity,
+        &mut Summoner,
+        &GridPos,
+        &mut AiState,
+        &mut NextAction,
+    )>,
+    summoned_query: Query<&SummonedBy>,
+    index: Res<SpatialIndex>,
+    blocker_query: Query<(), With<BlocksMovement>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let rng = rng.stream("summons");
+    if game_state.phase != TurnPhase::EnemyAction {
+        return;
+    }
+    if let (Ok((current_map, map_rooms)), Ok((player_loc, effects))) =
+        (map_query.single(), player_query.single())
+    {
+        let invisible = effects.is_some_and(|e| e.has(Status::Invisible));
+        let map_data = &current_map.0;
+        // tiles summoned onto this turn, the new arrivals aren't in the index yet
+        let mut claimed: Vec<GridPos> = Vec::new();
+        let occupied = |tile: &GridPos, claimed: &[GridPos]| {
+            index.is_blocked(tile, &blocker_query)
+                || claimed.iter().any(|o| o.x == tile.x && o.y == tile.y)
+        };
+        for (entity, mut summoner, loc, mut state, mut action) in summoner_query.iter_mut() {
+            let distance = loc.chebyshev(player_loc);
+            if matches!(*state, AiState::Sleeping) {
+                if distance <= 1 {
+                    *state = AiState::Chasing {
+                        last_seen: *player_loc,
+                        turns_unseen: 0,
+                    };
+                }
+                continue;
+            }
+            // stays put, channelling, until the player comes into view
+            if invisible || !can_see(map_data, loc, player_loc) {
+                continue;
+            }
+            *state = AiState::Chasing {
+                last_seen: *player_loc,
+                turns_unseen: 0,
+            };
+            summoner.turns += 1;
+
+            if distance <= KEEP_DISTANCE {
+                // step to whichever open tile is farthest from the player, or lash out if cornered
+                let retreat = NEIGHBORS
+                    .iter()
+                    .filter(|&&(dx, dy)| can_move(map_data, loc, dx, dy))
+                    .map(|&(dx, dy)| loc.add(dx, dy))
+                    .filter(|tile| !occupied(tile, &claimed))
+                    .filter(|tile| tile.chebyshev(player_loc) > distance)
+                    .max_by_key(|tile| tile.chebyshev(player_loc));
+                action.0 = match retreat {
+                    Some(tile) => Some(Action::Move(Direction(tile.x - loc.x, tile.y - loc.y))),
+                    None if distance == 1 => Some(Action::Move(Direction(
+                        player_loc.x - loc.x,
+                        player_loc.y - loc.y,
+                    ))),
+                    None => None,
+                };
+                continue;
+            }
+
+            if !summoner.turns.is_multiple_of(SUMMON_INTERVAL) {
+                continue;
+            }
+            let summoned = summoned_query.iter().filter(|s| s.0 == entity).count();
+            if summoned >= MAX_SUMMONS {
+                continue;
+            }
+            // summons only show up on open ground in the summoner's own room
+            let room = match map_rooms.rooms.iter().find(|room| room.contains(loc)) {
+                Some(room) => room,
+                None => continue,
+            };
+            let free_tiles: Vec<GridPos> = (room.bottom..room.bottom + room.height)
+                .flat_map(|y| (room.left..room.left + room.width).map(move |x| GridPos::new(x, y)))
+                .filter(|tile| map_data.is_walkable(tile.x, tile.y))
+                .filter(|tile| !occupied(tile, &claimed))
+                .filter(|tile| (tile.x, tile.y) != (player_loc.x, player_loc.y))
+                .collect();
+            if free_tiles.is_empty() {
+                continue;
+            }
+            let tile = free_tiles[rng.gen_range(0..free_tiles.len())];
+            claimed.push(tile);
+            let minion = match spawn_enemy(
+                &mut commands,
+                &templates,
+                &modifiers,
+                &tileset,
+                &window,
+                EnemyKind::Rat,
+                tile,
+            ) {
+                Some(minion) => minion,
+                None => continue,
+            };
+            commands
+                .entity(minion)
+                .insert(SummonedBy(entity))
+                .insert(AiState::Chasing {
+                    last_seen: *player_loc,
+                    turns_unseen: 0,
+                });
+        }
+    }
+}
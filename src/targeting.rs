@@ -0,0 +1,256 @@
+use crate::enemy::Disguised;
+use crate::fov::{Explored, FieldOfView};
+use crate::item::{Identification, Item};
+use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::status::StatusEffects;
+use bevy::prelude::*;
+
+pub struct TargetingPlugin;
+
+// the tile cursor shared by look mode, spells, the bow and throwing. whoever puts it up
+// reads the tile back when the player confirms, the arrow keys move it in the meantime
+#[derive(Default)]
+pub struct TargetCursor {
+    tile: Option<GridPos>,
+    origin: GridPos,
+    // how far from the origin it can go, None for anywhere on the map
+    range: Option<i32>,
+}
+impl TargetCursor {
+    pub fn show(&mut self, origin: &GridPos, range: Option<i32>) {
+        self.tile = Some(*origin);
+        self.origin = *origin;
+        self.range = range;
+    }
+
+    pub fn hide(&mut self) {
+        self.tile = None;
+    }
+
+    pub fn tile(&self) -> Option<&GridPos> {
+        self.tile.as_ref()
+    }
+}
+
+struct CursorMarker;
+struct CursorPanel;
+struct CursorText;
+
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(TargetCursor::default())
+            .add_system(
+                move_cursor
+                    .system()
+                    .label("cursor")
+                    .after("input")
+                    .before("cast")
+                    .before("aim"),
+            )
+            .add_system(draw_cursor.system().after("cast").after("aim"));
+    }
+}
+
+fn move_cursor(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut target_cursor: ResMut<TargetCursor>,
+    map_query: Query<&Map>,
+) {
+    let cursor = match target_cursor.tile() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let dx = keyboard_input.just_pressed(KeyCode::Right) as i32
+        - keyboard_input.just_pressed(KeyCode::Left) as i32;
+    let dy = keyboard_input.just_pressed(KeyCode::Up) as i32
+        - keyboard_input.just_pressed(KeyCode::Down) as i32;
+    if (dx, dy) == (0, 0) {
+        return;
+    }
+    let moved = cursor.add(dx, dy);
+    let in_range = target_cursor
+        .range
+        .is_none_or(|range| target_cursor.origin.chebyshev(&moved) <= range);
+    let on_map = map_query
+        .single()
+        .is_ok_and(|current_map| current_map.0.in_bounds(moved.x, moved.y));
+    if in_range && on_map {
+        target_cursor.tile = Some(moved);
+    }
+}
+
+// keeps the cursor sprite on its tile and the readout in step with what's under it
+fn draw_cursor(
+    mut commands: Commands,
+    target_cursor: Res<TargetCursor>,
+    (font, materials, window, identification): (
+        Res<UiFont>,
+        Res<Materials>,
+        Res<WinSize>,
+        Res<Identification>,
+    ),
+    fov: Res<FieldOfView>,
+    index: Res<SpatialIndex>,
+    map_query: Query<(&Map, &Explored)>,
+    thing_query: Query<
+        (
+            Option<&Name>,
+            Option<&Stats>,
+            Option<&StatusEffects>,
+            Option<&Item>,
+            Option<&Disguised>,
+        ),
+        Without<Player>,
+    >,
+    stairs_query: Query<&OnMap, With<Stairs>>,
+    mut marker_query: Query<&mut Transform, With<CursorMarker>>,
+    marker_entity_query: Query<Entity, With<CursorMarker>>,
+    panel_query: Query<Entity, With<CursorPanel>>,
+    mut text_query: Query<&mut Text, With<CursorText>>,
+) {
+    if !target_cursor.is_changed() {
+        return;
+    }
+    let cursor = match target_cursor.tile() {
+        Some(cursor) => cursor,
+        None => {
+            for entity in marker_entity_query.iter().chain(panel_query.iter()) {
+                commands.entity(entity).despawn_recursive();
+            }
+            return;
+        }
+    };
+    let readout = match map_query.single() {
+        Ok((current_map, explored)) => describe_tile(
+            cursor,
+            &current_map.0,
+            explored,
+            &fov,
+            &index,
+            &identification,
+            &thing_query,
+            &stairs_query,
+        ),
+        Err(_) => return,
+    };
+    let translation = cursor.to_world(window.tile).extend(13.);
+
+    if let Ok(mut marker_tf) = marker_query.single_mut() {
+        marker_tf.translation = translation;
+        if let Ok(mut text) = text_query.single_mut() {
+            text.sections[0].value = readout;
+        }
+        return;
+    }
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.target.clone(),
+            sprite: Sprite::new(Vec2::splat(window.tile)),
+            transform: Transform::from_translation(translation),
+            ..Default::default()
+        })
+        .insert(CursorMarker);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(6.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(CursorPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        readout,
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 16.,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(CursorText);
+        });
+}
+
+// everything the player can make out on a tile, one line each. only what's in view is
+// listed, remembered tiles just say what the ground is
+fn describe_tile(
+    loc: &GridPos,
+    map_data: &DungeonMap,
+    explored: &Explored,
+    fov: &FieldOfView,
+    index: &SpatialIndex,
+    identification: &Identification,
+    thing_query: &Query<
+        (
+            Option<&Name>,
+            Option<&Stats>,
+            Option<&StatusEffects>,
+            Option<&Item>,
+            Option<&Disguised>,
+        ),
+        Without<Player>,
+    >,
+    stairs_query: &Query<&OnMap, With<Stairs>>,
+) -> String {
+    if !explored.is_explored(loc.x, loc.y) {
+        return "Unexplored".to_string();
+    }
+    let mut lines = vec![map_data
+        .tile_at(loc.x, loc.y)
+        .map_or("Floor", Tile::describe)
+        .to_string()];
+    if stairs_query.iter().any(|on_map| on_map.0 == *loc) {
+        lines.push("Stairs down".to_string());
+    }
+    if !fov.is_visible(loc.x, loc.y) {
+        lines.push("(out of sight)".to_string());
+        return lines.join("\n");
+    }
+    for &entity in index.at(loc) {
+        let (name, stats, effects, item, disguised) = match thing_query.get(entity) {
+            Ok(thing) => thing,
+            Err(_) => continue,
+        };
+        let line = match (item, disguised, stats, name) {
+            (Some(item), ..) => identification.describe(item),
+            // a mimic looks like any other chest until it moves
+            (_, Some(_), ..) => "chest".to_string(),
+            (_, _, Some(stats), Some(name)) => {
+                let health = match stats.hp * 4 / stats.max_hp.max(1) {
+                    4 => "unhurt",
+                    3 => "wounded",
+                    2 => "badly wounded",
+                    _ => "near death",
+                };
+                let mut states = vec![health.to_string()];
+                if let Some(effects) = effects {
+                    states.extend(
+                        effects
+                            .0
+                            .iter()
+                            .map(|(status, _)| status.name().to_lowercase()),
+                    );
+                }
+                format!("{} ({})", name.as_str(), states.join(", "))
+            }
+            (.., Some(name)) => name.as_str().to_string(),
+            _ => continue,
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,283 @@
+use crate::aoe::{spawn_highlight, AoeShape};
+use crate::inventory::{Equipment, Inventory, InventoryScreen};
+use crate::item::{spawn_item, Identification, ItemKind, ItemMaterials, Potion};
+use crate::level::LevelUp;
+use crate::magic::Casting;
+use crate::map_view::MapView;
+use crate::message_log::{capitalize, MessageLog};
+use crate::npc::ActiveDialogue;
+use crate::prelude::*;
+use crate::status::{Status, StatusEffects};
+use crate::targeting::TargetCursor;
+use crate::visibility::line;
+use bevy::prelude::*;
+
+pub struct ThrowingPlugin;
+
+const THROW_RANGE: i32 = 5;
+const BOW_RANGE: i32 = 8;
+// a shattered potion splashes everyone this close to where it broke
+const SPLASH: AoeShape = AoeShape::Circle(1);
+// a splash is weaker than drinking the whole thing
+const SPLASH_HEAL: i32 = 6;
+const SPLASH_TURNS: u32 = 8;
+
+// what the cursor is aiming
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shot {
+    // the item at this spot in the pack
+    Throw(usize),
+    // an arrow from the equipped bow
+    Arrow,
+}
+
+impl Shot {
+    fn range(&self) -> i32 {
+        match self {
+            Shot::Throw(_) => THROW_RANGE,
+            Shot::Arrow => BOW_RANGE,
+        }
+    }
+}
+
+// look mode and the throwing and shooting cursor, player input is blocked while either
+// is up. both move the shared TargetCursor
+pub enum Aiming {
+    Idle,
+    // just looking around, nothing happens on the chosen tile
+    Looking,
+    Targeting(Shot),
+}
+impl Aiming {
+    pub fn is_busy(&self) -> bool {
+        !matches!(self, Aiming::Idle)
+    }
+}
+
+// the player used up their turn throwing or shooting something
+pub struct ThrowEvent {
+    pub thrower: Entity,
+}
+
+// one tinted tile of the aiming overlay
+struct AimTarget;
+
+impl Plugin for ThrowingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Aiming::Idle)
+            .add_event::<ThrowEvent>()
+            // after player input, so the key that lets go can't also move the player
+            .add_system(aim_input.system().label("aim").after("input"))
+            .add_system(land_thrown_items.system());
+    }
+}
+
+// X looks around, F shoots the equipped bow, T on the inventory screen throws the selected
+// item. the arrow keys move the cursor, enter or space lets go, escape puts it away
+fn aim_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    (dialogue, casting, level_up, inventory_screen, map_view): (
+        Res<ActiveDialogue>,
+        Res<Casting>,
+        Res<LevelUp>,
+        Res<InventoryScreen>,
+        Res<MapView>,
+    ),
+    (materials, window, identification): (Res<Materials>, Res<WinSize>, Res<Identification>),
+    mut aiming: ResMut<Aiming>,
+    mut target_cursor: ResMut<TargetCursor>,
+    mut log: ResMut<MessageLog>,
+    mut ev_fire: EventWriter<FireProjectileEvent>,
+    mut ev_throw: EventWriter<ThrowEvent>,
+    map_query: Query<&Map>,
+    mut player_query: Query<(Entity, &GridPos, &mut Inventory, &Equipment), With<Player>>,
+    overlay_query: Query<Entity, With<AimTarget>>,
+) {
+    let (player, player_loc, mut inventory, equipment) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let map_data = match map_query.single() {
+        Ok(current_map) => &current_map.0,
+        Err(_) => return,
+    };
+
+    // the inventory screen sets the shot without a cursor, it goes up here
+    if let Aiming::Targeting(shot) = &*aiming {
+        if target_cursor.tile().is_none() {
+            target_cursor.show(player_loc, Some(shot.range()));
+        }
+    }
+    // read through a plain borrow so the overlay only gets redrawn when something changes
+    let targeting = match &*aiming {
+        Aiming::Idle => None,
+        Aiming::Looking => {
+            if keyboard_input.just_pressed(KeyCode::Escape)
+                || keyboard_input.just_pressed(KeyCode::X)
+            {
+                target_cursor.hide();
+                *aiming = Aiming::Idle;
+            }
+            return;
+        }
+        Aiming::Targeting(shot) => target_cursor.tile().map(|cursor| (*shot, *cursor)),
+    };
+    match targeting {
+        None => {
+            let can_act = !game_state.animating_actions
+                && game_state.has_map
+                && game_state.phase == TurnPhase::PlayerInput
+                && !dialogue.is_open()
+                && !casting.is_busy()
+                && !level_up.is_choosing()
+                && !inventory_screen.open
+                && !map_view.open;
+            if can_act && keyboard_input.just_pressed(KeyCode::X) {
+                target_cursor.show(player_loc, None);
+                *aiming = Aiming::Looking;
+            } else if can_act && keyboard_input.just_pressed(KeyCode::F) {
+                let has_bow = equipment
+                    .weapon
+                    .as_ref()
+                    .is_some_and(|item| item.kind == ItemKind::Bow);
+                if !has_bow {
+                    log.add("You don't have a bow ready.");
+                } else if inventory.quiver().is_none() {
+                    log.add("You're out of arrows.");
+                } else {
+                    target_cursor.show(player_loc, Some(BOW_RANGE));
+                    *aiming = Aiming::Targeting(Shot::Arrow);
+                }
+            }
+        }
+        Some((shot, cursor)) => {
+            if keyboard_input.just_pressed(KeyCode::Escape) {
+                target_cursor.hide();
+                *aiming = Aiming::Idle;
+            } else if keyboard_input.just_pressed(KeyCode::Return)
+                || keyboard_input.just_pressed(KeyCode::Space)
+            {
+                if (cursor.x, cursor.y) == (player_loc.x, player_loc.y) {
+                    return;
+                }
+                let payload = match shot {
+                    Shot::Throw(i) if i < inventory.items.len() => {
+                        let item = inventory.items.remove(i);
+                        log.add(format!("You throw {}.", identification.describe(&item)));
+                        Some(item)
+                    }
+                    Shot::Arrow => {
+                        // the last arrow takes its slot in the pack with it
+                        if let Some(quiver) = inventory.quiver() {
+                            *quiver -= 1;
+                        }
+                        inventory
+                            .items
+                            .retain(|item| item.kind != ItemKind::Arrows(0));
+                        None
+                    }
+                    _ => {
+                        target_cursor.hide();
+                        *aiming = Aiming::Idle;
+                        return;
+                    }
+                };
+                ev_fire.send(FireProjectileEvent {
+                    source: player,
+                    from: *player_loc,
+                    to: cursor,
+                    power: None,
+                    element: None,
+                    payload,
+                });
+                ev_throw.send(ThrowEvent { thrower: player });
+                target_cursor.hide();
+                *aiming = Aiming::Idle;
+            }
+        }
+    }
+
+    // the cursor can also be put up by the inventory screen, so redraw on any change
+    if !aiming.is_changed() && !target_cursor.is_changed() {
+        return;
+    }
+    for tile in overlay_query.iter() {
+        commands.entity(tile).despawn();
+    }
+    if let (Aiming::Targeting(shot), Some(cursor)) = (&*aiming, target_cursor.tile()) {
+        // the flight path, plus the splash if it's a potion
+        let mut tiles = line(player_loc, cursor);
+        if let Shot::Throw(i) = shot {
+            if inventory.items.get(*i).is_some_and(|item| item.shatters()) {
+                tiles.extend(SPLASH.tiles(map_data, cursor, cursor));
+            }
+        }
+        for loc in tiles.iter() {
+            let tile = spawn_highlight(&mut commands, materials.target.clone(), &window, loc, 12.);
+            commands.entity(tile).insert(AimTarget);
+        }
+    }
+}
+
+// potions break and splash everyone nearby, anything else is left lying where it fell
+fn land_thrown_items(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    (item_materials, window): (Res<ItemMaterials>, Res<WinSize>),
+    mut identification: ResMut<Identification>,
+    mut log: ResMut<MessageLog>,
+    mut ev_landed: EventReader<ProjectileLandedEvent>,
+    map_query: Query<&Map>,
+    mut actor_query: Query<(Entity, &GridPos, &mut Stats, Option<&mut StatusEffects>)>,
+) {
+    for landed in ev_landed.iter() {
+        let potion = match landed.item.kind {
+            ItemKind::Potion(potion) => potion,
+            _ => {
+                spawn_item(
+                    &mut commands,
+                    &item_materials,
+                    &window,
+                    landed.item.clone(),
+                    landed.location,
+                );
+                continue;
+            }
+        };
+        // seeing what the splash does gives it away
+        identification.learn(landed.item.kind);
+        log.add(format!("{} shatters.", capitalize(&landed.item.describe())));
+        let map_data = match map_query.single() {
+            Ok(current_map) => &current_map.0,
+            Err(_) => continue,
+        };
+        let splashed = SPLASH.tiles(map_data, &landed.location, &landed.location);
+        let until = game_state.turn + SPLASH_TURNS;
+        for (entity, loc, mut stats, effects) in actor_query.iter_mut() {
+            if !splashed
+                .iter()
+                .any(|tile| (tile.x, tile.y) == (loc.x, loc.y))
+            {
+                continue;
+            }
+            let status = match potion {
+                Potion::Healing => {
+                    stats.hp = (stats.hp + SPLASH_HEAL).min(stats.max_hp);
+                    continue;
+                }
+                Potion::Strength => Status::Strength,
+                Potion::Invisibility => Status::Invisible,
+            };
+            match effects {
+                Some(mut effects) => effects.add(status, until),
+                None => {
+                    commands
+                        .entity(entity)
+                        .insert(StatusEffects(vec![(status, until)]));
+                }
+            }
+        }
+    }
+}
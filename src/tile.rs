@@ -0,0 +1,213 @@
+use crate::prelude::*;
+use crate::settings::Palette;
+use array2d::Array2D;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const TILE_SIZE: f32 = 48.;
+// the map is drawn in square chunks of this many tiles a side
+pub const CHUNK_SIZE: i32 = 16;
+
+// the sprite sheet the map, the player, chests and enemies are drawn from
+pub struct Tileset(pub Handle<TextureAtlas>);
+
+// the fixed cells of the tileset. enemies pick their own cell in the enemy data file
+#[derive(Clone, Copy, PartialEq)]
+pub enum TileSprite {
+    Floor = 0,
+    // plain white, for tinting
+    Blank = 1,
+    Stairs = 2,
+    Void = 3,
+    Player = 4,
+    Chest = 5,
+    Torch = 34,
+    // the first of sixteen wall variants, see wall_mask
+    Wall = 16,
+}
+
+impl TileSprite {
+    pub fn sprite(self) -> TextureAtlasSprite {
+        TextureAtlasSprite::new(self as u32)
+    }
+
+    // the sprite for the map tile at x, y, in the player's palette. walls look at their
+    // neighbours so runs of wall join up, and off the edge of the map is drawn as solid rock
+    pub fn at(map_data: &DungeonMap, x: i32, y: i32, palette: Palette) -> TextureAtlasSprite {
+        let (sprite, color) = match map_data.tile_at(x, y) {
+            Some(Tile::Ground) => (TileSprite::Floor.sprite(), palette.retint(FLOOR_TEXTURE)),
+            Some(Tile::Wall) => (
+                TextureAtlasSprite::new(TileSprite::Wall as u32 + wall_mask(map_data, x, y)),
+                palette.retint(WALL_TEXTURE),
+            ),
+            Some(Tile::Chasm) => (
+                TileSprite::Blank.sprite(),
+                palette.tint(Color::rgb(0.06, 0.05, 0.08)),
+            ),
+            Some(Tile::Door) => (
+                TileSprite::Blank.sprite(),
+                palette.tint(Color::rgb(0.4, 0.32, 0.22)),
+            ),
+            Some(Tile::Bridge) => (
+                TileSprite::Floor.sprite(),
+                palette.tint(Color::rgb(0.75, 0.55, 0.35)),
+            ),
+            None => (TileSprite::Void.sprite(), palette.retint(WALL_TEXTURE)),
+        };
+        TextureAtlasSprite { color, ..sprite }
+    }
+}
+
+// the main colour of the floor and wall cells in the tileset, which palettes recolour from
+const FLOOR_TEXTURE: Color = Color::rgb(0.2, 0.2, 0.21);
+const WALL_TEXTURE: Color = Color::rgb(0.9, 0.3, 0.3);
+
+// which of a wall's sides run on into more wall: 1 north, 2 east, 4 south, 8 west.
+// the void past the edge of the map counts as wall
+pub fn wall_mask(map_data: &DungeonMap, x: i32, y: i32) -> u32 {
+    [(0, 1), (1, 0), (0, -1), (-1, 0)]
+        .iter()
+        .enumerate()
+        .filter(|(_, (dx, dy))| map_data.is_opaque(x + dx, y + dy))
+        .map(|(bit, _)| 1 << bit)
+        .sum()
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Tile {
+    Ground,
+    Wall,
+    // floor that has fallen away, nothing can cross it but it can be seen over
+    Chasm,
+    // a closed gate in a doorway, opened by whatever it's wired to
+    Door,
+    // planks run out over a chasm, walked on like floor
+    Bridge,
+}
+
+impl Tile {
+    // how the readouts name it
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Tile::Ground => "Floor",
+            Tile::Wall => "A wall",
+            Tile::Chasm => "A chasm",
+            Tile::Door => "A closed gate",
+            Tile::Bridge => "A bridge",
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub enum MapStyle {
+    Standard,
+    Circular,
+    Cross,
+}
+
+// the tiles of a floor by x, y, with y going up the screen. everything past the edges
+// is solid rock, so callers can ask about any tile without checking the bounds first
+#[derive(Clone)]
+pub struct DungeonMap(Array2D<Tile>);
+
+impl DungeonMap {
+    pub fn filled(tile: Tile, width: usize, height: usize) -> Self {
+        DungeonMap(Array2D::filled_with(tile, height, width))
+    }
+
+    // bottom row first, the way saves store it. panics on ragged rows
+    pub fn from_rows(rows: &[Vec<Tile>]) -> Self {
+        DungeonMap(Array2D::from_rows(rows))
+    }
+
+    pub fn rows(&self) -> Vec<Vec<Tile>> {
+        self.0.as_rows()
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.num_columns()
+    }
+
+    pub fn height(&self) -> usize {
+        self.0.num_rows()
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width() && (y as usize) < self.height()
+    }
+
+    // None off the edge of the map
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<&Tile> {
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        self.0.get(y as usize, x as usize)
+    }
+
+    // off the edge of the map is left alone
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: Tile) {
+        if self.in_bounds(x, y) {
+            self.0.set(y as usize, x as usize, tile).ok();
+        }
+    }
+
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        matches!(self.tile_at(x, y), Some(Tile::Ground) | Some(Tile::Bridge))
+    }
+
+    // blocks sight and light
+    pub fn is_opaque(&self, x: i32, y: i32) -> bool {
+        matches!(
+            self.tile_at(x, y),
+            Some(Tile::Wall) | Some(Tile::Door) | None
+        )
+    }
+
+    // the eight tiles around x, y that are on the map
+    pub fn iter_neighbors(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| (dx, dy) != (0, 0))
+            .map(move |(dx, dy)| (x + dx, y + dy))
+            .filter(move |&(nx, ny)| self.in_bounds(nx, ny))
+    }
+}
+
+pub struct Map(pub DungeonMap, pub GridPos);
+
+pub struct MapElement;
+
+// the map's tile sprites by chunk. a chunk is spawned the first time the camera comes near
+// it and after that only hidden and shown again. lives on the Map entity
+#[derive(Default)]
+pub struct MapChunks(pub HashMap<(i32, i32), Chunk>);
+
+pub struct Chunk {
+    pub tiles: Vec<Entity>,
+    pub shown: bool,
+}
+
+// bounds of a real (non-dummy) room, in tiles
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoomArea {
+    pub left: i32,
+    pub bottom: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl RoomArea {
+    pub fn contains(&self, loc: &GridPos) -> bool {
+        loc.x >= self.left
+            && loc.x < self.left + self.width
+            && loc.y >= self.bottom
+            && loc.y < self.bottom + self.height
+    }
+}
+
+// stored alongside the Map, spawn_room indexes into rooms
+pub struct MapRooms {
+    pub rooms: Vec<RoomArea>,
+    pub spawn_room: usize,
+}
@@ -0,0 +1,120 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+
+pub struct TransitionPlugin;
+
+// seconds the card stays up once the new floor is in, to let its tiles get drawn
+const HOLD: f32 = 0.3;
+// seconds it then takes to fade away
+const FADE: f32 = 0.5;
+
+// covers the screen from leaving one floor until the next one is built and drawn, so a
+// half-built floor never shows
+struct TransitionCard {
+    // counts up from when the new floor was in
+    shown: f32,
+    material: Handle<ColorMaterial>,
+}
+
+struct TransitionText;
+
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // in the same frame the old floor goes, so it never flashes half cleared either
+        app.add_system(show_transition.system().after("cleanup"))
+            // holds still while paused, and goes away with the run
+            .add_system_to_stage("app_state", fade_transition.system());
+    }
+}
+
+fn show_transition(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    font: Res<UiFont>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut ev_finished_map: EventReader<FinishedMapEvent>,
+    card_query: Query<Entity, With<TransitionCard>>,
+) {
+    if ev_finished_map.iter().next().is_none() {
+        return;
+    }
+    for card in card_query.iter() {
+        commands.entity(card).despawn_recursive();
+    }
+    // the map has already counted the new floor
+    let message = if game_state.depth <= 1 {
+        "Entering the dungeon\u{2026}".to_string()
+    } else {
+        format!("Descending to floor {}\u{2026}", game_state.depth)
+    };
+    let material = materials.add(Color::BLACK.into());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: material.clone(),
+            ..Default::default()
+        })
+        .insert(TransitionCard {
+            shown: 0.,
+            material,
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        message,
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 28.,
+                            color: Color::rgb(0.95, 0.85, 0.4),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(TransitionText);
+        });
+}
+
+fn fade_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<GameState>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut card_query: Query<(Entity, &mut TransitionCard)>,
+    mut text_query: Query<&mut Text, With<TransitionText>>,
+) {
+    for (entity, mut card) in card_query.iter_mut() {
+        match app_state.current() {
+            AppState::MainMenu => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+            AppState::InGame => {}
+            _ => continue,
+        }
+        // the floor is still being built
+        if !game_state.has_map {
+            continue;
+        }
+        card.shown += time.delta_seconds();
+        let alpha = 1. - ((card.shown - HOLD) / FADE).clamp(0., 1.);
+        if alpha <= 0. {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if let Some(material) = materials.get_mut(&card.material) {
+            material.color.set_a(alpha);
+        }
+        for mut text in text_query.iter_mut() {
+            text.sections[0].style.color.set_a(alpha);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::in_game;
+use crate::inventory::ItemUsedEvent;
+use crate::magic::SpellCastEvent;
+use crate::prelude::*;
+use crate::throwing::ThrowEvent;
+use bevy::prelude::*;
+
+pub struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            end_player_turn
+                .system()
+                .label("turn")
+                .after("combat")
+                .after("cast")
+                .after("aim")
+                .after("inventory"),
+        )
+        // runs after the update stage has applied its commands,
+        // so MovingTo inserts and removals from this frame are visible
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            advance_turn.system().with_run_criteria(in_game.system()),
+        );
+    }
+}
+
+// a successful move, attack, spell, block, wait, throw, item use or interaction by the player hands the turn over to the enemies
+fn end_player_turn(
+    mut game_state: ResMut<GameState>,
+    mut ev_move_resolved: EventReader<MoveResolvedEvent>,
+    mut ev_attack: EventReader<AttackEvent>,
+    mut ev_finished_map: EventReader<FinishedMapEvent>,
+    mut ev_cast: EventReader<SpellCastEvent>,
+    mut ev_block: EventReader<BlockEvent>,
+    mut ev_wait: EventReader<WaitEvent>,
+    mut ev_item_used: EventReader<ItemUsedEvent>,
+    mut ev_throw: EventReader<ThrowEvent>,
+    mut ev_interact: EventReader<InteractEvent>,
+    player_query: Query<Entity, With<Player>>,
+) {
+    if ev_finished_map.iter().next().is_some() {
+        // a new floor always starts on the player's turn
+        game_state.phase = TurnPhase::PlayerInput;
+        return;
+    }
+    if let Ok(player_entity) = player_query.single() {
+        let player_moved = ev_move_resolved.iter().any(|ev| ev.actor == player_entity);
+        let player_attacked = ev_attack.iter().any(|ev| ev.attacker == player_entity);
+        let player_cast = ev_cast.iter().any(|ev| ev.caster == player_entity);
+        let player_blocked = ev_block.iter().any(|ev| ev.actor == player_entity);
+        let player_waited = ev_wait.iter().any(|ev| ev.actor == player_entity);
+        let player_used_item = ev_item_used.iter().any(|ev| ev.user == player_entity);
+        let player_threw = ev_throw.iter().any(|ev| ev.thrower == player_entity);
+        let player_interacted = ev_interact.iter().any(|ev| ev.actor == player_entity);
+        let player_acted = player_moved
+            || player_attacked
+            || player_cast
+            || player_blocked
+            || player_waited
+            || player_used_item
+            || player_threw
+            || player_interacted;
+        if game_state.phase == TurnPhase::PlayerInput && player_acted {
+            game_state.phase = TurnPhase::PlayerAnimating;
+        }
+    }
+}
+
+// waits for every in-flight animation and projectile to land before moving to the next phase
+fn advance_turn(
+    mut game_state: ResMut<GameState>,
+    moving_query: Query<(), Or<(With<MovingTo>, With<Projectile>)>>,
+) {
+    game_state.animating_actions = moving_query.iter().next().is_some();
+    if game_state.animating_actions {
+        return;
+    }
+    match game_state.phase {
+        TurnPhase::PlayerAnimating => game_state.phase = TurnPhase::AllyAction,
+        TurnPhase::AllyAnimating => game_state.phase = TurnPhase::EnemyAction,
+        TurnPhase::EnemyAnimating => {
+            game_state.phase = TurnPhase::PlayerInput;
+            game_state.turn += 1;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,280 @@
+use crate::ai::AiState;
+use crate::chest::{spawn_chest, Chest};
+use crate::circuit::{spawn_mechanism, spawn_switch, Mechanism, Switch};
+use crate::enemy::{spawn_enemy, EnemyKind, EnemyTemplates};
+use crate::fov::Explored;
+use crate::item::{spawn_item, Item, ItemKind, ItemMaterials};
+use crate::light::spawn_torch;
+use crate::map::spawn_stairs;
+use crate::prelude::*;
+use crate::replay::Replay;
+use crate::settings::Settings;
+use bevy::prelude::*;
+
+pub struct TutorialPlugin;
+
+// the tutorial floor, top row first. # is wall, @ the player's start, > the stairs,
+// c a chest, l a lever, g the gate it opens, % a ration, r a rat and t a torch. a digit
+// brings up that prompt when the player steps onto it
+const LAYOUT: [&str; 8] = [
+    "#######################################################",
+    "#.......#.........#.........#.........#.........#.....#",
+    "#.......#.........#....l....#.........#.........#.....#",
+    "#.......#....c....#.........#.........#....r....#.....#",
+    "#...@...1.........2.........g3...%....4.........5..>..#",
+    "#.......#.........#.........#.........#.........#.....#",
+    "#...t...#....t....#....t....#....t....#....t....#..t..#",
+    "#######################################################",
+];
+
+// the left edge and width of each room, all of them the full height of the floor
+const ROOMS: [(i32, i32); 6] = [(1, 7), (9, 9), (19, 9), (29, 9), (39, 9), (49, 5)];
+
+// the one lever on the floor and the gate it works
+const GATE_CIRCUIT: Circuit = Circuit(0);
+
+const PROMPTS: [&str; 6] = [
+    "Move with the arrow keys, or click a tile to walk there. Holding two arrows goes \
+     diagonally. Head through the gap to the east.",
+    "Chests hold loot. Walk into this one to open it, then stand on whatever falls out \
+     and press G to pick it up.",
+    "A gate bars the way east. Stand next to the lever and press E to throw it, and the \
+     gate will grind open. Levers and pressure plates work gates, bridges and traps.",
+    "That's a ration. Stand on it and press G to pick it up. I opens your pack, and U \
+     eats it once you're hungry.",
+    "A rat! Walk into an enemy to attack it. B raises your guard for a turn, and . lets \
+     one go by.",
+    "Those are the stairs down. Stand on them and press Space to start the run proper.",
+];
+
+// on the Map of the tutorial floor, the prompt the player has got up to
+pub struct TutorialFloor {
+    doorways: Vec<(GridPos, usize)>,
+    shown: usize,
+}
+
+struct TutorialPanel;
+struct TutorialText;
+
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            create_tutorial_floor
+                .system()
+                .label("tutorial_floor")
+                .after("cleanup"),
+        )
+        .add_system(finish_tutorial.system().before("cleanup"))
+        .add_system(update_prompt.system().after("resolve"));
+    }
+}
+
+// laid out like a loaded save, so none of the floor spawners add to it
+fn create_tutorial_floor(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    mut spawn_tiles: ResMut<SpawnTiles>,
+    tileset: Res<Tileset>,
+    window: Res<WinSize>,
+    (materials, item_materials): (Res<Materials>, Res<ItemMaterials>),
+    templates: Res<EnemyTemplates>,
+    modifiers: Res<DifficultyModifiers>,
+) {
+    if game_state.has_map || !game_state.tutorial {
+        return;
+    }
+    let height = LAYOUT.len() as i32;
+    let mut rows = Vec::new();
+    let mut spawn = GridPos::default();
+    let mut doorways = Vec::new();
+    // bottom row first, the way the map stores it
+    for (y, line) in LAYOUT.iter().rev().enumerate() {
+        let mut row = Vec::new();
+        for (x, c) in line.chars().enumerate() {
+            let loc = GridPos::new(x as i32, y as i32);
+            row.push(match c {
+                '#' => Tile::Wall,
+                'g' => Tile::Door,
+                _ => Tile::Ground,
+            });
+            match c {
+                '@' => spawn = loc,
+                '>' => spawn_stairs(&mut commands, &tileset, &window, loc),
+                't' => spawn_torch(&mut commands, &tileset, &window, loc),
+                'l' => {
+                    let lever = Switch::Lever { pulled: false };
+                    spawn_switch(&mut commands, &materials, &window, lever, GATE_CIRCUIT, loc);
+                }
+                'g' => {
+                    let gate = Mechanism::Door;
+                    spawn_mechanism(&mut commands, &materials, &window, gate, GATE_CIRCUIT, loc);
+                }
+                'c' => spawn_chest(
+                    &mut commands,
+                    &tileset,
+                    &window,
+                    Chest {
+                        locked: false,
+                        jammed: false,
+                        trap: None,
+                        searched: false,
+                        spotted: false,
+                    },
+                    loc,
+                ),
+                '%' => {
+                    let ration = Item::new(ItemKind::Ration);
+                    spawn_item(&mut commands, &item_materials, &window, ration, loc);
+                }
+                'r' => {
+                    let rat = spawn_enemy(
+                        &mut commands,
+                        &templates,
+                        &modifiers,
+                        &tileset,
+                        &window,
+                        EnemyKind::Rat,
+                        loc,
+                    );
+                    // waits for the player to come to it
+                    if let Some(rat) = rat {
+                        commands.entity(rat).insert(AiState::Sleeping);
+                    }
+                }
+                _ => {
+                    if let Some(step) = c.to_digit(10) {
+                        doorways.push((loc, step as usize));
+                    }
+                }
+            }
+        }
+        rows.push(row);
+    }
+    let map = DungeonMap::from_rows(&rows);
+    let rooms = ROOMS
+        .iter()
+        .map(|&(left, width)| RoomArea {
+            left,
+            bottom: 1,
+            width,
+            height: height - 2,
+        })
+        .collect();
+    *spawn_tiles = SpawnTiles(vec![spawn]);
+    commands
+        .spawn()
+        .insert(Explored::new(&map))
+        .insert(Map(map, spawn))
+        .insert(MapRooms {
+            rooms,
+            spawn_room: 0,
+        })
+        .insert(MapChunks::default())
+        .insert(Restored)
+        .insert(TutorialFloor { doorways, shown: 0 });
+    game_state.has_map = true;
+}
+
+// getting through it once is enough, later runs skip it. a replay leaves the setting be
+fn finish_tutorial(
+    game_state: Res<GameState>,
+    replay: Res<Replay>,
+    mut settings: ResMut<Settings>,
+    mut ev_finished_map: EventReader<FinishedMapEvent>,
+    tutorial_query: Query<(), With<TutorialFloor>>,
+) {
+    // the same check cleanup_map makes, a new run doesn't count as getting through it
+    let leaving = ev_finished_map.iter().next().is_some()
+        && game_state.depth > 0
+        && tutorial_query.iter().next().is_some();
+    if leaving && settings.tutorial && !replay.is_playing() {
+        settings.tutorial = false;
+    }
+}
+
+// a panel along the top with the prompt for the part of the floor the player has reached
+fn update_prompt(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    materials: Res<Materials>,
+    mut tutorial_query: Query<&mut TutorialFloor>,
+    player_query: Query<&GridPos, With<Player>>,
+    panel_query: Query<Entity, With<TutorialPanel>>,
+    mut text_query: Query<&mut Text, With<TutorialText>>,
+) {
+    let mut floor = match tutorial_query.single_mut() {
+        Ok(floor) => floor,
+        Err(_) => {
+            for panel in panel_query.iter() {
+                commands.entity(panel).despawn_recursive();
+            }
+            return;
+        }
+    };
+    if let Ok(loc) = player_query.single() {
+        let reached = floor
+            .doorways
+            .iter()
+            .find(|(doorway, _)| doorway == loc)
+            .map(|(_, step)| *step);
+        // walking back doesn't bring the old prompts back
+        if let Some(step) = reached.filter(|step| *step > floor.shown) {
+            floor.shown = step;
+        }
+    }
+    let prompt = PROMPTS[floor.shown.min(PROMPTS.len() - 1)];
+    match text_query.single_mut() {
+        Ok(mut text) => {
+            if text.sections[0].value != prompt {
+                text.sections[0].value = prompt.to_string();
+            }
+        }
+        Err(_) if panel_query.iter().next().is_none() => {
+            spawn_panel(&mut commands, &font, &materials, prompt)
+        }
+        // it went up this frame, the text isn't in yet
+        Err(_) => {}
+    }
+}
+
+fn spawn_panel(commands: &mut Commands, font: &UiFont, materials: &Materials, prompt: &str) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(25.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(50.), Val::Auto),
+                justify_content: JustifyContent::Center,
+                padding: Rect::all(Val::Px(8.)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(TutorialPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: prompt.to_string(),
+                            style: TextStyle {
+                                font: font.0.clone(),
+                                font_size: 18.,
+                                color: Color::rgb(0.95, 0.9, 0.7),
+                            },
+                        }],
+                        alignment: TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .insert(TutorialText);
+        });
+}
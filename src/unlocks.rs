@@ -0,0 +1,198 @@
+use crate::boss::Boss;
+use crate::class::{ClassKit, PlayerClass};
+use crate::item::{Item, ItemKind, Potion};
+use crate::message_log::MessageLog;
+use crate::prelude::*;
+use crate::profile::Profile;
+use crate::replay::Replay;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub struct UnlocksPlugin;
+
+// kept apart from the run save, so it outlives every run
+const UNLOCKS_FILE: &str = "unlocks.ron";
+// the floor the Ranger unlocks on
+const RANGER_DEPTH: u32 = 5;
+
+// milestones that carry over between runs, each one unlocks something for every run after
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Achievement {
+    ReachFloor,
+    SlayBoss,
+    Escape,
+}
+
+// what an achievement hands out
+pub enum Unlock {
+    Class(PlayerClass),
+    // carried in the pack at the start of every run
+    StartingItem(ItemKind),
+    Layout(FloorLayout),
+}
+
+impl Achievement {
+    pub const ALL: [Achievement; 3] = [
+        Achievement::ReachFloor,
+        Achievement::SlayBoss,
+        Achievement::Escape,
+    ];
+
+    pub fn describe(&self) -> String {
+        match self {
+            Achievement::ReachFloor => format!("Reach floor {}", RANGER_DEPTH),
+            Achievement::SlayBoss => "Slay a boss".to_string(),
+            Achievement::Escape => "Escape the dungeon".to_string(),
+        }
+    }
+
+    pub fn unlock(&self) -> Unlock {
+        match self {
+            Achievement::ReachFloor => Unlock::Class(PlayerClass::Ranger),
+            Achievement::SlayBoss => Unlock::StartingItem(ItemKind::Potion(Potion::Healing)),
+            Achievement::Escape => Unlock::Layout(FloorLayout::Sprawling),
+        }
+    }
+}
+
+impl Unlock {
+    pub fn describe(&self) -> String {
+        match self {
+            Unlock::Class(class) => format!("the {} class", class.name()),
+            Unlock::StartingItem(ItemKind::Potion(Potion::Healing)) => {
+                "a healing potion to start with".to_string()
+            }
+            Unlock::StartingItem(_) => "an item to start with".to_string(),
+            Unlock::Layout(layout) => format!("{} floors", layout.name().to_lowercase()),
+        }
+    }
+}
+
+// the profile's achievements, loaded with the profile
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Unlocks {
+    earned: Vec<Achievement>,
+}
+
+impl Plugin for UnlocksPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(track_achievements.system().after("combat"))
+            .add_system_set_to_stage(
+                "app_state",
+                SystemSet::on_enter(AppState::Victory).with_system(earn_escape.system()),
+            );
+    }
+}
+
+fn unlocks_path(profile: &Profile) -> Option<PathBuf> {
+    Some(profile.dir()?.join(UNLOCKS_FILE))
+}
+
+impl Unlocks {
+    pub fn load(profile: &Profile) -> Self {
+        unlocks_path(profile)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|data| ron::de::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, profile: &Profile) {
+        let path = match unlocks_path(profile) {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| ron::ser::to_string(self).map_err(|e| e.to_string()))
+            .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!("couldn't save {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn has(&self, achievement: Achievement) -> bool {
+        self.earned.contains(&achievement)
+    }
+
+    fn earn(&mut self, achievement: Achievement, profile: &Profile) {
+        self.earned.push(achievement);
+        self.save(profile);
+    }
+
+    // anything no achievement hands out is there from the start
+    fn has_unlock(&self, is_it: impl Fn(&Unlock) -> bool) -> bool {
+        let mut sources = Achievement::ALL
+            .iter()
+            .filter(|achievement| is_it(&achievement.unlock()))
+            .peekable();
+        sources.peek().is_none() || sources.any(|achievement| self.has(*achievement))
+    }
+
+    pub fn has_class(&self, class: PlayerClass) -> bool {
+        self.has_unlock(|unlock| matches!(unlock, Unlock::Class(c) if *c == class))
+    }
+
+    pub fn has_layout(&self, layout: FloorLayout) -> bool {
+        self.has_unlock(|unlock| matches!(unlock, Unlock::Layout(l) if *l == layout))
+    }
+
+    // a class's kit plus whatever the profile has earned to start with
+    pub fn kit(&self, class: PlayerClass) -> ClassKit {
+        let mut kit = class.kit();
+        for achievement in self.earned.iter() {
+            if let Unlock::StartingItem(kind) = achievement.unlock() {
+                kit.pack.push(Item::new(kind));
+            }
+        }
+        kit
+    }
+}
+
+fn announce(log: &mut MessageLog, achievement: Achievement) {
+    log.add(format!(
+        "{}: unlocked {}.",
+        achievement.describe(),
+        achievement.unlock().describe()
+    ));
+}
+
+fn track_achievements(
+    game_state: Res<GameState>,
+    profile: Res<Profile>,
+    mut unlocks: ResMut<Unlocks>,
+    mut log: ResMut<MessageLog>,
+    mut ev_death: EventReader<DeathEvent>,
+    boss_query: Query<(), With<Boss>>,
+    player_query: Query<(), With<Player>>,
+    replay: Res<Replay>,
+) {
+    // a replay can't earn anything its recording didn't
+    if replay.is_playing() {
+        return;
+    }
+    let mut earned = Vec::new();
+    if game_state.depth >= RANGER_DEPTH {
+        earned.push(Achievement::ReachFloor);
+    }
+    for death in ev_death.iter() {
+        if boss_query.get(death.entity).is_ok() && player_query.get(death.killer).is_ok() {
+            earned.push(Achievement::SlayBoss);
+        }
+    }
+    for achievement in earned {
+        if !unlocks.has(achievement) {
+            unlocks.earn(achievement, &profile);
+            announce(&mut log, achievement);
+        }
+    }
+}
+
+fn earn_escape(profile: Res<Profile>, mut unlocks: ResMut<Unlocks>, replay: Res<Replay>) {
+    if !replay.is_playing() && !unlocks.has(Achievement::Escape) {
+        unlocks.earn(Achievement::Escape, &profile);
+    }
+}
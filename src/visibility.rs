@@ -0,0 +1,381 @@
+use crate::prelude::*;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+// Bresenham line between two tiles, not including the start tile
+pub fn line(from: &GridPos, to: &GridPos) -> Vec<GridPos> {
+    let mut tiles = Vec::new();
+    let dx = (to.x - from.x).abs();
+    let dy = -(to.y - from.y).abs();
+    let sx = if from.x < to.x { 1 } else { -1 };
+    let sy = if from.y < to.y { 1 } else { -1 };
+    let mut err = dx + dy;
+    let (mut x, mut y) = (from.x, from.y);
+    while (x, y) != (to.x, to.y) {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        tiles.push(GridPos::new(x, y));
+    }
+    tiles
+}
+
+// true if no wall sits strictly between the two tiles along the line. what projectiles
+// and blasts follow, sight goes by the shadowcasting below
+pub fn line_of_sight(map_data: &DungeonMap, from: &GridPos, to: &GridPos) -> bool {
+    let tiles = line(from, to);
+    let between = tiles.len().saturating_sub(1);
+    tiles
+        .iter()
+        .take(between)
+        .all(|loc| !map_data.is_opaque(loc.x, loc.y))
+}
+
+// every tile seen from the origin out to a round radius, walls included. this is
+// symmetric shadowcasting: one floor tile sees another exactly when the other sees it back,
+// so nothing can shoot at the player from a spot the player can't see
+pub fn field_of_view(map_data: &DungeonMap, origin: &GridPos, radius: i32) -> HashSet<GridPos> {
+    let mut visible = HashSet::new();
+    visible.insert(*origin);
+    for quadrant in Quadrant::ALL.iter() {
+        quadrant.scan(map_data, origin, radius, &mut |tile| {
+            let offset = *tile - **origin;
+            // round rather than square, the corners are further than they look
+            let in_range = offset.x * offset.x + offset.y * offset.y <= radius * radius + radius;
+            if in_range && map_data.in_bounds(tile.x, tile.y) {
+                visible.insert(tile);
+            }
+        });
+    }
+    visible
+}
+
+// whether one tile can be seen from another, the same answer field_of_view gives
+pub fn is_visible(map_data: &DungeonMap, from: &GridPos, to: &GridPos) -> bool {
+    if from == to {
+        return true;
+    }
+    let depth = from.chebyshev(to);
+    let mut seen = false;
+    for quadrant in Quadrant::ALL.iter() {
+        quadrant.scan(map_data, from, depth, &mut |tile| seen |= tile == *to);
+    }
+    seen
+}
+
+// the four wedges around the origin, each scanned row by row moving away from it
+#[derive(Clone, Copy)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+// col / depth as an exact fraction, den is always positive
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    // the slope through the near corner of a tile, where shadows start and end
+    fn at(depth: i32, col: i32) -> Slope {
+        Slope {
+            num: 2 * col - 1,
+            den: 2 * depth,
+        }
+    }
+}
+
+// the stretch of one row of a quadrant that light still reaches
+#[derive(Clone, Copy)]
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    // every tile the light touches, rounding half-covered tiles in
+    fn cols(&self) -> RangeInclusive<i32> {
+        let min = (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den);
+        let max = -(self.end.den - 2 * self.depth * self.end.num).div_euclid(2 * self.end.den);
+        min..=max
+    }
+
+    // floor tiles only count as seen if their centre is in the light, which is what keeps
+    // the whole thing symmetric
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+impl Quadrant {
+    const ALL: [Quadrant; 4] = [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ];
+
+    fn tile(self, origin: &GridPos, depth: i32, col: i32) -> GridPos {
+        match self {
+            Quadrant::North => origin.add(col, depth),
+            Quadrant::East => origin.add(depth, col),
+            Quadrant::South => origin.add(col, -depth),
+            Quadrant::West => origin.add(-depth, col),
+        }
+    }
+
+    // calls reveal on every tile seen in the quadrant up to max_depth rows out
+    fn scan(
+        self,
+        map_data: &DungeonMap,
+        origin: &GridPos,
+        max_depth: i32,
+        reveal: &mut impl FnMut(GridPos),
+    ) {
+        let mut rows = vec![Row {
+            depth: 1,
+            start: Slope { num: -1, den: 1 },
+            end: Slope { num: 1, den: 1 },
+        }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > max_depth {
+                continue;
+            }
+            let mut prev_wall = None;
+            for col in row.cols() {
+                let tile = self.tile(origin, row.depth, col);
+                let wall = map_data.is_opaque(tile.x, tile.y);
+                if wall || row.is_symmetric(col) {
+                    reveal(tile);
+                }
+                // coming out from behind a wall, the light starts again here
+                if prev_wall == Some(true) && !wall {
+                    row.start = Slope::at(row.depth, col);
+                }
+                // a wall casts a shadow over the rest of the row behind it
+                if prev_wall == Some(false) && wall {
+                    let mut next = row.next();
+                    next.end = Slope::at(row.depth, col);
+                    rows.push(next);
+                }
+                prev_wall = Some(wall);
+            }
+            if prev_wall == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a map from rows of text, top row first: '#' is wall, anything else floor
+    fn map(text: &str) -> DungeonMap {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        let height = rows.len();
+        let width = rows[0].len();
+        let mut map_data = DungeonMap::filled(Tile::Wall, width, height);
+        for (row, line) in rows.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c != '#' {
+                    map_data.set_tile(x as i32, (height - 1 - row) as i32, Tile::Ground);
+                }
+            }
+        }
+        map_data
+    }
+
+    #[test]
+    fn line_skips_the_start_and_ends_on_the_target() {
+        let from = GridPos::new(0, 0);
+        assert!(line(&from, &from).is_empty());
+        assert_eq!(
+            line(&from, &GridPos::new(3, 0)),
+            vec![GridPos::new(1, 0), GridPos::new(2, 0), GridPos::new(3, 0)]
+        );
+        assert_eq!(
+            line(&from, &GridPos::new(-2, -2)),
+            vec![GridPos::new(-1, -1), GridPos::new(-2, -2)]
+        );
+        // never longer than the chebyshev distance, whichever way it goes
+        for (x, y) in [(5, 2), (-3, 7), (1, -6), (-4, -4)].iter() {
+            let to = GridPos::new(*x, *y);
+            let tiles = line(&from, &to);
+            assert_eq!(tiles.len() as i32, from.chebyshev(&to));
+            assert_eq!(tiles.last(), Some(&to));
+        }
+    }
+
+    #[test]
+    fn line_of_sight_ignores_the_endpoints() {
+        let map_data = map("
+            #####
+            #...#
+            #####
+            ");
+        // a wall at the far end is still in sight, it's only what's between that counts
+        assert!(line_of_sight(
+            &map_data,
+            &GridPos::new(1, 1),
+            &GridPos::new(4, 1)
+        ));
+        assert!(!line_of_sight(
+            &map_data,
+            &GridPos::new(1, 1),
+            &GridPos::new(1, 4)
+        ));
+    }
+
+    #[test]
+    fn open_room_is_all_visible_including_its_walls() {
+        let map_data = map("
+            #######
+            #.....#
+            #.....#
+            #.....#
+            #######
+            ");
+        let visible = field_of_view(&map_data, &GridPos::new(3, 2), 8);
+        for y in 0..5 {
+            for x in 0..7 {
+                assert!(visible.contains(&GridPos::new(x, y)), "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn pillars_cast_shadows() {
+        let map_data = map("
+            #########
+            #.......#
+            #..#....#
+            #.......#
+            #########
+            ");
+        let visible = field_of_view(&map_data, &GridPos::new(1, 2), 8);
+        assert!(visible.contains(&GridPos::new(3, 2)));
+        assert!(!visible.contains(&GridPos::new(4, 2)));
+        assert!(!visible.contains(&GridPos::new(6, 2)));
+        // the shadow widens with distance but the rows beside the pillar stay lit
+        assert!(visible.contains(&GridPos::new(4, 3)));
+        assert!(visible.contains(&GridPos::new(4, 1)));
+    }
+
+    #[test]
+    fn a_long_wall_is_seen_all_along_from_beside_it() {
+        let map_data = map("
+            ##########
+            #........#
+            ##########
+            ");
+        let visible = field_of_view(&map_data, &GridPos::new(1, 1), 20);
+        for x in 0..10 {
+            assert!(visible.contains(&GridPos::new(x, 2)), "wall at {}", x);
+            assert!(visible.contains(&GridPos::new(x, 0)), "wall at {}", x);
+        }
+    }
+
+    #[test]
+    fn diagonal_gaps_are_seen_through_straight_on() {
+        let map_data = map("
+            #####
+            #.#.#
+            ##.##
+            #.#.#
+            #####
+            ");
+        // the walls at (1, 2) and (2, 3) only touch at a corner, the way a diagonal
+        // corridor is built, so looking down it works
+        assert!(is_visible(
+            &map_data,
+            &GridPos::new(1, 3),
+            &GridPos::new(3, 1)
+        ));
+        // but the pockets to either side are round a corner
+        assert!(!is_visible(
+            &map_data,
+            &GridPos::new(1, 3),
+            &GridPos::new(1, 1)
+        ));
+        assert!(!is_visible(
+            &map_data,
+            &GridPos::new(1, 3),
+            &GridPos::new(3, 3)
+        ));
+    }
+
+    #[test]
+    fn radius_is_round() {
+        let map_data = map("
+            ###########
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            #.........#
+            ###########
+            ");
+        let origin = GridPos::new(5, 5);
+        let visible = field_of_view(&map_data, &origin, 3);
+        assert!(visible.contains(&GridPos::new(8, 5)));
+        assert!(visible.contains(&GridPos::new(7, 7)));
+        assert!(!visible.contains(&GridPos::new(8, 8)));
+        assert!(!visible.contains(&GridPos::new(9, 5)));
+    }
+
+    #[test]
+    fn sight_is_symmetric_between_floor_tiles() {
+        let map_data = map("
+            ############
+            #....#.....#
+            #.#.....#..#
+            #...##.....#
+            #.#....#.#.#
+            ##...#.....#
+            #....#..##.#
+            #.#........#
+            ############
+            ");
+        let floors: Vec<GridPos> = (0..map_data.height() as i32)
+            .flat_map(|y| (0..map_data.width() as i32).map(move |x| GridPos::new(x, y)))
+            .filter(|loc| map_data.is_walkable(loc.x, loc.y))
+            .collect();
+        for a in floors.iter() {
+            let seen = field_of_view(&map_data, a, 12);
+            for b in floors.iter() {
+                let there = field_of_view(&map_data, b, 12);
+                assert_eq!(seen.contains(b), there.contains(a), "{:?} and {:?}", a, b);
+                assert_eq!(seen.contains(b), is_visible(&map_data, a, b));
+            }
+        }
+    }
+}
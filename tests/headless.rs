@@ -0,0 +1,56 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_dungeon::headless::{run_script, Ending, PlayerClass, Script, ScriptedInput};
+
+// a long wander in random directions, the same one for the same seed
+fn wander(seed: u64, steps: usize) -> Vec<ScriptedInput> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..steps)
+        .map(|_| ScriptedInput::Step(rng.gen_range(-1..=1), rng.gen_range(-1..=1)))
+        .collect()
+}
+
+fn script(seed: u64, class: PlayerClass, inputs: Vec<ScriptedInput>) -> Script {
+    Script {
+        seed,
+        class,
+        layout: Default::default(),
+        mode: Default::default(),
+        difficulty: Default::default(),
+        inputs,
+    }
+}
+
+#[test]
+fn a_run_starts_on_the_first_floor() {
+    let outcome = run_script(&script(1, PlayerClass::Warrior, Vec::new()));
+    assert_eq!(outcome.ending, Ending::Finished);
+    assert_eq!(outcome.depth, 1);
+    assert_eq!(outcome.turn, 0);
+    assert!(outcome.hp > 0);
+    assert!(outcome
+        .log
+        .iter()
+        .any(|line| line == "You set out as a warrior."));
+}
+
+#[test]
+fn the_same_script_plays_out_the_same() {
+    let run = script(7, PlayerClass::Rogue, wander(7, 150));
+    let first = run_script(&run);
+    let second = run_script(&run);
+    assert_ne!(first.ending, Ending::Stuck);
+    assert!(first.turn > 0);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn the_seed_decides_the_run() {
+    let inputs = wander(3, 100);
+    let one = run_script(&script(11, PlayerClass::Mage, inputs.clone()));
+    let other = run_script(&script(12, PlayerClass::Mage, inputs));
+    assert_ne!(one.ending, Ending::Stuck);
+    assert_ne!(other.ending, Ending::Stuck);
+    // the same steps through a different dungeon don't end up in the same place
+    assert_ne!(one, other);
+}